@@ -1,7 +1,7 @@
 // Copyright 2018-2024 the Deno authors. MIT license.
 
 use lazy_static::lazy_static;
-use miette::{miette, Context, Result};
+use miette::{miette, Context, LabeledSpan, Result};
 use pest::iterators::Pair;
 use pest::pratt_parser::{Assoc, Op, PrattParser};
 use pest::Parser;
@@ -25,6 +25,10 @@ pub struct SequentialList {
 #[error("Invalid sequential list item")]
 pub struct SequentialListItem {
   pub is_async: bool,
+  /// The line of the input the item starts on, starting at 1
+  pub line: usize,
+  /// The item as it was written, which names the job when it is async
+  pub text: String,
   pub sequence: Sequence,
 }
 
@@ -37,6 +41,8 @@ pub struct SequentialListItem {
 pub enum Sequence {
   #[error("Invalid shell variable")]
   ShellVar(EnvVar),
+  #[error("Invalid array assignment")]
+  ArrayAssignment(ArrayAssignment),
   #[error("Invalid pipeline")]
   Pipeline(Pipeline),
   #[error("Invalid boolean list")]
@@ -146,7 +152,8 @@ pub enum PipeSequenceOperator {
 #[error("Invalid command")]
 pub struct Command {
   pub inner: CommandInner,
-  pub redirect: Option<Redirect>,
+  /// Applied in order, so `> file 2>&1` sends both to the file
+  pub redirects: Vec<Redirect>,
 }
 
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
@@ -164,6 +171,21 @@ pub enum CommandInner {
   If(IfClause),
   #[error("Invalid arithmetic expression")]
   ArithmeticExpression(Arithmetic),
+  #[error("Invalid for loop")]
+  For(ForLoop),
+  /// `select name in words; do body; done`, which has the same parts as a
+  /// for loop
+  #[error("Invalid select loop")]
+  Select(ForLoop),
+  #[error("Invalid while loop")]
+  While(WhileLoop),
+  #[error("Invalid case clause")]
+  Case(CaseClause),
+  /// `{ list; }`, which runs the list in the current shell
+  #[error("Invalid brace group")]
+  BraceGroup(Box<SequentialList>),
+  #[error("Invalid function definition")]
+  FunctionDefinition(FunctionDefinition),
 }
 
 impl From<Command> for Sequence {
@@ -188,7 +210,7 @@ pub struct SimpleCommand {
 impl From<SimpleCommand> for Command {
   fn from(c: SimpleCommand) -> Self {
     Command {
-      redirect: None,
+      redirects: Vec::new(),
       inner: CommandInner::Simple(c),
     }
   }
@@ -232,6 +254,76 @@ pub enum ElsePart {
   Else(SequentialList),
 }
 
+/// `for name in words; do body; done`
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Debug, PartialEq, Eq, Clone, Error)]
+#[error("Invalid for loop")]
+pub struct ForLoop {
+  pub var_name: String,
+  /// The words to loop over, the positional parameters when omitted
+  pub wordlist: Option<Vec<Word>>,
+  pub body: SequentialList,
+}
+
+/// `name() compound-command`
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Debug, PartialEq, Eq, Clone, Error)]
+#[error("Invalid function definition")]
+pub struct FunctionDefinition {
+  pub name: String,
+  pub body: Box<Command>,
+  /// The definition as it was written, shown by `declare -f` and `type`
+  pub text: String,
+}
+
+/// `while condition; do body; done`, or with `until` the body runs until
+/// the condition succeeds
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Debug, PartialEq, Eq, Clone, Error)]
+#[error("Invalid while loop")]
+pub struct WhileLoop {
+  pub condition: Condition,
+  pub body: SequentialList,
+  pub until: bool,
+}
+
+/// `case word in pattern) body;; esac`
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Debug, PartialEq, Eq, Clone, Error)]
+#[error("Invalid case clause")]
+pub struct CaseClause {
+  pub word: Word,
+  pub items: Vec<CaseItem>,
+}
+
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Debug, PartialEq, Eq, Clone, Error)]
+#[error("Invalid case item")]
+pub struct CaseItem {
+  /// The alternative patterns (`a|b)`)
+  pub patterns: Vec<Word>,
+  pub body: SequentialList,
+  pub terminator: CaseTerminator,
+}
+
+/// What happens after the body of a case item ran.
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CaseTerminator {
+  /// `;;`, or the end of the last item, the case is done
+  Break,
+  /// `;&`, the body of the next item runs too
+  FallThrough,
+  /// `;;&`, the patterns of the next items are tested
+  Continue,
+}
+
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
 #[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
 #[derive(Debug, PartialEq, Eq, Clone, Error)]
@@ -254,6 +346,8 @@ pub enum ConditionInner {
     op: Option<UnaryOp>,
     right: Word,
   },
+  /// Commands whose exit code is the condition (`if grep -q x file; then`)
+  Command(SequentialList),
 }
 
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
@@ -306,14 +400,82 @@ pub enum UnaryOp {
 pub struct EnvVar {
   pub name: String,
   pub value: Word,
+  /// `name+=value`, which appends the value
+  pub append: bool,
 }
 
 impl EnvVar {
   pub fn new(name: String, value: Word) -> Self {
-    EnvVar { name, value }
+    EnvVar {
+      name,
+      value,
+      append: false,
+    }
   }
 }
 
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(
+  feature = "serialization",
+  serde(rename_all = "camelCase", tag = "kind")
+)]
+#[derive(Debug, PartialEq, Eq, Clone, Error)]
+#[error("Invalid array assignment")]
+pub enum ArrayAssignment {
+  /// `name=(a b c)` or, when appending, `name+=(d)`
+  List {
+    name: String,
+    values: Vec<ArrayElement>,
+    append: bool,
+  },
+  /// `name[index]=value`
+  Element {
+    name: String,
+    index: ArrayIndex,
+    value: Word,
+  },
+}
+
+/// A value in an array literal, `([key]=value other)`
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ArrayElement {
+  pub index: Option<ArrayIndex>,
+  pub value: Word,
+}
+
+/// The index of an array element. Whether it is an arithmetic expression or
+/// a string key is only known once the array is looked up, so indices that
+/// parse as arithmetic keep their source text as well.
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(
+  feature = "serialization",
+  serde(rename_all = "camelCase", tag = "kind", content = "value")
+)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ArrayIndex {
+  /// `[i + 1]`, or the key `i + 1` of an associative array
+  Arithmetic(Arithmetic, String),
+  /// `[$i]` or `["some key"]`
+  Word(Word),
+}
+
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(
+  feature = "serialization",
+  serde(rename_all = "camelCase", tag = "kind", content = "value")
+)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ArraySubscript {
+  /// `[0]`, `[i + 1]`, `[$i]` or `[key]`
+  Index(ArrayIndex),
+  /// `[@]`, every element is a separate word, even when quoted
+  All,
+  /// `[*]`, the elements joined by a space when quoted
+  AllJoined,
+}
+
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
 #[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
 #[derive(Debug, PartialEq, Eq, Clone, Error)]
@@ -374,13 +536,40 @@ impl Word {
 #[error("Invalid variable modifier")]
 pub enum VariableModifier {
   #[error("Invalid substring")]
-  Substring {
-    begin: Word,
-    length: Option<Word>,
+  Substring { begin: Word, length: Option<Word> },
+  /// `${name:-word}`, or `${name-word}` without `colon`
+  DefaultValue { value: Word, colon: bool },
+  /// `${name:=word}`, or `${name=word}` without `colon`
+  AssignDefault { value: Word, colon: bool },
+  /// `${name:+word}`, or `${name+word}` without `colon`
+  AlternateValue { value: Word, colon: bool },
+  /// `${name:?word}`, or `${name?word}` without `colon`
+  ErrorIfUnset { message: Word, colon: bool },
+  /// `${name#pattern}`, or `${name##pattern}` with `longest`
+  RemovePrefix { pattern: Word, longest: bool },
+  /// `${name%pattern}`, or `${name%%pattern}` with `longest`
+  RemoveSuffix { pattern: Word, longest: bool },
+  /// `${name/pattern/replacement}` and its variants
+  Replace {
+    pattern: Word,
+    replacement: Word,
+    mode: ReplaceMode,
   },
-  DefaultValue(Word),
-  AssignDefault(Word),
-  AlternateValue(Word),
+}
+
+/// Which matches of the pattern `${name/pattern/replacement}` replaces.
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ReplaceMode {
+  /// `/`, the first (longest) match
+  First,
+  /// `//`, every match
+  All,
+  /// `/#`, a match at the start
+  Prefix,
+  /// `/%`, a match at the end
+  Suffix,
 }
 
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
@@ -394,8 +583,19 @@ pub enum WordPart {
   Text(String),
   #[error("Invalid variable")]
   Variable(String, Option<Box<VariableModifier>>),
+  #[error("Invalid array expansion")]
+  Array(String, ArraySubscript, Option<Box<VariableModifier>>),
+  /// `${#name}` or `${#name[@]}`
+  #[error("Invalid length expansion")]
+  Length(String, Option<ArraySubscript>),
+  /// `${!name[@]}`, the indices or keys of an array
+  #[error("Invalid array keys expansion")]
+  ArrayKeys(String, ArraySubscript),
   #[error("Invalid command")]
   Command(SequentialList),
+  /// `<(list)` or `>(list)`
+  #[error("Invalid process substitution")]
+  ProcessSubstitution(ProcessSubstitution),
   #[error("Invalid quoted string")]
   Quoted(Vec<WordPart>),
   #[error("Invalid tilde prefix")]
@@ -404,6 +604,31 @@ pub enum WordPart {
   Arithmetic(Arithmetic),
   #[error("Invalid exit status")]
   ExitStatus,
+  /// `name=(a b c)` as an argument of `declare`, which is assigned once the
+  /// variable is declared
+  #[error("Invalid array assignment")]
+  ArrayAssignment(ArrayAssignment),
+}
+
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Invalid process substitution")]
+pub struct ProcessSubstitution {
+  pub kind: ProcessSubstitutionKind,
+  pub list: SequentialList,
+  /// The substitution as it was written, for messages
+  pub text: String,
+}
+
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessSubstitutionKind {
+  /// `<(list)`, the command reads the output of the list
+  Read,
+  /// `>(list)`, the command writes to the input of the list
+  Write,
 }
 
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
@@ -449,11 +674,12 @@ pub enum ArithmeticPart {
     operator: UnaryArithmeticOp,
     operand: Box<ArithmeticPart>,
   },
+  /// `++name` or `--name`, the value after the change
+  #[error("Invalid pre arithmetic expression")]
+  PreArithmeticExpr { name: String, operator: IncrementOp },
+  /// `name++` or `name--`, the value before the change
   #[error("Invalid post arithmetic expression")]
-  PostArithmeticExpr {
-    operand: Box<ArithmeticPart>,
-    operator: PostArithmeticOp,
-  },
+  PostArithmeticExpr { name: String, operator: IncrementOp },
   #[error("Invalid variable")]
   Variable(String),
   #[error("Invalid number")]
@@ -508,8 +734,8 @@ pub enum UnaryArithmeticOp {
 
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
 #[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum PostArithmeticOp {
+#[derive(Debug, Clone, PartialEq, Eq, Copy)]
+pub enum IncrementOp {
   Increment, // ++
   Decrement, // --
 }
@@ -535,6 +761,12 @@ pub struct Redirect {
   pub maybe_fd: Option<RedirectFd>,
   pub op: RedirectOp,
   pub io_file: IoFile,
+  /// Where the redirect is in the parsed input, for errors
+  #[cfg_attr(feature = "serialization", serde(skip))]
+  pub span: miette::SourceSpan,
+  /// The redirect as it was written
+  #[cfg_attr(feature = "serialization", serde(skip))]
+  pub text: String,
 }
 
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
@@ -569,6 +801,9 @@ pub enum RedirectOp {
 pub enum RedirectOpInput {
   #[error("Invalid input redirect")]
   Redirect,
+  /// `<<< word`, the expanded word and a newline are the input
+  #[error("Invalid here-string")]
+  HereString,
 }
 
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
@@ -587,24 +822,19 @@ lazy_static! {
     use Rule::*;
 
     PrattParser::new()
-      .op(
-        Op::infix(assign, Right)
-          | Op::infix(multiply_assign, Right)
-          | Op::infix(divide_assign, Right)
-          | Op::infix(modulo_assign, Right)
-          | Op::infix(add_assign, Right)
-          | Op::infix(subtract_assign, Right)
-          | Op::infix(left_shift_assign, Right)
-          | Op::infix(right_shift_assign, Right)
-          | Op::infix(bitwise_and_assign, Right)
-          | Op::infix(bitwise_xor_assign, Right)
-          | Op::infix(bitwise_or_assign, Right),
-      )
+      .op(Op::infix(conditional, Right))
       .op(Op::infix(logical_or, Left))
       .op(Op::infix(logical_and, Left))
       .op(Op::infix(bitwise_or, Left))
       .op(Op::infix(bitwise_xor, Left))
       .op(Op::infix(bitwise_and, Left))
+      .op(Op::infix(equal, Left) | Op::infix(not_equal, Left))
+      .op(
+        Op::infix(less, Left)
+          | Op::infix(less_equal, Left)
+          | Op::infix(greater, Left)
+          | Op::infix(greater_equal, Left),
+      )
       .op(Op::infix(left_shift, Left) | Op::infix(right_shift, Left))
       .op(Op::infix(add, Left) | Op::infix(subtract, Left))
       .op(
@@ -613,6 +843,13 @@ lazy_static! {
           | Op::infix(modulo, Left),
       )
       .op(Op::infix(power, Right))
+      // like in bash, `-2 ** 2` is 4
+      .op(
+        Op::prefix(unary_plus)
+          | Op::prefix(unary_minus)
+          | Op::prefix(logical_not)
+          | Op::prefix(bitwise_not),
+      )
   };
 }
 
@@ -620,19 +857,149 @@ lazy_static! {
 #[grammar = "grammar.pest"]
 struct ShellParser;
 
+/// A syntax error, with the location in the input where it was found.
+#[derive(Debug, Error)]
+#[error("Failed to parse input")]
+pub struct ParseError {
+  input: String,
+  /// The input with the name given to it by [`ParseError::with_filename`]
+  named_input: Option<miette::NamedSource<String>>,
+  span: miette::SourceSpan,
+  /// What the parser expected at that location
+  expected: String,
+}
+
+impl ParseError {
+  fn new(input: &str, error: pest::error::Error<Rule>) -> Self {
+    let (start, end) = match error.location {
+      pest::error::InputLocation::Pos(pos) => (pos, pos),
+      pest::error::InputLocation::Span(span) => span,
+    };
+    // an error at the end of the input points at its last character, so
+    // that it is still shown on the last line
+    let start = match input[..start].chars().next_back() {
+      Some(c) if start == input.len() => start - c.len_utf8(),
+      _ => start,
+    };
+    ParseError {
+      input: input.to_string(),
+      named_input: None,
+      span: (start, end.max(start + 1).min(input.len()) - start).into(),
+      expected: error.variant.message().to_string(),
+    }
+  }
+
+  /// Names the file the input came from, which is shown with the location
+  /// of the error.
+  pub fn with_filename(mut self, filename: impl AsRef<str>) -> Self {
+    self.named_input =
+      Some(miette::NamedSource::new(filename, self.input.clone()));
+    self
+  }
+
+  /// The line and column (starting at 1) of the error
+  pub fn line_col(&self) -> (usize, usize) {
+    let before = &self.input[..self.span.offset()];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+      before.matches('\n').count() + 1,
+      before[line_start..].chars().count() + 1,
+    )
+  }
+}
+
+impl miette::Diagnostic for ParseError {
+  fn source_code(&self) -> Option<&dyn miette::SourceCode> {
+    match &self.named_input {
+      Some(named_input) => Some(named_input),
+      None => Some(&self.input),
+    }
+  }
+
+  fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+    Some(Box::new(std::iter::once(LabeledSpan::new_with_span(
+      Some(self.expected.clone()),
+      self.span,
+    ))))
+  }
+}
+
 pub fn debug_parse(input: &str) {
   let parsed = ShellParser::parse(Rule::FILE, input);
   pest_ascii_tree::print_ascii_tree(parsed);
 }
 
 pub fn parse(input: &str) -> Result<SequentialList> {
-  let mut pairs = ShellParser::parse(Rule::FILE, input).map_err(|e| {
-    miette::Error::new(e.into_miette()).context("Failed to parse input")
-  })?;
+  let mut pairs = ShellParser::parse(Rule::FILE, input)
+    .map_err(|e| ParseError::new(input, e))?;
 
   parse_file(pairs.next().unwrap())
 }
 
+/// Parses `input` as an arithmetic expression without the surrounding `$((` and
+/// `))`, as done for values assigned to integer variables.
+pub fn parse_arithmetic(input: &str) -> Result<Arithmetic> {
+  let mut pairs =
+    ShellParser::parse(Rule::ARITHMETIC_VALUE, input).map_err(|e| {
+      miette::Error::new(e.into_miette())
+        .context("Failed to parse arithmetic expression")
+    })?;
+  let inner = pairs.next().unwrap().into_inner().next().unwrap();
+  let parts = parse_arithmetic_sequence(inner)?;
+  Ok(Arithmetic { parts })
+}
+
+/// Parses `input` and returns the source text of each of its top level
+/// commands, ending with ` &` if the command runs in the background. A
+/// compound command such as a loop is a single command.
+pub fn split_commands(input: &str) -> Result<Vec<String>> {
+  let mut pairs = ShellParser::parse(Rule::FILE, input)
+    .map_err(|e| ParseError::new(input, e))?;
+  let complete_command = pairs.next().unwrap().into_inner().next().unwrap();
+  let mut commands = Vec::new();
+  for list in complete_command.into_inner() {
+    if list.as_rule() != Rule::list {
+      continue;
+    }
+    for item in list.into_inner() {
+      match item.as_rule() {
+        Rule::and_or => commands.push(item.as_str().trim().to_string()),
+        Rule::separator_op if item.as_str() == "&" => {
+          if let Some(last) = commands.last_mut() {
+            last.push_str(" &");
+          }
+        }
+        _ => {}
+      }
+    }
+  }
+  Ok(commands)
+}
+
+/// Returns whether `input` is cut off in the middle of a command, such as an
+/// unterminated quote or an `if` without its `fi`, so that more lines are
+/// needed before it can be run.
+///
+/// This is the case when the input ends with a line continuation or `&&`, or
+/// the parser fails at the very end of the input. Errors anywhere else are
+/// real syntax errors that more input won't fix.
+pub fn is_incomplete(input: &str) -> bool {
+  let trailing_backslashes =
+    input.bytes().rev().take_while(|&c| c == b'\\').count();
+  // the grammar accepts a trailing `&&` as two `&` separators
+  if trailing_backslashes % 2 == 1 || input.trim_end().ends_with("&&") {
+    return true;
+  }
+  let Err(err) = ShellParser::parse(Rule::FILE, input) else {
+    return false;
+  };
+  let end = match err.location {
+    pest::error::InputLocation::Pos(pos) => pos,
+    pest::error::InputLocation::Span((_, end)) => end,
+  };
+  end >= input.len()
+}
+
 fn parse_file(pairs: Pair<Rule>) -> Result<SequentialList> {
   parse_complete_command(pairs.into_inner().next().unwrap())
 }
@@ -666,9 +1033,13 @@ fn parse_list(
   for item in pair.into_inner() {
     match item.as_rule() {
       Rule::and_or => {
+        let line = item.line_col().0;
+        let text = item.as_str().trim_end().to_string();
         let sequence = parse_and_or(item)?;
         items.push(SequentialListItem {
           is_async: false,
+          line,
+          text,
           sequence,
         });
       }
@@ -720,10 +1091,14 @@ fn parse_term(
   for item in pair.into_inner() {
     match item.as_rule() {
       Rule::and_or => {
+        let line = item.line_col().0;
+        let text = item.as_str().trim_end().to_string();
         let sequence = parse_and_or(item)?;
         items.push(SequentialListItem {
           sequence,
           is_async: false,
+          line,
+          text,
         });
       }
       Rule::separator_op => {
@@ -744,6 +1119,9 @@ fn parse_and_or(pair: Pair<Rule>) -> Result<Sequence> {
   let mut items = pair.into_inner();
   let first_item = items.next().unwrap();
   let mut current = match first_item.as_rule() {
+    Rule::ARRAY_ASSIGNMENT_WORD => {
+      Sequence::ArrayAssignment(parse_array_assignment(first_item)?)
+    }
     Rule::ASSIGNMENT_WORD => parse_shell_var(first_item)?,
     Rule::pipeline => parse_pipeline(first_item)?,
     _ => unreachable!(),
@@ -777,45 +1155,112 @@ fn parse_and_or(pair: Pair<Rule>) -> Result<Sequence> {
 }
 
 fn parse_shell_var(pair: Pair<Rule>) -> Result<Sequence> {
+  Ok(Sequence::ShellVar(parse_env_var(pair)?))
+}
+
+fn parse_array_assignment(pair: Pair<Rule>) -> Result<ArrayAssignment> {
   let mut inner = pair.into_inner();
   let name = inner
     .next()
     .ok_or_else(|| miette!("Expected variable name"))?
     .as_str()
     .to_string();
-  let value = inner
-    .next()
-    .ok_or_else(|| miette!("Expected variable value"))?;
-  let value = parse_assignment_value(value)?;
-  Ok(Sequence::ShellVar(EnvVar { name, value }))
+  let mut next = inner.next();
+  let append = matches!(&next, Some(p) if p.as_rule() == Rule::APPEND);
+  if append {
+    next = inner.next();
+  }
+  let next = next.ok_or_else(|| miette!("Expected array value"))?;
+  let assignment = match next.as_rule() {
+    Rule::ARRAY_LITERAL => ArrayAssignment::List {
+      name,
+      values: next
+        .into_inner()
+        .map(parse_array_element)
+        .collect::<Result<Vec<_>>>()?,
+      append,
+    },
+    Rule::ARRAY_INDEX => ArrayAssignment::Element {
+      name,
+      index: parse_array_index(next)?,
+      value: match inner.next() {
+        Some(value) => parse_assignment_value(value)?,
+        None => Word::new_empty(),
+      },
+    },
+    _ => {
+      return Err(miette!(
+        "Unexpected rule in array assignment: {:?}",
+        next.as_rule()
+      ))
+    }
+  };
+  Ok(assignment)
+}
+
+fn parse_array_element(pair: Pair<Rule>) -> Result<ArrayElement> {
+  if pair.as_rule() != Rule::ARRAY_KEYED_VALUE {
+    return Ok(ArrayElement {
+      index: None,
+      value: parse_word(pair)?,
+    });
+  }
+  let mut inner = pair.into_inner();
+  let index = parse_array_index(inner.next().unwrap())?;
+  let value = match inner.next() {
+    Some(value) => parse_word(value)?,
+    None => Word::new_empty(),
+  };
+  Ok(ArrayElement {
+    index: Some(index),
+    value,
+  })
+}
+
+fn parse_array_index(pair: Pair<Rule>) -> Result<ArrayIndex> {
+  let inner = pair.into_inner().next().unwrap();
+  match inner.as_rule() {
+    Rule::arithmetic_sequence => {
+      let text = inner.as_str().trim().to_string();
+      let parts = parse_arithmetic_sequence(inner)?;
+      Ok(ArrayIndex::Arithmetic(Arithmetic { parts }, text))
+    }
+    _ => Ok(ArrayIndex::Word(parse_word(inner)?)),
+  }
+}
+
+fn parse_array_subscript(pair: Pair<Rule>) -> Result<ArraySubscript> {
+  let inner = pair.into_inner().next().unwrap();
+  match inner.as_rule() {
+    Rule::ARRAY_ALL => Ok(ArraySubscript::All),
+    Rule::ARRAY_ALL_JOINED => Ok(ArraySubscript::AllJoined),
+    _ => Ok(ArraySubscript::Index(parse_array_index(inner)?)),
+  }
 }
 
 fn parse_pipeline(pair: Pair<Rule>) -> Result<Sequence> {
   let pipeline_str = pair.as_str();
+  let pipeline_str_start = pair.as_span().start();
   let mut inner = pair.into_inner();
 
-  // Check if the first element is Bang (negation)
-  let first = inner
-    .next()
-    .ok_or_else(|| miette!("Expected pipeline content"))?;
-  let (negated, pipe_sequence) = if first.as_rule() == Rule::Bang {
+  // Each leading Bang negates the status again, so `! !` cancels out
+  let mut negated = false;
+  let pipe_sequence = loop {
+    let pair = inner
+      .next()
+      .ok_or_else(|| miette!("Expected pipe sequence after negation"))?;
+    if pair.as_rule() != Rule::Bang {
+      break pair;
+    }
     // If it's Bang, check for whitespace
-    if pipeline_str.len() > 1
-      && !pipeline_str[1..2].chars().next().unwrap().is_whitespace()
-    {
+    let rest = &pipeline_str[pair.as_span().end() - pipeline_str_start..];
+    if !rest.starts_with(char::is_whitespace) {
       return Err(miette!(
-        "Perhaps you meant to add a space after the exclamation point to negate the command?\n  ! {}", 
+        "Perhaps you meant to add a space after the exclamation point to negate the command?\n  ! {}",
         pipeline_str
       ));
     }
-    // Get the actual pipe sequence after whitespace
-    let pipe_sequence = inner
-      .next()
-      .ok_or_else(|| miette!("Expected pipe sequence after negation"))?;
-    (true, pipe_sequence)
-  } else {
-    // If it's not Bang, this element itself is the pipe_sequence
-    (false, first)
+    negated = !negated;
   };
 
   let pipeline_inner = parse_pipe_sequence(pipe_sequence)?;
@@ -866,21 +1311,32 @@ fn parse_pipe_sequence(pair: Pair<Rule>) -> Result<PipelineInner> {
 }
 
 fn parse_command(pair: Pair<Rule>) -> Result<Command> {
-  let inner = pair.into_inner().next().unwrap();
+  let inner = pair.clone().into_inner().next().unwrap();
   match inner.as_rule() {
     Rule::simple_command => parse_simple_command(inner),
-    Rule::compound_command => parse_compound_command(inner),
-    Rule::function_definition => {
-      Err(miette!("Function definitions are not supported yet"))
-    }
+    Rule::compound_command => parse_redirected_compound_command(pair),
+    Rule::function_definition => parse_function_definition(inner),
     _ => Err(miette!("Unexpected rule in command: {:?}", inner.as_rule())),
   }
 }
 
+/// Parses a compound command followed by an optional redirect list, as in
+/// `(echo a) > file` or `{ echo b; } 2>&1`.
+fn parse_redirected_compound_command(pair: Pair<Rule>) -> Result<Command> {
+  let mut inner = pair.into_inner();
+  let mut command = parse_compound_command(inner.next().unwrap())?;
+  if let Some(redirect_list) = inner.next() {
+    for io_redirect in redirect_list.into_inner() {
+      command.redirects.push(parse_io_redirect(io_redirect)?);
+    }
+  }
+  Ok(command)
+}
+
 fn parse_simple_command(pair: Pair<Rule>) -> Result<Command> {
   let mut env_vars = Vec::new();
   let mut args = Vec::new();
-  let mut redirect = None;
+  let mut redirects = Vec::new();
 
   for item in pair.into_inner() {
     match item.as_rule() {
@@ -904,13 +1360,29 @@ fn parse_simple_command(pair: Pair<Rule>) -> Result<Command> {
       Rule::cmd_suffix => {
         for suffix in item.into_inner() {
           match suffix.as_rule() {
-            Rule::UNQUOTED_PENDING_WORD => args.push(parse_word(suffix)?),
+            Rule::UNQUOTED_PENDING_WORD | Rule::ASSIGNMENT_WORD => {
+              args.push(parse_word(suffix)?)
+            }
             Rule::io_redirect => {
-              redirect = Some(parse_io_redirect(suffix)?);
+              redirects.push(parse_io_redirect(suffix)?);
             }
             Rule::QUOTED_WORD => {
               args.push(Word::new(vec![parse_quoted_word(suffix)?]))
             }
+            Rule::ARRAY_ASSIGNMENT_WORD => {
+              let is_declaration = matches!(
+                args.first().map(|word| word.parts().as_slice()),
+                Some([WordPart::Text(name)])
+                  if name == "declare" || name == "typeset"
+              );
+              if !is_declaration {
+                return Err(miette!(
+                  "Array assignments are only supported as arguments of declare"
+                ));
+              }
+              let assignment = parse_array_assignment(suffix)?;
+              args.push(Word::new(vec![WordPart::ArrayAssignment(assignment)]));
+            }
             _ => {
               return Err(miette!(
                 "Unexpected rule in cmd_suffix: {:?}",
@@ -931,39 +1403,43 @@ fn parse_simple_command(pair: Pair<Rule>) -> Result<Command> {
 
   Ok(Command {
     inner: CommandInner::Simple(SimpleCommand { env_vars, args }),
-    redirect,
+    redirects,
   })
 }
 
 fn parse_compound_command(pair: Pair<Rule>) -> Result<Command> {
   let inner = pair.into_inner().next().unwrap();
   match inner.as_rule() {
-    Rule::brace_group => {
-      Err(miette!("Unsupported compound command brace_group"))
-    }
+    Rule::brace_group => parse_brace_group(inner),
     Rule::subshell => parse_subshell(inner),
-    Rule::for_clause => Err(miette!("Unsupported compound command for_clause")),
-    Rule::case_clause => {
-      Err(miette!("Unsupported compound command case_clause"))
-    }
+    Rule::for_clause => Ok(Command {
+      inner: CommandInner::For(parse_for_clause(inner)?),
+      redirects: Vec::new(),
+    }),
+    Rule::select_clause => Ok(Command {
+      inner: CommandInner::Select(parse_for_clause(inner)?),
+      redirects: Vec::new(),
+    }),
+    Rule::case_clause => Ok(Command {
+      inner: CommandInner::Case(parse_case_clause(inner)?),
+      redirects: Vec::new(),
+    }),
     Rule::if_clause => {
       let if_clause = parse_if_clause(inner)?;
       Ok(Command {
         inner: CommandInner::If(if_clause),
-        redirect: None,
+        redirects: Vec::new(),
       })
     }
-    Rule::while_clause => {
-      Err(miette!("Unsupported compound command while_clause"))
-    }
-    Rule::until_clause => {
-      Err(miette!("Unsupported compound command until_clause"))
-    }
+    Rule::while_clause | Rule::until_clause => Ok(Command {
+      inner: CommandInner::While(parse_while_clause(inner)?),
+      redirects: Vec::new(),
+    }),
     Rule::ARITHMETIC_EXPRESSION => {
       let arithmetic_expression = parse_arithmetic_expression(inner)?;
       Ok(Command {
         inner: CommandInner::ArithmeticExpression(arithmetic_expression),
-        redirect: None,
+        redirects: Vec::new(),
       })
     }
     _ => Err(miette!(
@@ -979,19 +1455,208 @@ fn parse_subshell(pair: Pair<Rule>) -> Result<Command> {
     parse_compound_list(inner, &mut items)?;
     Ok(Command {
       inner: CommandInner::Subshell(Box::new(SequentialList { items })),
-      redirect: None,
+      redirects: Vec::new(),
     })
   } else {
     Err(miette!("Unexpected end of input in subshell"))
   }
 }
 
+fn parse_brace_group(pair: Pair<Rule>) -> Result<Command> {
+  let mut items = Vec::new();
+  for item in pair.into_inner() {
+    match item.as_rule() {
+      Rule::Lbrace | Rule::Rbrace => {}
+      Rule::compound_list => parse_compound_list(item, &mut items)?,
+      _ => {
+        return Err(miette!(
+          "Unexpected rule in brace_group: {:?}",
+          item.as_rule()
+        ))
+      }
+    }
+  }
+  Ok(Command {
+    inner: CommandInner::BraceGroup(Box::new(SequentialList { items })),
+    redirects: Vec::new(),
+  })
+}
+
+fn parse_function_definition(pair: Pair<Rule>) -> Result<Command> {
+  let text = pair.as_str().trim_end().to_string();
+  let mut inner = pair.into_inner();
+  let name = inner.next().unwrap().as_str().to_string();
+  let body = inner
+    .find(|item| item.as_rule() == Rule::function_body)
+    .ok_or_else(|| miette!("Expected a function body for {name}"))?;
+  let body = parse_redirected_compound_command(body)?;
+  Ok(Command {
+    inner: CommandInner::FunctionDefinition(FunctionDefinition {
+      name,
+      body: Box::new(body),
+      text,
+    }),
+    redirects: Vec::new(),
+  })
+}
+
+fn parse_for_clause(pair: Pair<Rule>) -> Result<ForLoop> {
+  let rule = pair.as_rule();
+  let mut var_name = None;
+  let mut wordlist = None;
+  let mut body = None;
+  for item in pair.into_inner() {
+    match item.as_rule() {
+      Rule::For | Rule::Select | Rule::sequential_sep => {}
+      Rule::name => var_name = Some(item.as_str().to_string()),
+      // `for x in; do` loops over nothing
+      Rule::In => wordlist = Some(Vec::new()),
+      Rule::wordlist => {
+        wordlist = Some(
+          item
+            .into_inner()
+            .map(parse_word)
+            .collect::<Result<Vec<_>>>()?,
+        );
+      }
+      Rule::do_group => body = Some(parse_do_group(item)?),
+      _ => {
+        return Err(miette!(
+          "Unexpected rule in {:?}: {:?}",
+          rule,
+          item.as_rule()
+        ))
+      }
+    }
+  }
+  Ok(ForLoop {
+    var_name: var_name.ok_or_else(|| miette!("Expected name in {:?}", rule))?,
+    wordlist,
+    body: body.ok_or_else(|| miette!("Expected do group in {:?}", rule))?,
+  })
+}
+
+fn parse_while_clause(pair: Pair<Rule>) -> Result<WhileLoop> {
+  let until = pair.as_rule() == Rule::until_clause;
+  let mut inner = pair.into_inner();
+  // skip the `while` or `until` keyword
+  inner.next();
+  let condition = inner
+    .next()
+    .ok_or_else(|| miette!("Expected condition in while loop"))?;
+  let condition = parse_condition(condition)?;
+  let body_pair = inner
+    .next()
+    .ok_or_else(|| miette!("Expected do group in while loop"))?;
+  Ok(WhileLoop {
+    condition,
+    body: parse_do_group(body_pair)?,
+    until,
+  })
+}
+
+fn parse_do_group(pair: Pair<Rule>) -> Result<SequentialList> {
+  let mut items = Vec::new();
+  for item in pair.into_inner() {
+    match item.as_rule() {
+      Rule::Do | Rule::Done => {}
+      Rule::compound_list => parse_compound_list(item, &mut items)?,
+      _ => {
+        return Err(miette!(
+          "Unexpected rule in do_group: {:?}",
+          item.as_rule()
+        ))
+      }
+    }
+  }
+  Ok(SequentialList { items })
+}
+
+fn parse_case_clause(pair: Pair<Rule>) -> Result<CaseClause> {
+  let mut word = None;
+  let mut items = Vec::new();
+  for item in pair.into_inner() {
+    match item.as_rule() {
+      Rule::Case | Rule::In | Rule::Esac => {}
+      Rule::UNQUOTED_PENDING_WORD => word = Some(parse_word(item)?),
+      Rule::case_list => {
+        for case_item in item.into_inner() {
+          items.push(parse_case_item(case_item)?);
+        }
+      }
+      _ => {
+        return Err(miette!(
+          "Unexpected rule in case_clause: {:?}",
+          item.as_rule()
+        ))
+      }
+    }
+  }
+  Ok(CaseClause {
+    word: word.ok_or_else(|| miette!("Expected word after case"))?,
+    items,
+  })
+}
+
+fn parse_case_item(pair: Pair<Rule>) -> Result<CaseItem> {
+  let mut patterns = Vec::new();
+  let mut body = Vec::new();
+  let mut terminator = CaseTerminator::Break;
+  for item in pair.into_inner() {
+    match item.as_rule() {
+      Rule::pattern => {
+        for pattern in item.into_inner() {
+          patterns.push(match pattern.as_rule() {
+            // a pattern that is literally `esac`
+            Rule::Esac => Word::new_word("esac"),
+            _ => parse_word(pattern)?,
+          });
+        }
+      }
+      Rule::compound_list => parse_compound_list(item, &mut body)?,
+      Rule::DSEMI => {}
+      Rule::SEMI_AND => terminator = CaseTerminator::FallThrough,
+      Rule::DSEMI_AND => terminator = CaseTerminator::Continue,
+      _ => {
+        return Err(miette!(
+          "Unexpected rule in case item: {:?}",
+          item.as_rule()
+        ))
+      }
+    }
+  }
+  Ok(CaseItem {
+    patterns,
+    body: SequentialList { items: body },
+    terminator,
+  })
+}
+
+/// Parses the condition of an `if`, `elif`, `while` or `until`, either a
+/// test like `[ -f file ]` or a list of commands.
+fn parse_condition(pair: Pair<Rule>) -> Result<Condition> {
+  match pair.as_rule() {
+    Rule::conditional_expression => parse_conditional_expression(pair),
+    Rule::compound_list => {
+      let mut items = Vec::new();
+      parse_compound_list(pair, &mut items)?;
+      Ok(Condition {
+        condition_inner: ConditionInner::Command(SequentialList { items }),
+      })
+    }
+    _ => Err(miette!(
+      "Unexpected rule in if condition: {:?}",
+      pair.as_rule()
+    )),
+  }
+}
+
 fn parse_if_clause(pair: Pair<Rule>) -> Result<IfClause> {
   let mut inner = pair.into_inner();
   let condition = inner
     .next()
     .ok_or_else(|| miette!("Expected condition after If"))?;
-  let condition = parse_conditional_expression(condition)?;
+  let condition = parse_condition(condition)?;
 
   let then_body_pair = inner
     .next()
@@ -1022,7 +1687,7 @@ fn parse_else_part(pair: Pair<Rule>) -> Result<ElsePart> {
       let condition = inner
         .next()
         .ok_or_else(|| miette!("Expected condition after Elif"))?;
-      let condition = parse_conditional_expression(condition)?;
+      let condition = parse_condition(condition)?;
 
       let then_body_pair = inner
         .next()
@@ -1207,7 +1872,11 @@ fn parse_word(pair: Pair<Rule>) -> Result<Word> {
   let mut parts = Vec::new();
 
   match pair.as_rule() {
-    Rule::UNQUOTED_PENDING_WORD => {
+    // the file name of a redirect is parsed like any other word
+    Rule::UNQUOTED_PENDING_WORD
+    | Rule::FILE_NAME_PENDING_WORD
+    | Rule::ASSIGNMENT_PENDING_WORD => {
+      let rule = pair.as_rule();
       for part in pair.into_inner() {
         match part.as_rule() {
           Rule::EXIT_STATUS => parts.push(WordPart::ExitStatus),
@@ -1233,7 +1902,11 @@ fn parse_word(pair: Pair<Rule>) -> Result<Word> {
                 }
               }
             }
-            if let Some(WordPart::Text(ref mut text)) = parts.last_mut() {
+            if matches!(escaped_char.as_str(), "{" | "}" | ",") {
+              // quoted, so it isn't part of a brace expansion
+              parts.push(WordPart::Quoted(vec![WordPart::Text(escaped_char)]));
+            } else if let Some(WordPart::Text(ref mut text)) = parts.last_mut()
+            {
               text.push_str(&escaped_char);
             } else {
               parts.push(WordPart::Text(escaped_char));
@@ -1244,6 +1917,12 @@ fn parse_word(pair: Pair<Rule>) -> Result<Word> {
               parse_complete_command(part.into_inner().next().unwrap())?;
             parts.push(WordPart::Command(command));
           }
+          Rule::BACKTICK_COMMAND => {
+            parts.push(parse_backtick_command(part, false)?);
+          }
+          Rule::PROCESS_SUBSTITUTION => {
+            parts.push(parse_process_substitution(part)?);
+          }
           Rule::VARIABLE_EXPANSION => {
             let variable_expansion = parse_variable_expansion(part)?;
             parts.push(variable_expansion);
@@ -1262,7 +1941,8 @@ fn parse_word(pair: Pair<Rule>) -> Result<Word> {
           }
           _ => {
             return Err(miette!(
-              "Unexpected rule in UNQUOTED_PENDING_WORD: {:?}",
+              "Unexpected rule in {:?}: {:?}",
+              rule,
               part.as_rule()
             ));
           }
@@ -1274,53 +1954,36 @@ fn parse_word(pair: Pair<Rule>) -> Result<Word> {
       parts.push(quoted);
     }
     Rule::ASSIGNMENT_WORD => {
-      let assignment_str = pair.as_str().to_string();
-      parts.push(WordPart::Text(assignment_str));
-    }
-    Rule::FILE_NAME_PENDING_WORD => {
-      for part in pair.into_inner() {
-        match part.as_rule() {
-          Rule::UNQUOTED_ESCAPE_CHAR => {
-            if let Some(WordPart::Text(ref mut text)) = parts.last_mut() {
-              text.push(part.as_str().chars().next().unwrap());
-            } else {
-              parts.push(WordPart::Text(part.as_str().to_string()));
-            }
-          }
-          Rule::VARIABLE => {
-            parts.push(WordPart::Variable(part.as_str().to_string(), None))
-          }
-          Rule::UNQUOTED_CHAR => {
-            if let Some(WordPart::Text(ref mut text)) = parts.last_mut() {
-              text.push(part.as_str().chars().next().unwrap());
-            } else {
-              parts.push(WordPart::Text(part.as_str().to_string()));
-            }
-          }
-          Rule::QUOTED_WORD => {
-            let quoted = parse_quoted_word(part)?;
-            parts.push(quoted);
-          }
-          Rule::TILDE_PREFIX => {
-            let tilde_prefix = parse_tilde_prefix(part)?;
-            parts.push(tilde_prefix);
-          }
-          Rule::ARITHMETIC_EXPRESSION => {
-            let arithmetic_expression = parse_arithmetic_expression(part)?;
-            parts.push(WordPart::Arithmetic(arithmetic_expression));
-          }
-          _ => {
-            return Err(miette!(
-              "Unexpected rule in FILE_NAME_PENDING_WORD: {:?}",
-              part.as_rule()
-            ));
-          }
+      let EnvVar {
+        name,
+        value,
+        append,
+      } = parse_env_var(pair)?;
+      let operator = if append { "+=" } else { "=" };
+      parts.push(WordPart::Text(format!("{name}{operator}")));
+      for part in value.into_parts() {
+        match part {
+          WordPart::Text(text) => push_text(&mut parts, &text),
+          part => parts.push(part),
         }
       }
     }
-    Rule::PARAMETER_PENDING_WORD => {
+    Rule::PARAMETER_PENDING_WORD
+    | Rule::PARAMETER_VALUE_WORD
+    | Rule::PARAMETER_PATTERN_WORD
+    | Rule::ARRAY_INDEX_WORD => {
+      let rule = pair.as_rule();
       for part in pair.into_inner() {
         match part.as_rule() {
+          Rule::EXIT_STATUS => parts.push(WordPart::ExitStatus),
+          Rule::SUB_COMMAND => {
+            let command =
+              parse_complete_command(part.into_inner().next().unwrap())?;
+            parts.push(WordPart::Command(command));
+          }
+          Rule::BACKTICK_COMMAND => {
+            parts.push(parse_backtick_command(part, false)?);
+          }
           Rule::PARAMETER_ESCAPE_CHAR => {
             let mut chars = part.as_str().chars();
             let mut escaped_char = String::new();
@@ -1328,6 +1991,9 @@ fn parse_word(pair: Pair<Rule>) -> Result<Word> {
               match c {
                 '\\' => {
                   let next_char = chars.next().unwrap_or('\0');
+                  if next_char == '\\' {
+                    escaped_char.push('\\');
+                  }
                   escaped_char.push(next_char);
                 }
                 _ => {
@@ -1350,7 +2016,7 @@ fn parse_word(pair: Pair<Rule>) -> Result<Word> {
             let quoted = parse_quoted_word(part)?;
             parts.push(quoted);
           }
-          Rule::TILDE_PREFIX => {
+          Rule::TILDE_PREFIX | Rule::PARAMETER_TILDE_PREFIX => {
             let tilde_prefix = parse_tilde_prefix(part)?;
             parts.push(tilde_prefix);
           }
@@ -1367,7 +2033,8 @@ fn parse_word(pair: Pair<Rule>) -> Result<Word> {
           }
           _ => {
             return Err(miette!(
-              "Unexpected rule in PARAMETER_PENDING_WORD: {:?}",
+              "Unexpected rule in {:?}: {:?}",
+              rule,
               part.as_rule()
             ));
           }
@@ -1429,6 +2096,9 @@ fn parse_arithmetic_expr(pair: Pair<Rule>) -> Result<ArithmeticPart> {
             Rule::subtract_assign => AssignmentOp::SubtractAssign,
             Rule::left_shift_assign => AssignmentOp::LeftShiftAssign,
             Rule::right_shift_assign => AssignmentOp::RightShiftAssign,
+            Rule::bitwise_and_assign => AssignmentOp::BitwiseAndAssign,
+            Rule::bitwise_xor_assign => AssignmentOp::BitwiseXorAssign,
+            Rule::bitwise_or_assign => AssignmentOp::BitwiseOrAssign,
             _ => {
               return Err(miette!(
                 "Unexpected assignment operator: {:?}",
@@ -1439,29 +2109,46 @@ fn parse_arithmetic_expr(pair: Pair<Rule>) -> Result<ArithmeticPart> {
           value: Box::new(value),
         })
       }
-      Rule::triple_conditional_expr => {
-        let mut inner = primary.into_inner();
-        let condition = parse_arithmetic_expr(inner.next().unwrap())?;
-        let true_expr = parse_arithmetic_expr(inner.next().unwrap())?;
-        let false_expr = parse_arithmetic_expr(inner.next().unwrap())?;
-        Ok(ArithmeticPart::TripleConditionalExpr {
-          condition: Box::new(condition),
-          true_expr: Box::new(true_expr),
-          false_expr: Box::new(false_expr),
-        })
-      }
-      Rule::unary_arithmetic_expr => parse_unary_arithmetic_expr(primary),
+      Rule::increment_expr => parse_increment_expr(primary),
       Rule::VARIABLE => {
         Ok(ArithmeticPart::Variable(primary.as_str().to_string()))
       }
-      Rule::NUMBER => Ok(ArithmeticPart::Number(primary.as_str().to_string())),
+      Rule::ARITHMETIC_PARAMETER => Ok(parse_arithmetic_parameter(primary)),
+      Rule::ARITHMETIC_NUMBER => {
+        Ok(ArithmeticPart::Number(primary.as_str().to_string()))
+      }
       _ => Err(miette!(
         "Unexpected rule in arithmetic expression: {:?}",
         primary.as_rule()
       )),
     })
+    .map_prefix(|op, operand| {
+      let operator = match op.as_rule() {
+        Rule::unary_plus => UnaryArithmeticOp::Plus,
+        Rule::unary_minus => UnaryArithmeticOp::Minus,
+        Rule::logical_not => UnaryArithmeticOp::LogicalNot,
+        Rule::bitwise_not => UnaryArithmeticOp::BitwiseNot,
+        _ => {
+          return Err(miette!("Unexpected prefix operator: {:?}", op.as_rule()))
+        }
+      };
+      Ok(ArithmeticPart::UnaryArithmeticExpr {
+        operator,
+        operand: Box::new(operand?),
+      })
+    })
     .map_infix(|lhs, op, rhs| {
+      let (left, right) = (Box::new(lhs?), Box::new(rhs?));
       let operator = match op.as_rule() {
+        Rule::conditional => {
+          let true_expr =
+            parse_arithmetic_expr(op.into_inner().next().unwrap())?;
+          return Ok(ArithmeticPart::TripleConditionalExpr {
+            condition: left,
+            true_expr: Box::new(true_expr),
+            false_expr: right,
+          });
+        }
         Rule::add => BinaryArithmeticOp::Add,
         Rule::subtract => BinaryArithmeticOp::Subtract,
         Rule::multiply => BinaryArithmeticOp::Multiply,
@@ -1475,81 +2162,86 @@ fn parse_arithmetic_expr(pair: Pair<Rule>) -> Result<ArithmeticPart> {
         Rule::bitwise_or => BinaryArithmeticOp::BitwiseOr,
         Rule::logical_and => BinaryArithmeticOp::LogicalAnd,
         Rule::logical_or => BinaryArithmeticOp::LogicalOr,
-        _ => {
-          return Err(miette!("Unexpected infix operator: {:?}", op.as_rule()))
+        rule => {
+          let operator = match rule {
+            Rule::equal => BinaryOp::Equal,
+            Rule::not_equal => BinaryOp::NotEqual,
+            Rule::less => BinaryOp::LessThan,
+            Rule::less_equal => BinaryOp::LessThanOrEqual,
+            Rule::greater => BinaryOp::GreaterThan,
+            Rule::greater_equal => BinaryOp::GreaterThanOrEqual,
+            _ => return Err(miette!("Unexpected infix operator: {:?}", rule)),
+          };
+          return Ok(ArithmeticPart::BinaryConditionalExpr {
+            left,
+            operator,
+            right,
+          });
         }
       };
       Ok(ArithmeticPart::BinaryArithmeticExpr {
-        left: Box::new(lhs?),
+        left,
         operator,
-        right: Box::new(rhs?),
+        right,
       })
     })
     .parse(pair.into_inner())
 }
 
-fn parse_unary_arithmetic_expr(pair: Pair<Rule>) -> Result<ArithmeticPart> {
-  let mut inner = pair.into_inner();
-  let first = inner.next().unwrap();
-
-  match first.as_rule() {
-    Rule::unary_arithmetic_op => {
-      let op = parse_unary_arithmetic_op(first)?;
-      let operand = parse_arithmetic_expr(inner.next().unwrap())?;
-      Ok(ArithmeticPart::UnaryArithmeticExpr {
-        operator: op,
-        operand: Box::new(operand),
-      })
-    }
-    Rule::post_arithmetic_op => {
-      let operand = parse_arithmetic_expr(inner.next().unwrap())?;
-      let op = parse_post_arithmetic_op(first)?;
-      Ok(ArithmeticPart::PostArithmeticExpr {
-        operand: Box::new(operand),
-        operator: op,
-      })
-    }
-    _ => {
-      let operand = parse_arithmetic_expr(first)?;
-      let op = parse_post_arithmetic_op(inner.next().unwrap())?;
-      Ok(ArithmeticPart::PostArithmeticExpr {
-        operand: Box::new(operand),
-        operator: op,
-      })
+fn parse_increment_expr(pair: Pair<Rule>) -> Result<ArithmeticPart> {
+  let inner = pair.into_inner().next().unwrap();
+  let rule = inner.as_rule();
+  let mut name = None;
+  let mut operator = None;
+  for pair in inner.into_inner() {
+    match pair.as_rule() {
+      Rule::VARIABLE => name = Some(pair.as_str().to_string()),
+      Rule::increment => operator = Some(IncrementOp::Increment),
+      Rule::decrement => operator = Some(IncrementOp::Decrement),
+      _ => {}
     }
   }
+  let (Some(name), Some(operator)) = (name, operator) else {
+    return Err(miette!("Expected a variable and `++` or `--`"));
+  };
+  Ok(match rule {
+    Rule::pre_increment => ArithmeticPart::PreArithmeticExpr { name, operator },
+    _ => ArithmeticPart::PostArithmeticExpr { name, operator },
+  })
 }
 
-fn parse_unary_arithmetic_op(pair: Pair<Rule>) -> Result<UnaryArithmeticOp> {
-  match pair.as_str() {
-    "+" => Ok(UnaryArithmeticOp::Plus),
-    "-" => Ok(UnaryArithmeticOp::Minus),
-    "!" => Ok(UnaryArithmeticOp::LogicalNot),
-    "~" => Ok(UnaryArithmeticOp::BitwiseNot),
-    _ => Err(miette!(
-      "Invalid unary arithmetic operator: {}",
-      pair.as_str()
-    )),
-  }
-}
-
-fn parse_post_arithmetic_op(pair: Pair<Rule>) -> Result<PostArithmeticOp> {
-  match pair.as_str() {
-    "++" => Ok(PostArithmeticOp::Increment),
-    "--" => Ok(PostArithmeticOp::Decrement),
-    _ => Err(miette!(
-      "Invalid post arithmetic operator: {}",
-      pair.as_str()
-    )),
-  }
+/// `$name`, `${name}` or `$?` in an arithmetic expression is the same as
+/// `name`.
+fn parse_arithmetic_parameter(pair: Pair<Rule>) -> ArithmeticPart {
+  let name = pair.into_inner().next().unwrap().as_str();
+  ArithmeticPart::Variable(name.to_string())
 }
 
 fn parse_variable_expansion(part: Pair<Rule>) -> Result<WordPart> {
-  let mut inner = part.into_inner();
+  let mut inner = part.into_inner().peekable();
   let variable = inner
     .next()
     .ok_or_else(|| miette!("Expected variable name"))?;
+  if variable.as_rule() == Rule::VAR_LENGTH {
+    let name = inner
+      .next()
+      .ok_or_else(|| miette!("Expected variable name"))?
+      .as_str()
+      .to_string();
+    let subscript = inner.next().map(parse_array_subscript).transpose()?;
+    return Ok(WordPart::Length(name, subscript));
+  }
   let variable_name = variable.as_str().to_string();
+  let subscript = match inner.peek() {
+    Some(pair) if pair.as_rule() == Rule::ARRAY_SUBSCRIPT => {
+      Some(parse_array_subscript(inner.next().unwrap())?)
+    }
+    Some(pair) if pair.as_rule() == Rule::ARRAY_KEYS => {
+      let subscript = parse_array_subscript(inner.next().unwrap())?;
+      return Ok(WordPart::ArrayKeys(variable_name, subscript));
+    }
+    _ => None,
+  };
 
   let modifier = inner.next();
   let parsed_modifier = if let Some(modifier) = modifier {
@@ -1569,25 +2261,69 @@ fn parse_variable_expansion(part: Pair<Rule>) -> Result<WordPart> {
         };
         Some(Box::new(VariableModifier::Substring { begin, length }))
       }
-      Rule::VAR_DEFAULT_VALUE => {
-        let value = if let Some(val) = modifier.into_inner().next() {
-          parse_word(val)?
-        } else {
-          Word::new_empty()
+      Rule::VAR_DEFAULT_VALUE
+      | Rule::VAR_ASSIGN_DEFAULT
+      | Rule::VAR_ALTERNATE_VALUE
+      | Rule::VAR_ERROR_IF_UNSET => {
+        let rule = modifier.as_rule();
+        let mut inner = modifier.into_inner().peekable();
+        let colon = inner
+          .next_if(|pair| pair.as_rule() == Rule::VAR_COLON)
+          .is_some();
+        let value = match inner.next() {
+          Some(value) => parse_word(value)?,
+          None => Word::new_empty(),
         };
-        Some(Box::new(VariableModifier::DefaultValue(value)))
+        Some(Box::new(match rule {
+          Rule::VAR_DEFAULT_VALUE => {
+            VariableModifier::DefaultValue { value, colon }
+          }
+          Rule::VAR_ASSIGN_DEFAULT => {
+            VariableModifier::AssignDefault { value, colon }
+          }
+          Rule::VAR_ALTERNATE_VALUE => {
+            VariableModifier::AlternateValue { value, colon }
+          }
+          _ => VariableModifier::ErrorIfUnset {
+            message: value,
+            colon,
+          },
+        }))
       }
-      Rule::VAR_ASSIGN_DEFAULT => {
-        let value = modifier.into_inner().next().unwrap();
-        Some(Box::new(VariableModifier::AssignDefault(parse_word(
-          value,
-        )?)))
+      Rule::VAR_REMOVE_PREFIX | Rule::VAR_REMOVE_SUFFIX => {
+        let rule = modifier.as_rule();
+        let mut inner = modifier.into_inner().peekable();
+        let longest = inner
+          .next_if(|pair| pair.as_rule() == Rule::VAR_LONGEST)
+          .is_some();
+        let pattern = match inner.next() {
+          Some(pattern) => parse_word(pattern)?,
+          None => Word::new_empty(),
+        };
+        Some(Box::new(if rule == Rule::VAR_REMOVE_PREFIX {
+          VariableModifier::RemovePrefix { pattern, longest }
+        } else {
+          VariableModifier::RemoveSuffix { pattern, longest }
+        }))
       }
-      Rule::VAR_ALTERNATE_VALUE => {
-        let value = modifier.into_inner().next().unwrap();
-        Some(Box::new(VariableModifier::AlternateValue(parse_word(
-          value,
-        )?)))
+      Rule::VAR_REPLACE => {
+        let mut mode = ReplaceMode::First;
+        let mut pattern = Word::new_empty();
+        let mut replacement = Word::new_empty();
+        for pair in modifier.into_inner() {
+          match pair.as_rule() {
+            Rule::VAR_REPLACE_ALL => mode = ReplaceMode::All,
+            Rule::VAR_REPLACE_PREFIX => mode = ReplaceMode::Prefix,
+            Rule::VAR_REPLACE_SUFFIX => mode = ReplaceMode::Suffix,
+            Rule::PARAMETER_PATTERN_WORD => pattern = parse_word(pair)?,
+            _ => replacement = parse_word(pair)?,
+          }
+        }
+        Some(Box::new(VariableModifier::Replace {
+          pattern,
+          replacement,
+          mode,
+        }))
       }
       _ => {
         return Err(miette!(
@@ -1599,7 +2335,12 @@ fn parse_variable_expansion(part: Pair<Rule>) -> Result<WordPart> {
   } else {
     None
   };
-  Ok(WordPart::Variable(variable_name, parsed_modifier))
+  Ok(match subscript {
+    Some(subscript) => {
+      WordPart::Array(variable_name, subscript, parsed_modifier)
+    }
+    None => WordPart::Variable(variable_name, parsed_modifier),
+  })
 }
 
 fn parse_tilde_prefix(pair: Pair<Rule>) -> Result<WordPart> {
@@ -1613,6 +2354,31 @@ fn parse_tilde_prefix(pair: Pair<Rule>) -> Result<WordPart> {
   Ok(WordPart::Tilde(tilde_prefix))
 }
 
+/// Parses `` `list` ``. Within it a backslash only escapes `\\`, `` ` `` and
+/// `$`, and also `"` when the backticks are in double quotes, the other
+/// backslashes are kept for the list.
+fn parse_backtick_command(
+  pair: Pair<Rule>,
+  is_quoted: bool,
+) -> Result<WordPart> {
+  let body = pair.into_inner().next().unwrap().as_str();
+  let mut text = String::with_capacity(body.len());
+  let mut chars = body.chars().peekable();
+  while let Some(c) = chars.next() {
+    if c == '\\' {
+      if let Some(&next) = chars.peek() {
+        if matches!(next, '\\' | '`' | '$') || (is_quoted && next == '"') {
+          text.push(next);
+          chars.next();
+          continue;
+        }
+      }
+    }
+    text.push(c);
+  }
+  Ok(WordPart::Command(parse(&text)?))
+}
+
 fn parse_quoted_word(pair: Pair<Rule>) -> Result<WordPart> {
   let mut parts = Vec::new();
   let inner = pair.into_inner().next().unwrap();
@@ -1622,12 +2388,18 @@ fn parse_quoted_word(pair: Pair<Rule>) -> Result<WordPart> {
       let inner = inner.into_inner().next().unwrap();
       for part in inner.into_inner() {
         match part.as_rule() {
-          Rule::EXIT_STATUS => parts.push(WordPart::Text("$?".to_string())),
+          Rule::EXIT_STATUS => parts.push(WordPart::ExitStatus),
           Rule::QUOTED_ESCAPE_CHAR => {
+            // in double quotes a backslash only escapes `$`, `` ` `` and `"`
+            let text = part.as_str();
+            let text = match text.strip_prefix('\\') {
+              Some(escaped @ ("$" | "`" | "\"")) => escaped,
+              _ => text,
+            };
             if let Some(WordPart::Text(ref mut s)) = parts.last_mut() {
-              s.push_str(part.as_str());
+              s.push_str(text);
             } else {
-              parts.push(WordPart::Text(part.as_str().to_string()));
+              parts.push(WordPart::Text(text.to_string()));
             }
           }
           Rule::SUB_COMMAND => {
@@ -1635,10 +2407,17 @@ fn parse_quoted_word(pair: Pair<Rule>) -> Result<WordPart> {
               parse_complete_command(part.into_inner().next().unwrap())?;
             parts.push(WordPart::Command(command));
           }
+          Rule::BACKTICK_COMMAND => {
+            parts.push(parse_backtick_command(part, true)?);
+          }
           Rule::VARIABLE_EXPANSION => {
             let variable_expansion = parse_variable_expansion(part)?;
             parts.push(variable_expansion);
           }
+          Rule::ARITHMETIC_EXPRESSION => {
+            let arithmetic_expression = parse_arithmetic_expression(part)?;
+            parts.push(WordPart::Arithmetic(arithmetic_expression));
+          }
           Rule::QUOTED_CHAR => {
             if let Some(WordPart::Text(ref mut s)) = parts.last_mut() {
               s.push_str(part.as_str());
@@ -1680,8 +2459,14 @@ fn parse_env_var(pair: Pair<Rule>) -> Result<EnvVar> {
     .as_str()
     .to_string();
 
+  let mut value = parts.next();
+  let append = matches!(&value, Some(p) if p.as_rule() == Rule::APPEND);
+  if append {
+    value = parts.next();
+  }
+
   // Get the value of the environment variable
-  let word_value = if let Some(value) = parts.next() {
+  let word_value = if let Some(value) = value {
     parse_assignment_value(value).context("Failed to parse assignment value")?
   } else {
     Word::new_empty()
@@ -1690,6 +2475,7 @@ fn parse_env_var(pair: Pair<Rule>) -> Result<EnvVar> {
   Ok(EnvVar {
     name,
     value: word_value,
+    append,
   })
 }
 
@@ -1703,10 +2489,15 @@ fn parse_assignment_value(pair: Pair<Rule>) -> Result<Word> {
           parse_tilde_prefix(part).context("Failed to parse tilde prefix")?;
         parts.push(tilde_prefix);
       }
-      Rule::UNQUOTED_PENDING_WORD => {
-        let word_parts = parse_word(part)?;
-        parts.extend(word_parts.into_parts());
+      Rule::ASSIGNMENT_PENDING_WORD => {
+        for part in parse_word(part)?.into_parts() {
+          match part {
+            WordPart::Text(text) => push_text(&mut parts, &text),
+            part => parts.push(part),
+          }
+        }
       }
+      Rule::ASSIGNMENT_COLON => push_text(&mut parts, ":"),
       _ => {
         return Err(miette!(
           "Unexpected rule in assignment value: {:?}",
@@ -1719,7 +2510,31 @@ fn parse_assignment_value(pair: Pair<Rule>) -> Result<Word> {
   Ok(Word::new(parts))
 }
 
+/// Appends `text` to the text at the end of `parts`, if there is any.
+fn push_text(parts: &mut Vec<WordPart>, text: &str) {
+  match parts.last_mut() {
+    Some(WordPart::Text(last)) => last.push_str(text),
+    _ => parts.push(WordPart::Text(text.to_string())),
+  }
+}
+
+fn parse_process_substitution(pair: Pair<Rule>) -> Result<WordPart> {
+  let text = pair.as_str().to_string();
+  let mut inner = pair.into_inner();
+  let kind = match inner.next().unwrap().as_rule() {
+    Rule::PROCESS_INPUT => ProcessSubstitutionKind::Read,
+    _ => ProcessSubstitutionKind::Write,
+  };
+  let list = parse_complete_command(inner.next().unwrap())?;
+  Ok(WordPart::ProcessSubstitution(ProcessSubstitution {
+    kind,
+    list,
+    text,
+  }))
+}
+
 fn parse_io_redirect(pair: Pair<Rule>) -> Result<Redirect> {
+  let span = pair.as_span();
   let mut inner = pair.into_inner();
 
   // Parse the optional IO number or AMPERSAND
@@ -1746,6 +2561,8 @@ fn parse_io_redirect(pair: Pair<Rule>) -> Result<Redirect> {
     maybe_fd,
     op,
     io_file,
+    span: (span.start(), span.end() - span.start()).into(),
+    text: span.as_str().to_string(),
   })
 }
 
@@ -1760,6 +2577,7 @@ fn parse_io_file(pair: Pair<Rule>) -> Result<(RedirectOp, IoFile)> {
 
   let redirect_op = match op.as_rule() {
     Rule::LESS => RedirectOp::Input(RedirectOpInput::Redirect),
+    Rule::TLESS => RedirectOp::Input(RedirectOpInput::HereString),
     Rule::GREAT => RedirectOp::Output(RedirectOpOutput::Overwrite),
     Rule::DGREAT => RedirectOp::Output(RedirectOpOutput::Append),
     Rule::LESSAND | Rule::GREATAND => {
@@ -1812,7 +2630,8 @@ mod test {
   #[test]
   fn test_main() {
     assert!(parse("&& testing").is_err());
-    assert!(parse("test { test").is_err());
+    // like in bash, a brace that doesn't start a group is a word
+    assert!(parse("test { test").is_ok());
     assert!(parse("cp test/* other").is_ok());
     assert!(parse("cp test/? other").is_ok());
     assert!(parse("(test").is_err());
@@ -1828,7 +2647,397 @@ mod test {
     );
 
     assert!(parse("echo \"foo\" > out.txt").is_ok());
+
+    assert!(parse("arr=(a \"b c\" d) && arr+=(e) && arr[2]=x").is_ok());
+    assert!(parse("echo ${arr[0]} ${arr[-1]} \"${arr[@]}\" ${#arr[*]}").is_ok());
+    assert!(parse("echo ${arr[i+1]} ${arr[@]:1:2}").is_ok());
+    assert!(parse("echo ${arr[0}").is_err());
+    assert!(parse("map=([a]=1 [\"b c\"]=2 [$k]=) && map[key]=value").is_ok());
+    assert!(parse("echo ${map[key]} \"${!map[@]}\" ${!map[*]}").is_ok());
+    assert!(
+      parse("declare -A map=([a]=1 [b]=2) && typeset -a l+=(1 2)").is_ok()
+    );
+    assert!(parse("echo list=(1 2)").is_err());
+
+    assert!(parse("for i in a b; do echo $i; done").is_ok());
+    assert!(parse("for i\ndo\n  echo $i\ndone").is_ok());
+    assert!(
+      parse("while true; do break; done && until false; do :; done").is_ok()
+    );
+    assert!(
+      parse("if grep -q a b; then echo c; elif [ -f d ]; then e; fi").is_ok()
+    );
+    assert!(
+      parse("case $x in a|b) echo ab;; (c) ;; *) echo other; esac").is_ok()
+    );
+    assert!(parse("for i in a; do echo $i").is_err());
+    assert!(parse("case a in a) echo a;;").is_err());
+    // reserved words are only recognized as whole words
+    assert!(parse("install -m 644 a b && done.sh && fortune").is_ok());
+    // braces and commas are allowed in words, for brace expansion
+    assert!(parse("echo a{b,c} {1..3} a,b {} } \\{ && { echo; }").is_ok());
+  }
+
+  #[test]
+  fn test_loops() {
+    let list = parse("for i in a \"b c\"; do echo $i; done").unwrap();
+    let body = parse("echo $i").unwrap();
+    assert_eq!(
+      list.items[0].sequence,
+      Sequence::Pipeline(Pipeline {
+        negated: false,
+        inner: Command {
+          inner: CommandInner::For(ForLoop {
+            var_name: "i".to_string(),
+            wordlist: Some(vec![Word::new_word("a"), Word::new_string("b c")]),
+            body: body.clone(),
+          }),
+          redirects: Vec::new(),
+        }
+        .into(),
+      })
+    );
+
+    let list = parse("select i\ndo echo $i; done").unwrap();
+    // the body starts on the second line
+    let select_body = parse("\necho $i").unwrap();
+    assert_eq!(
+      list.items[0].sequence,
+      Sequence::Pipeline(Pipeline {
+        negated: false,
+        inner: Command {
+          inner: CommandInner::Select(ForLoop {
+            var_name: "i".to_string(),
+            wordlist: None,
+            body: select_body,
+          }),
+          redirects: Vec::new(),
+        }
+        .into(),
+      })
+    );
+
+    let list = parse("until false; do echo $i; done").unwrap();
+    let Sequence::Pipeline(Pipeline {
+      inner:
+        PipelineInner::Command(Command {
+          inner: CommandInner::While(while_loop),
+          ..
+        }),
+      ..
+    }) = &list.items[0].sequence
+    else {
+      panic!("expected a loop: {list:?}");
+    };
+    assert!(while_loop.until);
+    assert_eq!(while_loop.body, body);
+    assert_eq!(
+      while_loop.condition.condition_inner,
+      ConditionInner::Command(parse("false").unwrap())
+    );
+
+    let list = parse("case $x in a | b) echo $i;; esac").unwrap();
+    let Sequence::Pipeline(Pipeline {
+      inner:
+        PipelineInner::Command(Command {
+          inner: CommandInner::Case(case_clause),
+          ..
+        }),
+      ..
+    }) = &list.items[0].sequence
+    else {
+      panic!("expected a case clause: {list:?}");
+    };
+    assert_eq!(
+      case_clause.items,
+      vec![CaseItem {
+        patterns: vec![Word::new_word("a"), Word::new_word("b")],
+        body: body.clone(),
+        terminator: CaseTerminator::Break,
+      }]
+    );
+
+    let list =
+      parse("case $x in a) echo $i;& b) echo $i;;& *) ;; esac").unwrap();
+    let Sequence::Pipeline(Pipeline {
+      inner:
+        PipelineInner::Command(Command {
+          inner: CommandInner::Case(case_clause),
+          ..
+        }),
+      ..
+    }) = &list.items[0].sequence
+    else {
+      panic!("expected a case clause: {list:?}");
+    };
+    let terminators = case_clause
+      .items
+      .iter()
+      .map(|item| item.terminator)
+      .collect::<Vec<_>>();
+    assert_eq!(
+      terminators,
+      [
+        CaseTerminator::FallThrough,
+        CaseTerminator::Continue,
+        CaseTerminator::Break
+      ]
+    );
+    assert_eq!(case_clause.items[1].body, body);
+  }
+  #[test]
+  fn test_functions() {
+    let list = parse("greet() {\n  echo $1\n}").unwrap();
+    assert_eq!(
+      list.items[0].sequence,
+      Sequence::Pipeline(Pipeline {
+        negated: false,
+        inner: Command {
+          inner: CommandInner::FunctionDefinition(FunctionDefinition {
+            name: "greet".to_string(),
+            body: Box::new(Command {
+              inner: CommandInner::BraceGroup(Box::new(
+                parse("\necho $1").unwrap()
+              )),
+              redirects: Vec::new(),
+            }),
+            text: "greet() {\n  echo $1\n}".to_string(),
+          }),
+          redirects: Vec::new(),
+        }
+        .into(),
+      })
+    );
+
+    assert!(parse("f () ( echo sub ); f").is_ok());
+    assert!(parse("{ echo a; echo b; } && echo c").is_ok());
+    assert!(parse("f() echo a").is_err());
+  }
+
+  #[test]
+  fn test_process_substitution() {
+    let list = parse("diff <(sort a) file>(tee b )").unwrap();
+    let Sequence::Pipeline(Pipeline {
+      inner:
+        PipelineInner::Command(Command {
+          inner: CommandInner::Simple(command),
+          ..
+        }),
+      ..
+    }) = &list.items[0].sequence
+    else {
+      panic!("expected a simple command: {list:?}");
+    };
+    assert_eq!(
+      command.args[1],
+      Word(vec![WordPart::ProcessSubstitution(ProcessSubstitution {
+        kind: ProcessSubstitutionKind::Read,
+        list: parse("sort a").unwrap(),
+        text: "<(sort a)".to_string(),
+      })])
+    );
+    assert_eq!(
+      command.args[2],
+      Word(vec![
+        WordPart::Text("file".to_string()),
+        WordPart::ProcessSubstitution(ProcessSubstitution {
+          kind: ProcessSubstitutionKind::Write,
+          list: parse("tee b ").unwrap(),
+          text: ">(tee b )".to_string(),
+        }),
+      ])
+    );
+
+    assert!(parse("cat < <(echo a) && echo >(cat) > >(cat)").is_ok());
+    assert!(parse("cat <(echo a").is_err());
+  }
+
+  #[test]
+  fn test_redirects() {
+    let list = parse("head -n 1 <<< \"$x\" 2>&1 > out").unwrap();
+    let Sequence::Pipeline(Pipeline {
+      inner: PipelineInner::Command(command),
+      ..
+    }) = &list.items[0].sequence
+    else {
+      panic!("expected a command: {list:?}");
+    };
+    let CommandInner::Simple(simple) = &command.inner else {
+      panic!("expected a simple command: {command:?}");
+    };
+    assert_eq!(simple.args.len(), 3);
+    let redirects = command
+      .redirects
+      .iter()
+      .map(|redirect| (redirect.maybe_fd.clone(), redirect.op.clone()))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      redirects,
+      [
+        (None, RedirectOp::Input(RedirectOpInput::HereString)),
+        (
+          Some(RedirectFd::Fd(2)),
+          RedirectOp::Output(RedirectOpOutput::Overwrite)
+        ),
+        (None, RedirectOp::Output(RedirectOpOutput::Overwrite)),
+      ]
+    );
+    assert_eq!(
+      command.redirects[0].io_file,
+      IoFile::Word(Word(vec![WordPart::Quoted(vec![WordPart::Variable(
+        "x".to_string(),
+        None
+      )])]))
+    );
+    assert_eq!(command.redirects[1].io_file, IoFile::Fd(1));
+
+    assert!(parse("cat <<<").is_err());
+    assert!(parse("echo > $dir/${name}.txt < \"$(pwd)\"").is_ok());
+  }
+
+  #[test]
+  fn test_arithmetic() {
+    fn number(text: &str) -> Box<ArithmeticPart> {
+      Box::new(ArithmeticPart::Number(text.to_string()))
+    }
+    let binary = |left, operator, right| {
+      Box::new(ArithmeticPart::BinaryArithmeticExpr {
+        left,
+        operator,
+        right,
+      })
+    };
+
+    let parts = parse_arithmetic("1 + 2 * 3 - 4").unwrap().parts;
+    assert_eq!(
+      parts,
+      [*binary(
+        binary(
+          number("1"),
+          BinaryArithmeticOp::Add,
+          binary(number("2"), BinaryArithmeticOp::Multiply, number("3"))
+        ),
+        BinaryArithmeticOp::Subtract,
+        number("4")
+      )]
+    );
+    // the unary minus binds tighter than `**`, which is right associative
+    let parts = parse_arithmetic("-2 ** 3 ** 0x2").unwrap().parts;
+    assert_eq!(
+      parts,
+      [*binary(
+        Box::new(ArithmeticPart::UnaryArithmeticExpr {
+          operator: UnaryArithmeticOp::Minus,
+          operand: number("2"),
+        }),
+        BinaryArithmeticOp::Power,
+        binary(number("3"), BinaryArithmeticOp::Power, number("0x2"))
+      )]
+    );
+    let parts = parse_arithmetic("x == 1 ? y++ : --y").unwrap().parts;
+    assert_eq!(
+      parts,
+      [ArithmeticPart::TripleConditionalExpr {
+        condition: Box::new(ArithmeticPart::BinaryConditionalExpr {
+          left: Box::new(ArithmeticPart::Variable("x".to_string())),
+          operator: BinaryOp::Equal,
+          right: number("1"),
+        }),
+        true_expr: Box::new(ArithmeticPart::PostArithmeticExpr {
+          name: "y".to_string(),
+          operator: IncrementOp::Increment,
+        }),
+        false_expr: Box::new(ArithmeticPart::PreArithmeticExpr {
+          name: "y".to_string(),
+          operator: IncrementOp::Decrement,
+        }),
+      }]
+    );
+    assert!(matches!(
+      parse_arithmetic("x <<= a < b || c").unwrap().parts[..],
+      [ArithmeticPart::VariableAssignment {
+        op: AssignmentOp::LeftShiftAssign,
+        ..
+      }]
+    ));
+
+    assert!(parse("echo $((a ? b : c)) $((16#ff)) && ((i++))").is_ok());
+    assert!(parse("echo $((1 +))").is_err());
+    assert!(parse("echo $((1 ? 2))").is_err());
+  }
+
+  #[test]
+  fn test_parse_error() {
+    let error = parse("echo a\necho 'b").unwrap_err();
+    let error = error.downcast_ref::<ParseError>().unwrap();
+    assert_eq!(error.line_col(), (2, 7));
+    assert_eq!(error.expected, "expected SINGLE_QUOTE_END");
+
+    // the column counts characters, an error at the end of the input
+    // points at its last one
+    let error = parse("echo ä 'b").unwrap_err();
+    let error = error.downcast_ref::<ParseError>().unwrap();
+    assert_eq!(error.line_col(), (1, 9));
+  }
+
+  #[test]
+  fn test_split_commands() {
+    assert_eq!(
+      split_commands(
+        "echo a; cd /tmp && ls | wc -l\nsleep 1 &\n\nfor i in 1 2; do\n  echo $i\ndone\n"
+      )
+      .unwrap(),
+      vec![
+        "echo a",
+        "cd /tmp && ls | wc -l",
+        "sleep 1 &",
+        "for i in 1 2; do\n  echo $i\ndone",
+      ]
+    );
+    assert!(split_commands("").unwrap().is_empty());
+    assert!(split_commands("echo 'a").is_err());
+  }
+
+  #[test]
+  fn test_is_incomplete() {
+    for input in [
+      "echo 'foo",
+      "echo \"foo",
+      "echo foo \\",
+      "echo $(ls",
+      "echo `ls",
+      "echo \"`ls\"",
+      "echo foo |",
+      "true &&",
+      "if [ 1 = 1 ]; then",
+      "if [ 1 = 1 ]; then\n  echo foo\n",
+      "for i in a b; do",
+      "select i in a b; do",
+      "while true; do echo",
+      "case $a in",
+      "f() {",
+      "f() {\n  echo foo\n",
+    ] {
+      assert!(is_incomplete(input), "{input:?}");
+    }
+    for input in [
+      "",
+      "echo foo",
+      "echo 'foo\nbar'",
+      "echo foo \\\n  bar",
+      "if [ 1 = 1 ]; then\n  echo foo\nfi",
+      "echo foo \\\\",
+      "for i in a b; do\n  echo $i\ndone",
+      "case $a in\n  a) echo a;;\nesac",
+      "f() {\n  echo foo\n}",
+      "echo )",
+      "echo `ls`",
+      "&& foo",
+    ] {
+      assert!(!is_incomplete(input), "{input:?}");
+    }
   }
+
   #[test]
   fn test_sequential_list() {
     let parse_and_create = |input: &str| -> Result<SequentialList> {
@@ -1851,6 +3060,10 @@ mod test {
       items: vec![
         SequentialListItem {
           is_async: false,
+          line: 1,
+          text:
+            "Name=Value OtherVar=Other command arg1 || command2 arg12 arg13"
+              .to_string(),
           sequence: Sequence::BooleanList(Box::new(BooleanList {
             current: SimpleCommand {
               env_vars: vec![
@@ -1874,6 +3087,8 @@ mod test {
         },
         SequentialListItem {
           is_async: true,
+          line: 1,
+          text: "command3 && command4".to_string(),
           sequence: Sequence::BooleanList(Box::new(BooleanList {
             current: SimpleCommand {
               env_vars: vec![],
@@ -1890,6 +3105,8 @@ mod test {
         },
         SequentialListItem {
           is_async: false,
+          line: 1,
+          text: "command5".to_string(),
           sequence: SimpleCommand {
             env_vars: vec![],
             args: vec![Word::new_word("command5")],
@@ -1898,6 +3115,8 @@ mod test {
         },
         SequentialListItem {
           is_async: false,
+          line: 1,
+          text: "export ENV6=5".to_string(),
           sequence: SimpleCommand {
             env_vars: vec![],
             args: vec![Word::new_word("export"), Word::new_word("ENV6=5")],
@@ -1906,6 +3125,8 @@ mod test {
         },
         SequentialListItem {
           is_async: false,
+          line: 1,
+          text: "ENV7=other && command8 || command9".to_string(),
           sequence: Sequence::BooleanList(Box::new(BooleanList {
             current: Sequence::ShellVar(EnvVar::new(
               "ENV7".to_string(),
@@ -1929,6 +3150,8 @@ mod test {
         },
         SequentialListItem {
           is_async: false,
+          line: 1,
+          text: "cmd10 && (cmd11 || cmd12)".to_string(),
           sequence: Sequence::BooleanList(Box::new(BooleanList {
             current: SimpleCommand {
               env_vars: vec![],
@@ -1940,6 +3163,8 @@ mod test {
               inner: CommandInner::Subshell(Box::new(SequentialList {
                 items: vec![SequentialListItem {
                   is_async: false,
+                  line: 1,
+                  text: "cmd11 || cmd12".to_string(),
                   sequence: Sequence::BooleanList(Box::new(BooleanList {
                     current: SimpleCommand {
                       env_vars: vec![],
@@ -1955,7 +3180,7 @@ mod test {
                   })),
                 }],
               })),
-              redirect: None,
+              redirects: Vec::new(),
             }
             .into(),
           })),
@@ -1971,6 +3196,8 @@ mod test {
       items: vec![
         SequentialListItem {
           is_async: false,
+          line: 1,
+          text: "command1".to_string(),
           sequence: SimpleCommand {
             env_vars: vec![],
             args: vec![Word::new_word("command1")],
@@ -1979,6 +3206,8 @@ mod test {
         },
         SequentialListItem {
           is_async: false,
+          line: 1,
+          text: "command2".to_string(),
           sequence: SimpleCommand {
             env_vars: vec![],
             args: vec![Word::new_word("command2")],
@@ -1987,6 +3216,8 @@ mod test {
         },
         SequentialListItem {
           is_async: false,
+          line: 1,
+          text: "A='b' command3".to_string(),
           sequence: SimpleCommand {
             env_vars: vec![EnvVar::new("A".to_string(), Word::new_string("b"))],
             args: vec![Word::new_word("command3")],
@@ -2007,6 +3238,8 @@ mod test {
     let expected = SequentialList {
       items: vec![SequentialListItem {
         is_async: true,
+        line: 1,
+        text: "command".to_string(),
         sequence: SimpleCommand {
           env_vars: vec![],
           args: vec![Word::new_word("command")],
@@ -2022,6 +3255,8 @@ mod test {
     let expected = SequentialList {
       items: vec![SequentialListItem {
         is_async: false,
+        line: 1,
+        text: "test | other".to_string(),
         sequence: PipeSequence {
           current: SimpleCommand {
             env_vars: vec![],
@@ -2046,6 +3281,8 @@ mod test {
     let expected = SequentialList {
       items: vec![SequentialListItem {
         is_async: false,
+        line: 1,
+        text: "test |& other".to_string(),
         sequence: PipeSequence {
           current: SimpleCommand {
             env_vars: vec![],
@@ -2070,6 +3307,8 @@ mod test {
     let expected = SequentialList {
       items: vec![SequentialListItem {
         is_async: false,
+        line: 1,
+        text: "echo $MY_ENV".to_string(),
         sequence: SimpleCommand {
           env_vars: vec![],
           args: vec![
@@ -2088,6 +3327,8 @@ mod test {
     let expected = SequentialList {
       items: vec![SequentialListItem {
         is_async: false,
+        line: 1,
+        text: "! cmd1 | cmd2 && cmd3".to_string(),
         sequence: Sequence::BooleanList(Box::new(BooleanList {
           current: Pipeline {
             negated: true,
@@ -2134,6 +3375,7 @@ mod test {
       EnvVar {
         name: "Name".to_string(),
         value: Word::new_word("Value"),
+        append: false,
       }
     );
 
@@ -2142,6 +3384,7 @@ mod test {
       EnvVar {
         name: "Name".to_string(),
         value: Word::new_string("quoted value"),
+        append: false,
       }
     );
 
@@ -2150,6 +3393,7 @@ mod test {
       EnvVar {
         name: "Name".to_string(),
         value: Word::new_string("double quoted value"),
+        append: false,
       }
     );
 
@@ -2158,6 +3402,7 @@ mod test {
       EnvVar {
         name: "Name".to_string(),
         value: Word(vec![]),
+        append: false,
       }
     );
 
@@ -2168,6 +3413,8 @@ mod test {
         value: Word(vec![WordPart::Command(SequentialList {
           items: vec![SequentialListItem {
             is_async: false,
+            line: 1,
+            text: "test".to_string(),
             sequence: SimpleCommand {
               env_vars: vec![],
               args: vec![Word::new_word("test")],
@@ -2175,6 +3422,7 @@ mod test {
             .into(),
           }],
         })]),
+        append: false,
       }
     );
 
@@ -2185,12 +3433,56 @@ mod test {
         value: Word(vec![WordPart::Command(SequentialList {
           items: vec![SequentialListItem {
             is_async: false,
+            line: 1,
+            text: "OTHER=5".to_string(),
             sequence: Sequence::ShellVar(EnvVar {
               name: "OTHER".to_string(),
               value: Word::new_word("5"),
+              append: false,
             }),
           }],
         })]),
+        append: false,
+      }
+    );
+
+    // a tilde expands at the start of the value and after each colon
+    let tilde = |user: Option<&str>| {
+      WordPart::Tilde(TildePrefix::new(user.map(|user| user.to_string())))
+    };
+    assert_eq!(
+      parse_and_create("PATH=~/bin:~user:a~:$PATH:").unwrap(),
+      EnvVar {
+        name: "PATH".to_string(),
+        value: Word(vec![
+          tilde(None),
+          WordPart::Text("/bin:".to_string()),
+          tilde(Some("user")),
+          WordPart::Text(":a~:".to_string()),
+          WordPart::Variable("PATH".to_string(), None),
+          WordPart::Text(":".to_string()),
+        ]),
+        append: false,
+      }
+    );
+    assert_eq!(
+      parse_and_create("Name=a:'~'").unwrap(),
+      EnvVar {
+        name: "Name".to_string(),
+        value: Word(vec![
+          WordPart::Text("a:".to_string()),
+          WordPart::Quoted(vec![WordPart::Text("~".to_string())]),
+        ]),
+        append: false,
+      }
+    );
+
+    assert_eq!(
+      parse_and_create("Name+=more").unwrap(),
+      EnvVar {
+        name: "Name".to_string(),
+        value: Word::new_word("more"),
+        append: true,
       }
     );
   }
@@ -2203,6 +3495,8 @@ mod test {
       serde_json::json!({
         "items": [{
           "isAsync": false,
+          "line": 1,
+          "text": "./example > output.txt",
           "sequence": {
             "inner": {
               "inner": {
@@ -2214,7 +3508,7 @@ mod test {
                 "kind": "simple"
               },
               "kind": "command",
-              "redirect": {
+              "redirects": [{
                 "ioFile": {
                   "kind": "word",
                   "value": [{
@@ -2227,7 +3521,7 @@ mod test {
                   "kind": "output",
                   "value": "overwrite",
                 }
-              }
+              }]
             },
             "kind": "pipeline",
             "negated": false
@@ -2240,6 +3534,8 @@ mod test {
       serde_json::json!({
         "items": [{
           "isAsync": false,
+          "line": 1,
+          "text": "./example 2> output.txt",
           "sequence": {
             "inner": {
               "inner": {
@@ -2251,7 +3547,7 @@ mod test {
                 "kind": "simple"
               },
               "kind": "command",
-              "redirect": {
+              "redirects": [{
                 "ioFile": {
                   "kind": "word",
                   "value": [{
@@ -2267,7 +3563,7 @@ mod test {
                   "kind": "output",
                   "value": "overwrite",
                 }
-              }
+              }]
             },
             "kind": "pipeline",
             "negated": false
@@ -2280,6 +3576,8 @@ mod test {
       serde_json::json!({
         "items": [{
           "isAsync": false,
+          "line": 1,
+          "text": "./example &> output.txt",
           "sequence": {
             "inner": {
               "inner": {
@@ -2291,7 +3589,7 @@ mod test {
                 "kind": "simple"
               },
               "kind": "command",
-              "redirect": {
+              "redirects": [{
                 "ioFile": {
                   "kind": "word",
                   "value": [{
@@ -2306,7 +3604,7 @@ mod test {
                   "kind": "output",
                   "value": "overwrite",
                 }
-              }
+              }]
             },
             "kind": "pipeline",
             "negated": false
@@ -2319,6 +3617,8 @@ mod test {
       serde_json::json!({
         "items": [{
           "isAsync": false,
+          "line": 1,
+          "text": "./example < output.txt",
           "sequence": {
             "inner": {
               "inner": {
@@ -2330,7 +3630,7 @@ mod test {
                 "kind": "simple"
               },
               "kind": "command",
-              "redirect": {
+              "redirects": [{
                 "ioFile": {
                   "kind": "word",
                   "value": [{
@@ -2343,7 +3643,7 @@ mod test {
                   "kind": "input",
                   "value": "redirect",
                 }
-              }
+              }]
             },
             "kind": "pipeline",
             "negated": false
@@ -2357,6 +3657,8 @@ mod test {
       serde_json::json!({
         "items": [{
           "isAsync": false,
+          "line": 1,
+          "text": "./example <&0",
           "sequence": {
             "inner": {
               "inner": {
@@ -2368,7 +3670,7 @@ mod test {
                 "kind": "simple"
               },
               "kind": "command",
-              "redirect": {
+              "redirects": [{
                 "ioFile": {
                   "kind": "fd",
                   "value": 0,
@@ -2378,7 +3680,7 @@ mod test {
                   "kind": "input",
                   "value": "redirect",
                 }
-              }
+              }]
             },
             "kind": "pipeline",
             "negated": false
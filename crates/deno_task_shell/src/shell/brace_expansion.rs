@@ -0,0 +1,268 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+//! Brace expansion, which turns one word into several before any other
+//! expansion: `a{b,c}d` into `abd acd`, `{1..5..2}` into `1 3 5`, `{01..03}`
+//! into `01 02 03` and `{a..c}` into `a b c`. Braces can be nested, the
+//! words come out in the same order as in bash.
+//!
+//! Only the braces in the unquoted text of a word count, so `"{a,b}"` and
+//! `\{a,b}` stay as they are, as does a brace without a comma or a range,
+//! such as `{}` or `{a}`. The other parts of the word, like variables, are
+//! copied into each of the words unexpanded.
+
+use crate::parser::Word;
+use crate::parser::WordPart;
+
+/// A character of the unquoted text of a word, or any other part of it.
+#[derive(Clone)]
+enum Piece {
+  Char(char),
+  Part(WordPart),
+}
+
+/// The words that `word` expands to, which is only `word` itself if it has
+/// no braces to expand.
+pub fn expand_braces(word: Word) -> Vec<Word> {
+  let has_braces = word
+    .parts()
+    .iter()
+    .any(|part| matches!(part, WordPart::Text(text) if text.contains('{')));
+  if !has_braces {
+    return vec![word];
+  }
+  let pieces = word
+    .into_parts()
+    .into_iter()
+    .flat_map(|part| match part {
+      WordPart::Text(text) => text.chars().map(Piece::Char).collect(),
+      part => vec![Piece::Part(part)],
+    })
+    .collect::<Vec<_>>();
+  expand(&pieces).into_iter().map(into_word).collect()
+}
+
+fn expand(pieces: &[Piece]) -> Vec<Vec<Piece>> {
+  let mut start = 0;
+  while let Some(open) = find_char(pieces, start, '{') {
+    // a brace that doesn't expand is just text, the next one may
+    let Some((close, alternatives)) = parse_brace(pieces, open) else {
+      start = open + 1;
+      continue;
+    };
+    let preamble = &pieces[..open];
+    let postscripts = expand(&pieces[close + 1..]);
+    let mut words = Vec::new();
+    for alternative in alternatives {
+      for alternative in expand(&alternative) {
+        for postscript in &postscripts {
+          let mut word = preamble.to_vec();
+          word.extend(alternative.iter().cloned());
+          word.extend(postscript.iter().cloned());
+          words.push(word);
+        }
+      }
+    }
+    return words;
+  }
+  vec![pieces.to_vec()]
+}
+
+fn find_char(pieces: &[Piece], start: usize, c: char) -> Option<usize> {
+  pieces[start..]
+    .iter()
+    .position(|piece| matches!(piece, Piece::Char(p) if *p == c))
+    .map(|index| start + index)
+}
+
+/// Parses the brace at `open`, returning the index of its closing brace and
+/// its alternatives, or `None` if it doesn't expand.
+fn parse_brace(
+  pieces: &[Piece],
+  open: usize,
+) -> Option<(usize, Vec<Vec<Piece>>)> {
+  let mut depth = 0;
+  let mut commas = Vec::new();
+  let mut close = None;
+  for (index, piece) in pieces.iter().enumerate().skip(open + 1) {
+    match piece {
+      Piece::Char('{') => depth += 1,
+      Piece::Char('}') if depth == 0 => {
+        close = Some(index);
+        break;
+      }
+      Piece::Char('}') => depth -= 1,
+      Piece::Char(',') if depth == 0 => commas.push(index),
+      _ => {}
+    }
+  }
+  let close = close?;
+  if !commas.is_empty() {
+    let mut alternatives = Vec::new();
+    let mut start = open + 1;
+    for end in commas.into_iter().chain([close]) {
+      alternatives.push(pieces[start..end].to_vec());
+      start = end + 1;
+    }
+    return Some((close, alternatives));
+  }
+  let text = pieces[open + 1..close]
+    .iter()
+    .map(|piece| match piece {
+      Piece::Char(c) => Some(*c),
+      Piece::Part(_) => None,
+    })
+    .collect::<Option<String>>()?;
+  let sequence = parse_sequence(&text)?;
+  Some((
+    close,
+    sequence
+      .into_iter()
+      .map(|item| item.chars().map(Piece::Char).collect())
+      .collect(),
+  ))
+}
+
+/// Parses a sequence such as `1..10`, `10..1..3`, `01..10` or `a..e`.
+fn parse_sequence(text: &str) -> Option<Vec<String>> {
+  let mut bounds = text.split("..");
+  let (first, last) = (bounds.next()?, bounds.next()?);
+  let step = match bounds.next() {
+    // like in bash, the direction comes from the bounds
+    Some(step) => step.parse::<i64>().ok()?.unsigned_abs().max(1),
+    None => 1,
+  };
+  if bounds.next().is_some() {
+    return None;
+  }
+  if let (Ok(start), Ok(end)) = (first.parse::<i64>(), last.parse::<i64>()) {
+    // a leading zero pads all the numbers to the same width
+    let padded = |bound: &str| {
+      let digits = bound.trim_start_matches(['-', '+']);
+      digits.len() > 1 && digits.starts_with('0')
+    };
+    let width = if padded(first) || padded(last) {
+      first.len().max(last.len())
+    } else {
+      0
+    };
+    return Some(
+      range(start, end, step)
+        .map(|number| format!("{number:0width$}"))
+        .collect(),
+    );
+  }
+  let letter = |bound: &str| {
+    let mut chars = bound.chars();
+    match (chars.next(), chars.next()) {
+      (Some(c), None) if c.is_ascii_alphabetic() => Some(c as i64),
+      _ => None,
+    }
+  };
+  let (start, end) = (letter(first)?, letter(last)?);
+  Some(
+    range(start, end, step)
+      .map(|code| char::from(code as u8).to_string())
+      .collect(),
+  )
+}
+
+fn range(start: i64, end: i64, step: u64) -> impl Iterator<Item = i64> {
+  let count = start.abs_diff(end) / step;
+  let step = if start <= end {
+    step as i64
+  } else {
+    -(step as i64)
+  };
+  (0..=count as i64).map(move |index| start + index * step)
+}
+
+fn into_word(pieces: Vec<Piece>) -> Word {
+  let mut parts = Vec::new();
+  for piece in pieces {
+    match piece {
+      Piece::Char(c) => match parts.last_mut() {
+        Some(WordPart::Text(text)) => text.push(c),
+        _ => parts.push(WordPart::Text(c.to_string())),
+      },
+      Piece::Part(part) => parts.push(part),
+    }
+  }
+  Word::new(parts)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn expand_text(text: &str) -> Vec<String> {
+    expand_braces(Word::new_word(text))
+      .into_iter()
+      .map(|word| match word.into_parts().as_slice() {
+        [WordPart::Text(text)] => text.clone(),
+        parts => panic!("unexpected parts: {parts:?}"),
+      })
+      .collect()
+  }
+
+  #[test]
+  fn expands_lists() {
+    assert_eq!(expand_text("a{b,c}d"), ["abd", "acd"]);
+    assert_eq!(expand_text("{a,b}{1,2}"), ["a1", "a2", "b1", "b2"]);
+    assert_eq!(expand_text("x{,y}"), ["x", "xy"]);
+    assert_eq!(expand_text("{a,b{1,2},c}"), ["a", "b1", "b2", "c"]);
+    assert_eq!(
+      expand_text("src/{commands,completion}.rs"),
+      ["src/commands.rs", "src/completion.rs"]
+    );
+  }
+
+  #[test]
+  fn expands_sequences() {
+    assert_eq!(expand_text("file{1..3}"), ["file1", "file2", "file3"]);
+    assert_eq!(expand_text("{1..10..4}"), ["1", "5", "9"]);
+    assert_eq!(expand_text("{5..1..-2}"), ["5", "3", "1"]);
+    assert_eq!(expand_text("{-1..1}"), ["-1", "0", "1"]);
+    assert_eq!(expand_text("{08..10}"), ["08", "09", "10"]);
+    assert_eq!(expand_text("{1..003}"), ["001", "002", "003"]);
+    assert_eq!(expand_text("{a..e..2}"), ["a", "c", "e"]);
+    assert_eq!(expand_text("{C..A}"), ["C", "B", "A"]);
+    assert_eq!(expand_text("{1..2}{a,b}"), ["1a", "1b", "2a", "2b"]);
+  }
+
+  #[test]
+  fn keeps_braces_that_dont_expand() {
+    for text in [
+      "{}",
+      "{a}",
+      "a{b",
+      "a}b",
+      "{1..a}",
+      "{1..2..3..4}",
+      "{ab..c}",
+    ] {
+      assert_eq!(expand_text(text), [text]);
+    }
+    assert_eq!(expand_text("{a}{b,c}"), ["{a}b", "{a}c"]);
+    assert_eq!(expand_text("{{a,b}"), ["{a", "{b"]);
+
+    // only the unquoted text counts
+    let word = Word::new(vec![
+      WordPart::Text("{a".to_string()),
+      WordPart::Quoted(vec![WordPart::Text(",".to_string())]),
+      WordPart::Text("b}".to_string()),
+    ]);
+    assert_eq!(expand_braces(word.clone()), [word]);
+    let word = Word::new(vec![
+      WordPart::Text("{".to_string()),
+      WordPart::Variable("x".to_string(), None),
+      WordPart::Text(",b}".to_string()),
+    ]);
+    assert_eq!(
+      expand_braces(word),
+      [
+        Word::new(vec![WordPart::Variable("x".to_string(), None)]),
+        Word::new_word("b"),
+      ]
+    );
+  }
+}
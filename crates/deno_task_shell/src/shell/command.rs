@@ -33,7 +33,20 @@ pub fn execute_unresolved_command_name(
       match resolve_command(&command_name, &mut context, &args).await {
         Ok(command_path) => command_path,
         Err(ResolveCommandError::CommandPath(err)) => {
-          let _ = context.stderr.write_line(&format!("{}", err));
+          let message = match context.state.script_location() {
+            Some(location) => format!("{}: {}", location, err),
+            None => format!("shell: {}", err),
+          };
+          let _ = context.stderr.write_line(&message);
+          if let ResolveCommandPathError::CommandNotFound(name) = &err {
+            let suggestions = suggest_commands(name, &context.state);
+            if !suggestions.is_empty() {
+              let _ = context.stderr.write_line(&format!(
+                "did you mean: {}?",
+                suggestions.join(", ")
+              ));
+            }
+          }
           return ExecuteResult::Continue(
             err.exit_code(),
             Vec::new(),
@@ -73,7 +86,7 @@ enum CommandName {
 
 struct ResolvedCommand<'a> {
   command_name: CommandName,
-  args: Cow<'a, Vec<String>>,
+  args: Cow<'a, [String]>,
 }
 
 #[derive(Error, Debug)]
@@ -111,7 +124,7 @@ impl FailedShebangError {
 async fn resolve_command<'a>(
   command_name: &UnresolvedCommandName,
   context: &mut ShellCommandContext,
-  original_args: &'a Vec<String>,
+  original_args: &'a [String],
 ) -> Result<ResolvedCommand<'a>, ResolveCommandError> {
   let command_path = match resolve_command_path(
     &command_name.name,
@@ -122,6 +135,19 @@ async fn resolve_command<'a>(
     Err(err) => return Err(err.into()),
   };
 
+  #[cfg(unix)]
+  if command_name.name.contains('/') {
+    use std::os::unix::fs::PermissionsExt;
+    let not_executable = std::fs::metadata(&command_path)
+      .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 == 0);
+    if not_executable {
+      return Err(
+        ResolveCommandPathError::PermissionDenied(command_name.name.clone())
+          .into(),
+      );
+    }
+  }
+
   // only bother checking for a shebang when the path has a slash
   // in it because for global commands someone on Windows likely
   // won't have a script with a shebang in it on Windows
@@ -138,10 +164,9 @@ async fn resolve_command<'a>(
         args.push(command_path.to_string_lossy().to_string());
         (args.remove(0), args)
       } else {
-        (
-          shebang.command,
-          vec![command_path.to_string_lossy().to_string()],
-        )
+        let mut args = shebang.arg.into_iter().collect::<Vec<_>>();
+        args.push(command_path.to_string_lossy().to_string());
+        (shebang.command, args)
       };
       args.extend(original_args.iter().cloned());
       return Ok(ResolvedCommand {
@@ -189,13 +214,21 @@ async fn parse_shebang_args(
       return err_unsupported(text)
     }
   };
-  if cmd.redirect.is_some() {
+  if !cmd.redirects.is_empty() {
     return err_unsupported(text);
   }
   let cmd = match cmd.inner {
     crate::parser::CommandInner::Simple(cmd) => cmd,
     crate::parser::CommandInner::Subshell(_) => return err_unsupported(text),
-    crate::parser::CommandInner::If(_) => return err_unsupported(text),
+    crate::parser::CommandInner::If(_)
+    | crate::parser::CommandInner::For(_)
+    | crate::parser::CommandInner::Select(_)
+    | crate::parser::CommandInner::While(_)
+    | crate::parser::CommandInner::Case(_)
+    | crate::parser::CommandInner::BraceGroup(_)
+    | crate::parser::CommandInner::FunctionDefinition(_) => {
+      return err_unsupported(text)
+    }
     crate::parser::CommandInner::ArithmeticExpression(_) => {
       return err_unsupported(text)
     }
@@ -218,8 +251,10 @@ async fn parse_shebang_args(
 /// Errors for executable commands.
 #[derive(Error, Debug, PartialEq)]
 pub enum ResolveCommandPathError {
-  #[error("{}: command not found", .0)]
+  #[error("command not found: {}", .0)]
   CommandNotFound(String),
+  #[error("permission denied: {}", .0)]
+  PermissionDenied(String),
   #[error("command name was empty")]
   CommandEmpty,
 }
@@ -229,6 +264,7 @@ impl ResolveCommandPathError {
     match self {
       // Use the Exit status that is used in bash: https://www.gnu.org/software/bash/manual/bash.html#Exit-Status
       ResolveCommandPathError::CommandNotFound(_) => 127,
+      ResolveCommandPathError::PermissionDenied(_) => 126,
       ResolveCommandPathError::CommandEmpty => 1,
     }
   }
@@ -293,8 +329,7 @@ fn resolve_command_path_inner(
     let uc_command_name = command_name.to_uppercase();
     let path_ext = state
       .get_var("PATHEXT")
-      .map(|s| s.as_str())
-      .unwrap_or(".EXE;.CMD;.BAT;.COM");
+      .unwrap_or_else(|| ".EXE;.CMD;.BAT;.COM".to_string());
     let command_exts = path_ext
       .split(';')
       .map(|s| s.trim().to_uppercase())
@@ -338,9 +373,77 @@ fn resolve_command_path_inner(
   ))
 }
 
+/// The commands with names close to `name`, closest first, for the
+/// "command not found" error.
+fn suggest_commands(name: &str, state: &ShellState) -> Vec<String> {
+  // allow about one typo per three characters
+  let max_distance = name.chars().count() / 3;
+  if max_distance == 0 {
+    return Vec::new();
+  }
+  let mut names = state
+    .custom_command_names()
+    .map(str::to_string)
+    .chain(state.alias_map().keys().cloned())
+    .chain(state.functions().keys().cloned())
+    .collect::<Vec<_>>();
+  if let Some(path) = state.get_var("PATH") {
+    for folder in path.split(if cfg!(windows) { ';' } else { ':' }) {
+      let Ok(entries) = std::fs::read_dir(folder) else {
+        continue;
+      };
+      for entry in entries.flatten() {
+        if entry.file_type().is_ok_and(|t| !t.is_dir()) {
+          names.push(entry.file_name().to_string_lossy().to_string());
+        }
+      }
+    }
+  }
+  let mut suggestions = names
+    .into_iter()
+    .filter_map(|candidate| {
+      let distance = edit_distance(name, &candidate);
+      (distance <= max_distance).then_some((distance, candidate))
+    })
+    .collect::<Vec<_>>();
+  suggestions.sort();
+  suggestions.dedup();
+  suggestions
+    .into_iter()
+    .map(|(_, candidate)| candidate)
+    .take(3)
+    .collect()
+}
+
+/// The number of inserted, deleted, replaced and swapped adjacent
+/// characters that turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+  let a = a.chars().collect::<Vec<_>>();
+  let b = b.chars().collect::<Vec<_>>();
+  // the rows for the prefixes of `a` that are one and two characters shorter
+  let mut before_previous = vec![0; b.len() + 1];
+  let mut previous = (0..=b.len()).collect::<Vec<_>>();
+  for i in 1..=a.len() {
+    let mut current = vec![i; b.len() + 1];
+    for j in 1..=b.len() {
+      let cost = usize::from(a[i - 1] != b[j - 1]);
+      current[j] = (previous[j] + 1)
+        .min(current[j - 1] + 1)
+        .min(previous[j - 1] + cost);
+      if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+        current[j] = current[j].min(before_previous[j - 2] + 1);
+      }
+    }
+    before_previous = std::mem::replace(&mut previous, current);
+  }
+  previous[b.len()]
+}
+
 struct Shebang {
   string_split: bool,
   command: String,
+  /// The argument after the interpreter, when it isn't run with `env`
+  arg: Option<String>,
 }
 
 fn resolve_shebang(
@@ -353,31 +456,56 @@ fn resolve_shebang(
     }
     Err(err) => return Err(err),
   };
-  let text = b"#!/usr/bin/env ";
-  let mut buffer = vec![0; text.len()];
+  let mut buffer = [0; 2];
   match file.read_exact(&mut buffer) {
-    Ok(_) if buffer == text => (),
+    Ok(_) if &buffer == b"#!" => (),
     _ => return Ok(None),
   }
 
   let mut reader = BufReader::new(file);
   let mut line = String::new();
   reader.read_line(&mut line)?;
-  if line.is_empty() {
-    return Ok(None);
-  }
   let line = line.trim();
 
-  Ok(Some(if let Some(command) = line.strip_prefix("-S ") {
-    Shebang {
-      string_split: true,
-      command: command.to_string(),
-    }
-  } else {
-    Shebang {
-      string_split: false,
-      command: line.to_string(),
+  if let Some(line) = line.strip_prefix("/usr/bin/env ") {
+    let line = line.trim_start();
+    if line.is_empty() {
+      return Ok(None);
     }
+    return Ok(Some(if let Some(command) = line.strip_prefix("-S ") {
+      Shebang {
+        string_split: true,
+        command: command.to_string(),
+        arg: None,
+      }
+    } else {
+      Shebang {
+        string_split: false,
+        command: line.to_string(),
+        arg: None,
+      }
+    }));
+  }
+
+  // like the kernel, everything after the interpreter is a single argument
+  let (interpreter, arg) = match line.split_once(char::is_whitespace) {
+    Some((interpreter, arg)) => (interpreter, Some(arg.trim().to_string())),
+    None => (line, None),
+  };
+  if interpreter.is_empty() {
+    return Ok(None);
+  }
+  // an interpreter such as `/bin/sh` doesn't exist on Windows, so look up
+  // its name instead
+  let path = Path::new(interpreter);
+  let command = match path.file_name() {
+    Some(name) if !path.is_file() => name.to_string_lossy().to_string(),
+    _ => interpreter.to_string(),
+  };
+  Ok(Some(Shebang {
+    string_split: false,
+    command,
+    arg,
   }))
 }
 
@@ -426,4 +554,15 @@ mod local_test {
     });
     assert_eq!(result, Err(ResolveCommandPathError::CommandEmpty));
   }
+
+  #[test]
+  fn should_measure_edit_distance() {
+    assert_eq!(edit_distance("gti", "git"), 1);
+    assert_eq!(edit_distance("git", "git"), 0);
+    assert_eq!(edit_distance("ech", "echo"), 1);
+    assert_eq!(edit_distance("cta", "cat"), 1);
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+    assert_eq!(edit_distance("", "ls"), 2);
+    assert_eq!(edit_distance("ä", "a"), 1);
+  }
 }
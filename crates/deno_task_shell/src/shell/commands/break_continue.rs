@@ -0,0 +1,123 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use futures::future::LocalBoxFuture;
+use miette::bail;
+use miette::Result;
+
+use crate::shell::types::ExecuteResult;
+use crate::shell::types::LoopControl;
+
+use super::args::parse_arg_kinds;
+use super::args::ArgKind;
+use super::ShellCommand;
+use super::ShellCommandContext;
+
+pub struct BreakCommand;
+
+impl ShellCommand for BreakCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Exit from a for, while or until loop")
+  }
+
+  fn execute(
+    &self,
+    context: ShellCommandContext,
+  ) -> LocalBoxFuture<'static, ExecuteResult> {
+    Box::pin(futures::future::ready(execute_loop_control(
+      "break",
+      LoopControl::Break,
+      context,
+    )))
+  }
+}
+
+pub struct ContinueCommand;
+
+impl ShellCommand for ContinueCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Resume the next iteration of a for, while or until loop")
+  }
+
+  fn execute(
+    &self,
+    context: ShellCommandContext,
+  ) -> LocalBoxFuture<'static, ExecuteResult> {
+    Box::pin(futures::future::ready(execute_loop_control(
+      "continue",
+      LoopControl::Continue,
+      context,
+    )))
+  }
+}
+
+fn execute_loop_control(
+  name: &str,
+  control: fn(usize) -> LoopControl,
+  mut context: ShellCommandContext,
+) -> ExecuteResult {
+  let loop_depth = context.state.loop_depth();
+  if loop_depth == 0 {
+    let _ = context.stderr.write_line(&format!(
+      "{name}: only meaningful in a `for', `while', or `until' loop"
+    ));
+    return ExecuteResult::from_exit_code(0);
+  }
+  match parse_args(context.args) {
+    // like bash, a count larger than the number of loops leaves all of them
+    Ok(count) => ExecuteResult::LoopControl(
+      control(count.min(loop_depth)),
+      Vec::new(),
+      Vec::new(),
+    ),
+    Err(err) => {
+      let _ = context.stderr.write_line(&format!("{name}: {err}"));
+      ExecuteResult::from_exit_code(1)
+    }
+  }
+}
+
+fn parse_args(args: Vec<String>) -> Result<usize> {
+  let mut counts = Vec::new();
+  for arg in parse_arg_kinds(&args) {
+    match arg {
+      ArgKind::Arg(arg) => counts.push(arg),
+      _ => arg.bail_unsupported()?,
+    }
+  }
+
+  match counts.as_slice() {
+    [] => Ok(1),
+    [count] => match count.parse::<i64>() {
+      Ok(value) if value >= 1 => Ok(value as usize),
+      Ok(_) => bail!("{count}: loop count out of range"),
+      Err(_) => bail!("{count}: numeric argument required"),
+    },
+    _ => bail!("too many arguments"),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn parses_args() {
+    assert_eq!(parse_args(vec![]).unwrap(), 1);
+    assert_eq!(parse_args(vec!["2".to_string()]).unwrap(), 2);
+    assert_eq!(
+      parse_args(vec!["0".to_string()]).err().unwrap().to_string(),
+      "0: loop count out of range"
+    );
+    assert_eq!(
+      parse_args(vec!["a".to_string()]).err().unwrap().to_string(),
+      "a: numeric argument required"
+    );
+    assert_eq!(
+      parse_args(vec!["1".to_string(), "2".to_string()])
+        .err()
+        .unwrap()
+        .to_string(),
+      "too many arguments"
+    );
+  }
+}
@@ -18,6 +18,10 @@ use super::ShellCommandContext;
 pub struct CatCommand;
 
 impl ShellCommand for CatCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Concatenate files to standard output")
+  }
+
   fn execute(
     &self,
     context: ShellCommandContext,
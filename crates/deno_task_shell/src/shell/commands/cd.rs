@@ -20,6 +20,10 @@ use super::ShellCommandContext;
 pub struct CdCommand;
 
 impl ShellCommand for CdCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Change the working directory")
+  }
+
   fn execute(
     &self,
     mut context: ShellCommandContext,
@@ -37,14 +41,15 @@ impl ShellCommand for CdCommand {
   }
 }
 
-fn execute_cd(cwd: &Path, args: Vec<String>) -> Result<PathBuf> {
+/// The directory that `cd` with `args` changes to from `cwd`.
+pub(super) fn execute_cd(cwd: &Path, args: Vec<String>) -> Result<PathBuf> {
   // create a new vector to avoid modifying the original
   let mut args = args;
   if args.is_empty() {
     // append `~` to args
     args.push("~".to_string());
   }
-  let path = parse_args(args.clone())?;
+  let CdFlags { path, physical } = parse_args(args.clone())?;
   let new_dir = if path == "~" {
     dirs::home_dir()
       .ok_or_else(|| miette::miette!("Home directory not found"))?
@@ -59,17 +64,35 @@ fn execute_cd(cwd: &Path, args: Vec<String>) -> Result<PathBuf> {
   if !new_dir.is_dir() {
     bail!("{}: Not a directory", path)
   }
+  if physical {
+    return fs_util::canonicalize_path(&new_dir);
+  }
   Ok(new_dir)
 }
 
-fn parse_args(args: Vec<String>) -> Result<String> {
+#[derive(Debug, PartialEq)]
+struct CdFlags {
+  path: String,
+  /// Resolve symlinks in the new directory (`-P`) instead of keeping the
+  /// logical path (`-L`, the default).
+  physical: bool,
+}
+
+fn parse_args(args: Vec<String>) -> Result<CdFlags> {
   let args = parse_arg_kinds(&args);
   let mut paths = Vec::new();
+  let mut physical = false;
   for arg in args {
     match arg {
       ArgKind::Arg(arg) => {
         paths.push(arg);
       }
+      ArgKind::ShortFlag('L') => {
+        physical = false;
+      }
+      ArgKind::ShortFlag('P') => {
+        physical = true;
+      }
       _ => arg.bail_unsupported()?,
     }
   }
@@ -82,7 +105,10 @@ fn parse_args(args: Vec<String>) -> Result<String> {
     bail!("expected at least 1 argument")
   }
 
-  Ok(paths.remove(0).to_string())
+  Ok(CdFlags {
+    path: paths.remove(0).to_string(),
+    physical,
+  })
 }
 
 #[cfg(test)]
@@ -94,7 +120,20 @@ mod test {
 
   #[test]
   fn parses_args() {
-    assert_eq!(parse_args(vec!["test".to_string()]).unwrap(), "test");
+    assert_eq!(
+      parse_args(vec!["test".to_string()]).unwrap(),
+      CdFlags {
+        path: "test".to_string(),
+        physical: false,
+      }
+    );
+    assert_eq!(
+      parse_args(vec!["-P".to_string(), "test".to_string()]).unwrap(),
+      CdFlags {
+        path: "test".to_string(),
+        physical: true,
+      }
+    );
     assert_eq!(
       parse_args(vec!["a".to_string(), "b".to_string()])
         .err()
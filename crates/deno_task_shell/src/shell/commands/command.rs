@@ -0,0 +1,52 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use futures::future::LocalBoxFuture;
+
+use crate::shell::execute::execute_command_name;
+use crate::shell::types::ExecuteResult;
+
+use super::r#type::resolve_kind;
+use super::r#type::CommandKind;
+use super::ShellCommand;
+use super::ShellCommandContext;
+
+pub struct CommandCommand;
+
+impl ShellCommand for CommandCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Run a command, bypassing functions and aliases of the same name")
+  }
+
+  fn execute(
+    &self,
+    mut context: ShellCommandContext,
+  ) -> LocalBoxFuture<'static, ExecuteResult> {
+    if context.args.first().is_some_and(|arg| arg == "-v") {
+      let mut exit_code = 0;
+      for name in &context.args[1..] {
+        let line = match resolve_kind(&context.state, name) {
+          Some(CommandKind::Alias(value)) => {
+            format!("alias {name}='{}'", value.join(" "))
+          }
+          Some(CommandKind::Function(_) | CommandKind::Builtin) => name.clone(),
+          Some(CommandKind::File(path)) => path.display().to_string(),
+          None => {
+            exit_code = 1;
+            continue;
+          }
+        };
+        let _ = context.stdout.write_line(&line);
+      }
+      return Box::pin(futures::future::ready(ExecuteResult::from_exit_code(
+        exit_code,
+      )));
+    }
+    if context.args.is_empty() {
+      return Box::pin(futures::future::ready(ExecuteResult::from_exit_code(
+        0,
+      )));
+    }
+    let command_name = context.args.remove(0);
+    execute_command_name(command_name, context)
+  }
+}
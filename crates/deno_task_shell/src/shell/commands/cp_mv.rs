@@ -23,6 +23,10 @@ use super::ShellCommandContext;
 pub struct CpCommand;
 
 impl ShellCommand for CpCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Copy files and directories")
+  }
+
   fn execute(
     &self,
     context: ShellCommandContext,
@@ -172,6 +176,10 @@ fn parse_cp_args(cwd: &Path, args: Vec<String>) -> Result<CpFlags> {
 pub struct MvCommand;
 
 impl ShellCommand for MvCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Move or rename files")
+  }
+
   fn execute(
     &self,
     context: ShellCommandContext,
@@ -0,0 +1,448 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+use futures::future::LocalBoxFuture;
+use miette::bail;
+use miette::Result;
+
+use crate::shell::execute::evaluate_integer_value;
+use crate::shell::types::EnvChange;
+use crate::shell::types::ExecuteResult;
+use crate::shell::types::ShellState;
+use crate::shell::types::VarAttributes;
+
+use super::args::parse_arg_kinds;
+use super::args::ArgKind;
+use super::ShellCommand;
+use super::ShellCommandContext;
+
+pub struct DeclareCommand;
+
+impl ShellCommand for DeclareCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Declare variables and give them attributes")
+  }
+
+  fn execute(
+    &self,
+    mut context: ShellCommandContext,
+  ) -> LocalBoxFuture<'static, ExecuteResult> {
+    Box::pin(async move {
+      let result = match parse_args(context.args) {
+        Ok(DeclareFlags {
+          functions: Some(listing),
+          vars,
+          ..
+        }) => {
+          let names = vars.into_iter().map(|(name, _)| name).collect();
+          let (output, all_found) =
+            list_functions(&context.state, listing, names);
+          let _ = context.stdout.write_all(output.as_bytes());
+          return ExecuteResult::from_exit_code(if all_found { 0 } else { 1 });
+        }
+        Ok(flags) if flags.vars.is_empty() => {
+          let _ = context
+            .stdout
+            .write_all(list_vars(&context.state).as_bytes());
+          Ok(Vec::new())
+        }
+        Ok(flags) => declare_vars(flags, &mut context.state),
+        Err(err) => Err(err),
+      };
+      match result {
+        Ok(changes) => ExecuteResult::Continue(0, changes, Vec::new()),
+        Err(err) => {
+          let _ = context.stderr.write_line(&format!("declare: {err}"));
+          ExecuteResult::from_exit_code(1)
+        }
+      }
+    })
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum VarKind {
+  Scalar,
+  IndexedArray,
+  AssociativeArray,
+}
+
+/// What `declare -f` and `declare -F` show of the functions
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FunctionListing {
+  Definitions,
+  Names,
+}
+
+#[derive(Debug, PartialEq)]
+struct DeclareFlags {
+  kind: VarKind,
+  /// The attributes to add to the variables
+  attributes: VarAttributes,
+  /// Whether to export the variables `-x`
+  export: bool,
+  /// Whether to list functions instead of declaring variables
+  functions: Option<FunctionListing>,
+  /// The names to declare, with the value to assign if any
+  vars: Vec<(String, Option<String>)>,
+}
+
+fn declare_vars(
+  flags: DeclareFlags,
+  state: &mut ShellState,
+) -> Result<Vec<EnvChange>> {
+  let mut changes = Vec::new();
+  for (name, value) in flags.vars {
+    let current = state.var_attributes(&name);
+    if current.readonly && value.is_some() {
+      bail!("{name}: readonly variable");
+    }
+    let attributes = VarAttributes {
+      integer: current.integer || flags.attributes.integer,
+      readonly: current.readonly || flags.attributes.readonly,
+      // the case conversions exclude each other, the last one given wins
+      lowercase: flags.attributes.lowercase
+        || current.lowercase && !flags.attributes.uppercase,
+      uppercase: flags.attributes.uppercase
+        || current.uppercase && !flags.attributes.lowercase,
+    };
+    let mut var_changes = Vec::new();
+    // the variable only becomes readonly once its value is assigned
+    var_changes.push(EnvChange::SetVarAttributes(
+      name.clone(),
+      VarAttributes {
+        readonly: false,
+        ..attributes
+      },
+    ));
+    let value = match value {
+      Some(value) if attributes.integer => {
+        let (value, value_changes) = evaluate_integer_value(&value, state)
+          .map_err(|err| miette::miette!("{name}: {err}"))?;
+        var_changes.extend(value_changes);
+        Some(value)
+      }
+      value => value,
+    };
+    match flags.kind {
+      VarKind::Scalar => match value {
+        Some(value) if flags.export => {
+          var_changes.push(EnvChange::SetEnvVar(name.clone(), value))
+        }
+        Some(value) => {
+          var_changes.push(EnvChange::SetShellVar(name.clone(), value))
+        }
+        None if flags.export => {
+          if let Some(value) = state.get_var(&name) {
+            var_changes.push(EnvChange::SetEnvVar(name.clone(), value));
+          }
+        }
+        None => {}
+      },
+      VarKind::IndexedArray => {
+        if state.get_assoc(&name).is_some() {
+          bail!("{name}: cannot convert associative to indexed array");
+        }
+        let mut array = match state.get_array(&name) {
+          Some(array) => array.clone(),
+          None => state
+            .get_var(&name)
+            .map(|value| BTreeMap::from([(0, value)]))
+            .unwrap_or_default(),
+        };
+        if let Some(value) = value {
+          array.insert(0, value);
+        }
+        var_changes.push(EnvChange::SetArrayVar(name.clone(), array));
+      }
+      VarKind::AssociativeArray => {
+        if state.get_array(&name).is_some() {
+          bail!("{name}: cannot convert indexed to associative array");
+        }
+        let mut map = match state.get_assoc(&name) {
+          Some(map) => map.clone(),
+          // like bash, a scalar becomes the element with the key `0`
+          None => state
+            .get_var(&name)
+            .map(|value| BTreeMap::from([("0".to_string(), value)]))
+            .unwrap_or_default(),
+        };
+        if let Some(value) = value {
+          map.insert("0".to_string(), value);
+        }
+        var_changes.push(EnvChange::SetAssocVar(name.clone(), map));
+      }
+    }
+    if attributes.readonly {
+      var_changes.push(EnvChange::SetVarAttributes(name, attributes));
+    }
+    // later names may refer to the earlier ones (e.g. `declare -i a=1 b=a+1`)
+    for change in &var_changes {
+      state.apply_change(change);
+    }
+    changes.extend(var_changes);
+  }
+  Ok(changes)
+}
+
+/// Lists all variables in the format of `declare -p`, sorted by name.
+fn list_vars(state: &ShellState) -> String {
+  let names = state
+    .env_vars()
+    .keys()
+    .chain(state.shell_vars().keys())
+    .chain(state.array_vars().keys())
+    .chain(state.assoc_vars().keys())
+    .collect::<BTreeSet<_>>();
+  let mut output = String::new();
+  for name in names {
+    let attributes = state.var_attributes(name);
+    let mut flags = String::new();
+    let value = if let Some(array) = state.get_array(name) {
+      flags.push('a');
+      let elements = array
+        .iter()
+        .map(|(index, value)| format!("[{index}]={}", quote_value(value)))
+        .collect::<Vec<_>>();
+      format!("({})", elements.join(" "))
+    } else if let Some(map) = state.get_assoc(name) {
+      flags.push('A');
+      // like bash, each element is followed by a space
+      let elements = map
+        .iter()
+        .map(|(key, value)| format!("[{key}]={} ", quote_value(value)))
+        .collect::<String>();
+      format!("({elements})")
+    } else {
+      quote_value(&state.get_var(name).unwrap_or_default())
+    };
+    for (flag, is_set) in [
+      ('i', attributes.integer),
+      ('l', attributes.lowercase),
+      ('r', attributes.readonly),
+      ('u', attributes.uppercase),
+      ('x', state.env_vars().contains_key(name.as_str())),
+    ] {
+      if is_set {
+        flags.push(flag);
+      }
+    }
+    if flags.is_empty() {
+      flags.push('-');
+    }
+    output.push_str(&format!("declare -{flags} {name}={value}\n"));
+  }
+  output
+}
+
+/// Lists the given functions (all when empty) sorted by name, returning
+/// whether they were all found.
+fn list_functions(
+  state: &ShellState,
+  listing: FunctionListing,
+  names: Vec<String>,
+) -> (String, bool) {
+  let list_all = names.is_empty();
+  let names = if list_all {
+    state.functions().keys().cloned().collect::<BTreeSet<_>>()
+  } else {
+    names.into_iter().collect()
+  };
+  let mut all_found = true;
+  let mut output = String::new();
+  for name in names {
+    let Some(function) = state.function(&name) else {
+      all_found = false;
+      continue;
+    };
+    match listing {
+      FunctionListing::Definitions => output.push_str(&function.text),
+      FunctionListing::Names if list_all => {
+        output.push_str(&format!("declare -f {name}"))
+      }
+      FunctionListing::Names => output.push_str(&name),
+    }
+    output.push('\n');
+  }
+  (output, all_found)
+}
+
+/// Double quotes a value so that it can be read back by the shell.
+fn quote_value(value: &str) -> String {
+  let mut quoted = String::with_capacity(value.len() + 2);
+  quoted.push('"');
+  for c in value.chars() {
+    if matches!(c, '"' | '\\' | '$' | '`') {
+      quoted.push('\\');
+    }
+    quoted.push(c);
+  }
+  quoted.push('"');
+  quoted
+}
+
+fn parse_args(args: Vec<String>) -> Result<DeclareFlags> {
+  let mut kind = VarKind::Scalar;
+  let mut attributes = VarAttributes::default();
+  let mut export = false;
+  let mut functions = None;
+  let mut vars = Vec::new();
+  for arg in parse_arg_kinds(&args) {
+    match arg {
+      ArgKind::ShortFlag('a') => kind = VarKind::IndexedArray,
+      ArgKind::ShortFlag('A') => kind = VarKind::AssociativeArray,
+      ArgKind::ShortFlag('i') => attributes.integer = true,
+      ArgKind::ShortFlag('r') => attributes.readonly = true,
+      ArgKind::ShortFlag('x') => export = true,
+      ArgKind::ShortFlag('f') => functions = Some(FunctionListing::Definitions),
+      ArgKind::ShortFlag('F') => functions = Some(FunctionListing::Names),
+      ArgKind::ShortFlag('l') => {
+        attributes.lowercase = true;
+        attributes.uppercase = false;
+      }
+      ArgKind::ShortFlag('u') => {
+        attributes.uppercase = true;
+        attributes.lowercase = false;
+      }
+      ArgKind::Arg(arg) => vars.push(arg),
+      _ => arg.bail_unsupported()?,
+    }
+  }
+  let vars = if functions.is_some() {
+    // function names aren't limited to identifiers
+    vars
+      .into_iter()
+      .map(|name| (name.to_string(), None))
+      .collect()
+  } else {
+    vars
+      .into_iter()
+      .map(|arg| {
+        let (name, value) = match arg.split_once('=') {
+          Some((name, value)) => (name, Some(value.to_string())),
+          None => (arg, None),
+        };
+        let is_valid = name
+          .chars()
+          .next()
+          .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+          && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !is_valid {
+          bail!("`{arg}': not a valid identifier");
+        }
+        Ok((name.to_string(), value))
+      })
+      .collect::<Result<_>>()?
+  };
+  Ok(DeclareFlags {
+    kind,
+    attributes,
+    export,
+    functions,
+    vars,
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use std::collections::HashMap;
+
+  use super::*;
+
+  fn to_args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|a| a.to_string()).collect()
+  }
+
+  #[test]
+  fn parses_args() {
+    assert_eq!(
+      parse_args(to_args(&["-A", "map", "other=1"])).unwrap(),
+      DeclareFlags {
+        kind: VarKind::AssociativeArray,
+        attributes: VarAttributes::default(),
+        export: false,
+        functions: None,
+        vars: vec![
+          ("map".to_string(), None),
+          ("other".to_string(), Some("1".to_string())),
+        ],
+      }
+    );
+    assert_eq!(
+      parse_args(to_args(&["-a", "list"])).unwrap().kind,
+      VarKind::IndexedArray
+    );
+    assert_eq!(
+      parse_args(to_args(&["-irx", "-lu", "n=1"])).unwrap(),
+      DeclareFlags {
+        kind: VarKind::Scalar,
+        attributes: VarAttributes {
+          integer: true,
+          readonly: true,
+          lowercase: false,
+          uppercase: true,
+        },
+        export: true,
+        functions: None,
+        vars: vec![("n".to_string(), Some("1".to_string()))],
+      }
+    );
+    let flags = parse_args(to_args(&["-F", "my-func"])).unwrap();
+    assert_eq!(flags.functions, Some(FunctionListing::Names));
+    assert_eq!(flags.vars, vec![("my-func".to_string(), None)]);
+    assert_eq!(
+      parse_args(to_args(&["1x"])).err().unwrap().to_string(),
+      "`1x': not a valid identifier"
+    );
+    assert_eq!(
+      parse_args(to_args(&["-n", "x"])).err().unwrap().to_string(),
+      "unsupported flag: -n"
+    );
+  }
+
+  #[test]
+  fn lists_vars() {
+    let mut state = ShellState::new(
+      HashMap::from([("PATH".to_string(), "/bin".to_string())]),
+      &std::env::temp_dir(),
+      HashMap::new(),
+    );
+    state.apply_changes(&[
+      EnvChange::SetShellVar("x".to_string(), "a \"b\"".to_string()),
+      EnvChange::SetArrayVar(
+        "list".to_string(),
+        BTreeMap::from([(0, "1".to_string()), (5, "2".to_string())]),
+      ),
+      EnvChange::SetAssocVar(
+        "map".to_string(),
+        BTreeMap::from([("k".to_string(), "v".to_string())]),
+      ),
+      EnvChange::SetVarAttributes(
+        "x".to_string(),
+        VarAttributes {
+          readonly: true,
+          ..Default::default()
+        },
+      ),
+    ]);
+    let output = list_vars(&state);
+    let output = output.lines().filter(|line| !line.contains(" PWD="));
+    assert_eq!(
+      output.collect::<Vec<_>>(),
+      vec![
+        "declare -x PATH=\"/bin\"",
+        "declare -a list=([0]=\"1\" [5]=\"2\")",
+        "declare -A map=([k]=\"v\" )",
+        "declare -r x=\"a \\\"b\\\"\"",
+      ]
+    );
+  }
+
+  #[test]
+  fn quotes_values() {
+    assert_eq!(quote_value("a b"), "\"a b\"");
+    assert_eq!(quote_value("$x \"`\\"), "\"\\$x \\\"\\`\\\\\"");
+  }
+}
@@ -0,0 +1,306 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+//! `pushd`, `popd` and `dirs`. Like in bash, the directory stack starts
+//! with the current directory, followed by the directories saved with
+//! `pushd`. `+N` counts its entries from the left starting with zero, and
+//! `-N` from the right.
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use futures::future::LocalBoxFuture;
+use miette::bail;
+use miette::miette;
+use miette::Result;
+
+use crate::shell::types::EnvChange;
+use crate::shell::types::ExecuteResult;
+use crate::shell::types::ShellState;
+
+use super::args::parse_arg_kinds;
+use super::args::ArgKind;
+use super::cd::execute_cd;
+use super::ShellCommand;
+use super::ShellCommandContext;
+
+pub struct PushdCommand;
+
+impl ShellCommand for PushdCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Save the working directory on the directory stack and change it")
+  }
+
+  fn execute(
+    &self,
+    mut context: ShellCommandContext,
+  ) -> LocalBoxFuture<'static, ExecuteResult> {
+    let result = pushd(&context.state, &context.args);
+    Box::pin(futures::future::ready(change_stack(
+      &mut context,
+      "pushd",
+      result,
+    )))
+  }
+}
+
+pub struct PopdCommand;
+
+impl ShellCommand for PopdCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Remove a directory from the directory stack and change to the top")
+  }
+
+  fn execute(
+    &self,
+    mut context: ShellCommandContext,
+  ) -> LocalBoxFuture<'static, ExecuteResult> {
+    let result = popd(&context.state, &context.args);
+    Box::pin(futures::future::ready(change_stack(
+      &mut context,
+      "popd",
+      result,
+    )))
+  }
+}
+
+pub struct DirsCommand;
+
+impl ShellCommand for DirsCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Print or clear the directory stack")
+  }
+
+  fn execute(
+    &self,
+    mut context: ShellCommandContext,
+  ) -> LocalBoxFuture<'static, ExecuteResult> {
+    let result = match dirs(&context.state, &context.args) {
+      Ok(Dirs::Clear) => ExecuteResult::Continue(
+        0,
+        vec![EnvChange::SetDirStack(Vec::new())],
+        Vec::new(),
+      ),
+      Ok(Dirs::Print(output)) => {
+        let _ = context.stdout.write_all(output.as_bytes());
+        ExecuteResult::from_exit_code(0)
+      }
+      Err(err) => {
+        let _ = context.stderr.write_line(&format!("dirs: {err}"));
+        ExecuteResult::from_exit_code(1)
+      }
+    };
+    Box::pin(futures::future::ready(result))
+  }
+}
+
+/// Changes to the top of the new `stack` and saves the rest of it, then
+/// prints it like `dirs` does.
+fn change_stack(
+  context: &mut ShellCommandContext,
+  name: &str,
+  stack: Result<Vec<PathBuf>>,
+) -> ExecuteResult {
+  match stack {
+    Ok(mut stack) => {
+      let _ = context
+        .stdout
+        .write_line(&format_stack(&stack, DirsFormat::default()));
+      let cwd = stack.remove(0);
+      ExecuteResult::Continue(
+        0,
+        vec![EnvChange::SetDirStack(stack), EnvChange::Cd(cwd)],
+        Vec::new(),
+      )
+    }
+    Err(err) => {
+      let _ = context.stderr.write_line(&format!("{name}: {err}"));
+      ExecuteResult::from_exit_code(1)
+    }
+  }
+}
+
+/// The whole directory stack, starting with the current directory.
+fn full_stack(state: &ShellState) -> Vec<PathBuf> {
+  let mut stack = vec![state.cwd().clone()];
+  stack.extend(state.dir_stack().iter().cloned());
+  stack
+}
+
+/// `pushd dir` saves the current directory and changes to `dir`, `pushd +N`
+/// rotates the stack so that its Nth entry is at the top, and `pushd`
+/// swaps the top two entries.
+fn pushd(state: &ShellState, args: &[String]) -> Result<Vec<PathBuf>> {
+  let mut stack = full_stack(state);
+  match args {
+    [] => {
+      if stack.len() < 2 {
+        bail!("no other directory");
+      }
+      stack.swap(0, 1);
+    }
+    [arg] => match parse_index(arg, stack.len()) {
+      Some(index) => stack.rotate_left(index?),
+      None => {
+        let dir = execute_cd(state.cwd(), vec![arg.clone()])?;
+        stack.insert(0, dir);
+      }
+    },
+    _ => bail!("too many arguments"),
+  }
+  ensure_dir(&stack[0])?;
+  Ok(stack)
+}
+
+/// `popd` removes the top of the stack and `popd +N` its Nth entry.
+fn popd(state: &ShellState, args: &[String]) -> Result<Vec<PathBuf>> {
+  let mut stack = full_stack(state);
+  if stack.len() < 2 {
+    bail!("directory stack empty");
+  }
+  match args {
+    [] => {
+      stack.remove(0);
+    }
+    [arg] => match parse_index(arg, stack.len()) {
+      Some(index) => {
+        stack.remove(index?);
+      }
+      None => bail!("{arg}: invalid argument"),
+    },
+    _ => bail!("too many arguments"),
+  }
+  ensure_dir(&stack[0])?;
+  Ok(stack)
+}
+
+fn ensure_dir(dir: &Path) -> Result<()> {
+  if !dir.is_dir() {
+    bail!("{}: No such file or directory", dir.display());
+  }
+  Ok(())
+}
+
+/// Parses `+N` or `-N` into the index in a stack of `len` entries that it
+/// refers to, or returns `None` for any other argument.
+fn parse_index(arg: &str, len: usize) -> Option<Result<usize>> {
+  let (from_right, digits) = match arg.strip_prefix('+') {
+    Some(digits) => (false, digits),
+    None => (true, arg.strip_prefix('-')?),
+  };
+  if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+    return None;
+  }
+  let index = digits.parse::<usize>().ok().filter(|index| *index < len);
+  Some(
+    index
+      .map(|index| if from_right { len - 1 - index } else { index })
+      .ok_or_else(|| miette!("{arg}: directory stack index out of range")),
+  )
+}
+
+enum Dirs {
+  Clear,
+  Print(String),
+}
+
+#[derive(Default, Clone, Copy)]
+struct DirsFormat {
+  /// Full paths instead of `~` for the home directory (`-l`)
+  long: bool,
+  /// One entry per line (`-p`)
+  per_line: bool,
+  /// One entry per line, with its index (`-v`)
+  verbose: bool,
+}
+
+fn dirs(state: &ShellState, args: &[String]) -> Result<Dirs> {
+  let stack = full_stack(state);
+  let mut format = DirsFormat::default();
+  let mut index = None;
+  for arg in parse_arg_kinds(args) {
+    match arg {
+      ArgKind::ShortFlag('c') => return Ok(Dirs::Clear),
+      ArgKind::ShortFlag('l') => format.long = true,
+      ArgKind::ShortFlag('p') => format.per_line = true,
+      ArgKind::ShortFlag('v') => format.verbose = true,
+      ArgKind::Arg(arg) if index.is_none() => {
+        match parse_index(arg, stack.len()) {
+          Some(result) => index = Some(result?),
+          None => bail!("{arg}: invalid argument"),
+        }
+      }
+      _ => arg.bail_unsupported()?,
+    }
+  }
+  let output = match index {
+    Some(index) => format_dir(&stack[index], format.long),
+    None => format_stack(&stack, format),
+  };
+  Ok(Dirs::Print(format!("{output}\n")))
+}
+
+fn format_stack(stack: &[PathBuf], format: DirsFormat) -> String {
+  let dirs = stack.iter().map(|dir| format_dir(dir, format.long));
+  if format.verbose {
+    dirs
+      .enumerate()
+      .map(|(index, dir)| format!("{index:2}  {dir}"))
+      .collect::<Vec<_>>()
+      .join("\n")
+  } else if format.per_line {
+    dirs.collect::<Vec<_>>().join("\n")
+  } else {
+    dirs.collect::<Vec<_>>().join(" ")
+  }
+}
+
+/// The directory with the home directory shortened to `~`, unless `long`.
+fn format_dir(dir: &Path, long: bool) -> String {
+  if !long {
+    if let Some(home) = dirs::home_dir() {
+      if let Ok(rest) = dir.strip_prefix(&home) {
+        if rest.as_os_str().is_empty() {
+          return "~".to_string();
+        }
+        return format!("~/{}", rest.display());
+      }
+    }
+  }
+  dir.display().to_string()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn parses_indexes() {
+    let index = |arg, len| parse_index(arg, len).map(|index| index.ok());
+    assert_eq!(index("+0", 3), Some(Some(0)));
+    assert_eq!(index("+2", 3), Some(Some(2)));
+    assert_eq!(index("-0", 3), Some(Some(2)));
+    assert_eq!(index("-2", 3), Some(Some(0)));
+    assert_eq!(index("+3", 3), Some(None));
+    assert_eq!(index("-3", 3), Some(None));
+    assert_eq!(index("+99999999999999999999", 3), Some(None));
+    for arg in ["dir", "+", "-", "-1.5", "+a", "", "é"] {
+      assert_eq!(index(arg, 3), None, "{arg}");
+    }
+  }
+
+  #[test]
+  fn formats_stacks() {
+    let stack = [PathBuf::from("/a"), PathBuf::from("/b/c")];
+    assert_eq!(format_stack(&stack, DirsFormat::default()), "/a /b/c");
+    let format = DirsFormat {
+      per_line: true,
+      ..Default::default()
+    };
+    assert_eq!(format_stack(&stack, format), "/a\n/b/c");
+    let format = DirsFormat {
+      verbose: true,
+      ..Default::default()
+    };
+    assert_eq!(format_stack(&stack, format), " 0  /a\n 1  /b/c");
+  }
+}
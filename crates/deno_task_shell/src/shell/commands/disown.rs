@@ -0,0 +1,196 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use futures::future::LocalBoxFuture;
+use miette::Result;
+
+use crate::shell::jobs::JobStatus;
+use crate::shell::jobs::JobTable;
+use crate::shell::types::EnvChange;
+use crate::shell::types::ExecuteResult;
+
+use super::args::parse_arg_kinds;
+use super::args::ArgKind;
+use super::ShellCommand;
+use super::ShellCommandContext;
+
+pub struct DisownCommand;
+
+impl ShellCommand for DisownCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Remove jobs from the job table")
+  }
+
+  fn execute(
+    &self,
+    mut context: ShellCommandContext,
+  ) -> LocalBoxFuture<'static, ExecuteResult> {
+    let result = match parse_args(context.args) {
+      Ok(flags) => {
+        let (changes, errors) = execute_disown(context.state.jobs(), &flags);
+        for error in &errors {
+          let _ = context.stderr.write_line(&format!("disown: {error}"));
+        }
+        let exit_code = if errors.is_empty() { 0 } else { 1 };
+        ExecuteResult::Continue(exit_code, changes, Vec::new())
+      }
+      Err(err) => {
+        let _ = context.stderr.write_line(&format!("disown: {err}"));
+        ExecuteResult::Continue(2, Vec::new(), Vec::new())
+      }
+    };
+    Box::pin(futures::future::ready(result))
+  }
+}
+
+/// Returns the changes to the job table and an error for each job spec that
+/// didn't match a job.
+fn execute_disown(
+  jobs: &JobTable,
+  flags: &DisownFlags,
+) -> (Vec<EnvChange>, Vec<String>) {
+  let mut ids = Vec::new();
+  let mut errors = Vec::new();
+  if flags.job_specs.is_empty() {
+    if flags.all || flags.running {
+      ids.extend(
+        jobs
+          .iter()
+          .filter(|job| !flags.running || job.status == JobStatus::Running)
+          .map(|job| job.id),
+      );
+    } else {
+      match jobs.current() {
+        Some(job) => ids.push(job.id),
+        None => errors.push("current: no such job".to_string()),
+      }
+    }
+  } else {
+    for spec in &flags.job_specs {
+      match jobs.resolve(spec) {
+        Some(job) => {
+          if !flags.running || job.status == JobStatus::Running {
+            ids.push(job.id);
+          }
+        }
+        None => errors.push(format!("{spec}: no such job")),
+      }
+    }
+  }
+
+  let changes = ids
+    .into_iter()
+    .map(|id| {
+      if flags.no_hangup {
+        EnvChange::SetJobNoHangup(id)
+      } else {
+        EnvChange::RemoveJob(id)
+      }
+    })
+    .collect();
+  (changes, errors)
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct DisownFlags {
+  /// `-a`: all jobs when no job specs are given
+  all: bool,
+  /// `-r`: only running jobs
+  running: bool,
+  /// `-h`: keep the jobs, but don't send them a SIGHUP
+  no_hangup: bool,
+  job_specs: Vec<String>,
+}
+
+fn parse_args(args: Vec<String>) -> Result<DisownFlags> {
+  let mut flags = DisownFlags::default();
+  for arg in parse_arg_kinds(&args) {
+    match arg {
+      ArgKind::ShortFlag('a') => flags.all = true,
+      ArgKind::ShortFlag('r') => flags.running = true,
+      ArgKind::ShortFlag('h') => flags.no_hangup = true,
+      ArgKind::Arg(spec) => flags.job_specs.push(spec.to_string()),
+      _ => arg.bail_unsupported()?,
+    }
+  }
+  Ok(flags)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn flags(args: &[&str]) -> DisownFlags {
+    parse_args(args.iter().map(|a| a.to_string()).collect()).unwrap()
+  }
+
+  fn job_table() -> JobTable {
+    let mut jobs = JobTable::default();
+    jobs.add(Some(10), "sleep 100".to_string());
+    let stopped = jobs.add(Some(11), "vim".to_string());
+    jobs.get_mut(stopped).unwrap().status = JobStatus::Stopped;
+    jobs.add(Some(12), "cargo build".to_string());
+    jobs
+  }
+
+  #[test]
+  fn parses_args() {
+    assert_eq!(
+      flags(&["-ah", "%1"]),
+      DisownFlags {
+        all: true,
+        running: false,
+        no_hangup: true,
+        job_specs: vec!["%1".to_string()],
+      }
+    );
+    assert_eq!(
+      parse_args(vec!["-x".to_string()])
+        .err()
+        .unwrap()
+        .to_string(),
+      "unsupported flag: -x"
+    );
+  }
+
+  #[test]
+  fn disowns_jobs() {
+    let jobs = job_table();
+    assert_eq!(
+      execute_disown(&jobs, &flags(&[])),
+      (vec![EnvChange::RemoveJob(3)], vec![])
+    );
+    assert_eq!(
+      execute_disown(&jobs, &flags(&["%1", "%5"])),
+      (
+        vec![EnvChange::RemoveJob(1)],
+        vec!["%5: no such job".to_string()]
+      )
+    );
+    assert_eq!(
+      execute_disown(&jobs, &flags(&["-a"])),
+      (
+        vec![
+          EnvChange::RemoveJob(1),
+          EnvChange::RemoveJob(2),
+          EnvChange::RemoveJob(3)
+        ],
+        vec![]
+      )
+    );
+    assert_eq!(
+      execute_disown(&jobs, &flags(&["-r"])),
+      (
+        vec![EnvChange::RemoveJob(1), EnvChange::RemoveJob(3)],
+        vec![]
+      )
+    );
+    assert_eq!(
+      execute_disown(&jobs, &flags(&["-h", "%vim"])),
+      (vec![EnvChange::SetJobNoHangup(2)], vec![])
+    );
+    assert_eq!(
+      execute_disown(&JobTable::default(), &flags(&[])),
+      (vec![], vec!["current: no such job".to_string()])
+    );
+  }
+}
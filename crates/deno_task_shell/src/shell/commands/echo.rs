@@ -10,6 +10,10 @@ use super::ShellCommandContext;
 pub struct EchoCommand;
 
 impl ShellCommand for EchoCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Write arguments to standard output")
+  }
+
   fn execute(
     &self,
     mut context: ShellCommandContext,
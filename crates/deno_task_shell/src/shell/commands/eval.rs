@@ -0,0 +1,68 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use futures::future::LocalBoxFuture;
+use futures::FutureExt;
+
+use crate::parser::parse;
+use crate::parser::ParseError;
+use crate::shell::execute::execute_sequential_list;
+use crate::shell::execute::AsyncCommandBehavior;
+use crate::shell::types::ExecuteResult;
+
+use super::ShellCommand;
+use super::ShellCommandContext;
+
+pub struct EvalCommand;
+
+impl ShellCommand for EvalCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Run the arguments as a command in the current shell")
+  }
+
+  fn execute(
+    &self,
+    mut context: ShellCommandContext,
+  ) -> LocalBoxFuture<'static, ExecuteResult> {
+    let text = context.args.join(" ");
+    let list = match parse(&text) {
+      Ok(list) => list,
+      Err(err) => {
+        let err = match err.downcast::<ParseError>() {
+          Ok(err) => err.with_filename("eval").into(),
+          Err(err) => err,
+        };
+        let _ = context.stderr.write_line(&format!("{err:?}"));
+        return Box::pin(futures::future::ready(
+          ExecuteResult::from_exit_code(2),
+        ));
+      }
+    };
+    // unlike a function, the command shares the token of the caller, so
+    // that a failure with errexit stops the caller too. The depth is
+    // restored with the copy of the state once it finishes
+    let mut state = context.state;
+    if !state.enter_eval() {
+      let _ = context.stderr.write_line(&format!(
+        "eval: maximum nesting level exceeded ({})",
+        state.max_eval_depth()
+      ));
+      return Box::pin(futures::future::ready(ExecuteResult::from_exit_code(
+        1,
+      )));
+    }
+    async move {
+      // the changes are returned to the caller, so variables, aliases and
+      // functions defined by the command persist
+      execute_sequential_list(
+        list,
+        state,
+        context.stdin,
+        context.stdout,
+        context.stderr,
+        AsyncCommandBehavior::Yield,
+      )
+      .await
+    }
+    .boxed_local()
+  }
+}
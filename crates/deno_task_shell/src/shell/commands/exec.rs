@@ -0,0 +1,173 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use futures::future::LocalBoxFuture;
+
+use crate::shell::types::ExecuteResult;
+use crate::shell::types::ShellPipeReader;
+use crate::shell::types::ShellPipeWriter;
+
+use super::ShellCommand;
+use super::ShellCommandContext;
+
+pub struct ExecCommand;
+
+impl ShellCommand for ExecCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Replace the shell with a command, or redirect the shell itself")
+  }
+
+  fn execute(
+    &self,
+    mut context: ShellCommandContext,
+  ) -> LocalBoxFuture<'static, ExecuteResult> {
+    let exit_code = if context.args.is_empty() {
+      match redirect_shell(&context.stdin, &context.stdout, &context.stderr) {
+        Ok(()) => 0,
+        Err(err) => {
+          let _ = context.stderr.write_line(&format!("exec: {err}"));
+          1
+        }
+      }
+    } else {
+      exec(&mut context)
+    };
+    Box::pin(futures::future::ready(ExecuteResult::from_exit_code(
+      exit_code,
+    )))
+  }
+}
+
+/// Replaces the shell process with the command in the arguments. Only
+/// returns if that fails, with the exit code to use.
+fn exec(context: &mut ShellCommandContext) -> i32 {
+  let name = context.args.remove(0);
+  let command_path = match context.state.resolve_command_path(&name) {
+    Ok(command_path) => command_path,
+    Err(err) => {
+      let _ = context.stderr.write_line(&format!("exec: {err}"));
+      return err.exit_code();
+    }
+  };
+  let mut command = std::process::Command::new(&command_path);
+  command
+    .current_dir(context.state.cwd())
+    .args(&context.args)
+    .env_clear()
+    .envs(context.state.env_vars())
+    .stdin(context.stdin.clone().into_stdio())
+    .stdout(context.stdout.clone().into_stdio())
+    .stderr(context.stderr.clone().into_stdio());
+
+  replace_process(command, &name, context)
+}
+
+#[cfg(unix)]
+fn replace_process(
+  mut command: std::process::Command,
+  name: &str,
+  context: &mut ShellCommandContext,
+) -> i32 {
+  use std::os::unix::process::CommandExt;
+
+  command.arg0(name);
+  // an interactive shell ignores the job control signals, which the
+  // command would inherit
+  // SAFETY: only async-signal-safe functions are called before the exec
+  unsafe {
+    command.pre_exec(|| {
+      libc::signal(libc::SIGTSTP, libc::SIG_DFL);
+      libc::signal(libc::SIGTTIN, libc::SIG_DFL);
+      libc::signal(libc::SIGTTOU, libc::SIG_DFL);
+      Ok(())
+    });
+  }
+  let err = command.exec();
+  if context.state.shell_option(crate::ShellOptions::Monitor) {
+    crate::shell::job_control::init_shell();
+  }
+  let _ = context.stderr.write_line(&format!("exec: {name}: {err}"));
+  126
+}
+
+/// Windows can't replace a process, so the command runs as a child and
+/// the shell exits with its exit code.
+#[cfg(not(unix))]
+fn replace_process(
+  mut command: std::process::Command,
+  name: &str,
+  context: &mut ShellCommandContext,
+) -> i32 {
+  match command.status() {
+    Ok(status) => std::process::exit(status.code().unwrap_or(1)),
+    Err(err) => {
+      let _ = context.stderr.write_line(&format!("exec: {name}: {err}"));
+      126
+    }
+  }
+}
+
+/// Makes the redirects of an `exec` without a command permanent, by
+/// pointing the shell's own stdin, stdout and stderr at their targets.
+#[cfg(unix)]
+fn redirect_shell(
+  stdin: &ShellPipeReader,
+  stdout: &ShellPipeWriter,
+  stderr: &ShellPipeWriter,
+) -> std::io::Result<()> {
+  use std::io::Write;
+  use std::os::fd::AsRawFd;
+
+  let dev_null = std::fs::OpenOptions::new().write(true).open("/dev/null")?;
+  // without a redirect these are the shell's own streams and aren't
+  // touched, a pipe means that `exec` is part of a pipeline
+  let target = |writer: &ShellPipeWriter, fd| match writer {
+    ShellPipeWriter::StdFile(file) => Some(file.as_raw_fd()),
+    ShellPipeWriter::Null => Some(dev_null.as_raw_fd()),
+    ShellPipeWriter::Stdout if fd == libc::STDERR_FILENO => {
+      Some(libc::STDOUT_FILENO)
+    }
+    ShellPipeWriter::Stderr if fd == libc::STDOUT_FILENO => {
+      Some(libc::STDERR_FILENO)
+    }
+    _ => None,
+  };
+  let mut redirects = Vec::new();
+  if let ShellPipeReader::StdFile(file) = stdin {
+    redirects.push((file.as_raw_fd(), libc::STDIN_FILENO));
+  }
+  for (writer, fd) in
+    [(stdout, libc::STDOUT_FILENO), (stderr, libc::STDERR_FILENO)]
+  {
+    if let Some(source) = target(writer, fd) {
+      redirects.push((source, fd));
+    }
+  }
+  // what was written so far still goes to the old target
+  std::io::stdout().flush()?;
+  std::io::stderr().flush()?;
+  for (source, fd) in redirects {
+    // SAFETY: both are open file descriptors
+    if unsafe { libc::dup2(source, fd) } == -1 {
+      return Err(std::io::Error::last_os_error());
+    }
+  }
+  Ok(())
+}
+
+#[cfg(not(unix))]
+fn redirect_shell(
+  stdin: &ShellPipeReader,
+  stdout: &ShellPipeWriter,
+  stderr: &ShellPipeWriter,
+) -> std::io::Result<()> {
+  let redirected = matches!(stdin, ShellPipeReader::StdFile(_))
+    || !matches!(stdout, ShellPipeWriter::Stdout | ShellPipeWriter::OsPipe(_))
+    || !matches!(stderr, ShellPipeWriter::Stderr | ShellPipeWriter::OsPipe(_));
+  if redirected {
+    return Err(std::io::Error::new(
+      std::io::ErrorKind::Unsupported,
+      "redirecting the shell itself is not supported on Windows",
+    ));
+  }
+  Ok(())
+}
@@ -1,13 +1,54 @@
 // Copyright 2018-2024 the Deno authors. MIT license.
 
 use std::path::PathBuf;
+#[cfg(windows)]
+use std::sync::atomic::{AtomicUsize, Ordering};
 
+#[cfg(unix)]
+use crate::shell::job_control;
 use crate::ExecuteResult;
 use crate::FutureExecuteResult;
 use crate::ShellCommand;
 use crate::ShellCommandContext;
+#[cfg(unix)]
+use crate::ShellOptions;
 use futures::FutureExt;
 
+/// The number of external commands the shell waits for in the foreground.
+#[cfg(windows)]
+static FOREGROUND_PROCESSES: AtomicUsize = AtomicUsize::new(0);
+
+/// The exit code of a process that was ended by Ctrl-C on Windows.
+#[cfg(windows)]
+const STATUS_CONTROL_C_EXIT: i32 = 0xC000013Au32 as i32;
+
+/// Whether the shell waits for an external command. The console sends
+/// Ctrl-C to that command as well, so the shell should leave it to the
+/// command instead of cancelling it.
+#[cfg(windows)]
+pub fn has_foreground_process() -> bool {
+  FOREGROUND_PROCESSES.load(Ordering::Relaxed) > 0
+}
+
+/// Counts a foreground command for [`has_foreground_process`] while alive.
+#[cfg(windows)]
+struct ForegroundProcess;
+
+#[cfg(windows)]
+impl ForegroundProcess {
+  fn enter() -> Self {
+    FOREGROUND_PROCESSES.fetch_add(1, Ordering::Relaxed);
+    Self
+  }
+}
+
+#[cfg(windows)]
+impl Drop for ForegroundProcess {
+  fn drop(&mut self) {
+    FOREGROUND_PROCESSES.fetch_sub(1, Ordering::Relaxed);
+  }
+}
+
 /// Command that resolves the command name and
 /// executes it in a separate process.
 pub struct ExecutableCommand {
@@ -30,16 +71,46 @@ impl ShellCommand for ExecutableCommand {
     let command_name = self.command_path.clone();
     async move {
       let mut stderr = context.stderr;
-      let mut sub_command = tokio::process::Command::new(&command_name);
-      let child = sub_command
+      // commands attached to the terminal run in the foreground, so they
+      // get Ctrl-C and Ctrl-Z and can be resumed
+      #[cfg(unix)]
+      let job_control = context.state.shell_option(ShellOptions::Monitor)
+        && !context.state.in_background()
+        && (context.stdin.is_terminal() || context.stdout.is_terminal());
+      #[cfg(unix)]
+      let command_line = std::iter::once(display_name.as_str())
+        .chain(context.args.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(" ");
+      let mut sub_command = std::process::Command::new(&command_name);
+      sub_command
         .current_dir(context.state.cwd())
         .args(context.args)
         .env_clear()
         .envs(context.state.env_vars())
         .stdout(context.stdout.into_stdio())
         .stdin(context.stdin.into_stdio())
-        .stderr(stderr.clone().into_stdio())
-        .spawn();
+        .stderr(stderr.clone().into_stdio());
+      #[cfg(unix)]
+      if job_control {
+        return job_control::run_foreground(
+          sub_command,
+          command_line,
+          &context.state,
+          stderr,
+        )
+        .await;
+      }
+      // like in bash, the processes of background jobs don't get the
+      // signals of the terminal, e.g. Ctrl-C or the SIGHUP when it closes
+      #[cfg(unix)]
+      if context.state.shell_option(ShellOptions::Monitor)
+        && context.state.in_background()
+      {
+        std::os::unix::process::CommandExt::process_group(&mut sub_command, 0);
+      }
+      let mut sub_command = tokio::process::Command::from(sub_command);
+      let child = sub_command.spawn();
 
       let mut child = match child {
         Ok(child) => child,
@@ -54,14 +125,38 @@ impl ShellCommand for ExecutableCommand {
 
       // avoid deadlock since this is holding onto the pipes
       drop(sub_command);
+      if let Some(pid) = child.id() {
+        context.state.record_background_pid(pid);
+      }
+      #[cfg(windows)]
+      let _foreground =
+        (!context.state.in_background()).then(ForegroundProcess::enter);
+      // the handle of the child is closed once it is waited for, a copy of
+      // it is kept to read the times of the child after it exited
+      #[cfg(windows)]
+      let times_handle = child.raw_handle().and_then(|handle| {
+        // SAFETY: the handle stays open until the child is waited for
+        let handle =
+          unsafe { std::os::windows::io::BorrowedHandle::borrow_raw(handle) };
+        handle.try_clone_to_owned().ok()
+      });
 
       tokio::select! {
         result = child.wait() => match result {
-          Ok(status) => ExecuteResult::Continue(
-            status.code().unwrap_or(1),
-            Vec::new(),
-            Vec::new(),
-          ),
+          // the command got Ctrl-C, which stops the rest of the commands as
+          // if the shell got it
+          #[cfg(windows)]
+          Ok(status) if status.code() == Some(STATUS_CONTROL_C_EXIT) => {
+            ExecuteResult::for_cancellation()
+          }
+          Ok(status) => {
+            #[cfg(windows)]
+            if let Some(handle) = &times_handle {
+              let (user, system) = process_times(handle);
+              context.state.add_children_times(user, system);
+            }
+            ExecuteResult::Continue(exit_code(status), Vec::new(), Vec::new())
+          }
           Err(err) => {
             let _ = stderr.write_line(&format!("{}", err));
             ExecuteResult::Continue(1, Vec::new(), Vec::new())
@@ -76,3 +171,49 @@ impl ShellCommand for ExecutableCommand {
     .boxed_local()
   }
 }
+
+/// The user and system CPU time used by the process of `handle`.
+#[cfg(windows)]
+fn process_times(
+  handle: &std::os::windows::io::OwnedHandle,
+) -> (std::time::Duration, std::time::Duration) {
+  use std::os::windows::io::AsRawHandle;
+  use windows_sys::Win32::Foundation::FILETIME;
+  use windows_sys::Win32::System::Threading::GetProcessTimes;
+
+  fn to_duration(time: FILETIME) -> std::time::Duration {
+    // FILETIME counts in 100 nanosecond intervals
+    let ticks =
+      ((time.dwHighDateTime as u64) << 32) | time.dwLowDateTime as u64;
+    std::time::Duration::from_nanos(ticks * 100)
+  }
+
+  let empty = FILETIME {
+    dwLowDateTime: 0,
+    dwHighDateTime: 0,
+  };
+  let (mut creation, mut exit, mut kernel, mut user) =
+    (empty, empty, empty, empty);
+  // SAFETY: the handle is open and all pointers are valid, the times stay
+  // zero if the call fails
+  unsafe {
+    GetProcessTimes(
+      handle.as_raw_handle(),
+      &mut creation,
+      &mut exit,
+      &mut kernel,
+      &mut user,
+    );
+  }
+  (to_duration(user), to_duration(kernel))
+}
+
+/// The exit code of a process, like in bash it is 128 plus the signal for a
+/// process that was killed by one.
+fn exit_code(status: std::process::ExitStatus) -> i32 {
+  #[cfg(unix)]
+  if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+    return 128 + signal;
+  }
+  status.code().unwrap_or(1)
+}
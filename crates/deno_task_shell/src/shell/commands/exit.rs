@@ -14,6 +14,10 @@ use super::ShellCommandContext;
 pub struct ExitCommand;
 
 impl ShellCommand for ExitCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Exit the shell")
+  }
+
   fn execute(
     &self,
     mut context: ShellCommandContext,
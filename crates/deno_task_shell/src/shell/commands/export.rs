@@ -11,23 +11,35 @@ use super::ShellCommandContext;
 pub struct ExportCommand;
 
 impl ShellCommand for ExportCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Set environment variables")
+  }
+
   fn execute(
     &self,
-    context: ShellCommandContext,
+    mut context: ShellCommandContext,
   ) -> LocalBoxFuture<'static, ExecuteResult> {
+    let mut exit_code = 0;
     let mut changes = Vec::new();
     for arg in context.args {
       // ignore if it doesn't contain an equals
       if let Some(equals_index) = arg.find('=') {
         let arg_name = &arg[..equals_index];
         let arg_value = &arg[equals_index + 1..];
+        if context.state.var_attributes(arg_name).readonly {
+          let _ = context
+            .stderr
+            .write_line(&format!("export: {arg_name}: readonly variable"));
+          exit_code = 1;
+          continue;
+        }
         changes.push(EnvChange::SetEnvVar(
           arg_name.to_string(),
           arg_value.to_string(),
         ));
       }
     }
-    let result = ExecuteResult::Continue(0, changes, Vec::new());
+    let result = ExecuteResult::Continue(exit_code, changes, Vec::new());
     Box::pin(futures::future::ready(result))
   }
 }
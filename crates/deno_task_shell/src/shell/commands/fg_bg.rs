@@ -0,0 +1,224 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use futures::future::LocalBoxFuture;
+use miette::bail;
+use miette::Result;
+
+use crate::shell::jobs::Job;
+use crate::shell::jobs::JobTable;
+use crate::shell::types::EnvChange;
+use crate::shell::types::ExecuteResult;
+
+use super::ShellCommand;
+use super::ShellCommandContext;
+
+pub struct FgCommand;
+
+impl ShellCommand for FgCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Move a job to the foreground")
+  }
+
+  fn execute(
+    &self,
+    mut context: ShellCommandContext,
+  ) -> LocalBoxFuture<'static, ExecuteResult> {
+    Box::pin(async move {
+      let job = match resolve_job(context.state.jobs(), &context.args) {
+        Ok(job) => job.clone(),
+        Err(err) => {
+          let _ = context.stderr.write_line(&format!("fg: {err}"));
+          return ExecuteResult::from_exit_code(1);
+        }
+      };
+      // a job started with `&` isn't in its own process group, so it can
+      // only be waited for
+      if let Some(task) = &job.task {
+        let _ = context.stdout.write_line(&job.command);
+        return tokio::select! {
+          code = task.wait() => ExecuteResult::Continue(
+            code,
+            vec![EnvChange::RemoveJob(job.id)],
+            Vec::new(),
+          ),
+          _ = context.state.token().cancelled() => {
+            ExecuteResult::for_cancellation()
+          }
+        };
+      }
+      execute_fg(job, context).await
+    })
+  }
+}
+
+#[cfg(unix)]
+async fn execute_fg(
+  job: Job,
+  mut context: ShellCommandContext,
+) -> ExecuteResult {
+  use crate::shell::job_control;
+  use crate::shell::jobs::JobStatus;
+
+  let Some(pid) = job.pid else {
+    let _ = context
+      .stderr
+      .write_line(&format!("fg: job {} has no process", job.id));
+    return ExecuteResult::from_exit_code(1);
+  };
+  let _ = context.stdout.write_line(&job.command);
+  job_control::give_terminal(pid);
+  if let Err(err) = job_control::continue_job(pid) {
+    job_control::reclaim_terminal();
+    let _ = context.stderr.write_line(&format!("fg: {err}"));
+    return ExecuteResult::from_exit_code(1);
+  }
+  match job_control::wait_foreground(pid, &context.state).await {
+    Ok(job_control::WaitStatus::Exited(code)) => ExecuteResult::Continue(
+      code,
+      vec![EnvChange::RemoveJob(job.id)],
+      Vec::new(),
+    ),
+    Ok(job_control::WaitStatus::Stopped) => {
+      let job = Job {
+        status: JobStatus::Stopped,
+        ..job
+      };
+      let _ = context
+        .stderr
+        .write_line(&format!("\n{}", job.describe('+')));
+      // re-adding the job makes it the current one again
+      ExecuteResult::Continue(
+        job_control::STOPPED_EXIT_CODE,
+        vec![EnvChange::AddJob(job)],
+        Vec::new(),
+      )
+    }
+    Err(err) => {
+      let _ = context.stderr.write_line(&format!("fg: {err}"));
+      ExecuteResult::from_exit_code(1)
+    }
+  }
+}
+
+#[cfg(not(unix))]
+async fn execute_fg(
+  _job: Job,
+  mut context: ShellCommandContext,
+) -> ExecuteResult {
+  let _ = context
+    .stderr
+    .write_line("fg: job control is not supported on this platform");
+  ExecuteResult::from_exit_code(1)
+}
+
+pub struct BgCommand;
+
+impl ShellCommand for BgCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Continue a stopped job in the background")
+  }
+
+  fn execute(
+    &self,
+    mut context: ShellCommandContext,
+  ) -> LocalBoxFuture<'static, ExecuteResult> {
+    let result = match resolve_job(context.state.jobs(), &context.args) {
+      Ok(job) => {
+        let marker = context.state.jobs().marker(job.id);
+        execute_bg(job.clone(), marker, context)
+      }
+      Err(err) => {
+        let _ = context.stderr.write_line(&format!("bg: {err}"));
+        ExecuteResult::from_exit_code(1)
+      }
+    };
+    Box::pin(futures::future::ready(result))
+  }
+}
+
+#[cfg(unix)]
+fn execute_bg(
+  job: Job,
+  marker: char,
+  mut context: ShellCommandContext,
+) -> ExecuteResult {
+  use crate::shell::job_control;
+  use crate::shell::jobs::JobStatus;
+
+  if job.status == JobStatus::Running {
+    let _ = context
+      .stderr
+      .write_line(&format!("bg: job {} already in background", job.id));
+    return ExecuteResult::from_exit_code(0);
+  }
+  let Some(pid) = job.pid else {
+    let _ = context
+      .stderr
+      .write_line(&format!("bg: job {} has no process", job.id));
+    return ExecuteResult::from_exit_code(1);
+  };
+  if let Err(err) = job_control::continue_job(pid) {
+    let _ = context.stderr.write_line(&format!("bg: {err}"));
+    return ExecuteResult::from_exit_code(1);
+  }
+  let _ = context
+    .stdout
+    .write_line(&format!("[{}]{marker} {} &", job.id, job.command));
+  ExecuteResult::Continue(
+    0,
+    vec![EnvChange::SetJobStatus(job.id, JobStatus::Running)],
+    Vec::new(),
+  )
+}
+
+#[cfg(not(unix))]
+fn execute_bg(
+  _job: Job,
+  _marker: char,
+  mut context: ShellCommandContext,
+) -> ExecuteResult {
+  let _ = context
+    .stderr
+    .write_line("bg: job control is not supported on this platform");
+  ExecuteResult::from_exit_code(1)
+}
+
+/// Resolves the job spec given to `fg` or `bg`, the current job by default.
+fn resolve_job<'a>(jobs: &'a JobTable, args: &[String]) -> Result<&'a Job> {
+  match args {
+    [] => match jobs.current() {
+      Some(job) => Ok(job),
+      None => bail!("current: no such job"),
+    },
+    [spec] => match jobs.resolve(spec) {
+      Some(job) => Ok(job),
+      None => bail!("{spec}: no such job"),
+    },
+    _ => bail!("too many arguments"),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn resolves_jobs() {
+    let mut jobs = JobTable::default();
+    assert_eq!(
+      resolve_job(&jobs, &[]).err().unwrap().to_string(),
+      "current: no such job"
+    );
+    jobs.add(Some(10), "sleep 100".to_string());
+    jobs.add(Some(11), "vim".to_string());
+    assert_eq!(resolve_job(&jobs, &[]).unwrap().id, 2);
+    assert_eq!(resolve_job(&jobs, &["%1".to_string()]).unwrap().id, 1);
+    assert_eq!(
+      resolve_job(&jobs, &["%3".to_string()])
+        .err()
+        .unwrap()
+        .to_string(),
+      "%3: no such job"
+    );
+  }
+}
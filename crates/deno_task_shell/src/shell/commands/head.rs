@@ -20,6 +20,10 @@ use super::args::ArgKind;
 pub struct HeadCommand;
 
 impl ShellCommand for HeadCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Print the first lines of files")
+  }
+
   fn execute(
     &self,
     context: ShellCommandContext,
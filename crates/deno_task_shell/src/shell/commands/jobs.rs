@@ -0,0 +1,211 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use futures::future::LocalBoxFuture;
+use miette::Result;
+
+use crate::shell::jobs::JobStatus;
+use crate::shell::jobs::JobTable;
+use crate::shell::types::EnvChange;
+use crate::shell::types::ExecuteResult;
+
+use super::args::parse_arg_kinds;
+use super::args::ArgKind;
+use super::ShellCommand;
+use super::ShellCommandContext;
+
+pub struct JobsCommand;
+
+impl ShellCommand for JobsCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("List the jobs of the shell")
+  }
+
+  fn execute(
+    &self,
+    mut context: ShellCommandContext,
+  ) -> LocalBoxFuture<'static, ExecuteResult> {
+    let result = match parse_args(&context.args) {
+      Ok(flags) => {
+        let (jobs, changes) = update_statuses(context.state.jobs());
+        let (output, errors) = list_jobs(&jobs, &flags);
+        let _ = context.stdout.write_all(output.as_bytes());
+        for error in &errors {
+          let _ = context.stderr.write_line(&format!("jobs: {error}"));
+        }
+        let exit_code = if errors.is_empty() { 0 } else { 1 };
+        ExecuteResult::Continue(exit_code, changes, Vec::new())
+      }
+      Err(err) => {
+        let _ = context.stderr.write_line(&format!("jobs: {err}"));
+        ExecuteResult::Continue(2, Vec::new(), Vec::new())
+      }
+    };
+    Box::pin(futures::future::ready(result))
+  }
+}
+
+/// The jobs with their current status, and the changes that record it.
+/// Like in bash, the jobs that are done are listed once more and removed.
+#[cfg(unix)]
+fn update_statuses(jobs: &JobTable) -> (JobTable, Vec<EnvChange>) {
+  let mut updated = jobs.clone();
+  let done = updated.update_statuses();
+  let mut listed = jobs.clone();
+  let mut changes = Vec::new();
+  for job in updated.iter() {
+    if jobs.get(job.id).is_some_and(|old| old.status != job.status) {
+      changes.push(EnvChange::SetJobStatus(job.id, job.status));
+    }
+  }
+  changes.extend(done.iter().map(|job| EnvChange::RemoveJob(job.id)));
+  for job in updated.iter().chain(&done) {
+    if let Some(listed) = listed.get_mut(job.id) {
+      listed.status = job.status;
+    }
+  }
+  (listed, changes)
+}
+
+#[cfg(not(unix))]
+fn update_statuses(jobs: &JobTable) -> (JobTable, Vec<EnvChange>) {
+  (jobs.clone(), Vec::new())
+}
+
+/// Returns the listing of the jobs and an error for each job spec that
+/// didn't match a job.
+fn list_jobs(jobs: &JobTable, flags: &JobsFlags) -> (String, Vec<String>) {
+  let mut selected = Vec::new();
+  let mut errors = Vec::new();
+  if flags.job_specs.is_empty() {
+    selected.extend(jobs.iter());
+  } else {
+    for spec in &flags.job_specs {
+      match jobs.resolve(spec) {
+        Some(job) => selected.push(job),
+        None => errors.push(format!("{spec}: no such job")),
+      }
+    }
+  }
+
+  let mut output = String::new();
+  for job in selected {
+    if (flags.running && job.status != JobStatus::Running)
+      || (flags.stopped && job.status != JobStatus::Stopped)
+    {
+      continue;
+    }
+    let marker = jobs.marker(job.id);
+    let line = if flags.pids {
+      match job.pid {
+        Some(pid) => pid.to_string(),
+        None => continue,
+      }
+    } else if flags.long {
+      job.describe_long(marker)
+    } else {
+      job.describe(marker)
+    };
+    output.push_str(&line);
+    output.push('\n');
+  }
+  (output, errors)
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct JobsFlags {
+  /// `-l`: include the process ids
+  long: bool,
+  /// `-p`: only the process ids
+  pids: bool,
+  /// `-r`: only running jobs
+  running: bool,
+  /// `-s`: only stopped jobs
+  stopped: bool,
+  job_specs: Vec<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<JobsFlags> {
+  let mut flags = JobsFlags::default();
+  for arg in parse_arg_kinds(args) {
+    match arg {
+      ArgKind::ShortFlag('l') => flags.long = true,
+      ArgKind::ShortFlag('p') => flags.pids = true,
+      ArgKind::ShortFlag('r') => flags.running = true,
+      ArgKind::ShortFlag('s') => flags.stopped = true,
+      ArgKind::Arg(spec) => flags.job_specs.push(spec.to_string()),
+      _ => arg.bail_unsupported()?,
+    }
+  }
+  Ok(flags)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn flags(args: &[&str]) -> JobsFlags {
+    parse_args(&args.iter().map(|a| a.to_string()).collect::<Vec<_>>()).unwrap()
+  }
+
+  fn job_table() -> JobTable {
+    let mut jobs = JobTable::default();
+    jobs.add(Some(10), "sleep 100".to_string());
+    let stopped = jobs.add(Some(11), "vim".to_string());
+    jobs.get_mut(stopped).unwrap().status = JobStatus::Stopped;
+    jobs.add(None, "cargo build".to_string());
+    jobs
+  }
+
+  #[test]
+  fn lists_jobs() {
+    let jobs = job_table();
+    assert_eq!(
+      list_jobs(&jobs, &flags(&[])),
+      (
+        "[1]   Running                 sleep 100 &\n\
+         [2]-  Stopped                 vim\n\
+         [3]+  Running                 cargo build &\n"
+          .to_string(),
+        vec![]
+      )
+    );
+    assert_eq!(
+      list_jobs(&jobs, &flags(&["-l", "%+", "%-"])),
+      (
+        "[3]+       Running                 cargo build &\n\
+         [2]-    11 Stopped                 vim\n"
+          .to_string(),
+        vec![]
+      )
+    );
+    assert_eq!(
+      list_jobs(&jobs, &flags(&["-p"])),
+      ("10\n11\n".to_string(), vec![])
+    );
+    assert_eq!(
+      list_jobs(&jobs, &flags(&["-s", "%sleep", "%?vi", "%4"])),
+      (
+        "[2]-  Stopped                 vim\n".to_string(),
+        vec!["%4: no such job".to_string()]
+      )
+    );
+    assert_eq!(list_jobs(&jobs, &flags(&["-r"])).0.lines().count(), 2);
+  }
+
+  #[test]
+  fn parses_args() {
+    assert_eq!(
+      flags(&["-lr", "%1"]),
+      JobsFlags {
+        long: true,
+        running: true,
+        job_specs: vec!["%1".to_string()],
+        ..Default::default()
+      }
+    );
+    assert_eq!(
+      parse_args(&["-x".to_string()]).err().unwrap().to_string(),
+      "unsupported flag: -x"
+    );
+  }
+}
@@ -0,0 +1,337 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use futures::future::LocalBoxFuture;
+use miette::bail;
+use miette::miette;
+use miette::Result;
+
+use crate::shell::types::ExecuteResult;
+
+use super::ShellCommand;
+use super::ShellCommandContext;
+
+pub struct KillCommand;
+
+impl ShellCommand for KillCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Send a signal to processes or jobs")
+  }
+
+  fn execute(
+    &self,
+    mut context: ShellCommandContext,
+  ) -> LocalBoxFuture<'static, ExecuteResult> {
+    let exit_code = match parse_args(&context.args) {
+      Ok(Kill::List(args)) => match list_signals(&args) {
+        Ok(output) => {
+          let _ = context.stdout.write_line(&output);
+          0
+        }
+        Err(err) => {
+          let _ = context.stderr.write_line(&format!("kill: {err}"));
+          1
+        }
+      },
+      Ok(Kill::Send { signal, targets }) => {
+        let errors = send_signal(&context, signal, &targets);
+        for error in &errors {
+          let _ = context.stderr.write_line(&format!("kill: {error}"));
+        }
+        if errors.is_empty() {
+          0
+        } else {
+          1
+        }
+      }
+      Err(err) => {
+        let _ = context.stderr.write_line(&format!("kill: {err}"));
+        2
+      }
+    };
+    Box::pin(futures::future::ready(ExecuteResult::from_exit_code(
+      exit_code,
+    )))
+  }
+}
+
+#[derive(Debug, PartialEq)]
+enum Kill {
+  /// `kill -l`, with the signals or exit codes to name
+  List(Vec<String>),
+  /// Sends `signal` to the pids or job specs in `targets`
+  Send { signal: i32, targets: Vec<String> },
+}
+
+/// Parses `kill [-s name | -n number | -name | -number] target...` and
+/// `kill -l [signal...]`. The signal comes before the targets, a target
+/// starting with `-` (a process group) can follow `--`.
+fn parse_args(args: &[String]) -> Result<Kill> {
+  let mut signal = None;
+  let mut args = args.iter();
+  let mut targets = Vec::new();
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "-l" | "-L" => return Ok(Kill::List(args.cloned().collect())),
+      "-s" | "-n" => {
+        let name = args
+          .next()
+          .ok_or_else(|| miette!("{arg}: option requires an argument"))?;
+        signal = Some(parse_signal(name)?);
+      }
+      "--" => {
+        targets.extend(args.cloned());
+        break;
+      }
+      _ if signal.is_none() && arg.len() > 1 && arg.starts_with('-') => {
+        signal = Some(parse_signal(&arg[1..])?);
+      }
+      _ => {
+        targets.push(arg.clone());
+        targets.extend(args.cloned());
+        break;
+      }
+    }
+  }
+  if targets.is_empty() {
+    bail!("usage: kill [-s sigspec | -n signum | -sigspec] pid | jobspec ... or kill -l [sigspec]");
+  }
+  Ok(Kill::Send {
+    signal: signal.unwrap_or(SIGTERM),
+    targets,
+  })
+}
+
+#[cfg(unix)]
+const SIGTERM: i32 = libc::SIGTERM;
+#[cfg(not(unix))]
+const SIGTERM: i32 = 15;
+
+/// The signals that can be sent by name, in the order of their numbers.
+#[cfg(unix)]
+const SIGNALS: &[(&str, i32)] = &[
+  ("HUP", libc::SIGHUP),
+  ("INT", libc::SIGINT),
+  ("QUIT", libc::SIGQUIT),
+  ("ILL", libc::SIGILL),
+  ("TRAP", libc::SIGTRAP),
+  ("ABRT", libc::SIGABRT),
+  ("BUS", libc::SIGBUS),
+  ("FPE", libc::SIGFPE),
+  ("KILL", libc::SIGKILL),
+  ("USR1", libc::SIGUSR1),
+  ("SEGV", libc::SIGSEGV),
+  ("USR2", libc::SIGUSR2),
+  ("PIPE", libc::SIGPIPE),
+  ("ALRM", libc::SIGALRM),
+  ("TERM", libc::SIGTERM),
+  ("CHLD", libc::SIGCHLD),
+  ("CONT", libc::SIGCONT),
+  ("STOP", libc::SIGSTOP),
+  ("TSTP", libc::SIGTSTP),
+  ("TTIN", libc::SIGTTIN),
+  ("TTOU", libc::SIGTTOU),
+  ("URG", libc::SIGURG),
+  ("XCPU", libc::SIGXCPU),
+  ("XFSZ", libc::SIGXFSZ),
+  ("VTALRM", libc::SIGVTALRM),
+  ("PROF", libc::SIGPROF),
+  ("WINCH", libc::SIGWINCH),
+  ("IO", libc::SIGIO),
+  ("SYS", libc::SIGSYS),
+];
+
+/// Windows has no signals, only the names of the ones that terminate a
+/// process are known, so that they can be listed.
+#[cfg(not(unix))]
+const SIGNALS: &[(&str, i32)] = &[
+  ("HUP", 1),
+  ("INT", 2),
+  ("QUIT", 3),
+  ("KILL", 9),
+  ("TERM", 15),
+];
+
+/// Parses a signal like `TERM`, `SIGTERM`, `term` or `15`. `0` only checks
+/// whether the process exists.
+fn parse_signal(text: &str) -> Result<i32> {
+  if let Ok(number) = text.parse::<i32>() {
+    if number == 0 || SIGNALS.iter().any(|(_, signal)| *signal == number) {
+      return Ok(number);
+    }
+  }
+  let name = text.to_ascii_uppercase();
+  let name = name.strip_prefix("SIG").unwrap_or(&name);
+  SIGNALS
+    .iter()
+    .find(|(signal_name, _)| *signal_name == name)
+    .map(|(_, signal)| *signal)
+    .ok_or_else(|| miette!("{text}: invalid signal specification"))
+}
+
+/// `kill -l` lists the names of the signals. With arguments, it names the
+/// signal of each number, or of each exit code of a command killed by a
+/// signal, and gives the number of each name.
+fn list_signals(args: &[String]) -> Result<String> {
+  if args.is_empty() {
+    let names = SIGNALS.iter().map(|(name, _)| *name);
+    return Ok(names.collect::<Vec<_>>().join(" "));
+  }
+  let mut lines = Vec::new();
+  for arg in args {
+    let line = match arg.parse::<i32>() {
+      Ok(number) => {
+        let signal = if number > 128 { number - 128 } else { number };
+        SIGNALS
+          .iter()
+          .find(|(_, s)| *s == signal)
+          .map(|(name, _)| name.to_string())
+          .ok_or_else(|| miette!("{arg}: invalid signal specification"))?
+      }
+      Err(_) => parse_signal(arg)?.to_string(),
+    };
+    lines.push(line);
+  }
+  Ok(lines.join("\n"))
+}
+
+/// Sends `signal` to each target and returns an error for each one that
+/// failed. A job spec sends it to the process group of a stopped job, and
+/// to the process of a job started with `&`.
+#[cfg(unix)]
+fn send_signal(
+  context: &ShellCommandContext,
+  signal: i32,
+  targets: &[String],
+) -> Vec<String> {
+  use crate::shell::jobs::Job;
+  use crate::shell::jobs::JobStatus;
+
+  let mut errors = Vec::new();
+  for target in targets {
+    let (pid, stopped) = if target.starts_with('%') {
+      match context.state.jobs().resolve(target) {
+        Some(job) => match (job.pid, &job.task) {
+          // a job started with `&` isn't in its own process group
+          (Some(pid), Some(_)) => (pid as i32, false),
+          (Some(pid), None) => {
+            (-(pid as i32), job.status == JobStatus::Stopped)
+          }
+          // a job of builtins has no process, it is stopped instead
+          (None, Some(task)) => {
+            if signal != 0 {
+              task.kill(signal);
+            }
+            continue;
+          }
+          (None, None) => {
+            errors.push(format!("{target}: job has no process"));
+            continue;
+          }
+        },
+        None => {
+          errors.push(format!("{target}: no such job"));
+          continue;
+        }
+      }
+    } else {
+      match target.parse::<i32>() {
+        Ok(pid) => match context.state.jobs().find_pid(pid as u32) {
+          // `$!` of a job of builtins
+          Some(Job {
+            pid: None,
+            task: Some(task),
+            ..
+          }) => {
+            if signal != 0 {
+              task.kill(signal);
+            }
+            continue;
+          }
+          _ => (pid, false),
+        },
+        Err(_) => {
+          errors
+            .push(format!("{target}: arguments must be process or job IDs"));
+          continue;
+        }
+      }
+    };
+    // SAFETY: sending a signal has no memory safety preconditions
+    if unsafe { libc::kill(pid, signal) } == -1 {
+      let err = std::io::Error::last_os_error();
+      errors.push(format!("({}) - {err}", pid.abs()));
+      continue;
+    }
+    // like in bash, a stopped job is continued so that it gets the signal
+    // that is meant to end it
+    if stopped && matches!(signal, libc::SIGTERM | libc::SIGHUP) {
+      // SAFETY: sending a signal has no memory safety preconditions
+      unsafe {
+        libc::kill(pid, libc::SIGCONT);
+      }
+    }
+  }
+  errors
+}
+
+#[cfg(not(unix))]
+fn send_signal(
+  _context: &ShellCommandContext,
+  _signal: i32,
+  _targets: &[String],
+) -> Vec<String> {
+  vec!["signals are not supported on this platform".to_string()]
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|a| a.to_string()).collect()
+  }
+
+  #[test]
+  fn parses_args() {
+    let send = |signal, targets: &[&str]| Kill::Send {
+      signal,
+      targets: args(targets),
+    };
+    assert_eq!(parse_args(&args(&["10"])).unwrap(), send(SIGTERM, &["10"]));
+    assert_eq!(
+      parse_args(&args(&["-9", "%1", "-2"])).unwrap(),
+      send(9, &["%1", "-2"])
+    );
+    assert_eq!(
+      parse_args(&args(&["-s", "sigkill", "10"])).unwrap(),
+      send(9, &["10"])
+    );
+    assert_eq!(
+      parse_args(&args(&["-n", "2", "--", "-10"])).unwrap(),
+      send(2, &["-10"])
+    );
+    assert_eq!(
+      parse_args(&args(&["-l", "15"])).unwrap(),
+      Kill::List(args(&["15"]))
+    );
+    assert_eq!(
+      parse_args(&args(&["-FOO", "10"]))
+        .err()
+        .unwrap()
+        .to_string(),
+      "FOO: invalid signal specification"
+    );
+    assert!(parse_args(&args(&["-s", "TERM"])).is_err());
+    assert!(parse_args(&args(&["-s"])).is_err());
+  }
+
+  #[test]
+  fn lists_signals() {
+    assert!(list_signals(&[]).unwrap().starts_with("HUP INT QUIT"));
+    assert_eq!(
+      list_signals(&args(&["9", "137", "TERM", "sigint"])).unwrap(),
+      "KILL\nKILL\n15\n2"
+    );
+    assert!(list_signals(&args(&["99"])).is_err());
+  }
+}
@@ -0,0 +1,157 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use futures::future::LocalBoxFuture;
+use futures::FutureExt;
+use miette::bail;
+use miette::Result;
+
+use crate::shell::types::EnvChange;
+use crate::shell::types::ExecuteResult;
+
+use super::args::parse_arg_kinds;
+use super::args::ArgKind;
+use super::ShellCommand;
+use super::ShellCommandContext;
+
+pub struct MapfileCommand;
+
+impl ShellCommand for MapfileCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Read lines from standard input into an array")
+  }
+
+  fn execute(
+    &self,
+    mut context: ShellCommandContext,
+  ) -> LocalBoxFuture<'static, ExecuteResult> {
+    async move {
+      let flags = match parse_args(context.args) {
+        Ok(flags) => flags,
+        Err(err) => {
+          let _ = context.stderr.write_line(&format!("mapfile: {err}"));
+          return ExecuteResult::from_exit_code(2);
+        }
+      };
+      // read on a blocking thread, the writer may be part of the same pipeline
+      let text = context.stdin.pipe_to_string_handle().await.unwrap();
+      let lines = split_lines(&text, &flags).into_iter().enumerate();
+      ExecuteResult::Continue(
+        0,
+        vec![EnvChange::SetArrayVar(flags.name, lines.collect())],
+        Vec::new(),
+      )
+    }
+    .boxed_local()
+  }
+}
+
+#[derive(Debug, PartialEq)]
+struct MapfileFlags {
+  name: String,
+  strip_newlines: bool,
+  /// The maximum number of lines to read, or all when `None`
+  count: Option<usize>,
+}
+
+fn split_lines(text: &str, flags: &MapfileFlags) -> Vec<String> {
+  text
+    .split_inclusive('\n')
+    .take(flags.count.unwrap_or(usize::MAX))
+    .map(|line| {
+      if flags.strip_newlines {
+        line.strip_suffix('\n').unwrap_or(line).to_string()
+      } else {
+        line.to_string()
+      }
+    })
+    .collect()
+}
+
+fn parse_args(args: Vec<String>) -> Result<MapfileFlags> {
+  let mut name = None;
+  let mut strip_newlines = false;
+  let mut count = None;
+  let mut args = parse_arg_kinds(&args).into_iter();
+  while let Some(arg) = args.next() {
+    match arg {
+      ArgKind::ShortFlag('t') => strip_newlines = true,
+      ArgKind::ShortFlag('n') => {
+        let Some(ArgKind::Arg(value)) = args.next() else {
+          bail!("-n: option requires an argument");
+        };
+        count = match value.parse::<usize>() {
+          // like bash, a count of zero reads all lines
+          Ok(0) => None,
+          Ok(value) => Some(value),
+          Err(_) => bail!("{value}: invalid line count"),
+        };
+      }
+      ArgKind::Arg(arg) if name.is_none() => {
+        let is_valid = arg
+          .chars()
+          .next()
+          .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+          && arg.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !is_valid {
+          bail!("`{arg}': not a valid identifier");
+        }
+        name = Some(arg.to_string());
+      }
+      _ => arg.bail_unsupported()?,
+    }
+  }
+
+  Ok(MapfileFlags {
+    name: name.unwrap_or_else(|| "MAPFILE".to_string()),
+    strip_newlines,
+    count,
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn to_args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|a| a.to_string()).collect()
+  }
+
+  #[test]
+  fn parses_args() {
+    assert_eq!(
+      parse_args(vec![]).unwrap(),
+      MapfileFlags {
+        name: "MAPFILE".to_string(),
+        strip_newlines: false,
+        count: None,
+      }
+    );
+    assert_eq!(
+      parse_args(to_args(&["-t", "-n", "2", "lines"])).unwrap(),
+      MapfileFlags {
+        name: "lines".to_string(),
+        strip_newlines: true,
+        count: Some(2),
+      }
+    );
+    assert_eq!(
+      parse_args(to_args(&["-n", "x"])).err().unwrap().to_string(),
+      "x: invalid line count"
+    );
+    assert_eq!(
+      parse_args(to_args(&["1abc"])).err().unwrap().to_string(),
+      "`1abc': not a valid identifier"
+    );
+  }
+
+  #[test]
+  fn splits_lines() {
+    let mut flags = parse_args(vec![]).unwrap();
+    assert_eq!(split_lines("a\nb\n\nc", &flags), ["a\n", "b\n", "\n", "c"]);
+    flags.strip_newlines = true;
+    assert_eq!(split_lines("a\nb\n\nc", &flags), ["a", "b", "", "c"]);
+    flags.count = Some(2);
+    assert_eq!(split_lines("a\nb\n\nc", &flags), ["a", "b"]);
+    assert!(split_lines("", &flags).is_empty());
+  }
+}
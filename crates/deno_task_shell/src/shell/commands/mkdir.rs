@@ -18,6 +18,10 @@ use super::ShellCommandContext;
 pub struct MkdirCommand;
 
 impl ShellCommand for MkdirCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Create directories")
+  }
+
   fn execute(
     &self,
     context: ShellCommandContext,
@@ -1,19 +1,35 @@
 // Copyright 2018-2024 the Deno authors. MIT license.
 
 mod args;
+mod break_continue;
 mod cat;
 mod cd;
+mod command;
 mod cp_mv;
+mod declare;
+mod dir_stack;
+mod disown;
 mod echo;
+mod eval;
+mod exec;
 mod executable;
 mod exit;
 mod export;
+mod fg_bg;
 mod head;
+mod jobs;
+mod kill;
+mod mapfile;
 mod mkdir;
 mod pwd;
+mod repeat;
+mod r#return;
 mod rm;
+mod shift;
 mod sleep;
+mod r#type;
 mod unset;
+mod wait;
 mod xargs;
 
 use std::collections::HashMap;
@@ -21,6 +37,8 @@ use std::rc::Rc;
 
 use futures::future::LocalBoxFuture;
 
+#[cfg(windows)]
+pub use executable::has_foreground_process;
 pub use executable::ExecutableCommand;
 
 pub use args::parse_arg_kinds;
@@ -34,6 +52,14 @@ use super::types::ShellState;
 
 pub fn builtin_commands() -> HashMap<String, Rc<dyn ShellCommand>> {
   HashMap::from([
+    (
+      "bg".to_string(),
+      Rc::new(fg_bg::BgCommand) as Rc<dyn ShellCommand>,
+    ),
+    (
+      "break".to_string(),
+      Rc::new(break_continue::BreakCommand) as Rc<dyn ShellCommand>,
+    ),
     (
       "cat".to_string(),
       Rc::new(cat::CatCommand) as Rc<dyn ShellCommand>,
@@ -42,14 +68,42 @@ pub fn builtin_commands() -> HashMap<String, Rc<dyn ShellCommand>> {
       "cd".to_string(),
       Rc::new(cd::CdCommand) as Rc<dyn ShellCommand>,
     ),
+    (
+      "command".to_string(),
+      Rc::new(command::CommandCommand) as Rc<dyn ShellCommand>,
+    ),
+    (
+      "continue".to_string(),
+      Rc::new(break_continue::ContinueCommand) as Rc<dyn ShellCommand>,
+    ),
     (
       "cp".to_string(),
       Rc::new(cp_mv::CpCommand) as Rc<dyn ShellCommand>,
     ),
+    (
+      "declare".to_string(),
+      Rc::new(declare::DeclareCommand) as Rc<dyn ShellCommand>,
+    ),
+    (
+      "dirs".to_string(),
+      Rc::new(dir_stack::DirsCommand) as Rc<dyn ShellCommand>,
+    ),
+    (
+      "disown".to_string(),
+      Rc::new(disown::DisownCommand) as Rc<dyn ShellCommand>,
+    ),
     (
       "echo".to_string(),
       Rc::new(echo::EchoCommand) as Rc<dyn ShellCommand>,
     ),
+    (
+      "eval".to_string(),
+      Rc::new(eval::EvalCommand) as Rc<dyn ShellCommand>,
+    ),
+    (
+      "exec".to_string(),
+      Rc::new(exec::ExecCommand) as Rc<dyn ShellCommand>,
+    ),
     (
       "exit".to_string(),
       Rc::new(exit::ExitCommand) as Rc<dyn ShellCommand>,
@@ -58,10 +112,22 @@ pub fn builtin_commands() -> HashMap<String, Rc<dyn ShellCommand>> {
       "export".to_string(),
       Rc::new(export::ExportCommand) as Rc<dyn ShellCommand>,
     ),
+    (
+      "fg".to_string(),
+      Rc::new(fg_bg::FgCommand) as Rc<dyn ShellCommand>,
+    ),
     (
       "head".to_string(),
       Rc::new(head::HeadCommand) as Rc<dyn ShellCommand>,
     ),
+    (
+      "jobs".to_string(),
+      Rc::new(jobs::JobsCommand) as Rc<dyn ShellCommand>,
+    ),
+    (
+      "kill".to_string(),
+      Rc::new(kill::KillCommand) as Rc<dyn ShellCommand>,
+    ),
     (
       "mkdir".to_string(),
       Rc::new(mkdir::MkdirCommand) as Rc<dyn ShellCommand>,
@@ -70,14 +136,42 @@ pub fn builtin_commands() -> HashMap<String, Rc<dyn ShellCommand>> {
       "mv".to_string(),
       Rc::new(cp_mv::MvCommand) as Rc<dyn ShellCommand>,
     ),
+    (
+      "popd".to_string(),
+      Rc::new(dir_stack::PopdCommand) as Rc<dyn ShellCommand>,
+    ),
+    (
+      "pushd".to_string(),
+      Rc::new(dir_stack::PushdCommand) as Rc<dyn ShellCommand>,
+    ),
     (
       "pwd".to_string(),
       Rc::new(pwd::PwdCommand) as Rc<dyn ShellCommand>,
     ),
+    (
+      "repeat".to_string(),
+      Rc::new(repeat::RepeatCommand) as Rc<dyn ShellCommand>,
+    ),
+    (
+      "return".to_string(),
+      Rc::new(r#return::ReturnCommand) as Rc<dyn ShellCommand>,
+    ),
     (
       "rm".to_string(),
       Rc::new(rm::RmCommand) as Rc<dyn ShellCommand>,
     ),
+    (
+      "mapfile".to_string(),
+      Rc::new(mapfile::MapfileCommand) as Rc<dyn ShellCommand>,
+    ),
+    (
+      "readarray".to_string(),
+      Rc::new(mapfile::MapfileCommand) as Rc<dyn ShellCommand>,
+    ),
+    (
+      "shift".to_string(),
+      Rc::new(shift::ShiftCommand) as Rc<dyn ShellCommand>,
+    ),
     (
       "sleep".to_string(),
       Rc::new(sleep::SleepCommand) as Rc<dyn ShellCommand>,
@@ -86,14 +180,31 @@ pub fn builtin_commands() -> HashMap<String, Rc<dyn ShellCommand>> {
       "true".to_string(),
       Rc::new(ExitCodeCommand(0)) as Rc<dyn ShellCommand>,
     ),
+    (
+      // the no-op whose arguments are still expanded, like `: "${1:?}"`
+      ":".to_string(),
+      Rc::new(ExitCodeCommand(0)) as Rc<dyn ShellCommand>,
+    ),
     (
       "false".to_string(),
       Rc::new(ExitCodeCommand(1)) as Rc<dyn ShellCommand>,
     ),
+    (
+      "type".to_string(),
+      Rc::new(r#type::TypeCommand) as Rc<dyn ShellCommand>,
+    ),
+    (
+      "typeset".to_string(),
+      Rc::new(declare::DeclareCommand) as Rc<dyn ShellCommand>,
+    ),
     (
       "unset".to_string(),
       Rc::new(unset::UnsetCommand) as Rc<dyn ShellCommand>,
     ),
+    (
+      "wait".to_string(),
+      Rc::new(wait::WaitCommand) as Rc<dyn ShellCommand>,
+    ),
     (
       "xargs".to_string(),
       Rc::new(xargs::XargsCommand) as Rc<dyn ShellCommand>,
@@ -124,6 +235,11 @@ pub trait ShellCommand {
     &self,
     context: ShellCommandContext,
   ) -> LocalBoxFuture<'static, ExecuteResult>;
+
+  /// A short summary of the command, e.g. shown next to completions.
+  fn description(&self) -> Option<&'static str> {
+    None
+  }
 }
 
 macro_rules! execute_with_cancellation {
@@ -153,4 +269,12 @@ impl ShellCommand for ExitCodeCommand {
       self.0,
     )))
   }
+
+  fn description(&self) -> Option<&'static str> {
+    if self.0 == 0 {
+      Some("Return a successful result")
+    } else {
+      Some("Return an unsuccessful result")
+    }
+  }
 }
@@ -16,6 +16,10 @@ use super::ShellCommandContext;
 pub struct PwdCommand;
 
 impl ShellCommand for PwdCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Print the current working directory")
+  }
+
   fn execute(
     &self,
     mut context: ShellCommandContext,
@@ -36,7 +40,7 @@ impl ShellCommand for PwdCommand {
 
 fn execute_pwd(cwd: &Path, args: Vec<String>) -> Result<String> {
   let flags = parse_args(args)?;
-  let cwd = if flags.logical {
+  let cwd = if flags.physical {
     fs_util::canonicalize_path(cwd)
       .with_context(|| format!("error canonicalizing: {}", cwd.display()))?
   } else {
@@ -47,18 +51,20 @@ fn execute_pwd(cwd: &Path, args: Vec<String>) -> Result<String> {
 
 #[derive(Debug, PartialEq)]
 struct PwdFlags {
-  logical: bool,
+  /// Resolve symlinks instead of printing the path that was cd'd through.
+  physical: bool,
 }
 
 fn parse_args(args: Vec<String>) -> Result<PwdFlags> {
-  let mut logical = false;
+  let mut physical = false;
   for arg in parse_arg_kinds(&args) {
     match arg {
       ArgKind::ShortFlag('L') => {
-        logical = true;
+        // the default, but the last flag wins
+        physical = false;
       }
       ArgKind::ShortFlag('P') => {
-        // ignore, this is the default
+        physical = true;
       }
       ArgKind::Arg(_) => {
         // args are ignored by pwd
@@ -67,7 +73,7 @@ fn parse_args(args: Vec<String>) -> Result<PwdFlags> {
     }
   }
 
-  Ok(PwdFlags { logical })
+  Ok(PwdFlags { physical })
 }
 
 #[cfg(test)]
@@ -77,14 +83,18 @@ mod test {
 
   #[test]
   fn parses_args() {
-    assert_eq!(parse_args(vec![]).unwrap(), PwdFlags { logical: false });
+    assert_eq!(parse_args(vec![]).unwrap(), PwdFlags { physical: false });
     assert_eq!(
       parse_args(vec!["-P".to_string()]).unwrap(),
-      PwdFlags { logical: false }
+      PwdFlags { physical: true }
     );
     assert_eq!(
       parse_args(vec!["-L".to_string()]).unwrap(),
-      PwdFlags { logical: true }
+      PwdFlags { physical: false }
+    );
+    assert_eq!(
+      parse_args(vec!["-L".to_string(), "-P".to_string()]).unwrap(),
+      PwdFlags { physical: true }
     );
     assert!(parse_args(vec!["test".to_string()]).is_ok());
     assert_eq!(
@@ -0,0 +1,118 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use futures::future::LocalBoxFuture;
+use futures::FutureExt;
+use miette::bail;
+use miette::Result;
+
+use crate::shell::execute::execute_command_args;
+use crate::shell::types::ExecuteResult;
+
+use super::ShellCommand;
+use super::ShellCommandContext;
+
+pub struct RepeatCommand;
+
+impl ShellCommand for RepeatCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Run a command a number of times")
+  }
+
+  fn execute(
+    &self,
+    mut context: ShellCommandContext,
+  ) -> LocalBoxFuture<'static, ExecuteResult> {
+    let (count, args) = match parse_args(context.args) {
+      Ok(args) => args,
+      Err(err) => {
+        let _ = context.stderr.write_line(&format!("repeat: {err}"));
+        return Box::pin(futures::future::ready(
+          ExecuteResult::from_exit_code(2),
+        ));
+      }
+    };
+    async move {
+      let mut state = context.state;
+      let mut changes = Vec::new();
+      let mut handles = Vec::new();
+      let mut exit_code = 0;
+      for _ in 0..count {
+        let result = execute_command_args(
+          args.clone(),
+          state.clone(),
+          context.stdin.clone(),
+          context.stdout.clone(),
+          context.stderr.clone(),
+        )
+        .await;
+        match result {
+          ExecuteResult::Continue(code, env_changes, new_handles) => {
+            // later runs see the changes of the earlier ones
+            state.apply_changes(&env_changes);
+            changes.extend(env_changes);
+            handles.extend(new_handles);
+            exit_code = code;
+            if code != 0 && state.exit_on_error() {
+              break;
+            }
+          }
+          ExecuteResult::Return(code, env_changes, new_handles) => {
+            changes.extend(env_changes);
+            handles.extend(new_handles);
+            return ExecuteResult::Return(code, changes, handles);
+          }
+          ExecuteResult::LoopControl(control, env_changes, new_handles) => {
+            changes.extend(env_changes);
+            handles.extend(new_handles);
+            return ExecuteResult::LoopControl(control, changes, handles);
+          }
+          ExecuteResult::Exit(code, new_handles) => {
+            handles.extend(new_handles);
+            return ExecuteResult::Exit(code, handles);
+          }
+        }
+      }
+      ExecuteResult::Continue(exit_code, changes, handles)
+    }
+    .boxed_local()
+  }
+}
+
+/// Returns the count and the command with its arguments.
+fn parse_args(mut args: Vec<String>) -> Result<(usize, Vec<String>)> {
+  if args.is_empty() {
+    bail!("missing count");
+  }
+  let count = args.remove(0);
+  let Some(count) = count.parse::<usize>().ok().filter(|&count| count > 0)
+  else {
+    bail!("{count}: expected a positive integer");
+  };
+  if args.is_empty() {
+    bail!("missing command");
+  }
+  Ok((count, args))
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn parses_args() {
+    let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect();
+    assert_eq!(
+      parse_args(args(&["3", "echo", "-n", "hi"])).unwrap(),
+      (3, args(&["echo", "-n", "hi"]))
+    );
+    for (args, err) in [
+      (args(&[]), "missing count"),
+      (args(&["2"]), "missing command"),
+      (args(&["0", "ls"]), "0: expected a positive integer"),
+      (args(&["-1", "ls"]), "-1: expected a positive integer"),
+      (args(&["x", "ls"]), "x: expected a positive integer"),
+    ] {
+      assert_eq!(parse_args(args).err().unwrap().to_string(), err);
+    }
+  }
+}
@@ -0,0 +1,104 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use futures::future::LocalBoxFuture;
+use miette::bail;
+use miette::Result;
+
+use crate::shell::types::ExecuteResult;
+
+use super::args::parse_arg_kinds;
+use super::args::ArgKind;
+use super::ShellCommand;
+use super::ShellCommandContext;
+
+pub struct ReturnCommand;
+
+impl ShellCommand for ReturnCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Return from a function or sourced script")
+  }
+
+  fn execute(
+    &self,
+    mut context: ShellCommandContext,
+  ) -> LocalBoxFuture<'static, ExecuteResult> {
+    let result = if context.state.source_depth() == 0
+      && context.state.function_depth() == 0
+    {
+      let _ = context.stderr.write_line(
+        "return: can only `return' from a function or sourced script",
+      );
+      ExecuteResult::from_exit_code(1)
+    } else {
+      match execute_return(context.state.last_command_exit_code(), context.args)
+      {
+        Ok(code) => ExecuteResult::Return(code, Vec::new(), Vec::new()),
+        Err(err) => {
+          let _ = context.stderr.write_line(&format!("return: {err}"));
+          ExecuteResult::Return(2, Vec::new(), Vec::new())
+        }
+      }
+    };
+    Box::pin(futures::future::ready(result))
+  }
+}
+
+fn execute_return(last_exit_code: i32, args: Vec<String>) -> Result<i32> {
+  let exit_code = parse_args(args)?.unwrap_or(last_exit_code);
+
+  Ok(if exit_code < 0 {
+    let code = -exit_code % 256;
+    256 - code
+  } else {
+    exit_code % 256
+  })
+}
+
+fn parse_args(args: Vec<String>) -> Result<Option<i32>> {
+  let args = parse_arg_kinds(&args);
+  let mut codes = Vec::new();
+  for arg in args {
+    match arg {
+      ArgKind::Arg(arg) => {
+        codes.push(arg);
+      }
+      _ => arg.bail_unsupported()?,
+    }
+  }
+
+  match codes.len() {
+    0 => Ok(None),
+    1 => match codes[0].parse::<i32>() {
+      Ok(value) => Ok(Some(value)),
+      Err(_) => bail!("{}: numeric argument required", codes[0]),
+    },
+    _ => bail!("too many arguments"),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn executes_return() {
+    assert_eq!(execute_return(3, vec![]).unwrap(), 3);
+    assert_eq!(execute_return(3, vec!["0".to_string()]).unwrap(), 0);
+    assert_eq!(execute_return(0, vec!["257".to_string()]).unwrap(), 1);
+    assert_eq!(execute_return(0, vec!["-1".to_string()]).unwrap(), 255);
+    assert_eq!(
+      execute_return(0, vec!["a".to_string()])
+        .err()
+        .unwrap()
+        .to_string(),
+      "a: numeric argument required"
+    );
+    assert_eq!(
+      execute_return(0, vec!["1".to_string(), "2".to_string()])
+        .err()
+        .unwrap()
+        .to_string(),
+      "too many arguments"
+    );
+  }
+}
@@ -19,6 +19,10 @@ use super::ShellCommandContext;
 pub struct RmCommand;
 
 impl ShellCommand for RmCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Remove files or directories")
+  }
+
   fn execute(
     &self,
     context: ShellCommandContext,
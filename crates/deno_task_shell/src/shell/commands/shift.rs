@@ -0,0 +1,117 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use futures::future::LocalBoxFuture;
+use miette::bail;
+use miette::Result;
+
+use crate::shell::types::EnvChange;
+use crate::shell::types::ExecuteResult;
+
+use super::args::parse_arg_kinds;
+use super::args::ArgKind;
+use super::ShellCommand;
+use super::ShellCommandContext;
+
+pub struct ShiftCommand;
+
+impl ShellCommand for ShiftCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Shift the positional parameters")
+  }
+
+  fn execute(
+    &self,
+    mut context: ShellCommandContext,
+  ) -> LocalBoxFuture<'static, ExecuteResult> {
+    let result =
+      match execute_shift(context.state.positional_args(), context.args) {
+        Ok(args) => ExecuteResult::Continue(
+          0,
+          vec![EnvChange::SetPositionalArgs(args)],
+          Vec::new(),
+        ),
+        Err(err) => {
+          let _ = context.stderr.write_line(&format!("shift: {err}"));
+          ExecuteResult::Continue(1, Vec::new(), Vec::new())
+        }
+      };
+    Box::pin(futures::future::ready(result))
+  }
+}
+
+/// Returns the positional parameters that remain after shifting.
+fn execute_shift(
+  positional_args: &[String],
+  args: Vec<String>,
+) -> Result<Vec<String>> {
+  let count = parse_args(args)?;
+  if count > positional_args.len() {
+    bail!("shift count out of range")
+  }
+  Ok(positional_args[count..].to_vec())
+}
+
+fn parse_args(args: Vec<String>) -> Result<usize> {
+  let args = parse_arg_kinds(&args);
+  let mut counts = Vec::new();
+  for arg in args {
+    match arg {
+      ArgKind::Arg(arg) => {
+        counts.push(arg);
+      }
+      _ => arg.bail_unsupported()?,
+    }
+  }
+
+  match counts.len() {
+    0 => Ok(1),
+    1 => match counts[0].parse::<usize>() {
+      Ok(value) => Ok(value),
+      Err(_) => bail!("{}: numeric argument required", counts[0]),
+    },
+    _ => bail!("too many arguments"),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn to_args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|a| a.to_string()).collect()
+  }
+
+  #[test]
+  fn parses_args() {
+    assert_eq!(parse_args(vec![]).unwrap(), 1);
+    assert_eq!(parse_args(to_args(&["3"])).unwrap(), 3);
+    assert_eq!(
+      parse_args(to_args(&["a"])).err().unwrap().to_string(),
+      "a: numeric argument required"
+    );
+    assert_eq!(
+      parse_args(to_args(&["1", "2"])).err().unwrap().to_string(),
+      "too many arguments"
+    );
+  }
+
+  #[test]
+  fn executes_shift() {
+    let positional = to_args(&["a", "b", "c"]);
+    assert_eq!(
+      execute_shift(&positional, vec![]).unwrap(),
+      to_args(&["b", "c"])
+    );
+    assert_eq!(
+      execute_shift(&positional, to_args(&["3"])).unwrap(),
+      Vec::<String>::new()
+    );
+    assert_eq!(
+      execute_shift(&positional, to_args(&["4"]))
+        .err()
+        .unwrap()
+        .to_string(),
+      "shift count out of range"
+    );
+  }
+}
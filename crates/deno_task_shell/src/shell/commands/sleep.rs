@@ -20,6 +20,10 @@ use super::ShellCommandContext;
 pub struct SleepCommand;
 
 impl ShellCommand for SleepCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Delay for a specified amount of time")
+  }
+
   fn execute(
     &self,
     context: ShellCommandContext,
@@ -0,0 +1,137 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use futures::future::LocalBoxFuture;
+use miette::Result;
+
+use crate::parser::FunctionDefinition;
+use crate::shell::types::ExecuteResult;
+use crate::shell::types::ShellState;
+
+use super::args::parse_arg_kinds;
+use super::args::ArgKind;
+use super::ShellCommand;
+use super::ShellCommandContext;
+
+pub struct TypeCommand;
+
+impl ShellCommand for TypeCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Show how each name would be interpreted as a command")
+  }
+
+  fn execute(
+    &self,
+    mut context: ShellCommandContext,
+  ) -> LocalBoxFuture<'static, ExecuteResult> {
+    let result = match parse_args(&context.args) {
+      Ok((short, names)) => {
+        let mut exit_code = 0;
+        for name in names {
+          match resolve_kind(&context.state, &name) {
+            Some(kind) if short => {
+              let _ = context.stdout.write_line(kind.short_name());
+            }
+            Some(kind) => {
+              let _ = context.stdout.write_line(&kind.describe(&name));
+            }
+            None => {
+              if !short {
+                let _ = context
+                  .stderr
+                  .write_line(&format!("type: {name}: not found"));
+              }
+              exit_code = 1;
+            }
+          }
+        }
+        ExecuteResult::from_exit_code(exit_code)
+      }
+      Err(err) => {
+        let _ = context.stderr.write_line(&format!("type: {err}"));
+        ExecuteResult::from_exit_code(2)
+      }
+    };
+    Box::pin(futures::future::ready(result))
+  }
+}
+
+/// What a command name refers to, in the order the shell looks them up.
+pub(super) enum CommandKind {
+  Alias(Vec<String>),
+  Function(Rc<FunctionDefinition>),
+  Builtin,
+  File(PathBuf),
+}
+
+impl CommandKind {
+  fn short_name(&self) -> &'static str {
+    match self {
+      CommandKind::Alias(_) => "alias",
+      CommandKind::Function(_) => "function",
+      CommandKind::Builtin => "builtin",
+      CommandKind::File(_) => "file",
+    }
+  }
+
+  fn describe(&self, name: &str) -> String {
+    match self {
+      CommandKind::Alias(value) => {
+        format!("{name} is aliased to `{}'", value.join(" "))
+      }
+      CommandKind::Function(function) => {
+        format!("{name} is a function\n{}", function.text)
+      }
+      CommandKind::Builtin => format!("{name} is a shell builtin"),
+      CommandKind::File(path) => format!("{name} is {}", path.display()),
+    }
+  }
+}
+
+pub(super) fn resolve_kind(
+  state: &ShellState,
+  name: &str,
+) -> Option<CommandKind> {
+  if let Some(value) = state.alias_map().get(name) {
+    Some(CommandKind::Alias(value.clone()))
+  } else if let Some(function) = state.function(name) {
+    Some(CommandKind::Function(function))
+  } else if state.resolve_custom_command(name).is_some() {
+    Some(CommandKind::Builtin)
+  } else {
+    state.resolve_command_path(name).ok().map(CommandKind::File)
+  }
+}
+
+fn parse_args(args: &[String]) -> Result<(bool, Vec<String>)> {
+  let mut short = false;
+  let mut names = Vec::new();
+  for arg in parse_arg_kinds(args) {
+    match arg {
+      ArgKind::ShortFlag('t') => short = true,
+      ArgKind::Arg(name) => names.push(name.to_string()),
+      _ => arg.bail_unsupported()?,
+    }
+  }
+  Ok((short, names))
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn parses_args() {
+    let args = ["-t", "ls", "cd"].map(String::from);
+    assert_eq!(
+      parse_args(&args).unwrap(),
+      (true, vec!["ls".to_string(), "cd".to_string()])
+    );
+    assert_eq!(
+      parse_args(&["-a".to_string()]).err().unwrap().to_string(),
+      "unsupported flag: -a"
+    );
+  }
+}
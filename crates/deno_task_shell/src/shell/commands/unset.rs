@@ -1,7 +1,6 @@
 // Copyright 2018-2024 the Deno authors. MIT license.
 
 use futures::future::LocalBoxFuture;
-use miette::bail;
 use miette::Result;
 
 use crate::shell::types::ExecuteResult;
@@ -13,16 +12,40 @@ use super::ShellCommandContext;
 pub struct UnsetCommand;
 
 impl ShellCommand for UnsetCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Unset shell and environment variables or functions")
+  }
+
   fn execute(
     &self,
     mut context: ShellCommandContext,
   ) -> LocalBoxFuture<'static, ExecuteResult> {
     let result = match parse_names(context.args) {
-      Ok(names) => ExecuteResult::Continue(
+      Ok((UnsetKind::Functions, names)) => ExecuteResult::Continue(
         0,
-        names.into_iter().map(EnvChange::UnsetVar).collect(),
+        names.into_iter().map(EnvChange::UnsetFunction).collect(),
         Vec::new(),
       ),
+      Ok((UnsetKind::Vars, names)) => {
+        let mut exit_code = 0;
+        let mut changes = Vec::new();
+        for name in names {
+          // like bash, a function is unset when there's no such variable
+          if context.state.get_var(&name).is_none()
+            && context.state.function(&name).is_some()
+          {
+            changes.push(EnvChange::UnsetFunction(name));
+          } else if context.state.var_attributes(&name).readonly {
+            let _ = context.stderr.write_line(&format!(
+              "unset: {name}: cannot unset: readonly variable"
+            ));
+            exit_code = 1;
+          } else {
+            changes.push(EnvChange::UnsetVar(name));
+          }
+        }
+        ExecuteResult::Continue(exit_code, changes, Vec::new())
+      }
       Err(err) => {
         let _ = context.stderr.write_line(&format!("unset: {err}"));
         ExecuteResult::Continue(1, Vec::new(), Vec::new())
@@ -32,20 +55,30 @@ impl ShellCommand for UnsetCommand {
   }
 }
 
-fn parse_names(mut args: Vec<String>) -> Result<Vec<String>> {
+#[derive(Debug, PartialEq)]
+enum UnsetKind {
+  Vars,
+  Functions,
+}
+
+fn parse_names(mut args: Vec<String>) -> Result<(UnsetKind, Vec<String>)> {
   match args.first() {
     None => {
       // Running the actual `unset` with no argument completes with success.
-      Ok(args)
+      Ok((UnsetKind::Vars, args))
     }
-    Some(flag) if flag == "-f" => bail!("unsupported flag: -f"),
-    Some(flag) if flag == "-v" => {
+    Some(flag) if flag == "-f" || flag == "-v" => {
+      let kind = if flag == "-f" {
+        UnsetKind::Functions
+      } else {
+        UnsetKind::Vars
+      };
       // It's fine to use `swap_remove` (instead of `remove`) because the order
       // of args doesn't matter for `unset` command.
       args.swap_remove(0);
-      Ok(args)
+      Ok((kind, args))
     }
-    Some(_) => Ok(args),
+    Some(_) => Ok((UnsetKind::Vars, args)),
   }
 }
 
@@ -57,23 +90,27 @@ mod test {
   fn parse_args() {
     assert_eq!(
       parse_names(vec!["VAR1".to_string()]).unwrap(),
-      vec!["VAR1".to_string()]
+      (UnsetKind::Vars, vec!["VAR1".to_string()])
     );
     assert_eq!(
       parse_names(vec!["VAR1".to_string(), "VAR2".to_string()]).unwrap(),
-      vec!["VAR1".to_string(), "VAR2".to_string()]
+      (
+        UnsetKind::Vars,
+        vec!["VAR1".to_string(), "VAR2".to_string()]
+      )
     );
-    assert!(parse_names(vec![]).unwrap().is_empty());
+    assert!(parse_names(vec![]).unwrap().1.is_empty());
     assert_eq!(
       parse_names(vec![
         "-f".to_string(),
-        "VAR1".to_string(),
-        "VAR2".to_string()
+        "FUNC1".to_string(),
+        "FUNC2".to_string()
       ])
-      .err()
-      .unwrap()
-      .to_string(),
-      "unsupported flag: -f".to_string()
+      .unwrap(),
+      (
+        UnsetKind::Functions,
+        vec!["FUNC2".to_string(), "FUNC1".to_string()]
+      )
     );
     assert_eq!(
       parse_names(vec![
@@ -82,7 +119,10 @@ mod test {
         "VAR2".to_string()
       ])
       .unwrap(),
-      vec!["VAR2".to_string(), "VAR1".to_string()]
+      (
+        UnsetKind::Vars,
+        vec!["VAR2".to_string(), "VAR1".to_string()]
+      )
     );
   }
 }
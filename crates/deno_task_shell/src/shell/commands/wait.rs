@@ -0,0 +1,156 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use futures::future::LocalBoxFuture;
+
+use crate::shell::jobs::Job;
+use crate::shell::jobs::JobStatus;
+use crate::shell::jobs::JobTable;
+use crate::shell::types::EnvChange;
+use crate::shell::types::ExecuteResult;
+use crate::shell::types::ShellState;
+
+use super::ShellCommand;
+use super::ShellCommandContext;
+
+pub struct WaitCommand;
+
+impl ShellCommand for WaitCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Wait for jobs to finish")
+  }
+
+  fn execute(
+    &self,
+    mut context: ShellCommandContext,
+  ) -> LocalBoxFuture<'static, ExecuteResult> {
+    Box::pin(async move {
+      let jobs = context.state.jobs();
+      let targets = if context.args.is_empty() {
+        // stopped jobs would never finish
+        jobs
+          .iter()
+          .filter(|job| job.status == JobStatus::Running)
+          .cloned()
+          .map(Ok)
+          .collect()
+      } else {
+        context
+          .args
+          .iter()
+          .map(|arg| resolve_target(jobs, arg))
+          .collect::<Vec<_>>()
+      };
+      let mut exit_code = 0;
+      let mut changes = Vec::new();
+      for target in targets {
+        match target {
+          Ok(job) => match wait_for(&job, &context.state).await {
+            Some((code, change)) => {
+              exit_code = code;
+              changes.push(change);
+            }
+            None => return ExecuteResult::for_cancellation(),
+          },
+          Err((err, code)) => {
+            let _ = context.stderr.write_line(&format!("wait: {err}"));
+            exit_code = code;
+          }
+        }
+      }
+      // like in bash, waiting for all jobs succeeds however they ended
+      if context.args.is_empty() {
+        exit_code = 0;
+      }
+      ExecuteResult::Continue(exit_code, changes, Vec::new())
+    })
+  }
+}
+
+/// Resolves a job spec or the pid of a job, or returns the error and the
+/// exit code for it.
+fn resolve_target(jobs: &JobTable, arg: &str) -> Result<Job, (String, i32)> {
+  if arg.starts_with('%') {
+    return jobs
+      .resolve(arg)
+      .cloned()
+      .ok_or_else(|| (format!("{arg}: no such job"), 127));
+  }
+  match arg.parse::<u32>() {
+    Ok(pid) => jobs
+      .find_pid(pid)
+      .cloned()
+      .ok_or_else(|| (format!("pid {pid} is not a child of this shell"), 127)),
+    Err(_) => Err((format!("`{arg}': not a pid or valid job spec"), 2)),
+  }
+}
+
+/// Waits until `job` exits or is stopped, and returns its exit code and the
+/// change to the job table. Returns `None` if the wait was cancelled.
+async fn wait_for(job: &Job, state: &ShellState) -> Option<(i32, EnvChange)> {
+  match &job.task {
+    Some(task) => tokio::select! {
+      code = task.wait() => Some((code, EnvChange::RemoveJob(job.id))),
+      _ = state.token().cancelled() => None,
+    },
+    None => wait_for_process(job, state).await,
+  }
+}
+
+#[cfg(unix)]
+async fn wait_for_process(
+  job: &Job,
+  state: &ShellState,
+) -> Option<(i32, EnvChange)> {
+  use crate::shell::job_control;
+
+  let Some(pid) = job.pid else {
+    return Some((0, EnvChange::RemoveJob(job.id)));
+  };
+  match job_control::wait_background(pid, state).await? {
+    Ok(job_control::WaitStatus::Exited(code)) => {
+      Some((code, EnvChange::RemoveJob(job.id)))
+    }
+    Ok(job_control::WaitStatus::Stopped) => Some((
+      job_control::STOPPED_EXIT_CODE,
+      EnvChange::SetJobStatus(job.id, JobStatus::Stopped),
+    )),
+    // the process was already reaped elsewhere
+    Err(_) => Some((0, EnvChange::RemoveJob(job.id))),
+  }
+}
+
+/// Only job control stops jobs, which is not supported on this platform.
+#[cfg(not(unix))]
+async fn wait_for_process(
+  job: &Job,
+  _state: &ShellState,
+) -> Option<(i32, EnvChange)> {
+  Some((0, EnvChange::RemoveJob(job.id)))
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn resolves_targets() {
+    let mut jobs = JobTable::default();
+    jobs.add(Some(10), "sleep 100".to_string());
+    jobs.add(Some(11), "vim".to_string());
+    assert_eq!(resolve_target(&jobs, "%1").unwrap().id, 1);
+    assert_eq!(resolve_target(&jobs, "%-").unwrap().id, 1);
+    assert_eq!(resolve_target(&jobs, "11").unwrap().id, 2);
+    assert_eq!(
+      resolve_target(&jobs, "%3").unwrap_err(),
+      ("%3: no such job".to_string(), 127)
+    );
+    assert_eq!(
+      resolve_target(&jobs, "12").unwrap_err(),
+      ("pid 12 is not a child of this shell".to_string(), 127)
+    );
+    assert_eq!(
+      resolve_target(&jobs, "x").unwrap_err(),
+      ("`x': not a pid or valid job spec".to_string(), 2)
+    );
+  }
+}
@@ -18,6 +18,10 @@ use super::ShellCommandContext;
 pub struct XargsCommand;
 
 impl ShellCommand for XargsCommand {
+  fn description(&self) -> Option<&'static str> {
+    Some("Build and run commands from standard input")
+  }
+
   fn execute(
     &self,
     mut context: ShellCommandContext,
@@ -1,5 +1,6 @@
 // Copyright 2018-2024 the Deno authors. MIT license.
 
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
@@ -15,24 +16,44 @@ use thiserror::Error;
 use tokio::task::JoinHandle;
 use tokio_util::sync::CancellationToken;
 
+use crate::parser::ArrayAssignment;
+use crate::parser::ArrayIndex;
+use crate::parser::ArraySubscript;
 use crate::parser::AssignmentOp;
 use crate::parser::BinaryOp;
+use crate::parser::CaseClause;
+use crate::parser::CaseTerminator;
 use crate::parser::Condition;
 use crate::parser::ConditionInner;
 use crate::parser::ElsePart;
+use crate::parser::ForLoop;
+use crate::parser::FunctionDefinition;
 use crate::parser::IoFile;
 use crate::parser::RedirectOpInput;
 use crate::parser::RedirectOpOutput;
+use crate::parser::ReplaceMode;
 use crate::parser::UnaryOp;
 use crate::parser::VariableModifier;
+use crate::parser::WhileLoop;
+use crate::shell::brace_expansion::expand_braces;
 use crate::shell::commands::ShellCommand;
 use crate::shell::commands::ShellCommandContext;
+use crate::shell::jobs::Job;
+use crate::shell::jobs::JobStatus;
+use crate::shell::jobs::JobTask;
+use crate::shell::pathname_expansion::expand_pathname;
+use crate::shell::process_substitution::take_process_substitutions;
+use crate::shell::process_substitution::ProcessSubstitutions;
+use crate::shell::process_substitution::SubstitutionDir;
+use crate::shell::tilde_expansion::expand_tilde;
 use crate::shell::types::pipe;
 use crate::shell::types::ArithmeticResult;
 use crate::shell::types::ArithmeticValue;
 use crate::shell::types::EnvChange;
 use crate::shell::types::ExecuteResult;
 use crate::shell::types::FutureExecuteResult;
+use crate::shell::types::LoopControl;
+use crate::shell::types::ShellOptions;
 use crate::shell::types::ShellPipeReader;
 use crate::shell::types::ShellPipeWriter;
 use crate::shell::types::ShellState;
@@ -44,6 +65,7 @@ use crate::parser::BinaryArithmeticOp;
 use crate::parser::Command;
 use crate::parser::CommandInner;
 use crate::parser::IfClause;
+use crate::parser::IncrementOp;
 use crate::parser::PipeSequence;
 use crate::parser::PipeSequenceOperator;
 use crate::parser::Pipeline;
@@ -65,7 +87,6 @@ use crate::shell::types::WordResult;
 use super::command::execute_unresolved_command_name;
 use super::command::UnresolvedCommandName;
 use super::types::ConditionalResult;
-use super::types::CANCELLATION_EXIT_CODE;
 
 /// Executes a `SequentialList` of commands in a deno_task_shell environment.
 ///
@@ -135,7 +156,9 @@ pub async fn execute_with_pipes(
 
   match result {
     ExecuteResult::Exit(code, _) => code,
-    ExecuteResult::Continue(exit_code, _, _) => exit_code,
+    ExecuteResult::Continue(exit_code, _, _)
+    | ExecuteResult::Return(exit_code, _, _) => exit_code,
+    ExecuteResult::LoopControl(_, _, _) => 0,
   }
 }
 
@@ -159,20 +182,64 @@ pub fn execute_sequential_list(
     let mut final_changes = Vec::new();
     let mut async_handles = Vec::new();
     let mut was_exit = false;
+    let mut was_return = false;
+    let mut loop_control = None;
     for item in list.items {
+      if state.shell_option(ShellOptions::NoExec) {
+        break;
+      }
+      state.set_line_number(item.line);
       if item.is_async {
-        let state = state.clone();
-        let stdin = stdin.clone();
-        let stdout = stdout.clone();
-        let stderr = stderr.clone();
+        // the job's token is a child one, so that a failure in the job
+        // doesn't cancel the commands after it
+        let mut job_state = state.with_child_token();
+        job_state.enter_background();
+        let task = JobTask::new(job_state.token().clone());
+        let job_task = task.clone();
+        let job_stdin = stdin.clone();
+        let job_stdout = stdout.clone();
+        let job_stderr = stderr.clone();
+        let last_pid = state.last_background_pid();
         async_handles.push(tokio::task::spawn_local(async move {
-          let main_token = state.token().clone();
-          let result =
-            execute_sequence(item.sequence, state, stdin, stdout, stderr).await;
+          let job_token = job_state.token().clone();
+          let result = execute_sequence(
+            item.sequence,
+            job_state,
+            job_stdin,
+            job_stdout,
+            job_stderr,
+          )
+          .await;
           let (exit_code, handles) = result.into_exit_code_and_handles();
-          wait_handles(exit_code, handles, main_token).await
+          let exit_code = wait_handles(exit_code, handles, job_token).await;
+          job_task.finish(exit_code);
+          exit_code
         }));
+        // let it start its process, so that `$!` refers to it right away
+        tokio::task::yield_now().await;
+        let pid = state
+          .last_background_pid()
+          .filter(|pid| Some(*pid) != last_pid);
+        let job = Job {
+          id: state.jobs().next_id(),
+          pid,
+          command: item.text,
+          status: JobStatus::Running,
+          no_hangup: false,
+          task: Some(task),
+        };
+        // a job of builtins has no process, but `kill $!` and `wait $!`
+        // still find it
+        state.set_last_background_pid(job.background_pid());
+        if state.is_interactive() {
+          let pid = pid.map(|pid| pid.to_string()).unwrap_or_default();
+          let _ = stderr.clone().write_line(&format!("[{}] {pid}", job.id));
+        }
+        let change = EnvChange::AddJob(job);
+        state.apply_change(&change);
+        final_changes.push(change);
       } else {
+        state.errexit_ignored().set(false);
         let result = execute_sequence(
           item.sequence,
           state.clone(),
@@ -190,15 +257,31 @@ pub fn execute_sequential_list(
           }
           ExecuteResult::Continue(exit_code, changes, handles) => {
             state.apply_changes(&changes);
-            state.apply_env_var("?", &exit_code.to_string());
+            state.set_last_command_exit_code(exit_code);
             final_changes.extend(changes);
             async_handles.extend(handles);
             // use the final sequential item's exit code
             final_exit_code = exit_code;
-            if state.exit_on_error() && exit_code != 0 {
+            if state.exits_on_failure(exit_code) {
               break;
             }
           }
+          ExecuteResult::Return(exit_code, changes, handles) => {
+            state.apply_changes(&changes);
+            final_changes.extend(changes);
+            async_handles.extend(handles);
+            final_exit_code = exit_code;
+            was_return = true;
+            break;
+          }
+          ExecuteResult::LoopControl(control, changes, handles) => {
+            state.apply_changes(&changes);
+            final_changes.extend(changes);
+            async_handles.extend(handles);
+            final_exit_code = 0;
+            loop_control = Some(control);
+            break;
+          }
         }
       }
     }
@@ -215,6 +298,10 @@ pub fn execute_sequential_list(
 
     if was_exit {
       ExecuteResult::Exit(final_exit_code, async_handles)
+    } else if was_return {
+      ExecuteResult::Return(final_exit_code, final_changes, async_handles)
+    } else if let Some(control) = loop_control {
+      ExecuteResult::LoopControl(control, final_changes, async_handles)
     } else {
       ExecuteResult::Continue(final_exit_code, final_changes, async_handles)
     }
@@ -222,25 +309,18 @@ pub fn execute_sequential_list(
   .boxed_local()
 }
 
+/// Waits for the background jobs of `handles`, which are cancelled when
+/// `exit_code` is a failure. Like in bash, the exit codes of the jobs don't
+/// change the exit code.
 async fn wait_handles(
-  mut exit_code: i32,
-  mut handles: Vec<JoinHandle<i32>>,
+  exit_code: i32,
+  handles: Vec<JoinHandle<i32>>,
   token: CancellationToken,
 ) -> i32 {
   if exit_code != 0 {
     token.cancel();
   }
-  while !handles.is_empty() {
-    let result = futures::future::select_all(handles).await;
-
-    // prefer the first non-zero then non-cancellation exit code
-    let new_exit_code = result.0.unwrap();
-    if matches!(exit_code, 0 | CANCELLATION_EXIT_CODE) && new_exit_code != 0 {
-      exit_code = new_exit_code;
-    }
-
-    handles = result.2;
-  }
+  futures::future::join_all(handles).await;
   exit_code
 }
 
@@ -255,25 +335,86 @@ fn execute_sequence(
   async move {
     match sequence {
       Sequence::ShellVar(var) => {
+        let attributes = state.var_attributes(&var.name);
+        if attributes.readonly {
+          return EvaluateWordTextError::ReadonlyVariable { name: var.name }
+            .into_exit_code(&state, &mut stderr);
+        }
         let value =
           match evaluate_word(var.value, &mut state, stdin, stderr.clone())
             .await
           {
             Ok(value) => value,
             Err(err) => {
-              return err.into_exit_code(&mut stderr);
+              return err.into_exit_code(&state, &mut stderr);
             }
           };
+        let mut value: String = value.into();
+        let operator = if var.append { "+=" } else { "=" };
+        let trace = format!("+ {}{operator}{value}", var.name);
+        let mut changes = Vec::new();
+        if attributes.integer {
+          // `n+=1` adds to the value of an integer variable
+          if let Some(current) = state.get_var(&var.name).filter(|_| var.append)
+          {
+            value = format!("{current}+({value})");
+          }
+          match evaluate_integer_value(&value, &mut state) {
+            Ok((result, result_changes)) => {
+              value = result;
+              changes = result_changes;
+            }
+            Err(err) => {
+              let _ = stderr.write_line(&format!("{}: {err}", var.name));
+              return ExecuteResult::from_exit_code(1);
+            }
+          }
+        } else if var.append {
+          value = state.get_var(&var.name).unwrap_or_default() + &value;
+        }
+
+        // like in bash, assigning to these seeds or resets them. The
+        // variables themselves are computed each time they are read
+        match var.name.as_str() {
+          "RANDOM" => state.seed_random(value.trim().parse().unwrap_or(0)),
+          "SECONDS" => state.reset_seconds(value.trim().parse().unwrap_or(0)),
+          _ => {}
+        }
 
         if state.print_trace() {
-          let _ = stdout.write_line(&format!("+ {}={}", var.name, value));
+          let _ = stdout.write_line(&trace);
         }
 
-        ExecuteResult::Continue(
-          0,
-          vec![EnvChange::SetShellVar(var.name, value.into())],
-          Vec::new(),
+        changes.push(EnvChange::SetShellVar(var.name, value));
+        ExecuteResult::Continue(0, changes, Vec::new())
+      }
+      Sequence::ArrayAssignment(assignment) => {
+        let name = match &assignment {
+          ArrayAssignment::List { name, .. }
+          | ArrayAssignment::Element { name, .. } => name,
+        };
+        if state.var_attributes(name).readonly {
+          return EvaluateWordTextError::ReadonlyVariable {
+            name: name.to_string(),
+          }
+          .into_exit_code(&state, &mut stderr);
+        }
+        match execute_array_assignment(
+          assignment,
+          &mut state,
+          stdin,
+          stderr.clone(),
         )
+        .await
+        {
+          Ok((trace, changes)) => {
+            if state.print_trace() {
+              let _ = stdout.write_line(&format!("+ {trace}"));
+            }
+            ExecuteResult::Continue(0, changes, Vec::new())
+          }
+          Err(err) => err.into_exit_code(&state, &mut stderr),
+        }
       }
       Sequence::BooleanList(list) => {
         let mut changes = vec![];
@@ -286,7 +427,9 @@ fn execute_sequence(
         )
         .await;
         let (exit_code, mut async_handles) = match first_result {
-          ExecuteResult::Exit(_, _) => return first_result,
+          ExecuteResult::Exit(_, _)
+          | ExecuteResult::Return(_, _, _)
+          | ExecuteResult::LoopControl(_, _, _) => return first_result,
           ExecuteResult::Continue(exit_code, sub_changes, async_handles) => {
             changes.extend(sub_changes);
             (exit_code, async_handles)
@@ -294,6 +437,7 @@ fn execute_sequence(
         };
 
         state.apply_changes(&changes);
+        state.set_last_command_exit_code(exit_code);
 
         let next = if list.op.moves_next_for_exit_code(exit_code) {
           Some(list.next)
@@ -312,6 +456,9 @@ fn execute_sequence(
             }
           }
         };
+        // like in bash, `set -e` only sees the status of the last command
+        // of the list, not of one that ended it early
+        state.errexit_ignored().set(next.is_none());
         if let Some(next) = next {
           let next_result =
             execute_sequence(next, state, stdin, stdout, stderr).await;
@@ -325,6 +472,16 @@ fn execute_sequence(
               async_handles.extend(sub_handles);
               ExecuteResult::Continue(exit_code, changes, async_handles)
             }
+            ExecuteResult::Return(exit_code, sub_changes, sub_handles) => {
+              changes.extend(sub_changes);
+              async_handles.extend(sub_handles);
+              ExecuteResult::Return(exit_code, changes, async_handles)
+            }
+            ExecuteResult::LoopControl(control, sub_changes, sub_handles) => {
+              changes.extend(sub_changes);
+              async_handles.extend(sub_handles);
+              ExecuteResult::LoopControl(control, changes, async_handles)
+            }
           }
         } else {
           ExecuteResult::Continue(exit_code, changes, async_handles)
@@ -340,13 +497,29 @@ fn execute_sequence(
 
 async fn execute_pipeline(
   pipeline: Pipeline,
-  state: ShellState,
+  mut state: ShellState,
   stdin: ShellPipeReader,
   stdout: ShellPipeWriter,
   stderr: ShellPipeWriter,
 ) -> ExecuteResult {
+  let errexit_ignored = state.errexit_ignored();
+  if pipeline.negated {
+    // like in bash, `set -e` ignores the failures within a negated pipeline
+    // and the negated status itself
+    state.set_shell_option(ShellOptions::ExitOnError, false);
+  }
+  // a compound command like `{ ! true; }` has the status of its last
+  // command, but a function or a subshell counts as a command of its own
+  let is_compound = matches!(
+    &pipeline.inner,
+    PipelineInner::Command(command)
+      if !matches!(command.inner, CommandInner::Simple(_) | CommandInner::Subshell(_))
+  );
   let result =
     execute_pipeline_inner(pipeline.inner, state, stdin, stdout, stderr).await;
+  if pipeline.negated || !is_compound {
+    errexit_ignored.set(pipeline.negated);
+  }
   if pipeline.negated {
     match result {
       ExecuteResult::Exit(code, handles) => ExecuteResult::Exit(code, handles),
@@ -354,6 +527,8 @@ async fn execute_pipeline(
         let new_code = if code == 0 { 1 } else { 0 };
         ExecuteResult::Continue(new_code, changes, handles)
       }
+      result @ (ExecuteResult::Return(_, _, _)
+      | ExecuteResult::LoopControl(_, _, _)) => result,
     }
   } else {
     result
@@ -369,7 +544,9 @@ async fn execute_pipeline_inner(
 ) -> ExecuteResult {
   match pipeline {
     PipelineInner::Command(command) => {
-      execute_command(command, state, stdin, stdout, stderr).await
+      let result = execute_command(command, state, stdin, stdout, stderr).await;
+      let exit_code = result.exit_code();
+      with_pipe_status(result, &[exit_code])
     }
     PipelineInner::PipeSequence(pipe_sequence) => {
       execute_pipe_sequence(*pipe_sequence, state, stdin, stdout, stderr).await
@@ -377,6 +554,37 @@ async fn execute_pipeline_inner(
   }
 }
 
+/// Sets `PIPESTATUS` to the exit codes of the commands of the pipeline
+/// that gave `result`, a single command is a pipeline of one.
+fn with_pipe_status(
+  result: ExecuteResult,
+  exit_codes: &[i32],
+) -> ExecuteResult {
+  let change = EnvChange::SetArrayVar(
+    "PIPESTATUS".to_string(),
+    exit_codes
+      .iter()
+      .map(ToString::to_string)
+      .enumerate()
+      .collect(),
+  );
+  match result {
+    ExecuteResult::Exit(code, handles) => ExecuteResult::Exit(code, handles),
+    ExecuteResult::Continue(code, mut changes, handles) => {
+      changes.push(change);
+      ExecuteResult::Continue(code, changes, handles)
+    }
+    ExecuteResult::Return(code, mut changes, handles) => {
+      changes.push(change);
+      ExecuteResult::Return(code, changes, handles)
+    }
+    ExecuteResult::LoopControl(control, mut changes, handles) => {
+      changes.push(change);
+      ExecuteResult::LoopControl(control, changes, handles)
+    }
+  }
+}
+
 #[derive(Debug)]
 enum RedirectPipe {
   Input(ShellPipeReader, Option<Vec<EnvChange>>),
@@ -391,13 +599,22 @@ async fn resolve_redirect_pipe(
   stderr: &mut ShellPipeWriter,
 ) -> Result<RedirectPipe, ExecuteResult> {
   match redirect.io_file.clone() {
+    IoFile::Word(word)
+      if redirect.op == RedirectOp::Input(RedirectOpInput::HereString) =>
+    {
+      resolve_here_string_pipe(word, state, stdin, stderr).await
+    }
     IoFile::Word(word) => {
-      resolve_redirect_word_pipe(word, &redirect.op, state, stdin, stderr).await
+      resolve_redirect_word_pipe(word, redirect, state, stdin, stderr).await
     }
     IoFile::Fd(fd) => match &redirect.op {
-      RedirectOp::Input(RedirectOpInput::Redirect) => {
-        let _ = stderr.write_line(
+      RedirectOp::Input(_) => {
+        write_redirect_error(
+          stderr,
+          state,
+          redirect,
           "shell: input redirecting file descriptors is not implemented",
+          "not supported",
         );
         Err(ExecuteResult::from_exit_code(1))
       }
@@ -405,8 +622,12 @@ async fn resolve_redirect_pipe(
         1 => Ok(RedirectPipe::Output(stdout.clone(), None)),
         2 => Ok(RedirectPipe::Output(stderr.clone(), None)),
         _ => {
-          let _ = stderr.write_line(
-            &format!("{:?}", miette!("shell: output redirecting file descriptors beyond stdout and stderr is not implemented")),
+          write_redirect_error(
+            stderr,
+            state,
+            redirect,
+            "shell: output redirecting file descriptors beyond stdout and stderr is not implemented",
+            "not supported",
           );
           Err(ExecuteResult::from_exit_code(1))
         }
@@ -415,30 +636,89 @@ async fn resolve_redirect_pipe(
   }
 }
 
+/// Writes the error of a redirect. If the script it is in is known, the
+/// redirect is shown in the script with `label` pointing at it.
+fn write_redirect_error(
+  stderr: &mut ShellPipeWriter,
+  state: &ShellState,
+  redirect: &Redirect,
+  message: &str,
+  label: &str,
+) {
+  // a function may have been defined in another script than the one being
+  // executed, so check that the redirect is where it points at
+  let range =
+    redirect.span.offset()..redirect.span.offset() + redirect.span.len();
+  let source = state
+    .script_source()
+    .filter(|source| source.inner().get(range) == Some(redirect.text.as_str()));
+  let message = match source {
+    Some(source) => {
+      let report = miette!(
+        labels = vec![miette::LabeledSpan::at(redirect.span, label)],
+        "{message}"
+      )
+      .with_source_code(source.clone());
+      format!("{report:?}")
+    }
+    None => message.to_string(),
+  };
+  let _ = stderr.write_line(&message);
+}
+
+/// The input of `<<< word`: the word expanded like in double quotes, so
+/// without word splitting or globbing, followed by a newline.
+async fn resolve_here_string_pipe(
+  word: Word,
+  state: &ShellState,
+  stdin: &ShellPipeReader,
+  stderr: &mut ShellPipeWriter,
+) -> Result<RedirectPipe, ExecuteResult> {
+  let words = evaluate_word_parts(
+    vec![WordPart::Quoted(word.into_parts())],
+    &mut state.clone(),
+    stdin.clone(),
+    stderr.clone(),
+  )
+  .await;
+  let words = match words {
+    Ok(words) => words,
+    Err(err) => return Err(err.into_exit_code(state, stderr)),
+  };
+  let text = format!("{}\n", words.join(" "));
+  let (reader, mut writer) = pipe();
+  // the pipe only holds so much until the command reads from it
+  tokio::task::spawn_blocking(move || writer.write_all(text.as_bytes()));
+  Ok(RedirectPipe::Input(reader, Some(words.changes)))
+}
+
 async fn resolve_redirect_word_pipe(
   word: Word,
-  redirect_op: &RedirectOp,
+  redirect: &Redirect,
   state: &ShellState,
   stdin: &ShellPipeReader,
   stderr: &mut ShellPipeWriter,
 ) -> Result<RedirectPipe, ExecuteResult> {
-  fn handle_std_result(
-    output_path: &Path,
-    std_file_result: std::io::Result<std::fs::File>,
-    stderr: &mut ShellPipeWriter,
-  ) -> Result<std::fs::File, ExecuteResult> {
-    match std_file_result {
+  let handle_std_result =
+    |output_path: &Path,
+     std_file_result: std::io::Result<std::fs::File>,
+     stderr: &mut ShellPipeWriter| match std_file_result {
       Ok(std_file) => Ok(std_file),
       Err(err) => {
-        let _ = stderr.write_line(&format!(
-          "error opening file for redirect ({}). {:#}",
-          output_path.display(),
-          err
-        ));
+        write_redirect_error(
+          stderr,
+          state,
+          redirect,
+          &format!(
+            "error opening file for redirect ({}). {:#}",
+            output_path.display(),
+            err
+          ),
+          "cannot be opened",
+        );
         Err(ExecuteResult::from_exit_code(1))
       }
-    }
-  }
+    };
 
   let words = evaluate_word_parts(
     word.into_parts(),
@@ -450,28 +730,40 @@ async fn resolve_redirect_word_pipe(
   let words = match words {
     Ok(word) => word,
     Err(err) => {
-      return Err(err.into_exit_code(stderr));
+      return Err(err.into_exit_code(state, stderr));
     }
   };
   // edge case that's not supported
   if words.value.is_empty() {
-    let _ = stderr.write_line("redirect path must be 1 argument, but found 0");
+    write_redirect_error(
+      stderr,
+      state,
+      redirect,
+      "redirect path must be 1 argument, but found 0",
+      "expands to nothing",
+    );
     return Err(ExecuteResult::from_exit_code(1));
   } else if words.value.len() > 1 {
-    let _ = stderr.write_line(&format!(
-      concat!(
-        "redirect path must be 1 argument, but found {0} ({1}). ",
-        "Did you mean to quote it (ex. \"{1}\")?"
+    write_redirect_error(
+      stderr,
+      state,
+      redirect,
+      &format!(
+        concat!(
+          "redirect path must be 1 argument, but found {0} ({1}). ",
+          "Did you mean to quote it (ex. \"{1}\")?"
+        ),
+        words.value.len(),
+        words.join(" ")
       ),
-      words.value.len(),
-      words.join(" ")
-    ));
+      &format!("expands to {} words", words.value.len()),
+    );
     return Err(ExecuteResult::from_exit_code(1));
   }
   let output_path = &words.value[0];
 
-  match &redirect_op {
-    RedirectOp::Input(RedirectOpInput::Redirect) => {
+  match &redirect.op {
+    RedirectOp::Input(_) => {
       let output_path = state.cwd().join(output_path);
       let std_file_result =
         std::fs::OpenOptions::new().read(true).open(&output_path);
@@ -509,15 +801,90 @@ async fn resolve_redirect_word_pipe(
 }
 
 async fn execute_command(
-  command: Command,
-  mut state: ShellState,
+  mut command: Command,
+  state: ShellState,
   stdin: ShellPipeReader,
   stdout: ShellPipeWriter,
   mut stderr: ShellPipeWriter,
 ) -> ExecuteResult {
-  let (stdin, stdout, mut stderr, changes) = if let Some(redirect) =
-    &command.redirect
+  // the substitutions start before the redirect is applied, so that a
+  // `>(list)` writes to the same output as the command would without it
+  let mut pending = Vec::new();
+  let mut in_shell = true;
+  // kept until the substitutions finished, which removes their files
+  let mut dir = SubstitutionDir::default();
+  if let CommandInner::Simple(command) = &mut command.inner {
+    in_shell = runs_in_shell(&state, command.args.first());
+    let (args, args_pending) = match take_process_substitutions(
+      std::mem::take(&mut command.args),
+      &mut dir,
+    ) {
+      Ok(taken) => taken,
+      Err(err) => return substitution_error(err, &mut stderr),
+    };
+    command.args = args;
+    pending.extend(args_pending);
+  }
+  for redirect in &mut command.redirects {
+    let IoFile::Word(word) = &mut redirect.io_file else {
+      continue;
+    };
+    let (mut words, redirect_pending) = match take_process_substitutions(
+      vec![std::mem::replace(word, Word::new_empty())],
+      &mut dir,
+    ) {
+      Ok(taken) => taken,
+      Err(err) => return substitution_error(err, &mut stderr),
+    };
+    *word = words.remove(0);
+    // like with `< <(list)`, the shell opens the file of a redirect itself,
+    // so it can't be a FIFO
+    in_shell |= !redirect_pending.is_empty();
+    pending.extend(redirect_pending);
+  }
+  if pending.is_empty() {
+    return execute_redirected_command(command, state, stdin, stdout, stderr)
+      .await;
+  }
+  let substitutions = match ProcessSubstitutions::start(
+    pending,
+    in_shell,
+    &state,
+    &stdin,
+    &stdout,
+    &mut stderr,
+  )
+  .await
   {
+    Ok(substitutions) => substitutions,
+    Err(result) => return result,
+  };
+  let result =
+    execute_redirected_command(command, state, stdin, stdout, stderr.clone())
+      .await;
+  substitutions.finish(&mut stderr).await;
+  result
+}
+
+fn substitution_error(
+  err: std::io::Error,
+  stderr: &mut ShellPipeWriter,
+) -> ExecuteResult {
+  let _ = stderr.write_line(&format!("shell: process substitution: {err}"));
+  ExecuteResult::from_exit_code(1)
+}
+
+async fn execute_redirected_command(
+  command: Command,
+  mut state: ShellState,
+  mut stdin: ShellPipeReader,
+  mut stdout: ShellPipeWriter,
+  mut stderr: ShellPipeWriter,
+) -> ExecuteResult {
+  let mut changes = Vec::new();
+  // each redirect sees the streams of the ones before it, so `2>&1` after
+  // `> file` points at the file
+  for redirect in &command.redirects {
     let pipe = match resolve_redirect_pipe(
       redirect,
       &state,
@@ -530,37 +897,42 @@ async fn execute_command(
       Ok(value) => value,
       Err(value) => return value,
     };
-    match pipe {
-      RedirectPipe::Input(pipe, changes) => match redirect.maybe_fd {
+    let redirect_changes = match pipe {
+      RedirectPipe::Input(pipe, redirect_changes) => match redirect.maybe_fd {
         Some(_) => {
           let _ = stderr.write_line(
             "input redirects with file descriptors are not supported",
           );
           return ExecuteResult::from_exit_code(1);
         }
-        None => (pipe, stdout, stderr, changes),
-      },
-      RedirectPipe::Output(pipe, changes) => match redirect.maybe_fd {
-        Some(RedirectFd::Fd(2)) => (stdin, stdout, pipe, changes),
-        Some(RedirectFd::Fd(1)) | None => (stdin, pipe, stderr, changes),
-        Some(RedirectFd::Fd(_)) => {
-          let _ = stderr.write_line(
-            "only redirecting to stdout (1) and stderr (2) is supported",
-          );
-          return ExecuteResult::from_exit_code(1);
+        None => {
+          stdin = pipe;
+          redirect_changes
         }
-        Some(RedirectFd::StdoutStderr) => (stdin, pipe.clone(), pipe, changes),
       },
+      RedirectPipe::Output(pipe, redirect_changes) => {
+        match redirect.maybe_fd {
+          Some(RedirectFd::Fd(2)) => stderr = pipe,
+          Some(RedirectFd::Fd(1)) | None => stdout = pipe,
+          Some(RedirectFd::Fd(_)) => {
+            let _ = stderr.write_line(
+              "only redirecting to stdout (1) and stderr (2) is supported",
+            );
+            return ExecuteResult::from_exit_code(1);
+          }
+          Some(RedirectFd::StdoutStderr) => {
+            stdout = pipe.clone();
+            stderr = pipe;
+          }
+        }
+        redirect_changes
+      }
+    };
+    if let Some(redirect_changes) = redirect_changes {
+      state.apply_changes(&redirect_changes);
+      changes.extend(redirect_changes);
     }
-  } else {
-    (stdin, stdout, stderr, None)
-  };
-  let mut changes = if let Some(changes) = changes {
-    state.apply_changes(&changes);
-    changes
-  } else {
-    Vec::new()
-  };
+  }
   match command.inner {
     CommandInner::Simple(command) => {
       // This can change the state, so we need to pass it by mutable reference
@@ -572,21 +944,54 @@ async fn execute_command(
         ExecuteResult::Exit(code, handles) => {
           ExecuteResult::Exit(code, handles)
         }
-        ExecuteResult::Continue(code, _, handles) => {
+        ExecuteResult::Continue(code, _, handles)
+        | ExecuteResult::Return(code, _, handles) => {
           ExecuteResult::Continue(code, changes, handles)
         }
+        ExecuteResult::LoopControl(_, _, handles) => {
+          ExecuteResult::Continue(0, changes, handles)
+        }
       }
     }
     CommandInner::If(if_clause) => {
       // The state can be changed
       execute_if_clause(if_clause, &mut state, stdin, stdout, stderr).await
     }
+    CommandInner::For(for_loop) => {
+      execute_for_loop(for_loop, state, stdin, stdout, stderr).await
+    }
+    CommandInner::Select(select_loop) => {
+      execute_select_loop(select_loop, state, stdin, stdout, stderr).await
+    }
+    CommandInner::While(while_loop) => {
+      execute_while_loop(while_loop, state, stdin, stdout, stderr).await
+    }
+    CommandInner::Case(case_clause) => {
+      execute_case_clause(case_clause, &mut state, stdin, stdout, stderr).await
+    }
+    CommandInner::BraceGroup(list) => {
+      execute_sequential_list(
+        *list,
+        state,
+        stdin,
+        stdout,
+        stderr,
+        AsyncCommandBehavior::Yield,
+      )
+      .await
+    }
+    CommandInner::FunctionDefinition(function) => {
+      changes.push(EnvChange::DefineFunction(Rc::new(function)));
+      ExecuteResult::Continue(0, changes, Vec::new())
+    }
     CommandInner::ArithmeticExpression(arithmetic) => {
       // The state can be changed
-      match execute_arithmetic_expression(arithmetic, &mut state).await {
+      match execute_arithmetic_expression(arithmetic, &mut state) {
+        // like in bash, it fails when the result is zero
         Ok(result) => {
+          let exit_code = if result.is_zero() { 1 } else { 0 };
           changes.extend(result.changes);
-          ExecuteResult::Continue(0, changes, Vec::new())
+          ExecuteResult::Continue(exit_code, changes, Vec::new())
         }
         Err(e) => {
           let _ = stderr.write_line(&e.to_string());
@@ -597,86 +1002,130 @@ async fn execute_command(
   }
 }
 
-async fn execute_arithmetic_expression(
+/// Evaluates the value assigned to a variable declared with `declare -i`,
+/// returning the resulting number.
+pub(crate) fn evaluate_integer_value(
+  value: &str,
+  state: &mut ShellState,
+) -> Result<(String, Vec<EnvChange>), Error> {
+  if value.trim().is_empty() {
+    return Ok(("0".to_string(), Vec::new()));
+  }
+  let arithmetic = crate::parser::parse_arithmetic(value)?;
+  let result = execute_arithmetic_expression(arithmetic, state)?;
+  Ok((result.value.to_string(), result.changes))
+}
+
+/// How deep the values of variables that are expressions themselves are
+/// evaluated, so that `x=x` fails instead of overflowing the stack.
+const MAX_ARITHMETIC_DEPTH: usize = 100;
+
+/// Evaluates `$((...))` or `((...))`. The variables that it assigns are set
+/// in `state`, and also returned as changes.
+fn execute_arithmetic_expression(
   arithmetic: Arithmetic,
   state: &mut ShellState,
 ) -> Result<ArithmeticResult, Error> {
-  evaluate_arithmetic(&arithmetic, state).await
+  evaluate_arithmetic(&arithmetic, state, 0)
 }
 
-async fn evaluate_arithmetic(
+fn evaluate_arithmetic(
   arithmetic: &Arithmetic,
   state: &mut ShellState,
+  depth: usize,
 ) -> Result<ArithmeticResult, Error> {
   let mut result = ArithmeticResult::new(ArithmeticValue::Integer(0));
   for part in &arithmetic.parts {
-    let part_result = Box::pin(evaluate_arithmetic_part(part, state)).await?;
+    let part_result = evaluate_arithmetic_part(part, state, depth)?;
     result.set_value(part_result.value);
     result.with_changes(part_result.changes);
   }
   Ok(result)
 }
 
-async fn evaluate_arithmetic_part(
+fn evaluate_arithmetic_part(
   part: &ArithmeticPart,
   state: &mut ShellState,
+  depth: usize,
 ) -> Result<ArithmeticResult, Error> {
   match part {
     ArithmeticPart::ParenthesesExpr(expr) => {
-      Box::pin(evaluate_arithmetic(expr, state)).await
+      evaluate_arithmetic(expr, state, depth)
     }
     ArithmeticPart::VariableAssignment { name, op, value } => {
-      let val = Box::pin(evaluate_arithmetic_part(value, state)).await?;
-      let mut applied_value = match op {
-        AssignmentOp::Assign => val.clone(),
-        _ => {
-          let var = state
-            .get_var(name)
-            .ok_or_else(|| miette::miette!("Undefined variable: {}", name))?;
-          let parsed_var = var.parse::<ArithmeticResult>().map_err(|e| {
-            miette::miette!("Failed to parse variable '{}': {}", name, e)
-          })?;
-          match op {
-            AssignmentOp::MultiplyAssign => val.checked_mul(&parsed_var),
-            AssignmentOp::DivideAssign => val.checked_div(&parsed_var),
-            AssignmentOp::ModuloAssign => val.checked_rem(&parsed_var),
-            AssignmentOp::AddAssign => val.checked_add(&parsed_var),
-            AssignmentOp::SubtractAssign => val.checked_sub(&parsed_var),
-            AssignmentOp::LeftShiftAssign => val.checked_shl(&parsed_var),
-            AssignmentOp::RightShiftAssign => val.checked_shr(&parsed_var),
-            AssignmentOp::BitwiseAndAssign => val.checked_and(&parsed_var),
-            AssignmentOp::BitwiseXorAssign => val.checked_xor(&parsed_var),
-            AssignmentOp::BitwiseOrAssign => val.checked_or(&parsed_var),
-            _ => unreachable!(),
-          }?
+      let value = evaluate_arithmetic_part(value, state, depth)?;
+      let operator = match op {
+        AssignmentOp::Assign => {
+          return assign_arithmetic_variable(name, value, state)
         }
+        AssignmentOp::MultiplyAssign => BinaryArithmeticOp::Multiply,
+        AssignmentOp::DivideAssign => BinaryArithmeticOp::Divide,
+        AssignmentOp::ModuloAssign => BinaryArithmeticOp::Modulo,
+        AssignmentOp::AddAssign => BinaryArithmeticOp::Add,
+        AssignmentOp::SubtractAssign => BinaryArithmeticOp::Subtract,
+        AssignmentOp::LeftShiftAssign => BinaryArithmeticOp::LeftShift,
+        AssignmentOp::RightShiftAssign => BinaryArithmeticOp::RightShift,
+        AssignmentOp::BitwiseAndAssign => BinaryArithmeticOp::BitwiseAnd,
+        AssignmentOp::BitwiseXorAssign => BinaryArithmeticOp::BitwiseXor,
+        AssignmentOp::BitwiseOrAssign => BinaryArithmeticOp::BitwiseOr,
       };
-      state.apply_env_var(name, &applied_value.to_string());
-      applied_value.with_changes(vec![EnvChange::SetShellVar(
-        name.clone(),
-        applied_value.to_string(),
-      )]);
-      Ok(applied_value)
+      let current = arithmetic_variable(name, state, depth)?;
+      let value = apply_binary_op(current, operator, value)?;
+      assign_arithmetic_variable(name, value, state)
     }
     ArithmeticPart::TripleConditionalExpr {
       condition,
       true_expr,
       false_expr,
     } => {
-      let cond = Box::pin(evaluate_arithmetic_part(condition, state)).await?;
-      if cond.is_zero() {
-        Box::pin(evaluate_arithmetic_part(true_expr, state)).await
+      let condition = evaluate_arithmetic_part(condition, state, depth)?;
+      // only the expression that is used is evaluated
+      let expr = if condition.is_zero() {
+        false_expr
       } else {
-        Box::pin(evaluate_arithmetic_part(false_expr, state)).await
-      }
+        true_expr
+      };
+      let result = evaluate_arithmetic_part(expr, state, depth)?;
+      let mut changes = condition.changes;
+      changes.extend(result.changes);
+      Ok(ArithmeticResult {
+        value: result.value,
+        changes,
+      })
+    }
+    ArithmeticPart::BinaryArithmeticExpr {
+      left,
+      operator:
+        operator
+        @ (BinaryArithmeticOp::LogicalAnd | BinaryArithmeticOp::LogicalOr),
+      right,
+    } => {
+      let lhs = evaluate_arithmetic_part(left, state, depth)?;
+      let lhs_true = !lhs.is_zero();
+      let mut changes = lhs.changes;
+      // the right side isn't evaluated when the left one decides
+      let value = match (operator, lhs_true) {
+        (BinaryArithmeticOp::LogicalAnd, false) => false,
+        (BinaryArithmeticOp::LogicalOr, true) => true,
+        _ => {
+          let rhs = evaluate_arithmetic_part(right, state, depth)?;
+          let rhs_true = !rhs.is_zero();
+          changes.extend(rhs.changes);
+          rhs_true
+        }
+      };
+      Ok(ArithmeticResult {
+        value: ArithmeticValue::Integer(value as i64),
+        changes,
+      })
     }
     ArithmeticPart::BinaryArithmeticExpr {
       left,
       operator,
       right,
     } => {
-      let lhs = Box::pin(evaluate_arithmetic_part(left, state)).await?;
-      let rhs = Box::pin(evaluate_arithmetic_part(right, state)).await?;
+      let lhs = evaluate_arithmetic_part(left, state, depth)?;
+      let rhs = evaluate_arithmetic_part(right, state, depth)?;
       apply_binary_op(lhs, *operator, rhs)
     }
     ArithmeticPart::BinaryConditionalExpr {
@@ -684,28 +1133,142 @@ async fn evaluate_arithmetic_part(
       operator,
       right,
     } => {
-      let lhs = Box::pin(evaluate_arithmetic_part(left, state)).await?;
-      let rhs = Box::pin(evaluate_arithmetic_part(right, state)).await?;
-      apply_conditional_binary_op(lhs, operator, rhs)
+      let lhs = evaluate_arithmetic_part(left, state, depth)?;
+      let rhs = evaluate_arithmetic_part(right, state, depth)?;
+      Ok(apply_conditional_binary_op(lhs, operator, rhs))
     }
     ArithmeticPart::UnaryArithmeticExpr { operator, operand } => {
-      let val = Box::pin(evaluate_arithmetic_part(operand, state)).await?;
+      let val = evaluate_arithmetic_part(operand, state, depth)?;
       apply_unary_op(*operator, val)
     }
-    ArithmeticPart::PostArithmeticExpr { operand, .. } => {
-      let val = Box::pin(evaluate_arithmetic_part(operand, state)).await?;
-      Ok(val)
+    ArithmeticPart::PreArithmeticExpr { name, operator }
+    | ArithmeticPart::PostArithmeticExpr { name, operator } => {
+      let current = arithmetic_variable(name, state, depth)?;
+      let one = ArithmeticResult::new(ArithmeticValue::Integer(1));
+      let value = match operator {
+        IncrementOp::Increment => current.checked_add(&one)?,
+        IncrementOp::Decrement => current.checked_sub(&one)?,
+      };
+      let result = assign_arithmetic_variable(name, value, state)?;
+      match part {
+        ArithmeticPart::PostArithmeticExpr { .. } => Ok(ArithmeticResult {
+          value: current.value,
+          changes: result.changes,
+        }),
+        _ => Ok(result),
+      }
     }
-    ArithmeticPart::Variable(name) => state
-      .get_var(name)
-      .and_then(|s| s.parse::<ArithmeticResult>().ok())
-      .ok_or_else(|| {
-        miette::miette!("Undefined or non-integer variable: {}", name)
-      }),
-    ArithmeticPart::Number(num_str) => num_str
-      .parse::<ArithmeticResult>()
-      .map_err(|e| miette::miette!(e.to_string())),
+    ArithmeticPart::Variable(name) => arithmetic_variable(name, state, depth),
+    ArithmeticPart::Number(text) => parse_arithmetic_number(text)
+      .map(ArithmeticResult::new)
+      .ok_or_else(|| miette::miette!("{}: invalid number", text)),
+  }
+}
+
+/// The value of a variable in an arithmetic expression. Like in bash, an
+/// unset or empty variable is 0, and a value that isn't a number is
+/// evaluated as an expression itself.
+fn arithmetic_variable(
+  name: &str,
+  state: &mut ShellState,
+  depth: usize,
+) -> Result<ArithmeticResult, Error> {
+  let value = match state.get_var(name) {
+    Some(value) => value,
+    None if state.shell_option(ShellOptions::NoUnset) => {
+      miette::bail!("{}: unbound variable", name)
+    }
+    None => String::new(),
+  };
+  let value = value.trim();
+  if value.is_empty() {
+    return Ok(ArithmeticResult::new(ArithmeticValue::Integer(0)));
+  }
+  if let Some(number) = parse_arithmetic_number(value) {
+    return Ok(ArithmeticResult::new(number));
+  }
+  if depth >= MAX_ARITHMETIC_DEPTH {
+    miette::bail!("{}: expression recursion level exceeded", name);
+  }
+  let arithmetic = crate::parser::parse_arithmetic(value)
+    .map_err(|_| miette::miette!("{}: syntax error in expression", value))?;
+  evaluate_arithmetic(&arithmetic, state, depth + 1)
+}
+
+fn assign_arithmetic_variable(
+  name: &str,
+  mut value: ArithmeticResult,
+  state: &mut ShellState,
+) -> Result<ArithmeticResult, Error> {
+  if state.var_attributes(name).readonly {
+    miette::bail!("{}: readonly variable", name);
+  }
+  let change = EnvChange::SetShellVar(name.to_string(), value.to_string());
+  state.apply_change(&change);
+  value.with_changes(vec![change]);
+  Ok(value)
+}
+
+/// Parses a number in an arithmetic expression or the value of a variable:
+/// `0x1f` in hex, `017` in octal, `base#digits` in a base from 2 to 64, or
+/// a decimal number.
+fn parse_arithmetic_number(text: &str) -> Option<ArithmeticValue> {
+  let (negative, digits) = match text.strip_prefix('-') {
+    Some(digits) => (true, digits),
+    None => (false, text.strip_prefix('+').unwrap_or(text)),
+  };
+  let is_digits = |text: &str, base: u32| {
+    !text.is_empty() && text.chars().all(|c| c.is_digit(base))
+  };
+  let value = if let Some(hex) = digits
+    .strip_prefix("0x")
+    .or_else(|| digits.strip_prefix("0X"))
+  {
+    is_digits(hex, 16).then(|| i64::from_str_radix(hex, 16).ok())??
+  } else if let Some((base, digits)) = digits.split_once('#') {
+    parse_in_base(is_digits(base, 10).then(|| base.parse().ok())??, digits)?
+  } else if is_digits(digits, 10) && digits.starts_with('0') {
+    i64::from_str_radix(digits, 8).ok()?
+  } else if is_digits(digits, 10) {
+    digits.parse().ok()?
+  } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+    let value = digits.parse::<f64>().ok()?;
+    return Some(ArithmeticValue::Float(if negative {
+      -value
+    } else {
+      value
+    }));
+  } else {
+    return None;
+  };
+  Some(ArithmeticValue::Integer(if negative {
+    value.wrapping_neg()
+  } else {
+    value
+  }))
+}
+
+/// Parses the digits of `base#digits`, which are `0-9`, `a-z`, `A-Z`, `@`
+/// and `_` in that order. Up to base 36 the letters are case insensitive.
+fn parse_in_base(base: u32, digits: &str) -> Option<i64> {
+  if !(2..=64).contains(&base) || digits.is_empty() {
+    return None;
   }
+  digits.chars().try_fold(0i64, |value, c| {
+    let digit = match c {
+      '0'..='9' => c as u32 - '0' as u32,
+      'a'..='z' => c as u32 - 'a' as u32 + 10,
+      'A'..='Z' if base <= 36 => c as u32 - 'A' as u32 + 10,
+      'A'..='Z' => c as u32 - 'A' as u32 + 36,
+      '@' => 62,
+      '_' => 63,
+      _ => return None,
+    };
+    if digit >= base {
+      return None;
+    }
+    value.checked_mul(base as i64)?.checked_add(digit as i64)
+  })
 }
 
 fn apply_binary_op(
@@ -725,16 +1288,12 @@ fn apply_binary_op(
     BinaryArithmeticOp::BitwiseAnd => lhs.checked_and(&rhs),
     BinaryArithmeticOp::BitwiseXor => lhs.checked_xor(&rhs),
     BinaryArithmeticOp::BitwiseOr => lhs.checked_or(&rhs),
-    BinaryArithmeticOp::LogicalAnd => Ok(if lhs.is_zero() && rhs.is_zero() {
-      ArithmeticResult::new(ArithmeticValue::Integer(0))
-    } else {
-      ArithmeticResult::new(ArithmeticValue::Integer(1))
-    }),
-    BinaryArithmeticOp::LogicalOr => Ok(if !lhs.is_zero() || !rhs.is_zero() {
-      ArithmeticResult::new(ArithmeticValue::Integer(1))
-    } else {
-      ArithmeticResult::new(ArithmeticValue::Integer(0))
-    }),
+    BinaryArithmeticOp::LogicalAnd => {
+      Ok(boolean_result(lhs, rhs, |lhs, rhs| lhs && rhs))
+    }
+    BinaryArithmeticOp::LogicalOr => {
+      Ok(boolean_result(lhs, rhs, |lhs, rhs| lhs || rhs))
+    }
   }
 }
 
@@ -742,38 +1301,50 @@ fn apply_conditional_binary_op(
   lhs: ArithmeticResult,
   op: &BinaryOp,
   rhs: ArithmeticResult,
-) -> Result<ArithmeticResult, Error> {
-  match op {
-    BinaryOp::Equal => Ok(if lhs == rhs {
-      ArithmeticResult::new(ArithmeticValue::Integer(1))
-    } else {
-      ArithmeticResult::new(ArithmeticValue::Integer(0))
-    }),
-    BinaryOp::NotEqual => Ok(if lhs != rhs {
-      ArithmeticResult::new(ArithmeticValue::Integer(1))
-    } else {
-      ArithmeticResult::new(ArithmeticValue::Integer(0))
-    }),
-    BinaryOp::LessThan => Ok(if lhs < rhs {
-      ArithmeticResult::new(ArithmeticValue::Integer(1))
-    } else {
-      ArithmeticResult::new(ArithmeticValue::Integer(0))
-    }),
-    BinaryOp::LessThanOrEqual => Ok(if lhs <= rhs {
-      ArithmeticResult::new(ArithmeticValue::Integer(1))
-    } else {
-      ArithmeticResult::new(ArithmeticValue::Integer(0))
-    }),
-    BinaryOp::GreaterThan => Ok(if lhs > rhs {
-      ArithmeticResult::new(ArithmeticValue::Integer(1))
-    } else {
-      ArithmeticResult::new(ArithmeticValue::Integer(0))
-    }),
-    BinaryOp::GreaterThanOrEqual => Ok(if lhs >= rhs {
-      ArithmeticResult::new(ArithmeticValue::Integer(1))
-    } else {
-      ArithmeticResult::new(ArithmeticValue::Integer(0))
-    }),
+) -> ArithmeticResult {
+  let ordering = match (&lhs.value, &rhs.value) {
+    (ArithmeticValue::Integer(lhs), ArithmeticValue::Integer(rhs)) => {
+      Some(lhs.cmp(rhs))
+    }
+    (lhs, rhs) => as_float(lhs).partial_cmp(&as_float(rhs)),
+  };
+  let is_true = ordering.is_some_and(|ordering| match op {
+    BinaryOp::Equal => ordering.is_eq(),
+    BinaryOp::NotEqual => ordering.is_ne(),
+    BinaryOp::LessThan => ordering.is_lt(),
+    BinaryOp::LessThanOrEqual => ordering.is_le(),
+    BinaryOp::GreaterThan => ordering.is_gt(),
+    BinaryOp::GreaterThanOrEqual => ordering.is_ge(),
+  });
+  // NaN is only unequal to everything
+  let is_true = is_true || (ordering.is_none() && *op == BinaryOp::NotEqual);
+  let mut changes = lhs.changes;
+  changes.extend(rhs.changes);
+  ArithmeticResult {
+    value: ArithmeticValue::Integer(is_true as i64),
+    changes,
+  }
+}
+
+fn as_float(value: &ArithmeticValue) -> f64 {
+  match value {
+    ArithmeticValue::Integer(value) => *value as f64,
+    ArithmeticValue::Float(value) => *value,
+  }
+}
+
+/// 1 or 0 for whether `op` is true for the operands being non-zero.
+fn boolean_result(
+  lhs: ArithmeticResult,
+  rhs: ArithmeticResult,
+  op: impl Fn(bool, bool) -> bool,
+) -> ArithmeticResult {
+  let value = op(!lhs.is_zero(), !rhs.is_zero());
+  let mut changes = lhs.changes;
+  changes.extend(rhs.changes);
+  ArithmeticResult {
+    value: ArithmeticValue::Integer(value as i64),
+    changes,
   }
 }
 
@@ -784,10 +1355,9 @@ fn apply_unary_op(
   match op {
     UnaryArithmeticOp::Plus => Ok(val),
     UnaryArithmeticOp::Minus => val.checked_neg(),
-    UnaryArithmeticOp::LogicalNot => Ok(if val.is_zero() {
-      ArithmeticResult::new(ArithmeticValue::Integer(1))
-    } else {
-      ArithmeticResult::new(ArithmeticValue::Integer(0))
+    UnaryArithmeticOp::LogicalNot => Ok(ArithmeticResult {
+      value: ArithmeticValue::Integer(val.is_zero() as i64),
+      changes: val.changes,
     }),
     UnaryArithmeticOp::BitwiseNot => val.checked_not(),
   }
@@ -795,7 +1365,7 @@ fn apply_unary_op(
 
 async fn execute_pipe_sequence(
   pipe_sequence: PipeSequence,
-  state: ShellState,
+  mut state: ShellState,
   stdin: ShellPipeReader,
   stdout: ShellPipeWriter,
   stderr: ShellPipeWriter,
@@ -803,8 +1373,17 @@ async fn execute_pipe_sequence(
   let mut wait_tasks = vec![];
   let mut last_output = Some(stdin);
   let mut next_inner: Option<PipelineInner> = Some(pipe_sequence.into());
+  state.enter_pipeline();
   while let Some(sequence) = next_inner.take() {
-    let (output_reader, output_writer) = pipe();
+    // the last command writes to the output directly, so that it can tell
+    // whether that is a terminal
+    let (output_reader, output_writer) =
+      if matches!(sequence, PipelineInner::Command(_)) {
+        (None, stdout.clone())
+      } else {
+        let (reader, writer) = pipe();
+        (Some(reader), writer)
+      };
     let (stderr, command) = match sequence {
       PipelineInner::PipeSequence(pipe_sequence) => {
         next_inner = Some(pipe_sequence.next);
@@ -818,50 +1397,67 @@ async fn execute_pipe_sequence(
       }
       PipelineInner::Command(command) => (stderr.clone(), command),
     };
+    // every command of a pipeline runs in a sub shell
+    let mut command_state = state.clone();
+    command_state.enter_subshell();
     wait_tasks.push(execute_command(
       command,
-      state.clone(),
+      command_state,
       last_output.take().unwrap(),
       output_writer.clone(),
       stderr.clone(),
     ));
-    last_output = Some(output_reader);
+    last_output = output_reader;
   }
-  let output_handle = tokio::task::spawn_blocking(|| {
-    last_output.unwrap().pipe_to_sender(stdout).unwrap();
-  });
   let mut results = futures::future::join_all(wait_tasks).await;
-  output_handle.await.unwrap();
+  let exit_codes: Vec<i32> =
+    results.iter().map(ExecuteResult::exit_code).collect();
+  // with `pipefail`, the rightmost failing command decides the exit code
+  let pipefail_code = if state.shell_option(ShellOptions::PipeFail) {
+    exit_codes.iter().copied().rfind(|code| *code != 0)
+  } else {
+    None
+  };
   let last_result = results.pop().unwrap();
 
   let (all_handles, changes): (Vec<_>, Vec<_>) = results
     .into_iter()
-    .map(|r| (r.into_handles_and_changes()))
+    .map(|r| r.into_handles_and_changes())
     .unzip();
   let all_handles: Vec<JoinHandle<i32>> =
     all_handles.into_iter().flatten().collect();
   let mut changes: Vec<EnvChange> = changes.into_iter().flatten().collect();
 
-  match last_result {
+  let result = match last_result {
     ExecuteResult::Exit(code, mut handles) => {
       handles.extend(all_handles);
-      ExecuteResult::Continue(code, changes, handles)
+      ExecuteResult::Continue(pipefail_code.unwrap_or(code), changes, handles)
     }
-    ExecuteResult::Continue(code, env_changes, mut handles) => {
+    ExecuteResult::Continue(code, env_changes, mut handles)
+    | ExecuteResult::Return(code, env_changes, mut handles) => {
+      // pipeline commands run in sub shells, so a `return` ends only them
       handles.extend(all_handles);
       changes.extend(env_changes);
-      ExecuteResult::Continue(code, changes, handles)
+      ExecuteResult::Continue(pipefail_code.unwrap_or(code), changes, handles)
     }
-  }
+    ExecuteResult::LoopControl(_, env_changes, mut handles) => {
+      // the same goes for `break` and `continue`
+      handles.extend(all_handles);
+      changes.extend(env_changes);
+      ExecuteResult::Continue(pipefail_code.unwrap_or(0), changes, handles)
+    }
+  };
+  with_pipe_status(result, &exit_codes)
 }
 
 async fn execute_subshell(
   list: Box<SequentialList>,
-  state: ShellState,
+  mut state: ShellState,
   stdin: ShellPipeReader,
   stdout: ShellPipeWriter,
   stderr: ShellPipeWriter,
 ) -> ExecuteResult {
+  state.enter_subshell();
   let result = execute_sequential_list(
     *list,
     state,
@@ -874,10 +1470,15 @@ async fn execute_subshell(
   .await;
 
   match result {
-    ExecuteResult::Exit(code, handles) => {
+    ExecuteResult::Exit(code, handles)
+    | ExecuteResult::Return(code, _, handles) => {
       // sub shells do not cause an exit
       ExecuteResult::Continue(code, Vec::new(), handles)
     }
+    ExecuteResult::LoopControl(_, _, handles) => {
+      // nor do they leave the loops of the parent
+      ExecuteResult::Continue(0, Vec::new(), handles)
+    }
     ExecuteResult::Continue(code, env_changes, handles) => {
       // env changes are not propagated
       ExecuteResult::Continue(code, env_changes, handles)
@@ -890,87 +1491,577 @@ async fn execute_if_clause(
   state: &mut ShellState,
   stdin: ShellPipeReader,
   stdout: ShellPipeWriter,
-  mut stderr: ShellPipeWriter,
+  stderr: ShellPipeWriter,
 ) -> ExecuteResult {
   let mut current_condition = if_clause.condition;
   let mut current_body = if_clause.then_body;
   let mut current_else = if_clause.else_part;
   let mut changes = Vec::new();
+  let mut handles = Vec::new();
 
   loop {
-    let condition_result = evaluate_condition(
+    let condition_result = execute_condition(
       current_condition,
       state,
       stdin.clone(),
+      stdout.clone(),
       stderr.clone(),
     )
     .await;
-    match condition_result {
-      Ok(ConditionalResult {
-        value: true,
-        changes: env_changes,
-      }) => {
+    let body = match condition_result {
+      Ok((
+        ConditionalResult {
+          value: true,
+          changes: env_changes,
+        },
+        condition_handles,
+      )) => {
         changes.extend(env_changes);
-        let exec_result = execute_sequential_list(
-          current_body,
-          state.clone(),
-          stdin,
-          stdout,
-          stderr,
-          AsyncCommandBehavior::Yield,
-        )
-        .await;
-        match exec_result {
-          ExecuteResult::Exit(code, handles) => {
-            return ExecuteResult::Exit(code, handles);
-          }
-          ExecuteResult::Continue(code, env_changes, handles) => {
-            changes.extend(env_changes);
-            return ExecuteResult::Continue(code, changes, handles);
-          }
-        }
+        handles.extend(condition_handles);
+        current_body
       }
-      Ok(ConditionalResult {
-        value: false,
-        changes: env_changes,
-      }) => {
+      Ok((
+        ConditionalResult {
+          value: false,
+          changes: env_changes,
+        },
+        condition_handles,
+      )) => {
         changes.extend(env_changes);
+        handles.extend(condition_handles);
         match current_else {
           Some(ElsePart::Elif(elif_clause)) => {
             current_condition = elif_clause.condition;
             current_body = elif_clause.then_body;
             current_else = elif_clause.else_part;
+            continue;
           }
-          Some(ElsePart::Else(else_body)) => {
-            let exec_result = execute_sequential_list(
-              else_body,
-              state.clone(),
-              stdin,
-              stdout,
-              stderr,
-              AsyncCommandBehavior::Yield,
-            )
-            .await;
-            match exec_result {
-              ExecuteResult::Exit(code, handles) => {
-                return ExecuteResult::Exit(code, handles);
-              }
-              ExecuteResult::Continue(code, env_changes, handles) => {
-                changes.extend(env_changes);
-                return ExecuteResult::Continue(code, changes, handles);
-              }
-            }
-          }
+          Some(ElsePart::Else(else_body)) => else_body,
           None => {
-            return ExecuteResult::Continue(0, changes, Vec::new());
+            return ExecuteResult::Continue(0, changes, handles);
           }
         }
       }
+      Err(result) => return result,
+    };
+    let exec_result = execute_sequential_list(
+      body,
+      state.clone(),
+      stdin,
+      stdout,
+      stderr,
+      AsyncCommandBehavior::Yield,
+    )
+    .await;
+    return match exec_result {
+      ExecuteResult::Exit(code, body_handles) => {
+        handles.extend(body_handles);
+        ExecuteResult::Exit(code, handles)
+      }
+      ExecuteResult::Continue(code, env_changes, body_handles) => {
+        changes.extend(env_changes);
+        handles.extend(body_handles);
+        ExecuteResult::Continue(code, changes, handles)
+      }
+      ExecuteResult::Return(code, env_changes, body_handles) => {
+        changes.extend(env_changes);
+        handles.extend(body_handles);
+        ExecuteResult::Return(code, changes, handles)
+      }
+      ExecuteResult::LoopControl(control, env_changes, body_handles) => {
+        changes.extend(env_changes);
+        handles.extend(body_handles);
+        ExecuteResult::LoopControl(control, changes, handles)
+      }
+    };
+  }
+}
+
+/// The exit code, changes and async commands accumulated over the
+/// iterations of a loop.
+#[derive(Default)]
+struct LoopResult {
+  exit_code: i32,
+  changes: Vec<EnvChange>,
+  handles: Vec<JoinHandle<i32>>,
+}
+
+impl LoopResult {
+  /// Adds the result of running the loop body once. Returns the result of
+  /// the whole loop if it should stop.
+  fn add_iteration(
+    &mut self,
+    result: ExecuteResult,
+    state: &mut ShellState,
+  ) -> Option<ExecuteResult> {
+    let (control, changes, handles) = match result {
+      ExecuteResult::Exit(code, handles) => {
+        self.handles.extend(handles);
+        return Some(ExecuteResult::Exit(
+          code,
+          std::mem::take(&mut self.handles),
+        ));
+      }
+      ExecuteResult::Return(code, changes, handles) => {
+        self.changes.extend(changes);
+        self.handles.extend(handles);
+        let result = std::mem::take(self);
+        return Some(ExecuteResult::Return(
+          code,
+          result.changes,
+          result.handles,
+        ));
+      }
+      ExecuteResult::Continue(code, changes, handles) => {
+        self.exit_code = code;
+        (None, changes, handles)
+      }
+      ExecuteResult::LoopControl(control, changes, handles) => {
+        self.exit_code = 0;
+        (Some(control), changes, handles)
+      }
+    };
+    state.apply_changes(&changes);
+    self.changes.extend(changes);
+    self.handles.extend(handles);
+    match control {
+      // the rest of the loop would not run anyway
+      _ if state.shell_option(ShellOptions::NoExec) => {
+        Some(std::mem::take(self).into_result())
+      }
+      None if state.exits_on_failure(self.exit_code) => {
+        Some(std::mem::take(self).into_result())
+      }
+      None => None,
+      Some(LoopControl::Break(count)) if count <= 1 => {
+        Some(std::mem::take(self).into_result())
+      }
+      Some(LoopControl::Continue(count)) if count <= 1 => None,
+      // leave this loop too and let the enclosing one handle the rest
+      Some(LoopControl::Break(count)) => {
+        let result = std::mem::take(self);
+        Some(ExecuteResult::LoopControl(
+          LoopControl::Break(count - 1),
+          result.changes,
+          result.handles,
+        ))
+      }
+      Some(LoopControl::Continue(count)) => {
+        let result = std::mem::take(self);
+        Some(ExecuteResult::LoopControl(
+          LoopControl::Continue(count - 1),
+          result.changes,
+          result.handles,
+        ))
+      }
+    }
+  }
+
+  fn into_result(self) -> ExecuteResult {
+    ExecuteResult::Continue(self.exit_code, self.changes, self.handles)
+  }
+}
+
+async fn execute_for_loop(
+  for_loop: ForLoop,
+  mut state: ShellState,
+  stdin: ShellPipeReader,
+  stdout: ShellPipeWriter,
+  mut stderr: ShellPipeWriter,
+) -> ExecuteResult {
+  let mut result = LoopResult::default();
+  let words = match evaluate_wordlist(
+    for_loop.wordlist,
+    &mut state,
+    &stdin,
+    &stderr,
+    &mut result,
+  )
+  .await
+  {
+    Ok(words) => words,
+    Err(err) => return err.into_exit_code(&state, &mut stderr),
+  };
+  state.enter_loop();
+  for word in words {
+    if state.token().is_cancelled() {
+      return ExecuteResult::for_cancellation();
+    }
+    let change = EnvChange::SetShellVar(for_loop.var_name.clone(), word);
+    state.apply_change(&change);
+    result.changes.push(change);
+    let body_result = execute_sequential_list(
+      for_loop.body.clone(),
+      state.clone(),
+      stdin.clone(),
+      stdout.clone(),
+      stderr.clone(),
+      AsyncCommandBehavior::Yield,
+    )
+    .await;
+    if let Some(result) = result.add_iteration(body_result, &mut state) {
+      return result;
+    }
+  }
+  result.into_result()
+}
+
+/// Evaluates the words of a for or select loop, which are the positional
+/// parameters when there is no `in` part.
+async fn evaluate_wordlist(
+  wordlist: Option<Vec<Word>>,
+  state: &mut ShellState,
+  stdin: &ShellPipeReader,
+  stderr: &ShellPipeWriter,
+  result: &mut LoopResult,
+) -> Result<Vec<String>, EvaluateWordTextError> {
+  match wordlist {
+    Some(wordlist) => {
+      let words =
+        evaluate_args(wordlist, state, stdin.clone(), stderr.clone()).await?;
+      state.apply_changes(&words.changes);
+      result.changes.extend(words.changes);
+      Ok(words.value)
+    }
+    None => Ok(state.positional_args().to_vec()),
+  }
+}
+
+/// Runs `select`, which prints a numbered menu of the words to stderr and
+/// then repeatedly prompts with `PS3` and reads a line from stdin. The line
+/// is stored in `REPLY` and the chosen word (or nothing, for an invalid
+/// choice) in the loop variable before the body is run. An empty line shows
+/// the menu again and the loop ends with `break` or at the end of the input.
+async fn execute_select_loop(
+  select_loop: ForLoop,
+  mut state: ShellState,
+  stdin: ShellPipeReader,
+  stdout: ShellPipeWriter,
+  mut stderr: ShellPipeWriter,
+) -> ExecuteResult {
+  let mut result = LoopResult::default();
+  let words = match evaluate_wordlist(
+    select_loop.wordlist,
+    &mut state,
+    &stdin,
+    &stderr,
+    &mut result,
+  )
+  .await
+  {
+    Ok(words) => words,
+    Err(err) => return err.into_exit_code(&state, &mut stderr),
+  };
+  if words.is_empty() {
+    return result.into_result();
+  }
+  state.enter_loop();
+  let mut show_menu = true;
+  loop {
+    if state.token().is_cancelled() {
+      return ExecuteResult::for_cancellation();
+    }
+    if show_menu {
+      let _ = stderr.write_all(format_select_menu(&words).as_bytes());
+      show_menu = false;
+    }
+    let prompt = state.get_var("PS3").unwrap_or_else(|| "#? ".to_string());
+    let _ = stderr.write_all(prompt.as_bytes());
+    let mut reader = stdin.clone();
+    let line = tokio::task::spawn_blocking(move || reader.read_line())
+      .await
+      .into_diagnostic()
+      .and_then(|line| line);
+    let line = match line {
+      Ok(Some(line)) => line,
+      Ok(None) => {
+        // like bash, end the prompt's line and fail at the end of input
+        let _ = stderr.write_line("");
+        result.exit_code = 1;
+        return result.into_result();
+      }
       Err(err) => {
-        return err.into_exit_code(&mut stderr);
+        let _ = stderr.write_line(&format!("select: {err}"));
+        result.exit_code = 1;
+        return result.into_result();
+      }
+    };
+    let reply = line.trim();
+    if reply.is_empty() {
+      show_menu = true;
+      continue;
+    }
+    let choice = reply
+      .parse::<usize>()
+      .ok()
+      .and_then(|n| words.get(n.checked_sub(1)?))
+      .cloned()
+      .unwrap_or_default();
+    for change in [
+      EnvChange::SetShellVar("REPLY".to_string(), reply.to_string()),
+      EnvChange::SetShellVar(select_loop.var_name.clone(), choice),
+    ] {
+      state.apply_change(&change);
+      result.changes.push(change);
+    }
+    let body_result = execute_sequential_list(
+      select_loop.body.clone(),
+      state.clone(),
+      stdin.clone(),
+      stdout.clone(),
+      stderr.clone(),
+      AsyncCommandBehavior::Yield,
+    )
+    .await;
+    if let Some(result) = result.add_iteration(body_result, &mut state) {
+      return result;
+    }
+  }
+}
+
+/// The menu of a select loop, one numbered word per line with the numbers
+/// aligned.
+fn format_select_menu(words: &[String]) -> String {
+  let width = words.len().to_string().len();
+  words
+    .iter()
+    .enumerate()
+    .map(|(index, word)| format!("{:>width$}) {word}\n", index + 1))
+    .collect()
+}
+
+async fn execute_while_loop(
+  while_loop: WhileLoop,
+  mut state: ShellState,
+  stdin: ShellPipeReader,
+  stdout: ShellPipeWriter,
+  stderr: ShellPipeWriter,
+) -> ExecuteResult {
+  let mut result = LoopResult::default();
+  state.enter_loop();
+  loop {
+    if state.token().is_cancelled() {
+      return ExecuteResult::for_cancellation();
+    }
+    let condition = match execute_condition(
+      while_loop.condition.clone(),
+      &mut state,
+      stdin.clone(),
+      stdout.clone(),
+      stderr.clone(),
+    )
+    .await
+    {
+      Ok((condition, handles)) => {
+        result.handles.extend(handles);
+        condition
       }
+      // the condition couldn't be evaluated
+      Err(ExecuteResult::Continue(code, changes, handles)) => {
+        result.exit_code = code;
+        result.changes.extend(changes);
+        result.handles.extend(handles);
+        return result.into_result();
+      }
+      Err(condition_result) => {
+        match result.add_iteration(condition_result, &mut state) {
+          Some(result) => return result,
+          None => continue,
+        }
+      }
+    };
+    state.apply_changes(&condition.changes);
+    result.changes.extend(condition.changes);
+    if condition.value == while_loop.until {
+      break;
+    }
+    let body_result = execute_sequential_list(
+      while_loop.body.clone(),
+      state.clone(),
+      stdin.clone(),
+      stdout.clone(),
+      stderr.clone(),
+      AsyncCommandBehavior::Yield,
+    )
+    .await;
+    if let Some(result) = result.add_iteration(body_result, &mut state) {
+      return result;
     }
   }
+  result.into_result()
+}
+
+async fn execute_case_clause(
+  case_clause: CaseClause,
+  state: &mut ShellState,
+  stdin: ShellPipeReader,
+  stdout: ShellPipeWriter,
+  mut stderr: ShellPipeWriter,
+) -> ExecuteResult {
+  // neither the word nor the patterns are expanded to file names
+  let pattern_state = |state: &ShellState| {
+    let mut state = state.clone();
+    state.set_shell_option(ShellOptions::NoGlob, true);
+    state
+  };
+  let mut word_state = pattern_state(state);
+  let mut changes = Vec::new();
+  let word = match evaluate_word(
+    case_clause.word,
+    &mut word_state,
+    stdin.clone(),
+    stderr.clone(),
+  )
+  .await
+  {
+    Ok(word) => word,
+    Err(err) => return err.into_exit_code(state, &mut stderr),
+  };
+  word_state.apply_changes(&word.changes);
+  state.apply_changes(&word.changes);
+  changes.extend(word.changes);
+
+  let mut exit_code = 0;
+  let mut handles = Vec::new();
+  let mut fall_through = false;
+  for item in case_clause.items {
+    if !fall_through {
+      let mut is_match = false;
+      for pattern in item.patterns {
+        let mut pattern_text = String::new();
+        for part in pattern.into_parts() {
+          // quoted parts of the pattern match literally
+          let is_quoted = matches!(part, WordPart::Quoted(_));
+          let part = match evaluate_word(
+            Word::new(vec![part]),
+            &mut word_state,
+            stdin.clone(),
+            stderr.clone(),
+          )
+          .await
+          {
+            Ok(part) => part,
+            Err(err) => return err.into_exit_code(state, &mut stderr),
+          };
+          word_state.apply_changes(&part.changes);
+          state.apply_changes(&part.changes);
+          changes.extend(part.changes);
+          if is_quoted {
+            pattern_text.push_str(&glob::Pattern::escape(&part.value));
+          } else {
+            pattern_text.push_str(&part.value);
+          }
+        }
+        if pattern_matches(&pattern_text, &word.value) {
+          is_match = true;
+          break;
+        }
+      }
+      if !is_match {
+        continue;
+      }
+    }
+
+    match execute_sequential_list(
+      item.body,
+      state.clone(),
+      stdin.clone(),
+      stdout.clone(),
+      stderr.clone(),
+      AsyncCommandBehavior::Yield,
+    )
+    .await
+    {
+      ExecuteResult::Exit(code, mut body_handles) => {
+        handles.append(&mut body_handles);
+        return ExecuteResult::Exit(code, handles);
+      }
+      ExecuteResult::Continue(code, env_changes, mut body_handles) => {
+        handles.append(&mut body_handles);
+        state.apply_changes(&env_changes);
+        changes.extend(env_changes);
+        exit_code = code;
+      }
+      ExecuteResult::Return(code, env_changes, mut body_handles) => {
+        handles.append(&mut body_handles);
+        changes.extend(env_changes);
+        return ExecuteResult::Return(code, changes, handles);
+      }
+      ExecuteResult::LoopControl(control, env_changes, mut body_handles) => {
+        handles.append(&mut body_handles);
+        changes.extend(env_changes);
+        return ExecuteResult::LoopControl(control, changes, handles);
+      }
+    }
+    match item.terminator {
+      CaseTerminator::Break => break,
+      CaseTerminator::FallThrough => fall_through = true,
+      CaseTerminator::Continue => {
+        // the patterns that follow see what the body changed
+        fall_through = false;
+        word_state = pattern_state(state);
+      }
+    }
+  }
+  ExecuteResult::Continue(exit_code, changes, handles)
+}
+
+/// Whether `text` matches the glob `pattern` of a `case` item. A pattern
+/// that isn't a valid glob only matches itself.
+pub fn pattern_matches(pattern: &str, text: &str) -> bool {
+  match glob::Pattern::new(pattern) {
+    Ok(glob) => glob.matches(text),
+    Err(_) => pattern == text,
+  }
+}
+
+/// Evaluates the condition of an `if` or a loop, along with the async
+/// commands it started. The error is the result to stop with, when the
+/// condition couldn't be evaluated or its commands left the current ones
+/// (e.g. with `exit` or `break`).
+async fn execute_condition(
+  condition: Condition,
+  state: &mut ShellState,
+  stdin: ShellPipeReader,
+  stdout: ShellPipeWriter,
+  mut stderr: ShellPipeWriter,
+) -> Result<(ConditionalResult, Vec<JoinHandle<i32>>), ExecuteResult> {
+  match condition.condition_inner {
+    ConditionInner::Command(list) => {
+      let mut condition_state = state.clone();
+      // like in bash, a failing condition doesn't exit with `set -e`
+      condition_state.set_shell_option(ShellOptions::ExitOnError, false);
+      let result = execute_sequential_list(
+        list,
+        condition_state,
+        stdin,
+        stdout,
+        stderr,
+        AsyncCommandBehavior::Yield,
+      )
+      .await;
+      match result {
+        ExecuteResult::Continue(code, changes, handles) => {
+          state.apply_changes(&changes);
+          state.set_last_command_exit_code(code);
+          Ok((ConditionalResult::new(code == 0, changes), handles))
+        }
+        result => Err(result),
+      }
+    }
+    condition_inner => evaluate_condition(
+      Condition { condition_inner },
+      state,
+      stdin,
+      stderr.clone(),
+    )
+    .await
+    .map(|result| {
+      state.set_last_command_exit_code(if result.value { 0 } else { 1 });
+      (result, Vec::new())
+    })
+    .map_err(|err| err.into_exit_code(state, &mut stderr)),
+  }
 }
 
 #[derive(Debug)]
@@ -1103,6 +2194,9 @@ async fn evaluate_condition(
         .into(),
       )
     }
+    ConditionInner::Command(_) => {
+      unreachable!("command conditions are run by execute_condition")
+    }
   }
 }
 
@@ -1113,13 +2207,23 @@ async fn execute_simple_command(
   mut stdout: ShellPipeWriter,
   mut stderr: ShellPipeWriter,
 ) -> ExecuteResult {
-  let args =
-    evaluate_args(command.args, state, stdin.clone(), stderr.clone()).await;
+  let (args, array_assignments) = take_array_assignments(command.args);
+  for assignment in &array_assignments {
+    let (ArrayAssignment::List { name, .. }
+    | ArrayAssignment::Element { name, .. }) = assignment;
+    if state.var_attributes(name).readonly {
+      return EvaluateWordTextError::ReadonlyVariable {
+        name: name.to_string(),
+      }
+      .into_exit_code(state, &mut stderr);
+    }
+  }
+  let args = evaluate_args(args, state, stdin.clone(), stderr.clone()).await;
 
   let (args, mut changes) = match args {
     Ok(args) => (args.value, args.changes),
     Err(err) => {
-      return err.into_exit_code(&mut stderr);
+      return err.into_exit_code(state, &mut stderr);
     }
   };
 
@@ -1131,33 +2235,97 @@ async fn execute_simple_command(
     let word_result = match word_result {
       Ok(word_result) => word_result,
       Err(err) => {
-        return err.into_exit_code(&mut stderr);
+        return err.into_exit_code(&state, &mut stderr);
       }
     };
-    state.apply_env_var(&env_var.name, &word_result.value);
-    changes.extend(word_result.changes);
-
+    let operator = if env_var.append { "+=" } else { "=" };
     if state.print_trace() {
-      let _ = stdout
-        .write_line(&format!("+ {:}={:}", env_var.name, word_result.value));
+      let _ = stdout.write_line(&format!(
+        "+ {:}{operator}{:}",
+        env_var.name, word_result.value
+      ));
     }
+    let value = match state.get_var(&env_var.name) {
+      Some(current) if env_var.append => current + &word_result.value,
+      _ => word_result.value,
+    };
+    state.apply_env_var(&env_var.name, &value);
+    changes.extend(word_result.changes);
   }
 
   if state.print_trace() {
     let _ = stdout.write_line(&format!("+ {:}", args.join(" ")));
   }
 
-  let result = execute_command_args(args, state, stdin, stdout, stderr).await;
+  let mut array_state = (!array_assignments.is_empty()).then(|| state.clone());
+  let result =
+    execute_command_args(args, state, stdin.clone(), stdout, stderr.clone())
+      .await;
   match result {
     ExecuteResult::Exit(code, handles) => ExecuteResult::Exit(code, handles),
     ExecuteResult::Continue(code, env_changes, handles) => {
       changes.extend(env_changes);
+      if let (0, Some(state)) = (code, array_state.as_mut()) {
+        // `declare` gave the variables their kind, which decides whether
+        // the subscripts are keys or indices
+        state.apply_changes(&changes);
+        for assignment in array_assignments {
+          let (ArrayAssignment::List { name, .. }
+          | ArrayAssignment::Element { name, .. }) = &assignment;
+          // the variable only becomes readonly once its value is assigned
+          let position = changes
+            .iter()
+            .rposition(|change| {
+              matches!(change, EnvChange::SetVarAttributes(var, attributes)
+                if var == name && attributes.readonly)
+            })
+            .unwrap_or(changes.len());
+          match execute_array_assignment(
+            assignment,
+            state,
+            stdin.clone(),
+            stderr.clone(),
+          )
+          .await
+          {
+            Ok((_, assignment_changes)) => {
+              state.apply_changes(&assignment_changes);
+              changes.splice(position..position, assignment_changes);
+            }
+            Err(err) => return err.into_exit_code(state, &mut stderr),
+          }
+        }
+      }
       ExecuteResult::Continue(code, changes, handles)
     }
+    ExecuteResult::Return(code, env_changes, handles) => {
+      changes.extend(env_changes);
+      ExecuteResult::Return(code, changes, handles)
+    }
+    ExecuteResult::LoopControl(control, env_changes, handles) => {
+      changes.extend(env_changes);
+      ExecuteResult::LoopControl(control, changes, handles)
+    }
   }
 }
 
-fn execute_command_args(
+/// Whether the command named by `word` runs on the shell's own thread, as a
+/// builtin or function, rather than as a process of its own.
+fn runs_in_shell(state: &ShellState, word: Option<&Word>) -> bool {
+  // a name that is only known once it is expanded could be either
+  let Some([WordPart::Text(name)]) = word.map(|word| word.parts().as_slice())
+  else {
+    return true;
+  };
+  let name = state
+    .alias_map()
+    .get(name)
+    .and_then(|alias| alias.first())
+    .unwrap_or(name);
+  state.function(name).is_some() || state.resolve_custom_command(name).is_some()
+}
+
+pub(crate) fn execute_command_args(
   mut args: Vec<String>,
   state: ShellState,
   stdin: ShellPipeReader,
@@ -1210,17 +2378,71 @@ fn execute_command_args(
         )
       }),
     };
-    match command_context.state.resolve_custom_command(&command_name) {
-      Some(command) => command.execute(command_context),
-      None => execute_unresolved_command_name(
-        UnresolvedCommandName {
-          name: command_name,
-          base_dir: command_context.state.cwd().to_path_buf(),
-        },
-        command_context,
-      ),
+    // functions take precedence over builtins and executables
+    match command_context.state.function(&command_name) {
+      Some(function) => execute_function(function, command_context),
+      None => execute_command_name(command_name, command_context),
+    }
+  }
+}
+
+/// Runs a builtin or an executable, without looking for a function of the
+/// same name like `command` does.
+pub(crate) fn execute_command_name(
+  command_name: String,
+  context: ShellCommandContext,
+) -> FutureExecuteResult {
+  match context.state.resolve_custom_command(&command_name) {
+    Some(command) => command.execute(context),
+    None => execute_unresolved_command_name(
+      UnresolvedCommandName {
+        name: command_name,
+        base_dir: context.state.cwd().to_path_buf(),
+      },
+      context,
+    ),
+  }
+}
+
+/// Calls a function with the arguments as its positional parameters.
+fn execute_function(
+  function: Rc<FunctionDefinition>,
+  mut context: ShellCommandContext,
+) -> FutureExecuteResult {
+  // like a sourced script, the function gets its own token so that a failing
+  // command (or `return 1`) doesn't cancel the caller
+  let mut state = context.state.with_child_token();
+  if !state.enter_function() {
+    let _ = context.stderr.write_line(&format!(
+      "{}: maximum function nesting level exceeded ({})",
+      function.name,
+      state.max_function_depth()
+    ));
+    return Box::pin(future::ready(ExecuteResult::from_exit_code(1)));
+  }
+  state.set_positional_args(context.args);
+  async move {
+    let result = execute_command(
+      (*function.body).clone(),
+      state,
+      context.stdin,
+      context.stdout,
+      context.stderr,
+    )
+    .await;
+    match result {
+      // `return` only leaves the function
+      ExecuteResult::Continue(code, mut changes, handles)
+      | ExecuteResult::Return(code, mut changes, handles) => {
+        // the caller's positional parameters are restored
+        changes
+          .retain(|change| !matches!(change, EnvChange::SetPositionalArgs(_)));
+        ExecuteResult::Continue(code, changes, handles)
+      }
+      result => result,
     }
   }
+  .boxed_local()
 }
 
 pub async fn evaluate_args(
@@ -1230,7 +2452,7 @@ pub async fn evaluate_args(
   stderr: ShellPipeWriter,
 ) -> Result<WordPartsResult, EvaluateWordTextError> {
   let mut result = WordPartsResult::new(Vec::new(), Vec::new());
-  for arg in args {
+  for arg in args.into_iter().flat_map(expand_braces) {
     let parts = evaluate_word_parts(
       arg.into_parts(),
       state,
@@ -1265,54 +2487,269 @@ pub enum EvaluateWordTextError {
   },
   #[error("glob: no matches found '{}'", pattern)]
   NoFilesMatched { pattern: String },
-  #[error("Failed to get home directory")]
-  FailedToGetHomeDirectory(miette::Error),
+  #[error("{0}")]
+  Other(miette::Error),
+  #[error("{}: unbound variable", name)]
+  UnboundVariable { name: String },
+  /// `${name:?message}` of an unset variable
+  #[error("{}: {}", name, message)]
+  ParameterNotSet { name: String, message: String },
+  #[error("{}: bad array subscript", name)]
+  BadArraySubscript { name: String },
+  #[error("{}: cannot assign in this way", name)]
+  CannotAssign { name: String },
+  #[error(
+    "{}: {}: must use subscript when assigning associative array",
+    name,
+    value
+  )]
+  AssocSubscriptRequired { name: String, value: String },
+  #[error("{}: readonly variable", name)]
+  ReadonlyVariable { name: String },
+  #[error(
+    "{}: process substitution is only supported in the arguments and redirects of commands",
+    text
+  )]
+  UnsupportedProcessSubstitution { text: String },
 }
 
 impl EvaluateWordTextError {
-  pub fn into_exit_code(self, stderr: &mut ShellPipeWriter) -> ExecuteResult {
+  pub fn into_exit_code(
+    self,
+    state: &ShellState,
+    stderr: &mut ShellPipeWriter,
+  ) -> ExecuteResult {
     let _ = stderr.write_line(&self.to_string());
-    ExecuteResult::from_exit_code(1)
+    match self {
+      // like bash, referencing an unset variable with `nounset` aborts
+      // the whole script rather than just the current command, but only
+      // the command at the prompt
+      Self::UnboundVariable { .. } | Self::ParameterNotSet { .. }
+        if !state.is_interactive() =>
+      {
+        ExecuteResult::Exit(1, Vec::new())
+      }
+      _ => ExecuteResult::from_exit_code(1),
+    }
   }
 }
 
 impl From<miette::Error> for EvaluateWordTextError {
   fn from(err: miette::Error) -> Self {
-    Self::FailedToGetHomeDirectory(err)
+    Self::Other(err)
   }
 }
 
+/// Evaluates the word of a modifier like `${name:-word}`. It is expanded
+/// like in double quotes, the expansion that the modifier is part of is
+/// globbed afterwards unless it is quoted itself.
+async fn evaluate_modifier_word(
+  word: &Word,
+  state: &mut ShellState,
+  stdin: ShellPipeReader,
+  stderr: ShellPipeWriter,
+) -> Result<WordResult, EvaluateWordTextError> {
+  let parts = vec![WordPart::Quoted(word.clone().into_parts())];
+  Ok(
+    evaluate_word_parts(parts, state, stdin, stderr)
+      .await?
+      .into(),
+  )
+}
+
+/// Evaluates the pattern of a modifier like `${name#pattern}`, where the
+/// quoted parts and the characters escaped with a backslash match
+/// literally. A pattern that isn't a valid glob only matches itself.
+async fn evaluate_modifier_pattern(
+  word: &Word,
+  state: &mut ShellState,
+  stdin: ShellPipeReader,
+  stderr: ShellPipeWriter,
+) -> Result<(glob::Pattern, Vec<EnvChange>), EvaluateWordTextError> {
+  let mut text = String::new();
+  let mut changes = Vec::new();
+  for part in word.parts() {
+    let is_quoted = matches!(part, WordPart::Quoted(_));
+    let part = evaluate_modifier_word(
+      &Word::new(vec![part.clone()]),
+      state,
+      stdin.clone(),
+      stderr.clone(),
+    )
+    .await?;
+    changes.extend(part.changes);
+    if is_quoted {
+      text.push_str(&glob::Pattern::escape(&part.value));
+    } else {
+      let mut chars = part.value.chars();
+      while let Some(c) = chars.next() {
+        match c {
+          '\\' => {
+            let escaped = chars.next().unwrap_or('\\');
+            text.push_str(&glob::Pattern::escape(&escaped.to_string()));
+          }
+          c => text.push(c),
+        }
+      }
+    }
+  }
+  let pattern = glob::Pattern::new(&text).unwrap_or_else(|_| {
+    glob::Pattern::new(&glob::Pattern::escape(&text)).unwrap()
+  });
+  Ok((pattern, changes))
+}
+
+/// The byte offsets of the characters of `text`, and of its end, so that
+/// patterns are matched against whole characters.
+fn char_boundaries(text: &str) -> Vec<usize> {
+  text
+    .char_indices()
+    .map(|(i, _)| i)
+    .chain([text.len()])
+    .collect()
+}
+
+/// `${name#pattern}` and `${name##pattern}`
+fn remove_prefix(
+  value: &str,
+  pattern: &glob::Pattern,
+  longest: bool,
+) -> String {
+  let mut ends = char_boundaries(value);
+  if longest {
+    ends.reverse();
+  }
+  match ends.into_iter().find(|end| pattern.matches(&value[..*end])) {
+    Some(end) => value[end..].to_string(),
+    None => value.to_string(),
+  }
+}
+
+/// `${name%pattern}` and `${name%%pattern}`
+fn remove_suffix(
+  value: &str,
+  pattern: &glob::Pattern,
+  longest: bool,
+) -> String {
+  let mut starts = char_boundaries(value);
+  if !longest {
+    starts.reverse();
+  }
+  match starts
+    .into_iter()
+    .find(|start| pattern.matches(&value[*start..]))
+  {
+    Some(start) => value[..start].to_string(),
+    None => value.to_string(),
+  }
+}
+
+/// `${name/pattern/replacement}`, which replaces the longest match that
+/// starts first, and its variants. Only the anchored ones replace an empty
+/// match.
+fn replace_matches(
+  value: &str,
+  pattern: &glob::Pattern,
+  replacement: &str,
+  mode: ReplaceMode,
+) -> String {
+  let bounds = char_boundaries(value);
+  match mode {
+    ReplaceMode::Prefix => {
+      match bounds
+        .iter()
+        .rev()
+        .find(|end| pattern.matches(&value[..**end]))
+      {
+        Some(end) => format!("{replacement}{}", &value[*end..]),
+        None => value.to_string(),
+      }
+    }
+    ReplaceMode::Suffix => {
+      match bounds
+        .iter()
+        .find(|start| pattern.matches(&value[**start..]))
+      {
+        Some(start) => format!("{}{replacement}", &value[..*start]),
+        None => value.to_string(),
+      }
+    }
+    ReplaceMode::First | ReplaceMode::All => {
+      let mut result = String::new();
+      let mut copied = 0;
+      let mut i = 0;
+      while i < bounds.len() {
+        let start = bounds[i];
+        let end = bounds[i + 1..]
+          .iter()
+          .rev()
+          .position(|end| pattern.matches(&value[start..*end]))
+          .map(|position| bounds.len() - 1 - position);
+        match end {
+          Some(end) => {
+            result.push_str(&value[copied..start]);
+            result.push_str(replacement);
+            copied = bounds[end];
+            if mode == ReplaceMode::First {
+              break;
+            }
+            i = end;
+          }
+          None => i += 1,
+        }
+      }
+      result.push_str(&value[copied..]);
+      result
+    }
+  }
+}
+
+/// Evaluates the offset or length of `${name:offset:length}`, which is an
+/// arithmetic expression.
+async fn evaluate_substring_index(
+  word: Word,
+  state: &mut ShellState,
+  stdin: ShellPipeReader,
+  stderr: ShellPipeWriter,
+) -> Result<(i64, Vec<EnvChange>), Error> {
+  let text = evaluate_word(word, state, stdin, stderr)
+    .await
+    .into_diagnostic()?;
+  let mut changes = text.changes;
+  let (value, arithmetic_changes) = evaluate_integer_value(&text.value, state)?;
+  changes.extend(arithmetic_changes);
+  let value = value
+    .parse::<i64>()
+    .map_err(|_| miette::miette!("Invalid integer: {}", text.value))?;
+  Ok((value, changes))
+}
+
 impl VariableModifier {
+  /// Applies the modifier to `value`, the current value of the variable
+  /// `name`.
   pub async fn apply(
     &self,
     name: &str,
+    value: Option<String>,
     state: &mut ShellState,
     stdin: ShellPipeReader,
     stderr: ShellPipeWriter,
-  ) -> Result<(Text, Option<Vec<EnvChange>>), miette::Report> {
+  ) -> Result<(Text, Option<Vec<EnvChange>>), EvaluateWordTextError> {
     match self {
-      VariableModifier::DefaultValue(default_value) => {
-        match state.get_var(name) {
-          Some(v) => {
-            let t: Text =
-              Text::new([OtherText(v.clone().to_string())].to_vec());
-            Ok((t, None))
-          }
+      VariableModifier::DefaultValue { value: word, colon } => {
+        match value.filter(|value| !(*colon && value.is_empty())) {
+          Some(v) => Ok((Text::new(vec![OtherText(v)]), None)),
           None => {
-            let v = evaluate_word(default_value.clone(), state, stdin, stderr)
-              .await
-              .into_diagnostic()?;
+            let v = evaluate_modifier_word(word, state, stdin, stderr).await?;
             Ok((v.value.into(), Some(v.changes)))
           }
         }
       }
-      VariableModifier::AssignDefault(default_value) => {
-        match state.get_var(name) {
-          Some(v) => Ok((v.clone().into(), None)),
+      VariableModifier::AssignDefault { value: word, colon } => {
+        match value.filter(|value| !(*colon && value.is_empty())) {
+          Some(v) => Ok((Text::new(vec![OtherText(v)]), None)),
           None => {
-            let v = evaluate_word(default_value.clone(), state, stdin, stderr)
-              .await
-              .into_diagnostic()?;
+            let v = evaluate_modifier_word(word, state, stdin, stderr).await?;
             state.apply_env_var(name, &v.value);
             let mut changes = v.changes;
             changes
@@ -1321,23 +2758,40 @@ impl VariableModifier {
           }
         }
       }
+      VariableModifier::ErrorIfUnset { message, colon } => {
+        match value.filter(|value| !(*colon && value.is_empty())) {
+          Some(v) => Ok((Text::new(vec![OtherText(v)]), None)),
+          None => {
+            let message = if message.parts().is_empty() {
+              if *colon {
+                "parameter null or not set".to_string()
+              } else {
+                "parameter not set".to_string()
+              }
+            } else {
+              evaluate_modifier_word(message, state, stdin, stderr)
+                .await?
+                .value
+            };
+            Err(EvaluateWordTextError::ParameterNotSet {
+              name: name.to_string(),
+              message,
+            })
+          }
+        }
+      }
       VariableModifier::Substring { begin, length } => {
-        if let Some(val) = state.get_var(name) {
+        if let Some(val) = value {
           let chars: Vec<char> = val.chars().collect();
 
-          let mut changes = Vec::new();
-
           // TODO figure out a way to get rid of cloning stdin and stderr
-          let begin =
-            evaluate_word(begin.clone(), state, stdin.clone(), stderr.clone())
-              .await
-              .into_diagnostic()
-              .and_then(|v| {
-                changes.extend(v.clone().changes); // TODO figure out a way to get rid of cloning here
-                v.to_integer().map_err(|e| {
-                  miette::miette!("Failed to parse start index: {:?}", e)
-                })
-              })?;
+          let (begin, mut changes) = evaluate_substring_index(
+            begin.clone(),
+            state,
+            stdin.clone(),
+            stderr.clone(),
+          )
+          .await?;
 
           let start = if begin < 0 {
             chars
@@ -1348,15 +2802,10 @@ impl VariableModifier {
           };
           let end = match length {
             Some(len) => {
-              let len = evaluate_word(len.clone(), state, stdin, stderr)
-                .await
-                .into_diagnostic()
-                .and_then(|v| {
-                  changes.extend(v.clone().changes); // TODO figure out a way to get rid of cloning here
-                  v.to_integer().map_err(|e| {
-                    miette::miette!("Failed to parse start index: {:?}", e)
-                  })
-                })?;
+              let (len, len_changes) =
+                evaluate_substring_index(len.clone(), state, stdin, stderr)
+                  .await?;
+              changes.extend(len_changes);
 
               if len < 0 {
                 chars
@@ -1370,39 +2819,85 @@ impl VariableModifier {
             None => chars.len(),
           };
           if start > end {
-            Err(miette::miette!(
-              "Invalid substring range: {}..{}",
-              start,
-              end
-            ))
+            Err(
+              miette::miette!("Invalid substring range: {}..{}", start, end)
+                .into(),
+            )
           } else {
             Ok((chars[start..end].iter().collect(), Some(changes)))
           }
         } else {
-          Err(miette::miette!("Undefined variable: {}", name))
+          Err(miette::miette!("Undefined variable: {}", name).into())
         }
       }
-      VariableModifier::AlternateValue(default_value) => {
-        let val = state.get_var(name);
-        if val.is_none() || val.unwrap().is_empty() {
+      VariableModifier::AlternateValue { value: word, colon } => {
+        if value.is_none_or(|value| *colon && value.is_empty()) {
           Ok(("".to_string().into(), None))
         } else {
-          let v = evaluate_word(default_value.clone(), state, stdin, stderr)
-            .await
-            .into_diagnostic()?;
+          let v = evaluate_modifier_word(word, state, stdin, stderr).await?;
           Ok((v.value.into(), Some(v.changes)))
         }
       }
+      VariableModifier::RemovePrefix { pattern, longest }
+      | VariableModifier::RemoveSuffix { pattern, longest } => {
+        let Some(value) = value else {
+          return unset_pattern_value(name, state);
+        };
+        let (pattern, changes) =
+          evaluate_modifier_pattern(pattern, state, stdin, stderr).await?;
+        let value = if matches!(self, VariableModifier::RemovePrefix { .. }) {
+          remove_prefix(&value, &pattern, *longest)
+        } else {
+          remove_suffix(&value, &pattern, *longest)
+        };
+        Ok((Text::new(vec![OtherText(value)]), Some(changes)))
+      }
+      VariableModifier::Replace {
+        pattern,
+        replacement,
+        mode,
+      } => {
+        let Some(value) = value else {
+          return unset_pattern_value(name, state);
+        };
+        let (pattern, mut changes) = evaluate_modifier_pattern(
+          pattern,
+          state,
+          stdin.clone(),
+          stderr.clone(),
+        )
+        .await?;
+        let replacement =
+          evaluate_modifier_word(replacement, state, stdin, stderr).await?;
+        changes.extend(replacement.changes);
+        let value =
+          replace_matches(&value, &pattern, &replacement.value, *mode);
+        Ok((Text::new(vec![OtherText(value)]), Some(changes)))
+      }
     }
   }
 }
 
+/// The result of a pattern modifier of an unset variable, which is empty
+/// unless `nounset` is set.
+fn unset_pattern_value(
+  name: &str,
+  state: &ShellState,
+) -> Result<(Text, Option<Vec<EnvChange>>), EvaluateWordTextError> {
+  if state.shell_option(ShellOptions::NoUnset) {
+    return Err(EvaluateWordTextError::UnboundVariable {
+      name: name.to_string(),
+    });
+  }
+  Ok((Text::new(Vec::new()), None))
+}
+
 fn evaluate_word_parts(
   parts: Vec<WordPart>,
   state: &mut ShellState,
   stdin: ShellPipeReader,
   stderr: ShellPipeWriter,
-) -> LocalBoxFuture<Result<WordPartsResult, EvaluateWordTextError>> {
+) -> LocalBoxFuture<'_, Result<WordPartsResult, EvaluateWordTextError>> {
   fn text_parts_to_string(parts: Vec<TextPart>) -> String {
     let mut result =
       String::with_capacity(parts.iter().map(|p| p.as_str().len()).sum());
@@ -1418,6 +2913,7 @@ fn evaluate_word_parts(
     is_quoted: bool,
   ) -> Result<WordPartsResult, EvaluateWordTextError> {
     if !is_quoted
+      && !state.shell_option(ShellOptions::NoGlob)
       && text_parts
         .iter()
         .filter_map(|p| match p {
@@ -1427,7 +2923,10 @@ fn evaluate_word_parts(
         .any(|text| text.chars().any(|c| matches!(c, '?' | '*' | '[')))
     {
       let mut current_text = String::new();
+      // the word itself, for when the pattern matches nothing
+      let mut literal = String::new();
       for text_part in text_parts {
+        literal.push_str(text_part.as_str());
         match text_part {
           TextPart::Quoted(text) => {
             for c in text.chars() {
@@ -1447,49 +2946,35 @@ fn evaluate_word_parts(
           }
         }
       }
-      let is_absolute = std::path::PathBuf::from(&current_text).is_absolute();
       let cwd = state.cwd();
-      let pattern = if is_absolute {
-        current_text
+      let pattern = if Path::new(&current_text).is_absolute() {
+        current_text.clone()
       } else {
         format!("{}/{}", cwd.display(), current_text)
       };
-      let result = glob::glob_with(
-        &pattern,
-        glob::MatchOptions {
-          // false because it should work the same way on case insensitive file systems
-          case_sensitive: false,
-          // true because it copies what sh does
-          require_literal_separator: true,
-          // true because it copies with sh does—these files are considered "hidden"
-          require_literal_leading_dot: true,
-        },
-      );
-      match result {
+      let dot_files = state.shell_option(ShellOptions::DotGlob);
+      let error = match expand_pathname(&current_text, cwd, dot_files) {
         Ok(paths) => {
-          let paths =
-            paths.into_iter().filter_map(|p| p.ok()).collect::<Vec<_>>();
-          if paths.is_empty() {
-            Err(EvaluateWordTextError::NoFilesMatched { pattern })
-          } else {
-            let paths = if is_absolute {
-              paths
-                .into_iter()
-                .map(|p| p.display().to_string())
-                .collect::<Vec<_>>()
-            } else {
-              paths
-                .into_iter()
-                .map(|p| {
-                  let path = p.strip_prefix(cwd).unwrap();
-                  path.display().to_string()
-                })
-                .collect::<Vec<_>>()
-            };
-            Ok(WordPartsResult::new(paths, Vec::new()))
+          let mut paths = paths.collect::<Vec<_>>();
+          if !paths.is_empty() {
+            paths.sort();
+            return Ok(WordPartsResult::new(paths, Vec::new()));
           }
+          EvaluateWordTextError::NoFilesMatched { pattern }
+        }
+        Err(mut err) => {
+          err.pos += pattern.len() - current_text.len();
+          EvaluateWordTextError::InvalidPattern { pattern, err }
         }
-        Err(err) => Err(EvaluateWordTextError::InvalidPattern { pattern, err }),
+      };
+      // a pattern that can't match anything is removed with `nullglob`,
+      // an error with `failglob` and otherwise kept as it is
+      if state.shell_option(ShellOptions::NullGlob) {
+        Ok(WordPartsResult::new(Vec::new(), Vec::new()))
+      } else if state.shell_option(ShellOptions::FailGlob) {
+        Err(error)
+      } else {
+        Ok(WordPartsResult::new(vec![literal], Vec::new()))
       }
     } else {
       Ok(WordPartsResult {
@@ -1505,7 +2990,7 @@ fn evaluate_word_parts(
     state: &mut ShellState,
     stdin: ShellPipeReader,
     stderr: ShellPipeWriter,
-  ) -> LocalBoxFuture<Result<WordPartsResult, EvaluateWordTextError>> {
+  ) -> LocalBoxFuture<'_, Result<WordPartsResult, EvaluateWordTextError>> {
     // recursive async, so requires boxing
     async move {
       let mut result = WordPartsResult::new(Vec::new(), Vec::new());
@@ -1516,10 +3001,21 @@ fn evaluate_word_parts(
             current_text.push(TextPart::Text(text));
             continue;
           }
+          WordPart::Variable(name, None) if name == "@" => {
+            // `$@` expands to one word per positional argument
+            let args = state.positional_args().iter().cloned();
+            Ok(Some(Text::new(args.map(OtherText).collect())))
+          }
           WordPart::Variable(name, modifier) => {
             if let Some(modifier) = modifier {
               let (text, env_changes) = modifier
-                .apply(&name, state, stdin.clone(), stderr.clone())
+                .apply(
+                  &name,
+                  state.get_var(&name),
+                  state,
+                  stdin.clone(),
+                  stderr.clone(),
+                )
                 .await?;
               if let Some(env_changes) = env_changes {
                 result.with_changes(env_changes);
@@ -1531,10 +3027,157 @@ fn evaluate_word_parts(
               let t: Text =
                 Text::new([OtherText(val.clone().to_string())].to_vec());
               Ok(Some(t))
+            } else if state.shell_option(ShellOptions::NoUnset) {
+              return Err(EvaluateWordTextError::UnboundVariable { name });
             } else {
               Err(miette::miette!("Undefined variable: {}", name))
             }
           }
+          WordPart::Array(name, subscript, modifier) => {
+            let values = array_values(state, &name);
+            match subscript {
+              ArraySubscript::Index(index) => {
+                let (index, changes) = evaluate_array_key(
+                  &name,
+                  index,
+                  state,
+                  stdin.clone(),
+                  stderr.clone(),
+                )
+                .await?;
+                result.with_changes(changes);
+                let value = array_element(state, &name, &index);
+                if let Some(modifier) = modifier {
+                  if matches!(*modifier, VariableModifier::AssignDefault { .. })
+                  {
+                    return Err(EvaluateWordTextError::CannotAssign {
+                      name: format!("{name}[{index}]"),
+                    });
+                  }
+                  let (text, env_changes) = modifier
+                    .apply(&name, value, state, stdin.clone(), stderr.clone())
+                    .await?;
+                  if let Some(env_changes) = env_changes {
+                    result.with_changes(env_changes);
+                  }
+                  Ok(Some(text))
+                } else if let Some(value) = value {
+                  Ok(Some(Text::new(vec![OtherText(value)])))
+                } else if state.shell_option(ShellOptions::NoUnset) {
+                  return Err(EvaluateWordTextError::UnboundVariable {
+                    name: format!("{name}[{index}]"),
+                  });
+                } else {
+                  Ok(None)
+                }
+              }
+              ArraySubscript::All | ArraySubscript::AllJoined => {
+                let mut values = values;
+                match modifier.map(|modifier| *modifier) {
+                  None => {}
+                  Some(VariableModifier::Substring { begin, length }) => {
+                    let (sliced, changes) = slice_array(
+                      array_entries(state, &name),
+                      begin,
+                      length,
+                      state,
+                      stdin.clone(),
+                      stderr.clone(),
+                    )
+                    .await?;
+                    values = sliced;
+                    result.with_changes(changes);
+                  }
+                  Some(VariableModifier::AssignDefault { .. }) => {
+                    return Err(EvaluateWordTextError::CannotAssign {
+                      name: format!("{name}[@]"),
+                    });
+                  }
+                  Some(
+                    modifier @ (VariableModifier::RemovePrefix { .. }
+                    | VariableModifier::RemoveSuffix { .. }
+                    | VariableModifier::Replace { .. }),
+                  ) => {
+                    // the pattern modifiers apply to each element
+                    let mut modified = Vec::with_capacity(values.len());
+                    for value in values {
+                      let (text, env_changes) = modifier
+                        .apply(
+                          &name,
+                          Some(value),
+                          state,
+                          stdin.clone(),
+                          stderr.clone(),
+                        )
+                        .await?;
+                      modified.push(text_parts_to_string(text.into_parts()));
+                      if let Some(env_changes) = env_changes {
+                        result.with_changes(env_changes);
+                      }
+                    }
+                    values = modified;
+                  }
+                  // the other modifiers treat the array as a single value
+                  Some(modifier) => {
+                    let value = (!values.is_empty()).then(|| values.join(" "));
+                    let (text, env_changes) = modifier
+                      .apply(&name, value, state, stdin.clone(), stderr.clone())
+                      .await?;
+                    values = text
+                      .into_parts()
+                      .into_iter()
+                      .map(|part| part.as_str().to_string())
+                      .collect();
+                    if let Some(env_changes) = env_changes {
+                      result.with_changes(env_changes);
+                    }
+                  }
+                }
+                if values.is_empty() {
+                  Ok(None)
+                } else if subscript == ArraySubscript::AllJoined {
+                  Ok(Some(Text::new(vec![OtherText(values.join(" "))])))
+                } else {
+                  Ok(Some(Text::new(
+                    values.into_iter().map(OtherText).collect(),
+                  )))
+                }
+              }
+            }
+          }
+          WordPart::Length(name, subscript) => {
+            let length = match subscript {
+              None => state.get_var(&name).map_or(0, |v| v.chars().count()),
+              Some(ArraySubscript::All | ArraySubscript::AllJoined) => {
+                array_values(state, &name).len()
+              }
+              Some(ArraySubscript::Index(index)) => {
+                let (index, changes) = evaluate_array_key(
+                  &name,
+                  index,
+                  state,
+                  stdin.clone(),
+                  stderr.clone(),
+                )
+                .await?;
+                result.with_changes(changes);
+                array_element(state, &name, &index)
+                  .map_or(0, |value| value.chars().count())
+              }
+            };
+            current_text.push(TextPart::Text(length.to_string()));
+            continue;
+          }
+          WordPart::ArrayKeys(name, subscript) => {
+            let keys = array_keys(state, &name);
+            if keys.is_empty() {
+              Ok(None)
+            } else if subscript == ArraySubscript::AllJoined {
+              Ok(Some(Text::new(vec![OtherText(keys.join(" "))])))
+            } else {
+              Ok(Some(Text::new(keys.into_iter().map(OtherText).collect())))
+            }
+          }
           WordPart::Command(list) => {
             let cmd = evaluate_command_substitution(
               list,
@@ -1546,7 +3189,29 @@ fn evaluate_word_parts(
             .await;
             Ok(Some(cmd.into()))
           }
+          WordPart::ArrayAssignment(assignment) => {
+            // these are assigned by `execute_simple_command`, so only the
+            // name is left to declare
+            let (ArrayAssignment::List { name, .. }
+            | ArrayAssignment::Element { name, .. }) = assignment;
+            current_text.push(TextPart::Text(name));
+            continue;
+          }
+          WordPart::ProcessSubstitution(substitution) => {
+            return Err(
+              EvaluateWordTextError::UnsupportedProcessSubstitution {
+                text: substitution.text,
+              },
+            );
+          }
           WordPart::Quoted(parts) => {
+            let splits_words = parts.iter().any(|part| {
+              matches!(
+                part,
+                WordPart::Array(_, ArraySubscript::All, _)
+                  | WordPart::ArrayKeys(_, ArraySubscript::All)
+              ) || matches!(part, WordPart::Variable(name, None) if name == "@")
+            });
             let res = evaluate_word_parts_inner(
               parts,
               true,
@@ -1561,26 +3226,34 @@ fn evaluate_word_parts(
               changes: env_changes,
             } = res;
             result.with_changes(env_changes);
-            current_text.push(TextPart::Quoted(value.join(" ")));
+            if !splits_words {
+              current_text.push(TextPart::Quoted(value.join(" ")));
+              continue;
+            }
+            // `"${array[@]}"` and `"$@"` expand to one word per element,
+            // joined with the text around the quotes at the start and the end
+            let mut words = value.into_iter();
+            if let Some(first) = words.next() {
+              current_text.push(TextPart::Quoted(first));
+            }
+            for word in words {
+              result.extend(evaluate_word_text(
+                state,
+                std::mem::take(&mut current_text),
+                is_quoted,
+              )?);
+              current_text.push(TextPart::Quoted(word));
+            }
             continue;
           }
           WordPart::Tilde(tilde_prefix) => {
-            if tilde_prefix.only_tilde() {
-              let home_str = dirs::home_dir()
-                .ok_or_else(|| miette::miette!("Failed to get home directory"))?
-                .display()
-                .to_string();
-              current_text.push(TextPart::Text(home_str));
-              continue;
-            } else {
-              Err(miette::miette!(
-                "Tilde expansion with username is not supported."
-              ))
-            }
+            current_text
+              .push(TextPart::Text(expand_tilde(state, &tilde_prefix)));
+            continue;
           }
           WordPart::Arithmetic(arithmetic) => {
             let arithmetic_result =
-              execute_arithmetic_expression(arithmetic, state).await?;
+              execute_arithmetic_expression(arithmetic, state)?;
             current_text.push(TextPart::Text(arithmetic_result.to_string()));
             result.with_changes(arithmetic_result.changes);
             continue;
@@ -1635,16 +3308,336 @@ fn evaluate_word_parts(
   evaluate_word_parts_inner(parts, false, state, stdin, stderr)
 }
 
+/// The elements of the array `name`. Like in bash, a scalar variable is an
+/// array with a single element.
+fn array_values(state: &ShellState, name: &str) -> Vec<String> {
+  if let Some(map) = state.get_assoc(name) {
+    return map.values().cloned().collect();
+  }
+  indexed_array(state, name).into_values().collect()
+}
+
+/// The indexed array `name`, where a scalar variable is the element with
+/// the index `0`.
+fn indexed_array(state: &ShellState, name: &str) -> BTreeMap<usize, String> {
+  match state.get_array(name) {
+    Some(array) => array.clone(),
+    None => state
+      .get_var(name)
+      .map(|value| BTreeMap::from([(0, value)]))
+      .unwrap_or_default(),
+  }
+}
+
+/// The elements of the array `name` with their indices. The elements of an
+/// associative array are indexed by their position.
+fn array_entries(state: &ShellState, name: &str) -> Vec<(usize, String)> {
+  match state.get_assoc(name) {
+    Some(map) => map.values().cloned().enumerate().collect(),
+    None => indexed_array(state, name).into_iter().collect(),
+  }
+}
+
+/// The keys of an associative array, or the indices of an indexed one.
+fn array_keys(state: &ShellState, name: &str) -> Vec<String> {
+  if let Some(map) = state.get_assoc(name) {
+    return map.keys().cloned().collect();
+  }
+  indexed_array(state, name)
+    .into_keys()
+    .map(|index| index.to_string())
+    .collect()
+}
+
+/// The index after the last element of `array`
+fn array_end(array: &BTreeMap<usize, String>) -> usize {
+  array.keys().next_back().map_or(0, |index| index + 1)
+}
+
+/// An evaluated array index, which is a string for associative arrays.
+enum ArrayKey {
+  Index(i64),
+  Key(String),
+}
+
+impl std::fmt::Display for ArrayKey {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ArrayKey::Index(index) => write!(f, "{index}"),
+      ArrayKey::Key(key) => write!(f, "{key}"),
+    }
+  }
+}
+
+async fn evaluate_array_key(
+  name: &str,
+  index: ArrayIndex,
+  state: &mut ShellState,
+  stdin: ShellPipeReader,
+  stderr: ShellPipeWriter,
+) -> Result<(ArrayKey, Vec<EnvChange>), EvaluateWordTextError> {
+  let is_assoc = state.get_assoc(name).is_some();
+  let word = match index {
+    // `map[a b]` is the key `a b` and not an arithmetic expression
+    ArrayIndex::Arithmetic(_, text) if is_assoc => {
+      return Ok((ArrayKey::Key(text), Vec::new()));
+    }
+    ArrayIndex::Arithmetic(arithmetic, _) => {
+      Word::new(vec![WordPart::Arithmetic(arithmetic)])
+    }
+    ArrayIndex::Word(word) => word,
+  };
+  let index = evaluate_word(word, state, stdin, stderr).await?;
+  if is_assoc {
+    return Ok((ArrayKey::Key(index.value), index.changes));
+  }
+  match index.value.trim().parse::<i64>() {
+    Ok(value) => Ok((ArrayKey::Index(value), index.changes)),
+    Err(_) => Err(EvaluateWordTextError::BadArraySubscript {
+      name: format!("{name}[{}]", index.value),
+    }),
+  }
+}
+
+fn array_element(
+  state: &ShellState,
+  name: &str,
+  key: &ArrayKey,
+) -> Option<String> {
+  match key {
+    ArrayKey::Key(key) => state.get_assoc(name)?.get(key).cloned(),
+    ArrayKey::Index(index) => {
+      let mut array = indexed_array(state, name);
+      resolve_array_index(array_end(&array), *index)
+        .and_then(|index| array.remove(&index))
+    }
+  }
+}
+
+/// Resolves a negative index from `end`, the index after the last element.
+fn resolve_array_index(end: usize, index: i64) -> Option<usize> {
+  if index < 0 {
+    end.checked_sub(usize::try_from(index.unsigned_abs()).ok()?)
+  } else {
+    usize::try_from(index).ok()
+  }
+}
+
+/// `${array[@]:begin:length}`, the `length` elements from the index `begin`
+/// on. Out of range slices are empty.
+async fn slice_array(
+  entries: Vec<(usize, String)>,
+  begin: Word,
+  length: Option<Word>,
+  state: &mut ShellState,
+  stdin: ShellPipeReader,
+  stderr: ShellPipeWriter,
+) -> Result<(Vec<String>, Vec<EnvChange>), Error> {
+  let (mut start, mut changes) =
+    evaluate_substring_index(begin, state, stdin.clone(), stderr.clone())
+      .await?;
+  if start < 0 {
+    let end = entries.last().map_or(0, |(index, _)| index + 1);
+    start += i64::try_from(end).into_diagnostic()?;
+    if start < 0 {
+      return Ok((Vec::new(), changes));
+    }
+  }
+  let values = entries
+    .into_iter()
+    .filter(|(index, _)| {
+      i64::try_from(*index).is_ok_and(|index| index >= start)
+    })
+    .map(|(_, value)| value)
+    .collect::<Vec<_>>();
+  let len = i64::try_from(values.len()).into_diagnostic()?;
+  let count = match length {
+    Some(length) => {
+      let (length, length_changes) =
+        evaluate_substring_index(length, state, stdin, stderr).await?;
+      changes.extend(length_changes);
+      if length < 0 {
+        len + length
+      } else {
+        length
+      }
+    }
+    None => len,
+  };
+  let values = values
+    .into_iter()
+    .take(usize::try_from(count).unwrap_or(0))
+    .collect();
+  Ok((values, changes))
+}
+
+async fn execute_array_assignment(
+  assignment: ArrayAssignment,
+  state: &mut ShellState,
+  stdin: ShellPipeReader,
+  stderr: ShellPipeWriter,
+) -> Result<(String, Vec<EnvChange>), EvaluateWordTextError> {
+  match assignment {
+    ArrayAssignment::List {
+      name,
+      values,
+      append,
+    } => {
+      let mut changes = Vec::new();
+      let mut trace = Vec::new();
+      if let Some(map) = state.get_assoc(&name) {
+        let mut map = if append {
+          map.clone()
+        } else {
+          Default::default()
+        };
+        for element in values {
+          let value =
+            evaluate_word(element.value, state, stdin.clone(), stderr.clone())
+              .await?;
+          changes.extend(value.changes);
+          let Some(index) = element.index else {
+            return Err(EvaluateWordTextError::AssocSubscriptRequired {
+              name,
+              value: value.value,
+            });
+          };
+          let (key, key_changes) = evaluate_array_key(
+            &name,
+            index,
+            state,
+            stdin.clone(),
+            stderr.clone(),
+          )
+          .await?;
+          changes.extend(key_changes);
+          trace.push(format!("[{key}]={}", value.value));
+          map.insert(key.to_string(), value.value);
+        }
+        let trace = format!("{name}=({})", trace.join(" "));
+        changes.push(EnvChange::SetAssocVar(name, map));
+        return Ok((trace, changes));
+      }
+
+      let mut array = if append {
+        indexed_array(state, &name)
+      } else {
+        BTreeMap::new()
+      };
+      // elements without an index go after the previous one
+      let mut next = array_end(&array);
+      for element in values {
+        if let Some(index) = element.index {
+          let value =
+            evaluate_word(element.value, state, stdin.clone(), stderr.clone())
+              .await?;
+          changes.extend(value.changes);
+          let (index, index_changes) = evaluate_array_key(
+            &name,
+            index,
+            state,
+            stdin.clone(),
+            stderr.clone(),
+          )
+          .await?;
+          changes.extend(index_changes);
+          let ArrayKey::Index(index) = index else {
+            unreachable!("indexed arrays have integer indices");
+          };
+          let Some(index) = resolve_array_index(array_end(&array), index)
+          else {
+            return Err(EvaluateWordTextError::BadArraySubscript {
+              name: format!("{name}[{index}]"),
+            });
+          };
+          trace.push(format!("[{index}]={}", value.value));
+          array.insert(index, value.value);
+          next = index + 1;
+        } else {
+          let result = evaluate_word_parts(
+            element.value.into_parts(),
+            state,
+            stdin.clone(),
+            stderr.clone(),
+          )
+          .await?;
+          changes.extend(result.changes);
+          for value in result.value {
+            trace.push(value.clone());
+            array.insert(next, value);
+            next += 1;
+          }
+        }
+      }
+      let trace = format!("{name}=({})", trace.join(" "));
+      changes.push(EnvChange::SetArrayVar(name, array));
+      Ok((trace, changes))
+    }
+    ArrayAssignment::Element { name, index, value } => {
+      let (index, mut changes) =
+        evaluate_array_key(&name, index, state, stdin.clone(), stderr.clone())
+          .await?;
+      let value = evaluate_word(value, state, stdin, stderr).await?;
+      changes.extend(value.changes);
+      let trace = format!("{name}[{index}]={}", value.value);
+      match index {
+        ArrayKey::Key(key) => {
+          let mut map = state.get_assoc(&name).cloned().unwrap_or_default();
+          map.insert(key, value.value);
+          changes.push(EnvChange::SetAssocVar(name, map));
+        }
+        ArrayKey::Index(index) => {
+          let mut array = indexed_array(state, &name);
+          let Some(index) = resolve_array_index(array_end(&array), index)
+          else {
+            return Err(EvaluateWordTextError::BadArraySubscript {
+              name: format!("{name}[{index}]"),
+            });
+          };
+          array.insert(index, value.value);
+          changes.push(EnvChange::SetArrayVar(name, array));
+        }
+      }
+      Ok((trace, changes))
+    }
+  }
+}
+
+/// Replaces the array assignments given to `declare` in `words` with the
+/// names they assign, so that `declare` declares the variables first.
+fn take_array_assignments(
+  words: Vec<Word>,
+) -> (Vec<Word>, Vec<ArrayAssignment>) {
+  let mut assignments = Vec::new();
+  let words = words
+    .into_iter()
+    .map(|word| {
+      let [WordPart::ArrayAssignment(assignment)] = word.parts().as_slice()
+      else {
+        return word;
+      };
+      let (ArrayAssignment::List { name, .. }
+      | ArrayAssignment::Element { name, .. }) = assignment;
+      let name = Word::new_word(name);
+      assignments.push(assignment.clone());
+      name
+    })
+    .collect();
+  (words, assignments)
+}
+
 async fn evaluate_command_substitution(
   list: SequentialList,
   state: &ShellState,
   stdin: ShellPipeReader,
   stderr: ShellPipeWriter,
 ) -> String {
+  let mut state = state.clone();
+  state.enter_subshell();
   let text = execute_with_stdout_as_text(|shell_stdout_writer| {
     execute_sequential_list(
       list,
-      state.clone(),
+      state,
       stdin,
       shell_stdout_writer,
       stderr,
@@ -1680,3 +3673,70 @@ async fn execute_with_stdout_as_text(
   let data = output_handle.await.unwrap();
   String::from_utf8_lossy(&data).to_string()
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::parser::Sequence;
+
+  fn redirect_error(state: &ShellState, command: &str) -> String {
+    let mut list = crate::parser::parse(command).unwrap();
+    let Sequence::Pipeline(Pipeline {
+      inner: PipelineInner::Command(Command { mut redirects, .. }),
+      ..
+    }) = list.items.remove(0).sequence
+    else {
+      panic!("not a redirect");
+    };
+    let redirect = redirects.remove(0);
+    let (reader, mut writer) = pipe();
+    write_redirect_error(&mut writer, state, &redirect, "bad redirect", "here");
+    drop(writer);
+    let mut output = Vec::new();
+    reader.pipe_to(&mut output).unwrap();
+    String::from_utf8(output).unwrap()
+  }
+
+  #[test]
+  fn parses_arithmetic_numbers() {
+    let integer = |text| match parse_arithmetic_number(text) {
+      Some(ArithmeticValue::Integer(value)) => Some(value),
+      _ => None,
+    };
+    assert_eq!(integer("42"), Some(42));
+    assert_eq!(integer("-42"), Some(-42));
+    assert_eq!(integer("0x1F"), Some(31));
+    assert_eq!(integer("017"), Some(15));
+    assert_eq!(integer("0"), Some(0));
+    assert_eq!(integer("2#1010"), Some(10));
+    assert_eq!(integer("16#fF"), Some(255));
+    assert_eq!(integer("64#A"), Some(36));
+    assert_eq!(integer("64#@_"), Some(62 * 64 + 63));
+    for text in ["09", "0x", "0x-1", "2#2", "1#0", "65#1", "#1", "a", ""] {
+      assert_eq!(parse_arithmetic_number(text), None, "{text}");
+    }
+    assert_eq!(
+      parse_arithmetic_number("1.5"),
+      Some(ArithmeticValue::Float(1.5))
+    );
+  }
+
+  #[test]
+  fn writes_redirect_errors_with_the_script() {
+    let cwd = std::env::current_dir().unwrap();
+    let mut state =
+      ShellState::new(Default::default(), &cwd, Default::default());
+    let command = "echo hi > /no/such/file";
+    assert_eq!(redirect_error(&state, command), "bad redirect\n");
+
+    state.set_script_source("script.sh", command);
+    let error = redirect_error(&state, command);
+    assert!(error.contains("script.sh:1:9"), "{error}");
+    assert!(error.contains("echo hi > /no/such/file"), "{error}");
+    assert!(error.contains("here"), "{error}");
+
+    // the redirect was parsed from another text, e.g. in a function
+    state.set_script_source("other.sh", "echo other text");
+    assert_eq!(redirect_error(&state, command), "bad redirect\n");
+  }
+}
@@ -0,0 +1,273 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+//! Job control on Unix: foreground commands run in their own process group
+//! which is given the terminal, so that Ctrl-C and Ctrl-Z reach only the
+//! command and the shell can later continue it with `fg` or `bg`.
+
+use std::cell::Cell;
+use std::io;
+use std::os::unix::process::CommandExt;
+
+use crate::shell::jobs::Job;
+use crate::shell::jobs::JobStatus;
+use crate::shell::types::EnvChange;
+use crate::shell::types::ExecuteResult;
+use crate::shell::types::ShellPipeWriter;
+use crate::shell::types::ShellState;
+
+/// How a waited for job changed its state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitStatus {
+  /// The job exited, or was killed by a signal (exit code 128 + signal)
+  Exited(i32),
+  /// The job was stopped, e.g. with Ctrl-Z
+  Stopped,
+}
+
+/// The exit code of a command that was stopped (128 + SIGTSTP), like bash.
+pub const STOPPED_EXIT_CODE: i32 = 128 + libc::SIGTSTP;
+
+/// The process group of a pipeline. The first command that starts creates
+/// it, the others join it, and the shell takes back the terminal once the
+/// last one is done.
+#[derive(Debug, Default)]
+pub struct ProcessGroup {
+  pgid: Cell<Option<u32>>,
+  running: Cell<usize>,
+}
+
+/// Ignores the job control signals in the shell itself, so that Ctrl-Z
+/// doesn't stop the shell and it can take back the terminal from a job.
+/// Called by interactive shells when they turn on `set -m`.
+pub fn init_shell() {
+  // SAFETY: changing the disposition of these signals has no preconditions
+  unsafe {
+    libc::signal(libc::SIGTSTP, libc::SIG_IGN);
+    libc::signal(libc::SIGTTIN, libc::SIG_IGN);
+    libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+  }
+}
+
+/// Runs `command` as a foreground job and waits until it exits or is
+/// stopped. A stopped command is added to the job table as a stopped job.
+pub async fn run_foreground(
+  mut command: std::process::Command,
+  command_line: String,
+  state: &ShellState,
+  mut stderr: ShellPipeWriter,
+) -> ExecuteResult {
+  // the builtins of a pipeline run in the shell and would wait forever
+  // for a stopped command, so only single commands can be stopped
+  let stoppable = state.process_group().is_none();
+  let group = state.process_group().cloned().unwrap_or_default();
+  let pgid = group.pgid.get().unwrap_or(0) as libc::pid_t;
+  // SAFETY: only async-signal-safe functions are called after the fork
+  unsafe {
+    command.pre_exec(move || {
+      libc::setpgid(0, pgid);
+      // the job control signals are still ignored here, so taking the
+      // terminal from the background doesn't stop the child
+      if libc::isatty(libc::STDIN_FILENO) == 1 {
+        libc::tcsetpgrp(libc::STDIN_FILENO, libc::getpgrp());
+      }
+      if stoppable {
+        libc::signal(libc::SIGTSTP, libc::SIG_DFL);
+      }
+      libc::signal(libc::SIGTTIN, libc::SIG_DFL);
+      libc::signal(libc::SIGTTOU, libc::SIG_DFL);
+      Ok(())
+    });
+  }
+  // a std child is used because tokio would reap the process in the
+  // background once the handle is dropped, even if it is only stopped
+  let child = match command.spawn() {
+    Ok(child) => child,
+    Err(err) => {
+      let _ = stderr.write_line(&format!(
+        "Error launching '{}': {}",
+        command.get_program().to_string_lossy(),
+        err
+      ));
+      return ExecuteResult::Continue(1, Vec::new(), Vec::new());
+    }
+  };
+  // avoid deadlock since this is holding onto the pipes
+  drop(command);
+
+  let pid = child.id();
+  let pgid = group.pgid.get().unwrap_or(pid);
+  group.pgid.set(Some(pgid));
+  group.running.set(group.running.get() + 1);
+  // also done here, so the terminal is handed over before waiting even if
+  // the child wasn't scheduled yet
+  // SAFETY: these calls have no memory safety preconditions
+  unsafe {
+    libc::setpgid(pid as libc::pid_t, pgid as libc::pid_t);
+  }
+  give_terminal(pgid);
+  let result = wait_or_kill(pid, state).await;
+  group.running.set(group.running.get() - 1);
+  if group.running.get() == 0 {
+    // later commands of the pipeline start a new group
+    group.pgid.set(None);
+    reclaim_terminal();
+  }
+  match result {
+    // the command got Ctrl-C instead of the shell, which stops the rest of
+    // the commands as if the shell got it
+    Ok(WaitStatus::Exited(crate::shell::types::CANCELLATION_EXIT_CODE)) => {
+      state.token().cancel();
+      ExecuteResult::for_cancellation()
+    }
+    Ok(WaitStatus::Exited(code)) => {
+      ExecuteResult::Continue(code, Vec::new(), Vec::new())
+    }
+    Ok(WaitStatus::Stopped) => {
+      let job = Job {
+        id: state.jobs().next_id(),
+        pid: Some(pid),
+        command: command_line,
+        status: JobStatus::Stopped,
+        no_hangup: false,
+        task: None,
+      };
+      let _ = stderr.write_line(&format!("\n{}", job.describe('+')));
+      ExecuteResult::Continue(
+        STOPPED_EXIT_CODE,
+        vec![EnvChange::AddJob(job)],
+        Vec::new(),
+      )
+    }
+    Err(err) => {
+      let _ = stderr.write_line(&format!("{}", err));
+      ExecuteResult::Continue(1, Vec::new(), Vec::new())
+    }
+  }
+}
+
+/// Waits for the foreground job with the process group `pid`, which has
+/// been given the terminal, then takes the terminal back.
+pub async fn wait_foreground(
+  pid: u32,
+  state: &ShellState,
+) -> io::Result<WaitStatus> {
+  let result = wait_or_kill(pid, state).await;
+  reclaim_terminal();
+  result
+}
+
+/// Waits for the process `pid`, or kills its process group when the
+/// command is cancelled.
+async fn wait_or_kill(pid: u32, state: &ShellState) -> io::Result<WaitStatus> {
+  tokio::select! {
+    result = wait(pid) => result,
+    _ = state.token().cancelled() => {
+      // SAFETY: these calls have no memory safety preconditions
+      unsafe {
+        let pgid = libc::getpgid(pid as libc::pid_t);
+        if pgid > 0 {
+          libc::kill(-pgid, libc::SIGKILL);
+        }
+      }
+      Ok(WaitStatus::Exited(crate::shell::types::CANCELLATION_EXIT_CODE))
+    }
+  }
+}
+
+/// Waits for the background job with the process group `pid` to exit or
+/// stop. Returns `None` if the command is cancelled first, which leaves the
+/// job running.
+pub async fn wait_background(
+  pid: u32,
+  state: &ShellState,
+) -> Option<io::Result<WaitStatus>> {
+  tokio::select! {
+    result = wait(pid) => Some(result),
+    _ = state.token().cancelled() => None,
+  }
+}
+
+/// Continues a stopped job by sending `SIGCONT` to its process group.
+pub fn continue_job(pid: u32) -> io::Result<()> {
+  // SAFETY: sending a signal has no memory safety preconditions
+  if unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGCONT) } == -1 {
+    return Err(io::Error::last_os_error());
+  }
+  Ok(())
+}
+
+/// Makes the process group `pid` the foreground process group of the
+/// terminal, if the shell has one.
+pub fn give_terminal(pid: u32) {
+  // SAFETY: these calls have no memory safety preconditions
+  unsafe {
+    if libc::isatty(libc::STDIN_FILENO) == 1 {
+      libc::tcsetpgrp(libc::STDIN_FILENO, pid as libc::pid_t);
+    }
+  }
+}
+
+/// Makes the shell the foreground process group of the terminal again.
+pub fn reclaim_terminal() {
+  // SAFETY: these calls have no memory safety preconditions
+  unsafe {
+    if libc::isatty(libc::STDIN_FILENO) == 1 {
+      libc::tcsetpgrp(libc::STDIN_FILENO, libc::getpgrp());
+    }
+  }
+}
+
+/// Waits until the process `pid` exits or is stopped.
+async fn wait(pid: u32) -> io::Result<WaitStatus> {
+  tokio::task::spawn_blocking(move || loop {
+    let mut status = 0;
+    // SAFETY: `status` is a valid pointer for the duration of the call
+    let result = unsafe {
+      libc::waitpid(pid as libc::pid_t, &mut status, libc::WUNTRACED)
+    };
+    if result == -1 {
+      let err = io::Error::last_os_error();
+      if err.kind() == io::ErrorKind::Interrupted {
+        continue;
+      }
+      return Err(err);
+    }
+    if let Some(status) = to_wait_status(status) {
+      return Ok(status);
+    }
+  })
+  .await
+  .map_err(io::Error::other)?
+}
+
+/// Checks whether the process `pid` exited or was stopped, without
+/// blocking.
+pub fn try_wait(pid: u32) -> Option<WaitStatus> {
+  let mut status = 0;
+  // SAFETY: `status` is a valid pointer for the duration of the call
+  let result = unsafe {
+    libc::waitpid(
+      pid as libc::pid_t,
+      &mut status,
+      libc::WNOHANG | libc::WUNTRACED,
+    )
+  };
+  match result {
+    0 => None,
+    // the process was already reaped elsewhere
+    -1 => Some(WaitStatus::Exited(0)),
+    _ => to_wait_status(status),
+  }
+}
+
+fn to_wait_status(status: libc::c_int) -> Option<WaitStatus> {
+  if libc::WIFEXITED(status) {
+    Some(WaitStatus::Exited(libc::WEXITSTATUS(status)))
+  } else if libc::WIFSIGNALED(status) {
+    Some(WaitStatus::Exited(128 + libc::WTERMSIG(status)))
+  } else if libc::WIFSTOPPED(status) {
+    Some(WaitStatus::Stopped)
+  } else {
+    None
+  }
+}
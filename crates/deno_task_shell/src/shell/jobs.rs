@@ -0,0 +1,405 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::fmt;
+
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+/// The state of a job in the job table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd)]
+pub enum JobStatus {
+  Running,
+  Stopped,
+  Done(i32),
+}
+
+impl fmt::Display for JobStatus {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      JobStatus::Running => write!(f, "Running"),
+      JobStatus::Stopped => write!(f, "Stopped"),
+      JobStatus::Done(0) => write!(f, "Done"),
+      JobStatus::Done(code) => write!(f, "Exit {code}"),
+    }
+  }
+}
+
+/// `$!` of a job without a process, e.g. of a builtin, is this plus the job
+/// number. It is above the pids of the supported platforms, so `kill` and
+/// `wait` find the job instead of a process.
+const NO_PROCESS_PID_BASE: u32 = 1 << 30;
+
+/// The task that runs a job started with `&`. Its processes are awaited by
+/// the task, so the job ends when the task sends its exit code, not when
+/// `waitpid` reports it.
+#[derive(Debug, Clone)]
+pub struct JobTask {
+  exit_code: watch::Sender<Option<i32>>,
+  token: CancellationToken,
+}
+
+impl JobTask {
+  /// A task that runs until `token` is cancelled.
+  pub fn new(token: CancellationToken) -> Self {
+    Self {
+      exit_code: watch::Sender::new(None),
+      token,
+    }
+  }
+
+  /// Records the exit code of the job, unless it already ended.
+  pub fn finish(&self, exit_code: i32) {
+    self.exit_code.send_if_modified(|current| {
+      let unset = current.is_none();
+      if unset {
+        *current = Some(exit_code);
+      }
+      unset
+    });
+  }
+
+  /// The exit code of the job, if it ended.
+  pub fn exit_code(&self) -> Option<i32> {
+    *self.exit_code.borrow()
+  }
+
+  /// Waits until the job ends and returns its exit code.
+  pub async fn wait(&self) -> i32 {
+    let mut receiver = self.exit_code.subscribe();
+    // the sender is kept by `self`, so the channel stays open
+    let exit_code = receiver
+      .wait_for(Option::is_some)
+      .await
+      .map(|exit_code| *exit_code);
+    exit_code.ok().flatten().unwrap_or_default()
+  }
+
+  /// Stops a job that has no process to send `signal` to, it ends as if
+  /// the signal killed it.
+  pub fn kill(&self, signal: i32) {
+    self.finish(128 + signal);
+    self.token.cancel();
+  }
+}
+
+impl PartialEq for JobTask {
+  fn eq(&self, other: &Self) -> bool {
+    self.exit_code.same_channel(&other.exit_code)
+  }
+}
+
+impl Eq for JobTask {}
+
+/// A job started by the shell, e.g. with `&` or stopped with Ctrl-Z.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+  /// The job number used in job specs like `%1`
+  pub id: usize,
+  /// The process id of the job, if it runs as a separate process
+  pub pid: Option<u32>,
+  /// The command line that started the job
+  pub command: String,
+  pub status: JobStatus,
+  /// Set by `disown -h`, the job will not receive a SIGHUP when the shell exits
+  pub no_hangup: bool,
+  /// The task of a job started with `&`, a stopped job has none
+  pub task: Option<JobTask>,
+}
+
+impl Job {
+  /// The pid of the job, or the id that stands in for it as `$!` if the
+  /// job has no process.
+  pub fn background_pid(&self) -> u32 {
+    self.pid.unwrap_or(NO_PROCESS_PID_BASE + self.id as u32)
+  }
+
+  /// Describes the job like bash does when it is stopped or done, e.g.
+  /// `[1]+  Stopped                 sleep 100`. The marker is `+` for the
+  /// current job, `-` for the previous one and a space otherwise.
+  pub fn describe(&self, marker: char) -> String {
+    format!("[{}]{marker}  {}", self.id, self.describe_status())
+  }
+
+  /// Describes the job with its process id, like `jobs -l`, e.g.
+  /// `[1]+ 12345 Stopped                 sleep 100`.
+  pub fn describe_long(&self, marker: char) -> String {
+    let pid = self.pid.map(|pid| pid.to_string()).unwrap_or_default();
+    format!("[{}]{marker} {pid:>5} {}", self.id, self.describe_status())
+  }
+
+  fn describe_status(&self) -> String {
+    let status = self.status.to_string();
+    // like in bash, a running job is shown as it was started
+    let background = if self.status == JobStatus::Running {
+      " &"
+    } else {
+      ""
+    };
+    format!("{status:<24}{}{background}", self.command)
+  }
+}
+
+/// The jobs of the shell, ordered from oldest to most recently started.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JobTable {
+  jobs: Vec<Job>,
+}
+
+impl JobTable {
+  /// Adds a running job and returns its job number.
+  pub fn add(&mut self, pid: Option<u32>, command: String) -> usize {
+    let id = self.next_id();
+    self.insert(Job {
+      id,
+      pid,
+      command,
+      status: JobStatus::Running,
+      no_hangup: false,
+      task: None,
+    });
+    id
+  }
+
+  /// The job number the next added job gets.
+  pub fn next_id(&self) -> usize {
+    self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1
+  }
+
+  /// Adds a job that was created with `next_id`, making it the current job.
+  pub fn insert(&mut self, job: Job) {
+    self.remove(job.id);
+    self.jobs.push(job);
+  }
+
+  pub fn get(&self, id: usize) -> Option<&Job> {
+    self.jobs.iter().find(|job| job.id == id)
+  }
+
+  pub fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+    self.jobs.iter_mut().find(|job| job.id == id)
+  }
+
+  pub fn remove(&mut self, id: usize) -> Option<Job> {
+    let index = self.jobs.iter().position(|job| job.id == id)?;
+    Some(self.jobs.remove(index))
+  }
+
+  /// The job of `pid`, which is also found by the id that stands in for
+  /// the pid of a job without a process, see [`Job::background_pid`].
+  pub fn find_pid(&self, pid: u32) -> Option<&Job> {
+    self.jobs.iter().find(|job| job.background_pid() == pid)
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = &Job> {
+    self.jobs.iter()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.jobs.is_empty()
+  }
+
+  /// The current job (`%+`), which is the most recently started one.
+  pub fn current(&self) -> Option<&Job> {
+    self.jobs.last()
+  }
+
+  /// The previous job (`%-`).
+  pub fn previous(&self) -> Option<&Job> {
+    self.jobs.iter().rev().nth(1)
+  }
+
+  /// The marker bash shows next to a job: `+` for the current job, `-` for
+  /// the previous one and a space otherwise.
+  pub fn marker(&self, id: usize) -> char {
+    if self.current().is_some_and(|job| job.id == id) {
+      '+'
+    } else if self.previous().is_some_and(|job| job.id == id) {
+      '-'
+    } else {
+      ' '
+    }
+  }
+
+  /// Checks which jobs have finished or were stopped since the last call,
+  /// updating their status. Finished jobs are removed from the table and
+  /// returned, so they can be reported.
+  #[cfg(unix)]
+  pub fn update_statuses(&mut self) -> Vec<Job> {
+    for job in &mut self.jobs {
+      // the processes of a task are reaped by the task
+      if let Some(task) = &job.task {
+        if let Some(code) = task.exit_code() {
+          job.status = JobStatus::Done(code);
+        }
+        continue;
+      }
+      let Some(pid) = job.pid else {
+        continue;
+      };
+      match super::job_control::try_wait(pid) {
+        Some(super::job_control::WaitStatus::Exited(code)) => {
+          job.status = JobStatus::Done(code)
+        }
+        Some(super::job_control::WaitStatus::Stopped) => {
+          job.status = JobStatus::Stopped
+        }
+        None => {}
+      }
+    }
+    let (done, jobs) = std::mem::take(&mut self.jobs)
+      .into_iter()
+      .partition(|job| matches!(job.status, JobStatus::Done(_)));
+    self.jobs = jobs;
+    done
+  }
+
+  /// Sends a SIGHUP to the jobs when the shell exits, except to the ones
+  /// marked with `disown -h`. A stopped job is also continued, so that it
+  /// gets the signal.
+  #[cfg(unix)]
+  pub fn hang_up(&self) {
+    for job in self.jobs.iter().filter(|job| !job.no_hangup) {
+      match (job.pid, &job.task) {
+        // SAFETY: sending a signal has no memory safety preconditions
+        (Some(pid), Some(_)) => unsafe {
+          libc::kill(pid as libc::pid_t, libc::SIGHUP);
+        },
+        // SAFETY: sending a signal has no memory safety preconditions
+        (Some(pid), None) => unsafe {
+          libc::kill(-(pid as libc::pid_t), libc::SIGHUP);
+          libc::kill(-(pid as libc::pid_t), libc::SIGCONT);
+        },
+        (None, Some(task)) => task.kill(libc::SIGHUP),
+        (None, None) => {}
+      }
+    }
+  }
+
+  /// Resolves a job spec such as `%1`, `%%`, `%+`, `%-`, `%name` (the
+  /// job whose command starts with `name`) or `%?text` (the job whose
+  /// command contains `text`) to a job.
+  pub fn resolve(&self, spec: &str) -> Option<&Job> {
+    let spec = spec.strip_prefix('%')?;
+    match spec {
+      "" | "%" | "+" => self.current(),
+      "-" => self.previous(),
+      _ => match spec.parse::<usize>() {
+        Ok(id) => self.get(id),
+        Err(_) => {
+          self
+            .jobs
+            .iter()
+            .rev()
+            .find(|job| match spec.strip_prefix('?') {
+              Some(text) => job.command.contains(text),
+              None => job.command.starts_with(spec),
+            })
+        }
+      },
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn resolves_job_specs() {
+    let mut jobs = JobTable::default();
+    assert_eq!(jobs.resolve("%%"), None);
+    assert_eq!(jobs.add(Some(10), "sleep 100".to_string()), 1);
+    assert_eq!(jobs.add(Some(11), "cargo build".to_string()), 2);
+
+    assert_eq!(jobs.resolve("%1").unwrap().pid, Some(10));
+    assert_eq!(jobs.resolve("%%").unwrap().id, 2);
+    assert_eq!(jobs.resolve("%+").unwrap().id, 2);
+    assert_eq!(jobs.resolve("%-").unwrap().id, 1);
+    assert_eq!(jobs.resolve("%sleep").unwrap().id, 1);
+    assert_eq!(jobs.resolve("%?build").unwrap().id, 2);
+    assert_eq!(jobs.resolve("%?100").unwrap().id, 1);
+    assert_eq!(jobs.resolve("%build"), None);
+    assert_eq!(jobs.resolve("%3"), None);
+    assert_eq!(jobs.resolve("1"), None);
+
+    assert_eq!(jobs.find_pid(11).unwrap().id, 2);
+    assert_eq!(jobs.find_pid(12), None);
+
+    // job numbers are not reused while later jobs exist
+    jobs.remove(1);
+    assert_eq!(jobs.add(None, "vim".to_string()), 3);
+    assert_eq!(jobs.marker(3), '+');
+    assert_eq!(jobs.marker(2), '-');
+    // a job without a process is found by the id that `$!` gets for it
+    let pid = jobs.get(3).unwrap().background_pid();
+    assert_eq!(pid, NO_PROCESS_PID_BASE + 3);
+    assert_eq!(jobs.find_pid(pid).unwrap().id, 3);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn reports_finished_tasks() {
+    let mut jobs = JobTable::default();
+    let task = JobTask::new(CancellationToken::new());
+    jobs.insert(Job {
+      id: 1,
+      pid: None,
+      command: "sleep 1".to_string(),
+      status: JobStatus::Running,
+      no_hangup: false,
+      task: Some(task.clone()),
+    });
+    assert_eq!(jobs.update_statuses(), Vec::new());
+    // a job that was killed keeps the exit code of the signal
+    task.kill(15);
+    task.finish(130);
+    let done = jobs.update_statuses();
+    assert_eq!(done[0].status, JobStatus::Done(143));
+    assert!(jobs.is_empty());
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn hangs_up_jobs_not_marked_no_hangup() {
+    let mut jobs = JobTable::default();
+    let tasks = [false, true].map(|no_hangup| {
+      let task = JobTask::new(CancellationToken::new());
+      jobs.insert(Job {
+        id: jobs.next_id(),
+        pid: None,
+        command: "sleep 1".to_string(),
+        status: JobStatus::Running,
+        no_hangup,
+        task: Some(task.clone()),
+      });
+      task
+    });
+    jobs.hang_up();
+    assert_eq!(tasks[0].exit_code(), Some(128 + libc::SIGHUP));
+    assert_eq!(tasks[1].exit_code(), None);
+  }
+
+  #[test]
+  fn describes_jobs() {
+    let mut job = Job {
+      id: 1,
+      pid: Some(10),
+      command: "sleep 100".to_string(),
+      status: JobStatus::Stopped,
+      no_hangup: false,
+      task: None,
+    };
+    assert_eq!(job.describe('+'), "[1]+  Stopped                 sleep 100");
+    assert_eq!(
+      job.describe_long('+'),
+      "[1]+    10 Stopped                 sleep 100"
+    );
+    job.status = JobStatus::Running;
+    assert_eq!(
+      job.describe_long(' '),
+      "[1]     10 Running                 sleep 100 &"
+    );
+    job.status = JobStatus::Done(2);
+    assert_eq!(job.describe('-'), "[1]-  Exit 2                  sleep 100");
+  }
+}
@@ -1,29 +1,48 @@
 // Copyright 2018-2024 the Deno authors. MIT license.
 
 pub use command::ResolveCommandPathError;
+pub use commands::builtin_commands;
+#[cfg(windows)]
+pub use commands::has_foreground_process;
 pub use commands::ExecutableCommand;
 pub use commands::ExecuteCommandArgsContext;
 pub use commands::ShellCommand;
 pub use commands::ShellCommandContext;
 pub use execute::execute;
 pub use execute::{
-  execute_sequential_list, execute_with_pipes, AsyncCommandBehavior,
+  execute_sequential_list, execute_with_pipes, pattern_matches,
+  AsyncCommandBehavior,
 };
+pub use jobs::Job;
+pub use jobs::JobStatus;
+pub use jobs::JobTable;
 pub use types::pipe;
+pub use types::CompletionSpec;
 pub use types::EnvChange;
 pub use types::ExecuteResult;
 pub use types::FutureExecuteResult;
+pub use types::LoopControl;
 pub use types::ShellOptions;
 pub use types::ShellPipeReader;
 pub use types::ShellPipeWriter;
 pub use types::ShellState;
+pub use types::SourceError;
+pub use types::VarAttributes;
+pub use types::CANCELLATION_EXIT_CODE;
 
 pub use commands::parse_arg_kinds;
 pub use commands::ArgKind;
 
 pub mod fs_util;
 
+mod brace_expansion;
 mod command;
 mod commands;
 mod execute;
+#[cfg(unix)]
+pub mod job_control;
+mod jobs;
+mod pathname_expansion;
+mod process_substitution;
+mod tilde_expansion;
 mod types;
@@ -0,0 +1,341 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+//! Pathname expansion, which replaces a word like `src/*.rs` with the paths
+//! that match it. Each component of the pattern is matched against the
+//! entries of one directory, so only the directories that the pattern can
+//! reach are read, and the matches are produced while walking them.
+//!
+//! A `**` component matches any number of directories, so `**/*.rs` finds
+//! the `.rs` files at any depth and `**` alone everything below. Like in
+//! bash, it doesn't descend into symlinked directories, which keeps it out
+//! of symlink loops, nor into hidden ones. A `**` that is only part of a
+//! component is the same as `*`. Directories that can't be read are skipped.
+//!
+//! Hidden files are only matched by a component that starts with a `.`,
+//! unless `dotglob` is set. `.` and `..` are never matched by a wildcard.
+
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+enum Component {
+  /// Text without wildcards, which is looked up instead of matched
+  Literal(String),
+  Pattern(glob::Pattern),
+  /// `**`
+  Recursive,
+}
+
+fn match_options(dot_files: bool) -> glob::MatchOptions {
+  glob::MatchOptions {
+    // false because it should work the same way on case insensitive file systems
+    case_sensitive: false,
+    // true because it copies what sh does
+    require_literal_separator: true,
+    // true because it copies with sh does—these files are considered "hidden"
+    require_literal_leading_dot: !dot_files,
+  }
+}
+
+/// The paths that match `pattern`, relative to `cwd` unless the pattern is
+/// absolute, in no particular order. The position in an error is the one in
+/// `pattern`. Hidden files are matched by wildcards if `dot_files` is set.
+pub fn expand_pathname(
+  pattern: &str,
+  cwd: &Path,
+  dot_files: bool,
+) -> Result<PathnameExpansion, glob::PatternError> {
+  let path = Path::new(pattern);
+  let (root, root_len) = if path.is_absolute() {
+    let root = path.ancestors().last().unwrap_or(path);
+    (PathBuf::from(root), root.to_string_lossy().len())
+  } else {
+    (cwd.to_path_buf(), 0)
+  };
+  let rest = &pattern[root_len..];
+  let shown = if path.is_absolute() {
+    root.to_string_lossy().to_string()
+  } else {
+    String::new()
+  };
+  // a trailing separator only matches directories, and is kept
+  let dirs_only = rest.ends_with(std::path::is_separator);
+  let mut components = Vec::new();
+  let mut offset = root_len;
+  for text in rest.split(std::path::is_separator) {
+    if !text.is_empty() {
+      components.push(parse_component(text).map_err(|mut err| {
+        err.pos += offset;
+        err
+      })?);
+    }
+    offset += text.len() + 1;
+  }
+  let mut stack = Vec::new();
+  if !components.is_empty() {
+    stack.push(Work::Enter {
+      dir: root,
+      shown,
+      index: 0,
+    });
+  }
+  Ok(PathnameExpansion {
+    components,
+    dirs_only,
+    dot_files,
+    stack,
+  })
+}
+
+fn parse_component(text: &str) -> Result<Component, glob::PatternError> {
+  if text == "**" {
+    return Ok(Component::Recursive);
+  }
+  if !text.contains(['*', '?', '[']) {
+    return Ok(Component::Literal(text.to_string()));
+  }
+  let mut text = text.to_string();
+  while text.contains("**") {
+    text = text.replace("**", "*");
+  }
+  Ok(Component::Pattern(glob::Pattern::new(&text)?))
+}
+
+/// An iterator over the matches of a pattern, see [`expand_pathname`].
+pub struct PathnameExpansion {
+  components: Vec<Component>,
+  dirs_only: bool,
+  dot_files: bool,
+  stack: Vec<Work>,
+}
+
+enum Work {
+  /// Matches the components from `index` on in `dir`, which is shown as
+  /// `shown` in the matches
+  Enter {
+    dir: PathBuf,
+    shown: String,
+    index: usize,
+  },
+  Match(String),
+}
+
+struct Entry {
+  name: String,
+  is_dir: bool,
+  is_symlink: bool,
+}
+
+impl Iterator for PathnameExpansion {
+  type Item = String;
+
+  fn next(&mut self) -> Option<String> {
+    while let Some(work) = self.stack.pop() {
+      let (dir, shown, index) = match work {
+        Work::Match(path) => return Some(path),
+        Work::Enter { dir, shown, index } => (dir, shown, index),
+      };
+      let is_last = index + 1 == self.components.len();
+      match &self.components[index] {
+        Component::Literal(name) => {
+          let path = dir.join(name);
+          let shown = join(&shown, name);
+          if is_last {
+            let exists = if self.dirs_only {
+              path.is_dir()
+            } else {
+              fs::symlink_metadata(&path).is_ok()
+            };
+            if exists {
+              return Some(self.finish(shown));
+            }
+          } else if path.is_dir() {
+            self.stack.push(Work::Enter {
+              dir: path,
+              shown,
+              index: index + 1,
+            });
+          }
+        }
+        Component::Pattern(pattern) => {
+          let options = match_options(self.dot_files);
+          let entries = read_entries(&dir)
+            .filter(|entry| pattern.matches_with(&entry.name, options));
+          let mut work = Vec::new();
+          for entry in entries {
+            let shown = join(&shown, &entry.name);
+            if is_last {
+              if entry.is_dir || !self.dirs_only {
+                work.push(Work::Match(self.finish(shown)));
+              }
+            } else if entry.is_dir {
+              work.push(Work::Enter {
+                dir: dir.join(&entry.name),
+                shown,
+                index: index + 1,
+              });
+            }
+          }
+          self.stack.extend(work.into_iter().rev());
+        }
+        Component::Recursive => {
+          let mut work = Vec::new();
+          if !is_last {
+            // `**` matching no directory at all
+            work.push(Work::Enter {
+              dir: dir.clone(),
+              shown: shown.clone(),
+              index: index + 1,
+            });
+          }
+          for entry in read_entries(&dir) {
+            if !self.dot_files && entry.name.starts_with('.') {
+              continue;
+            }
+            let shown = join(&shown, &entry.name);
+            if is_last && (entry.is_dir || !self.dirs_only) {
+              work.push(Work::Match(self.finish(shown.clone())));
+            }
+            if entry.is_dir && !entry.is_symlink {
+              work.push(Work::Enter {
+                dir: dir.join(&entry.name),
+                shown,
+                index,
+              });
+            }
+          }
+          self.stack.extend(work.into_iter().rev());
+        }
+      }
+    }
+    None
+  }
+}
+
+impl PathnameExpansion {
+  fn finish(&self, shown: String) -> String {
+    if self.dirs_only {
+      format!("{shown}/")
+    } else {
+      shown
+    }
+  }
+}
+
+fn join(shown: &str, name: &str) -> String {
+  if shown.is_empty() || shown.ends_with(std::path::is_separator) {
+    format!("{shown}{name}")
+  } else {
+    format!("{shown}{}{name}", std::path::MAIN_SEPARATOR)
+  }
+}
+
+/// The entries of `dir` sorted by name, or none if it can't be read.
+fn read_entries(dir: &Path) -> impl Iterator<Item = Entry> {
+  let mut entries = fs::read_dir(dir)
+    .into_iter()
+    .flatten()
+    .filter_map(|entry| {
+      let entry = entry.ok()?;
+      let file_type = entry.file_type().ok()?;
+      let is_symlink = file_type.is_symlink();
+      let is_dir = if is_symlink {
+        entry.path().is_dir()
+      } else {
+        file_type.is_dir()
+      };
+      Some(Entry {
+        name: entry.file_name().to_string_lossy().to_string(),
+        is_dir,
+        is_symlink,
+      })
+    })
+    .collect::<Vec<_>>();
+  entries.sort_by(|a, b| a.name.cmp(&b.name));
+  entries.into_iter()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn expand(pattern: &str, cwd: &Path) -> Vec<String> {
+    expand_with(pattern, cwd, false)
+  }
+
+  fn expand_with(pattern: &str, cwd: &Path, dot_files: bool) -> Vec<String> {
+    let mut paths = expand_pathname(pattern, cwd, dot_files)
+      .unwrap()
+      .map(|path| path.replace(std::path::MAIN_SEPARATOR, "/"))
+      .collect::<Vec<_>>();
+    paths.sort();
+    paths
+  }
+
+  #[test]
+  fn expands_recursive_patterns() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    for dir in ["a/b/c", ".hidden/d"] {
+      fs::create_dir_all(root.join(dir)).unwrap();
+    }
+    for file in ["top.rs", "a/one.rs", "a/b/two.txt", "a/b/c/three.rs"] {
+      fs::write(root.join(file), "").unwrap();
+    }
+    fs::write(root.join(".hidden/d/four.rs"), "").unwrap();
+
+    assert_eq!(
+      expand("**/*.rs", root),
+      ["a/b/c/three.rs", "a/one.rs", "top.rs"]
+    );
+    assert_eq!(expand("a/**/*.rs", root), ["a/b/c/three.rs", "a/one.rs"]);
+    assert_eq!(
+      expand("**", root),
+      [
+        "a",
+        "a/b",
+        "a/b/c",
+        "a/b/c/three.rs",
+        "a/b/two.txt",
+        "a/one.rs",
+        "top.rs"
+      ]
+    );
+    assert_eq!(expand("**/", root), ["a/", "a/b/", "a/b/c/"]);
+    assert_eq!(expand("**/c", root), ["a/b/c"]);
+    // hidden directories only when the pattern names them
+    assert_eq!(expand(".hidden/**/*.rs", root), [".hidden/d/four.rs"]);
+    assert_eq!(expand(".*/*/*.rs", root), [".hidden/d/four.rs"]);
+    // `**` within a component is `*`
+    assert_eq!(expand("t**.rs", root), ["top.rs"]);
+    assert_eq!(expand("a/*/two.txt", root), ["a/b/two.txt"]);
+    assert_eq!(expand("missing/**", root), Vec::<String>::new());
+    assert_eq!(
+      expand_with("**/*.rs", root, true),
+      [".hidden/d/four.rs", "a/b/c/three.rs", "a/one.rs", "top.rs"]
+    );
+    assert_eq!(expand_with("*", root, true), [".hidden", "a", "top.rs"]);
+
+    let absolute = format!("{}/**/three.rs", root.display());
+    assert_eq!(
+      expand(&absolute, Path::new("/")),
+      [format!("{}/a/b/c/three.rs", root.display())]
+    );
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn skips_symlinked_directories() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    fs::create_dir_all(root.join("a/b")).unwrap();
+    fs::write(root.join("a/b/file"), "").unwrap();
+    std::os::unix::fs::symlink("..", root.join("a/b/loop")).unwrap();
+
+    assert_eq!(expand("**/file", root), ["a/b/file"]);
+    assert_eq!(expand("**", root), ["a", "a/b", "a/b/file", "a/b/loop"]);
+    // a symlink that the pattern names is still followed
+    assert_eq!(expand("a/b/loop/b/file", root), ["a/b/loop/b/file"]);
+    assert_eq!(expand("a/*/loop/*/file", root), ["a/b/loop/b/file"]);
+  }
+}
@@ -0,0 +1,352 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+//! `<(list)` and `>(list)`, which are replaced with the path of a file that
+//! the command reads the output of the list from, or writes its input to.
+//!
+//! An external command gets a FIFO, so that the lists run at the same time
+//! as the command. Builtins and functions run on the shell's own thread,
+//! where blocking on a FIFO would keep the lists from running, so they get a
+//! temporary file instead: `<(list)` runs to completion before the command
+//! and `>(list)` after it. Windows has no FIFOs and always uses temporary
+//! files.
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::parser::ProcessSubstitution;
+use crate::parser::ProcessSubstitutionKind;
+use crate::parser::SequentialList;
+use crate::parser::Word;
+use crate::parser::WordPart;
+use crate::shell::execute::execute_with_pipes;
+use crate::shell::types::ExecuteResult;
+use crate::shell::types::ShellPipeReader;
+use crate::shell::types::ShellPipeWriter;
+use crate::shell::types::ShellState;
+
+/// A process substitution that was replaced with the path in a word, and
+/// is started with [`ProcessSubstitutions::start`].
+pub struct PendingSubstitution {
+  substitution: ProcessSubstitution,
+  path: PathBuf,
+}
+
+/// The directory that the FIFOs and files of the substitutions of a command
+/// are created in. It is created when the first one needs it and removed
+/// when this is dropped, after the command finished. Only the user can
+/// access it, so other users can't take the names of the files.
+#[derive(Default)]
+pub struct SubstitutionDir {
+  dir: Option<tempfile::TempDir>,
+  count: usize,
+}
+
+impl SubstitutionDir {
+  fn next_path(&mut self) -> std::io::Result<PathBuf> {
+    let dir = match &mut self.dir {
+      Some(dir) => dir,
+      None => {
+        let mut builder = tempfile::Builder::new();
+        builder.prefix("shell-substitution-");
+        #[cfg(unix)]
+        {
+          use std::os::unix::fs::PermissionsExt;
+          // the default follows the umask
+          builder.permissions(std::fs::Permissions::from_mode(0o700));
+        }
+        self.dir.insert(builder.tempdir()?)
+      }
+    };
+    self.count += 1;
+    Ok(dir.path().join(self.count.to_string()))
+  }
+}
+
+/// Replaces the process substitutions in `words` with the paths of the
+/// files in `dir` they are connected to.
+pub fn take_process_substitutions(
+  words: Vec<Word>,
+  dir: &mut SubstitutionDir,
+) -> std::io::Result<(Vec<Word>, Vec<PendingSubstitution>)> {
+  let mut pending = Vec::new();
+  let mut new_words = Vec::with_capacity(words.len());
+  for word in words {
+    let mut parts = Vec::new();
+    for part in word.into_parts() {
+      match part {
+        WordPart::ProcessSubstitution(substitution) => {
+          let path = dir.next_path()?;
+          // quoted, so the path is neither split nor globbed
+          let text = path.to_string_lossy().to_string();
+          pending.push(PendingSubstitution { substitution, path });
+          parts.push(WordPart::Quoted(vec![WordPart::Text(text)]));
+        }
+        part => parts.push(part),
+      }
+    }
+    new_words.push(Word::new(parts));
+  }
+  Ok((new_words, pending))
+}
+
+/// Creates the temporary file of a substitution. It fails instead of
+/// following a file or symlink that is already there, and only the user can
+/// read the file.
+fn create_file(path: &Path) -> std::io::Result<std::fs::File> {
+  let mut options = std::fs::OpenOptions::new();
+  options.write(true).create_new(true);
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::OpenOptionsExt;
+    options.mode(0o600);
+  }
+  options.open(path)
+}
+
+/// The process substitutions of a command, from when it starts until
+/// [`ProcessSubstitutions::finish`] after it finished.
+#[derive(Default)]
+pub struct ProcessSubstitutions(Vec<Running>);
+
+struct Running {
+  text: String,
+  path: PathBuf,
+  list: RunningList,
+}
+
+enum RunningList {
+  /// Runs at the same time as the command, connected through a FIFO.
+  /// `opened` is set once the list's end of it is open.
+  #[cfg(unix)]
+  Fifo {
+    kind: ProcessSubstitutionKind,
+    opened: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    handle: tokio::task::JoinHandle<i32>,
+  },
+  /// `<(list)` that ran before the command, with its exit code
+  Finished(i32),
+  /// `>(list)` that runs after the command, reading the temporary file
+  Pending {
+    list: SequentialList,
+    state: Box<ShellState>,
+    stdout: ShellPipeWriter,
+    stderr: ShellPipeWriter,
+  },
+}
+
+impl ProcessSubstitutions {
+  /// Creates the files of the substitutions and starts their lists.
+  /// `in_shell` is whether the command runs on the shell's thread, like a
+  /// builtin or function, and can't use a FIFO.
+  pub async fn start(
+    pending: Vec<PendingSubstitution>,
+    in_shell: bool,
+    state: &ShellState,
+    stdin: &ShellPipeReader,
+    stdout: &ShellPipeWriter,
+    stderr: &mut ShellPipeWriter,
+  ) -> Result<Self, ExecuteResult> {
+    let mut substitutions = Self::default();
+    if let Err(err) = substitutions
+      .start_lists(pending, in_shell, state, stdin, stdout, stderr)
+      .await
+    {
+      let _ = stderr.write_line(&format!("shell: process substitution: {err}"));
+      substitutions.finish(stderr).await;
+      return Err(ExecuteResult::from_exit_code(1));
+    }
+    Ok(substitutions)
+  }
+
+  async fn start_lists(
+    &mut self,
+    pending: Vec<PendingSubstitution>,
+    in_shell: bool,
+    state: &ShellState,
+    stdin: &ShellPipeReader,
+    stdout: &ShellPipeWriter,
+    stderr: &ShellPipeWriter,
+  ) -> std::io::Result<()> {
+    #[cfg(not(unix))]
+    let _ = in_shell;
+    for PendingSubstitution { substitution, path } in pending {
+      let ProcessSubstitution { kind, list, text } = substitution;
+      // like a command substitution, cancellation is contained to the list
+      let state = state.with_child_token();
+      #[cfg(unix)]
+      if !in_shell {
+        let list = start_fifo(kind, list, state, &path, stdin, stdout, stderr)?;
+        self.0.push(Running { text, path, list });
+        continue;
+      }
+      let file = create_file(&path)?;
+      let list = match kind {
+        ProcessSubstitutionKind::Read => RunningList::Finished(
+          execute_with_pipes(
+            list,
+            state,
+            stdin.clone(),
+            ShellPipeWriter::StdFile(file),
+            stderr.clone(),
+          )
+          .await,
+        ),
+        ProcessSubstitutionKind::Write => RunningList::Pending {
+          list,
+          state: Box::new(state),
+          stdout: stdout.clone(),
+          stderr: stderr.clone(),
+        },
+      };
+      self.0.push(Running { text, path, list });
+    }
+    Ok(())
+  }
+
+  /// Waits for the lists once the command finished and removes their
+  /// files. A list that failed is reported on `stderr`.
+  pub async fn finish(self, stderr: &mut ShellPipeWriter) {
+    for Running { text, path, list } in self.0 {
+      let exit_code = match list {
+        #[cfg(unix)]
+        RunningList::Fifo {
+          kind,
+          opened,
+          handle,
+        } => {
+          let unused = unblock_fifo(&path, kind, &opened).await;
+          let exit_code = handle.await.unwrap_or(1);
+          // a list the command never connected to fails writing to it,
+          // which isn't worth a warning
+          if unused {
+            0
+          } else {
+            exit_code
+          }
+        }
+        RunningList::Finished(exit_code) => exit_code,
+        RunningList::Pending {
+          list,
+          state,
+          stdout,
+          stderr: list_stderr,
+        } => match std::fs::File::open(&path) {
+          Ok(file) => {
+            execute_with_pipes(
+              list,
+              *state,
+              ShellPipeReader::StdFile(file),
+              stdout,
+              list_stderr,
+            )
+            .await
+          }
+          Err(err) => {
+            let _ = stderr.write_line(&format!("shell: {text}: {err}"));
+            1
+          }
+        },
+      };
+      let _ = std::fs::remove_file(&path);
+      if exit_code != 0 {
+        let _ = stderr
+          .write_line(&format!("shell: {text}: exited with code {exit_code}"));
+      }
+    }
+  }
+}
+
+#[cfg(unix)]
+fn start_fifo(
+  kind: ProcessSubstitutionKind,
+  list: SequentialList,
+  state: ShellState,
+  path: &Path,
+  stdin: &ShellPipeReader,
+  stdout: &ShellPipeWriter,
+  stderr: &ShellPipeWriter,
+) -> std::io::Result<RunningList> {
+  use std::os::unix::ffi::OsStrExt;
+  use std::sync::atomic::AtomicBool;
+  use std::sync::atomic::Ordering;
+  use std::sync::Arc;
+
+  let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+  // SAFETY: `c_path` is a valid C string
+  if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } == -1 {
+    return Err(std::io::Error::last_os_error());
+  }
+  let opened = Arc::new(AtomicBool::new(false));
+  // opening a FIFO blocks until the command opens the other end
+  let open = {
+    let path = path.to_path_buf();
+    let opened = opened.clone();
+    tokio::task::spawn_blocking(move || {
+      let file = std::fs::OpenOptions::new()
+        .read(kind == ProcessSubstitutionKind::Write)
+        .write(kind == ProcessSubstitutionKind::Read)
+        .open(path);
+      opened.store(true, Ordering::SeqCst);
+      file
+    })
+  };
+  let stdin = stdin.clone();
+  let stdout = stdout.clone();
+  let mut stderr = stderr.clone();
+  let handle = tokio::task::spawn_local(async move {
+    let file = match open.await {
+      Ok(Ok(file)) => file,
+      Ok(Err(err)) => {
+        let _ = stderr.write_line(&format!("shell: {err}"));
+        return 1;
+      }
+      Err(_) => return 1,
+    };
+    match kind {
+      ProcessSubstitutionKind::Read => {
+        let stdout = ShellPipeWriter::StdFile(file);
+        execute_with_pipes(list, state, stdin, stdout, stderr).await
+      }
+      ProcessSubstitutionKind::Write => {
+        let stdin = ShellPipeReader::StdFile(file);
+        execute_with_pipes(list, state, stdin, stdout, stderr).await
+      }
+    }
+  });
+  Ok(RunningList::Fifo {
+    kind,
+    opened,
+    handle,
+  })
+}
+
+/// Opens the command's end of a FIFO that the command didn't open, so the
+/// list doesn't wait for it forever. It is closed again once the list has
+/// opened its end, which then reads nothing or can't write. Returns whether
+/// the command didn't open it.
+#[cfg(unix)]
+async fn unblock_fifo(
+  path: &Path,
+  kind: ProcessSubstitutionKind,
+  opened: &std::sync::atomic::AtomicBool,
+) -> bool {
+  use std::os::unix::fs::OpenOptionsExt;
+  use std::sync::atomic::Ordering;
+
+  let unused = !opened.load(Ordering::SeqCst);
+
+  let mut other_end = None;
+  while !opened.load(Ordering::SeqCst) {
+    if other_end.is_none() {
+      // opening the writing end fails until the list is waiting to read
+      other_end = std::fs::OpenOptions::new()
+        .read(kind == ProcessSubstitutionKind::Read)
+        .write(kind == ProcessSubstitutionKind::Write)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+        .ok();
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+  }
+  unused
+}
@@ -0,0 +1,71 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+//! Tilde expansion: `~` is the home directory, `~user` the home directory of
+//! `user`, and `~+` and `~-` are `$PWD` and `$OLDPWD`. Like in bash, a prefix
+//! that doesn't name a directory stays as it is.
+
+use std::path::PathBuf;
+
+use crate::parser::TildePrefix;
+use crate::shell::types::ShellState;
+
+/// The text that `prefix` expands to.
+pub fn expand_tilde(state: &ShellState, prefix: &TildePrefix) -> String {
+  let dir = match prefix.user.as_deref() {
+    None => state
+      .get_var("HOME")
+      .or_else(|| dirs::home_dir().map(|dir| dir.display().to_string())),
+    Some("+") => state.get_var("PWD"),
+    Some("-") => state.get_var("OLDPWD"),
+    Some(user) => user_home_dir(user).map(|dir| dir.display().to_string()),
+  };
+  dir.unwrap_or_else(|| format!("~{}", prefix.user.as_deref().unwrap_or("")))
+}
+
+/// Looks up the home directory of `user` in the user database.
+#[cfg(unix)]
+fn user_home_dir(user: &str) -> Option<PathBuf> {
+  use std::ffi::CStr;
+  use std::ffi::CString;
+  use std::ffi::OsStr;
+  use std::os::unix::ffi::OsStrExt;
+
+  let name = CString::new(user).ok()?;
+  let mut buffer = vec![0 as libc::c_char; 1024];
+  // SAFETY: all zeros is a valid `passwd`, it only holds integers and pointers
+  let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+  let mut result = std::ptr::null_mut();
+  loop {
+    // SAFETY: the buffer and its length match, and the strings of `passwd`
+    // point into the buffer, which outlives them
+    let code = unsafe {
+      libc::getpwnam_r(
+        name.as_ptr(),
+        &mut passwd,
+        buffer.as_mut_ptr(),
+        buffer.len(),
+        &mut result,
+      )
+    };
+    // the buffer must fit all the fields of the entry
+    if code == libc::ERANGE && buffer.len() < 1 << 20 {
+      buffer.resize(buffer.len() * 2, 0);
+      continue;
+    }
+    break;
+  }
+  if result.is_null() || passwd.pw_dir.is_null() {
+    return None;
+  }
+  // SAFETY: `pw_dir` is a C string in the buffer
+  let dir = unsafe { CStr::from_ptr(passwd.pw_dir) };
+  Some(PathBuf::from(OsStr::from_bytes(dir.to_bytes())))
+}
+
+/// Windows has no user database to ask, but keeps the profiles of all users
+/// next to each other.
+#[cfg(not(unix))]
+fn user_home_dir(user: &str) -> Option<PathBuf> {
+  let dir = dirs::home_dir()?.parent()?.join(user);
+  dir.is_dir().then_some(dir)
+}
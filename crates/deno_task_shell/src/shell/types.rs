@@ -1,7 +1,9 @@
 // Copyright 2018-2024 the Deno authors. MIT license.
 
 use std::borrow::Cow;
+use std::cell::Cell;
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::fmt;
 use std::fmt::Display;
@@ -12,18 +14,24 @@ use std::path::Path;
 use std::path::PathBuf;
 use std::rc::Rc;
 use std::str::FromStr;
+use std::time::Duration;
+use std::time::Instant;
 
 use futures::future::LocalBoxFuture;
 use miette::Error;
 use miette::IntoDiagnostic;
 use miette::Result;
+use path_dedot::ParseDot;
 use tokio::task::JoinHandle;
 use tokio_util::sync::CancellationToken;
 
-use crate::shell::fs_util;
+use crate::parser::FunctionDefinition;
 
 use super::commands::builtin_commands;
 use super::commands::ShellCommand;
+use super::jobs::Job;
+use super::jobs::JobStatus;
+use super::jobs::JobTable;
 
 #[derive(Clone)]
 pub struct ShellState {
@@ -33,12 +41,26 @@ pub struct ShellState {
   /// Variables that should be evaluated within the shell and
   /// not passed down to any sub commands.
   shell_vars: HashMap<String, String>,
+  /// Indexed array variables (e.g. filled by `mapfile`), sorted by index.
+  /// They can be sparse, and are never passed down to sub commands.
+  array_vars: HashMap<String, BTreeMap<usize, String>>,
+  /// Associative array variables (`declare -A`), sorted by key
+  assoc_vars: HashMap<String, BTreeMap<String, String>>,
+  /// Attributes given to variables with `declare`
+  var_attributes: HashMap<String, VarAttributes>,
   /// The current working directory of the shell
   cwd: PathBuf,
+  /// The directories saved with `pushd`, the most recent first. The top of
+  /// the directory stack is the current directory, which isn't included
+  dir_stack: Vec<PathBuf>,
   /// The commands that are available in the shell
   commands: Rc<HashMap<String, Rc<dyn ShellCommand>>>,
   /// A map of aliases for commands (e.g. `ll=ls -al`)
   alias: HashMap<String, Vec<String>>,
+  /// The functions defined with `name() { ... }`
+  functions: HashMap<String, Rc<FunctionDefinition>>,
+  /// How the arguments of commands are completed, set with `complete`
+  completions: HashMap<String, CompletionSpec>,
   /// Token to cancel execution.
   token: CancellationToken,
   /// Git repository handling.
@@ -47,10 +69,62 @@ pub struct ShellState {
   git_branch: String, // Contents of `$git_root/.git/HEAD`
   last_command_cd: bool, // Was last command a `cd` (thus git_branch is current)?
   last_command_exit_code: i32, // Exit code of the last command
+  /// Whether `set -e` ignores the status of the command that just ran,
+  /// because it was negated with `!` or ended a `&&` or `||` list early.
+  /// It is shared with the states of the commands, which set it
+  errexit_ignored: Rc<Cell<bool>>,
+  /// How long the last interactive command took
+  last_command_duration: Option<Duration>,
   // The shell options to be modified using `set` command
   shell_options: HashMap<ShellOptions, bool>,
+  /// The files of the `source` invocations that are currently nested,
+  /// the outermost first
+  source_stack: Vec<PathBuf>,
+  /// How many function calls are currently nested
+  function_depth: usize,
+  /// How many `eval` invocations are currently nested
+  eval_depth: usize,
+  /// How many `for`, `while` or `until` loops are currently nested
+  loop_depth: usize,
+  /// The positional parameters (`$1`, `$2`, ...) of the current script
+  positional_args: Vec<String>,
+  /// The file the commands being executed were read from, if any
+  script_name: Option<String>,
+  /// The text that was parsed into the commands being executed, with the
+  /// name it is shown with in errors
+  script_source: Option<Rc<miette::NamedSource<String>>>,
+  /// The line of the command being executed, available as `$LINENO`
+  line_number: usize,
+  /// The state behind `$RANDOM`, `$SECONDS` and `$!`, shared with all
+  /// copies of the state so that each read sees the previous ones
+  dynamic_vars: Rc<DynamicVars>,
+  /// Whether the commands run in the background (with `&`), their
+  /// processes are recorded for `$!`
+  background: bool,
+  /// Whether the commands were typed at the prompt, where an error like an
+  /// unset variable with `set -u` ends the command rather than the shell
+  interactive: bool,
+  /// The process group shared by the commands of the current pipeline
+  #[cfg(unix)]
+  process_group: Option<Rc<super::job_control::ProcessGroup>>,
+  /// Background and stopped jobs
+  jobs: JobTable,
 }
 
+/// The default limit for nested `source` invocations. Can be overridden
+/// with the `SHELL_MAX_SOURCE_DEPTH` variable.
+pub const DEFAULT_MAX_SOURCE_DEPTH: usize = 100;
+
+/// The default limit for nested function calls, which keeps a runaway
+/// recursion from overflowing the stack. Can be overridden with the
+/// `SHELL_MAX_FUNCTION_DEPTH` variable.
+pub const DEFAULT_MAX_FUNCTION_DEPTH: usize = 100;
+
+/// The default limit for nested `eval` invocations, for a command that
+/// evaluates itself. Can be overridden with the `SHELL_MAX_EVAL_DEPTH`
+/// variable.
+pub const DEFAULT_MAX_EVAL_DEPTH: usize = 100;
+
 impl ShellState {
   pub fn new(
     env_vars: HashMap<String, String>,
@@ -63,7 +137,12 @@ impl ShellState {
     let mut result = Self {
       env_vars: Default::default(),
       shell_vars: Default::default(),
+      array_vars: Default::default(),
+      assoc_vars: Default::default(),
+      var_attributes: Default::default(),
       alias: Default::default(),
+      functions: Default::default(),
+      completions: Default::default(),
       cwd: PathBuf::new(),
       commands: Rc::new(commands),
       token: CancellationToken::default(),
@@ -72,11 +151,30 @@ impl ShellState {
       git_branch: String::new(),
       last_command_cd: false,
       last_command_exit_code: 0,
+      errexit_ignored: Default::default(),
+      last_command_duration: None,
       shell_options: {
         let mut map = HashMap::new();
         map.insert(ShellOptions::ExitOnError, true);
+        map.insert(ShellOptions::Emacs, true);
+        map.insert(ShellOptions::FailGlob, true);
         map
       },
+      source_stack: Vec::new(),
+      dir_stack: Vec::new(),
+      function_depth: 0,
+      eval_depth: 0,
+      loop_depth: 0,
+      positional_args: Vec::new(),
+      script_name: None,
+      script_source: None,
+      line_number: 0,
+      dynamic_vars: Rc::new(DynamicVars::new()),
+      background: false,
+      interactive: false,
+      #[cfg(unix)]
+      process_group: None,
+      jobs: JobTable::default(),
     };
     // ensure the data is normalized
     for (name, value) in env_vars {
@@ -90,10 +188,19 @@ impl ShellState {
     &self.cwd
   }
 
+  /// The directories saved with `pushd`, the most recent first.
+  pub fn dir_stack(&self) -> &[PathBuf] {
+    &self.dir_stack
+  }
+
   pub fn alias_map(&self) -> &HashMap<String, Vec<String>> {
     &self.alias
   }
 
+  pub fn completions(&self) -> &HashMap<String, CompletionSpec> {
+    &self.completions
+  }
+
   pub fn git_repository(&self) -> bool {
     self.git_repository
   }
@@ -118,11 +225,133 @@ impl ShellState {
     self.last_command_exit_code
   }
 
+  pub fn set_last_command_duration(&mut self, duration: Duration) {
+    self.last_command_duration = Some(duration);
+  }
+
+  pub fn last_command_duration(&self) -> Option<Duration> {
+    self.last_command_duration
+  }
+
+  pub fn source_depth(&self) -> usize {
+    self.source_stack.len()
+  }
+
+  /// The files being sourced, the outermost first.
+  pub fn source_stack(&self) -> &[PathBuf] {
+    &self.source_stack
+  }
+
+  pub fn max_source_depth(&self) -> usize {
+    self
+      .get_var("SHELL_MAX_SOURCE_DEPTH")
+      .and_then(|v| v.parse().ok())
+      .unwrap_or(DEFAULT_MAX_SOURCE_DEPTH)
+  }
+
+  /// Enters a nested `source` invocation of `path`.
+  ///
+  /// Fails without changing the state if `path` is already being sourced,
+  /// which would never end, or the maximum nesting depth would be exceeded.
+  pub fn enter_source(&mut self, path: &Path) -> Result<(), SourceError> {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let is_cycle = self.source_stack.contains(&path);
+    let max_depth = self.max_source_depth();
+    if is_cycle || self.source_stack.len() >= max_depth {
+      let mut chain = self.source_stack.clone();
+      chain.push(path);
+      return Err(if is_cycle {
+        SourceError::Cycle(chain)
+      } else {
+        SourceError::TooDeep(max_depth, chain)
+      });
+    }
+    self.source_stack.push(path);
+    Ok(())
+  }
+
+  pub fn function_depth(&self) -> usize {
+    self.function_depth
+  }
+
+  pub fn max_function_depth(&self) -> usize {
+    self
+      .get_var("SHELL_MAX_FUNCTION_DEPTH")
+      .and_then(|v| v.parse().ok())
+      .unwrap_or(DEFAULT_MAX_FUNCTION_DEPTH)
+  }
+
+  /// Enters a function call.
+  ///
+  /// Returns `false` without changing the depth if the maximum nesting
+  /// depth would be exceeded.
+  pub fn enter_function(&mut self) -> bool {
+    if self.function_depth >= self.max_function_depth() {
+      return false;
+    }
+    self.function_depth += 1;
+    true
+  }
+
+  pub fn max_eval_depth(&self) -> usize {
+    self
+      .get_var("SHELL_MAX_EVAL_DEPTH")
+      .and_then(|v| v.parse().ok())
+      .unwrap_or(DEFAULT_MAX_EVAL_DEPTH)
+  }
+
+  /// Enters a nested `eval` invocation.
+  ///
+  /// Returns `false` without changing the depth if the maximum nesting
+  /// depth would be exceeded.
+  pub fn enter_eval(&mut self) -> bool {
+    if self.eval_depth >= self.max_eval_depth() {
+      return false;
+    }
+    self.eval_depth += 1;
+    true
+  }
+
+  pub fn function(&self, name: &str) -> Option<Rc<FunctionDefinition>> {
+    self.functions.get(name).cloned()
+  }
+
+  pub fn functions(&self) -> &HashMap<String, Rc<FunctionDefinition>> {
+    &self.functions
+  }
+
+  pub fn loop_depth(&self) -> usize {
+    self.loop_depth
+  }
+
+  /// Enters the body of a loop, which `break` and `continue` refer to.
+  pub fn enter_loop(&mut self) {
+    self.loop_depth += 1;
+  }
+
+  /// Starts a sub shell, which like in bash can't `break` or `continue`
+  /// the loops it was started from.
+  pub fn enter_subshell(&mut self) {
+    self.loop_depth = 0;
+    self.interactive = false;
+  }
+
+  pub fn set_interactive(&mut self, interactive: bool) {
+    self.interactive = interactive;
+  }
+
+  pub fn is_interactive(&self) -> bool {
+    self.interactive
+  }
+
   pub fn env_vars(&self) -> &HashMap<String, String> {
     &self.env_vars
   }
 
-  pub fn get_var(&self, name: &str) -> Option<&String> {
+  pub fn get_var(&self, name: &str) -> Option<String> {
+    if let Some(value) = self.get_special_param(name) {
+      return Some(value);
+    }
     let (original_name, updated_name) = if cfg!(windows) {
       (
         Cow::Owned(name.to_string()),
@@ -135,6 +364,201 @@ impl ShellState {
       .env_vars
       .get(updated_name.as_ref())
       .or_else(|| self.shell_vars.get(original_name.as_ref()))
+      // like bash, `$array` is the first element
+      .or_else(|| {
+        self
+          .array_vars
+          .get(original_name.as_ref())
+          .and_then(|array| array.get(&0))
+      })
+      // and `$map` is the element with the key `0`
+      .or_else(|| {
+        self
+          .assoc_vars
+          .get(original_name.as_ref())
+          .and_then(|map| map.get("0"))
+      })
+      .cloned()
+  }
+
+  pub fn get_array(&self, name: &str) -> Option<&BTreeMap<usize, String>> {
+    self.array_vars.get(name)
+  }
+
+  pub fn get_assoc(&self, name: &str) -> Option<&BTreeMap<String, String>> {
+    self.assoc_vars.get(name)
+  }
+
+  pub fn shell_vars(&self) -> &HashMap<String, String> {
+    &self.shell_vars
+  }
+
+  pub fn array_vars(&self) -> &HashMap<String, BTreeMap<usize, String>> {
+    &self.array_vars
+  }
+
+  pub fn assoc_vars(&self) -> &HashMap<String, BTreeMap<String, String>> {
+    &self.assoc_vars
+  }
+
+  pub fn var_attributes(&self, name: &str) -> VarAttributes {
+    self.var_attributes.get(name).copied().unwrap_or_default()
+  }
+
+  /// Resolves the special parameters `$?`, `$$`, `$!`, `$0`, `$#`, `$@`,
+  /// `$*`, `$LINENO`, `$RANDOM`, `$SECONDS`, `$PPID` and the positional
+  /// parameters `$1`, `$2`, ...
+  fn get_special_param(&self, name: &str) -> Option<String> {
+    match name {
+      "?" => Some(self.last_command_exit_code.to_string()),
+      "$" => Some(std::process::id().to_string()),
+      "!" => self
+        .dynamic_vars
+        .last_background_pid
+        .get()
+        .map(|pid| pid.to_string()),
+      // the script, or the shell itself
+      "0" => self.script_name.clone().or_else(|| std::env::args().next()),
+      "LINENO" => Some(self.line_number.to_string()),
+      "RANDOM" => Some(self.dynamic_vars.next_random().to_string()),
+      "SECONDS" => Some(
+        self
+          .dynamic_vars
+          .seconds_start
+          .get()
+          .elapsed()
+          .as_secs()
+          .to_string(),
+      ),
+      #[cfg(unix)]
+      "PPID" => Some(std::os::unix::process::parent_id().to_string()),
+      "#" => Some(self.positional_args.len().to_string()),
+      "@" | "*" => Some(self.positional_args.join(" ")),
+      _ => match name.parse::<usize>() {
+        Ok(0) | Err(_) => None,
+        Ok(index) => self.positional_args.get(index - 1).cloned(),
+      },
+    }
+  }
+
+  pub fn positional_args(&self) -> &[String] {
+    &self.positional_args
+  }
+
+  pub fn set_positional_args(&mut self, args: Vec<String>) {
+    self.positional_args = args;
+  }
+
+  /// Runs the commands in the background, see [`Self::record_background_pid`].
+  pub fn enter_background(&mut self) {
+    self.background = true;
+    self.interactive = false;
+    // the foreground commands shouldn't see the ones of the background job
+    self.errexit_ignored = Default::default();
+  }
+
+  pub fn in_background(&self) -> bool {
+    self.background
+  }
+
+  /// Starts a pipeline, whose commands run in one process group so that
+  /// they all get Ctrl-C and Ctrl-Z.
+  pub fn enter_pipeline(&mut self) {
+    #[cfg(unix)]
+    {
+      self.process_group = Some(Rc::default());
+    }
+  }
+
+  #[cfg(unix)]
+  pub fn process_group(&self) -> Option<&Rc<super::job_control::ProcessGroup>> {
+    self.process_group.as_ref()
+  }
+
+  /// Records a process that was started, it becomes `$!` if it runs in the
+  /// background.
+  pub fn record_background_pid(&self, pid: u32) {
+    if self.background {
+      self.dynamic_vars.last_background_pid.set(Some(pid));
+    }
+  }
+
+  /// Sets `$!` to a job that was started, see [`Job::background_pid`].
+  pub fn set_last_background_pid(&self, pid: u32) {
+    self.dynamic_vars.last_background_pid.set(Some(pid));
+  }
+
+  /// Adds the CPU time used by a child that was waited for. Only done on
+  /// Windows, the kernel keeps this total on unix.
+  pub fn add_children_times(&self, user: Duration, system: Duration) {
+    let (total_user, total_system) = self.dynamic_vars.children_times.get();
+    self
+      .dynamic_vars
+      .children_times
+      .set((total_user + user, total_system + system));
+  }
+
+  /// The user and system CPU time used by the children that were waited
+  /// for, see [`Self::add_children_times`].
+  pub fn children_times(&self) -> (Duration, Duration) {
+    self.dynamic_vars.children_times.get()
+  }
+
+  /// The process that `$!` refers to.
+  pub fn last_background_pid(&self) -> Option<u32> {
+    self.dynamic_vars.last_background_pid.get()
+  }
+
+  /// Seeds the generator behind `$RANDOM`, as done by assigning to it.
+  pub fn seed_random(&self, seed: u32) {
+    self.dynamic_vars.random_state.set(seed);
+  }
+
+  /// Makes `$SECONDS` count from `seconds`, as done by assigning to it.
+  pub fn reset_seconds(&self, seconds: u64) {
+    let now = Instant::now();
+    let start = now.checked_sub(Duration::from_secs(seconds)).unwrap_or(now);
+    self.dynamic_vars.seconds_start.set(start);
+  }
+
+  pub fn set_script_name(&mut self, name: String) {
+    self.script_name = Some(name);
+  }
+
+  pub fn script_source(&self) -> Option<&miette::NamedSource<String>> {
+    self.script_source.as_deref()
+  }
+
+  /// Sets the text the commands are parsed from, so runtime errors can
+  /// point at where they happened in it.
+  pub fn set_script_source(&mut self, name: &str, text: &str) {
+    self.script_source =
+      Some(Rc::new(miette::NamedSource::new(name, text.to_string())));
+  }
+
+  pub fn line_number(&self) -> usize {
+    self.line_number
+  }
+
+  pub fn set_line_number(&mut self, line: usize) {
+    self.line_number = line;
+  }
+
+  /// The `file:line` of the command being executed, used to prefix error
+  /// messages of scripts. Commands that weren't read from a file have none.
+  pub fn script_location(&self) -> Option<String> {
+    self
+      .script_name
+      .as_ref()
+      .map(|name| format!("{}:{}", name, self.line_number))
+  }
+
+  pub fn jobs(&self) -> &JobTable {
+    &self.jobs
+  }
+
+  pub fn jobs_mut(&mut self) -> &mut JobTable {
+    &mut self.jobs
   }
 
   // Update self.git_branch using self.git_root
@@ -208,6 +632,10 @@ impl ShellState {
     self.shell_options.insert(option, value);
   }
 
+  pub fn shell_option(&self, option: ShellOptions) -> bool {
+    matches!(self.shell_options.get(&option), Some(true))
+  }
+
   pub fn exit_on_error(&mut self) -> bool {
     matches!(
       self.shell_options.get(&ShellOptions::ExitOnError),
@@ -215,6 +643,18 @@ impl ShellState {
     )
   }
 
+  /// Set when the command that just ran was negated or ended its `&&` or
+  /// `||` list early, reset before each command of a list.
+  pub(crate) fn errexit_ignored(&self) -> Rc<Cell<bool>> {
+    self.errexit_ignored.clone()
+  }
+
+  /// Whether a failure of the command that just ran should exit with
+  /// `set -e`.
+  pub(crate) fn exits_on_failure(&mut self, exit_code: i32) -> bool {
+    exit_code != 0 && self.exit_on_error() && !self.errexit_ignored.get()
+  }
+
   pub fn print_trace(&self) -> bool {
     matches!(
       self.shell_options.get(&ShellOptions::PrintTrace),
@@ -230,17 +670,59 @@ impl ShellState {
   }
 
   pub fn apply_change(&mut self, change: &EnvChange) {
+    if let Some(name) = change.var_name() {
+      // assignments to readonly variables are refused before getting here
+      if self.var_attributes(name).readonly {
+        return;
+      }
+    }
+    let attributes = change
+      .var_name()
+      .map(|name| self.var_attributes(name))
+      .unwrap_or_default();
     match change {
-      EnvChange::SetEnvVar(name, value) => self.apply_env_var(name, value),
+      EnvChange::SetEnvVar(name, value) => {
+        self.apply_env_var(name, &attributes.apply_case(value))
+      }
       EnvChange::SetShellVar(name, value) => {
+        let value = &attributes.apply_case(value);
         if self.env_vars.contains_key(name) {
           self.apply_env_var(name, value);
+        } else if let Some(array) = self.array_vars.get_mut(name) {
+          // assigning to an array sets the element with the index `0`
+          array.insert(0, value.to_string());
+        } else if let Some(map) = self.assoc_vars.get_mut(name) {
+          map.insert("0".to_string(), value.to_string());
         } else {
           self.shell_vars.insert(name.to_string(), value.to_string());
         }
       }
+      EnvChange::SetArrayVar(name, values) => {
+        self.shell_vars.remove(name);
+        self.assoc_vars.remove(name);
+        let values = values
+          .iter()
+          .map(|(index, value)| (*index, attributes.apply_case(value)))
+          .collect();
+        self.array_vars.insert(name.to_string(), values);
+      }
+      EnvChange::SetAssocVar(name, map) => {
+        self.shell_vars.remove(name);
+        self.array_vars.remove(name);
+        let map = map
+          .iter()
+          .map(|(key, value)| (key.clone(), attributes.apply_case(value)))
+          .collect();
+        self.assoc_vars.insert(name.to_string(), map);
+      }
+      EnvChange::SetVarAttributes(name, attributes) => {
+        self.var_attributes.insert(name.to_string(), *attributes);
+      }
       EnvChange::UnsetVar(name) => {
         self.shell_vars.remove(name);
+        self.array_vars.remove(name);
+        self.assoc_vars.remove(name);
+        self.var_attributes.remove(name);
         if cfg!(windows) {
           // environment variables are case insensitive on windows
           self.env_vars.remove(&name.to_uppercase());
@@ -249,9 +731,15 @@ impl ShellState {
         }
       }
       EnvChange::Cd(new_dir) => {
+        // like in bash, `~-` is where `cd` changed from
+        let old_dir = self.cwd.display().to_string();
+        self.env_vars.insert("OLDPWD".to_string(), old_dir);
         self.set_cwd(new_dir);
         self.last_command_cd = true;
       }
+      EnvChange::SetDirStack(stack) => {
+        self.dir_stack.clone_from(stack);
+      }
       EnvChange::AliasCommand(alias, cmd) => {
         self.alias.insert(
           alias.clone(),
@@ -261,9 +749,42 @@ impl ShellState {
       EnvChange::UnAliasCommand(alias) => {
         self.alias.remove(alias);
       }
+      EnvChange::DefineFunction(function) => {
+        self
+          .functions
+          .insert(function.name.clone(), function.clone());
+      }
+      EnvChange::UnsetFunction(name) => {
+        self.functions.remove(name);
+      }
+      EnvChange::SetCompletion(name, spec) => {
+        self.completions.insert(name.clone(), spec.clone());
+      }
+      EnvChange::RemoveCompletion(name) => {
+        self.completions.remove(name);
+      }
       EnvChange::SetShellOptions(option, value) => {
         self.set_shell_option(*option, *value);
       }
+      EnvChange::SetPositionalArgs(args) => {
+        self.set_positional_args(args.clone());
+      }
+      EnvChange::RemoveJob(id) => {
+        self.jobs.remove(*id);
+      }
+      EnvChange::SetJobNoHangup(id) => {
+        if let Some(job) = self.jobs.get_mut(*id) {
+          job.no_hangup = true;
+        }
+      }
+      EnvChange::AddJob(job) => {
+        self.jobs.insert(job.clone());
+      }
+      EnvChange::SetJobStatus(id, status) => {
+        if let Some(job) = self.jobs.get_mut(*id) {
+          job.status = *status;
+        }
+      }
     }
   }
 
@@ -276,11 +797,14 @@ impl ShellState {
     };
     if name == "PWD" {
       let cwd = PathBuf::from(value);
-      if cwd.is_absolute() {
-        if let Ok(cwd) = fs_util::canonicalize_path(&cwd) {
-          // this will update the environment variable too
-          self.set_cwd(&cwd);
-        }
+      // keep the logical path (don't resolve symlinks), but normalize it
+      if cwd.is_absolute() && cwd.is_dir() {
+        let cwd = match cwd.parse_dot() {
+          Ok(path) => path.to_path_buf(),
+          Err(_) => cwd,
+        };
+        // this will update the environment variable too
+        self.set_cwd(&cwd);
       }
     } else {
       self.shell_vars.remove(&name);
@@ -301,6 +825,11 @@ impl ShellState {
     self.commands.get(name).cloned()
   }
 
+  /// The names of the built-in and injected custom commands.
+  pub fn custom_command_names(&self) -> impl Iterator<Item = &str> {
+    self.commands.keys().map(String::as_str)
+  }
+
   /// Resolves the path to a command from the current working directory.
   ///
   /// Does not take injected custom commands into account.
@@ -322,22 +851,141 @@ impl ShellState {
   }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, PartialOrd)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum EnvChange {
   /// `export ENV_VAR=VALUE`
   SetEnvVar(String, String),
   /// `ENV_VAR=VALUE`
   SetShellVar(String, String),
+  /// Replace an indexed array variable (e.g. `mapfile`)
+  SetArrayVar(String, BTreeMap<usize, String>),
+  /// Replace an associative array variable (e.g. `declare -A`)
+  SetAssocVar(String, BTreeMap<String, String>),
+  /// Give a variable attributes (`declare -i`, `declare -r`, ...)
+  SetVarAttributes(String, VarAttributes),
   /// Create an alias for a command (e.g. ll=ls -al)
   AliasCommand(String, String),
   /// Remove an alias
   UnAliasCommand(String),
+  /// `name() { ... }`
+  DefineFunction(Rc<FunctionDefinition>),
+  /// `unset -f name`
+  UnsetFunction(String),
+  /// `complete -W "words" name`
+  SetCompletion(String, CompletionSpec),
+  /// `complete -r name`
+  RemoveCompletion(String),
   /// `unset ENV_VAR`
   UnsetVar(String),
   /// Set the current working directory to the new Path
   Cd(PathBuf),
+  /// Replace the directories saved with `pushd` (`popd`, `dirs -c`)
+  SetDirStack(Vec<PathBuf>),
   /// `set -ex`
   SetShellOptions(ShellOptions, bool),
+  /// Replace the positional parameters (e.g. `shift`)
+  SetPositionalArgs(Vec<String>),
+  /// Remove a job from the job table (`disown`)
+  RemoveJob(usize),
+  /// Keep the job, but don't send it a SIGHUP on exit (`disown -h`)
+  SetJobNoHangup(usize),
+  /// Add a job to the job table, e.g. a command stopped with Ctrl-Z
+  AddJob(Job),
+  /// Update the status of a job (`bg`, `fg`)
+  SetJobStatus(usize, JobStatus),
+}
+
+impl EnvChange {
+  /// The name of the variable this change modifies, if any
+  pub fn var_name(&self) -> Option<&str> {
+    match self {
+      EnvChange::SetEnvVar(name, _)
+      | EnvChange::SetShellVar(name, _)
+      | EnvChange::SetArrayVar(name, _)
+      | EnvChange::SetAssocVar(name, _)
+      | EnvChange::SetVarAttributes(name, _)
+      | EnvChange::UnsetVar(name) => Some(name),
+      _ => None,
+    }
+  }
+}
+
+struct DynamicVars {
+  random_state: Cell<u32>,
+  seconds_start: Cell<Instant>,
+  last_background_pid: Cell<Option<u32>>,
+  /// The user and system CPU time of the children that were waited for
+  children_times: Cell<(Duration, Duration)>,
+}
+
+impl DynamicVars {
+  fn new() -> Self {
+    let nanos = std::time::SystemTime::now()
+      .duration_since(std::time::UNIX_EPOCH)
+      .map(|time| time.subsec_nanos())
+      .unwrap_or_default();
+    DynamicVars {
+      random_state: Cell::new(nanos ^ std::process::id()),
+      seconds_start: Cell::new(Instant::now()),
+      last_background_pid: Cell::new(None),
+      children_times: Cell::new((Duration::ZERO, Duration::ZERO)),
+    }
+  }
+
+  /// The next number from 0 to 32767, using the same generator as bash so
+  /// that a seed gives the same sequence.
+  fn next_random(&self) -> u32 {
+    // Park-Miller "minimal standard" generator, computed without overflow
+    let mut state = self.random_state.get() as i64 % 0x7fffffff;
+    if state == 0 {
+      state = 123459876;
+    }
+    let (hi, lo) = (state / 127773, state % 127773);
+    let mut next = 16807 * lo - 2836 * hi;
+    if next < 0 {
+      next += 0x7fffffff;
+    }
+    self.random_state.set(next as u32);
+    let next = next as u32;
+    ((next >> 16) ^ (next & 0xffff)) & 32767
+  }
+}
+
+/// How the arguments of a command are completed, set with `complete`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CompletionSpec {
+  /// The words to complete `-W`
+  pub words: Vec<String>,
+  /// Whether file names are completed `-f`
+  pub files: bool,
+  /// Whether directory names are completed `-d`
+  pub directories: bool,
+}
+
+/// The attributes of a variable, set with `declare`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd)]
+pub struct VarAttributes {
+  /// Assignments are evaluated as arithmetic `-i`
+  pub integer: bool,
+  /// The variable can't be assigned to or unset `-r`
+  pub readonly: bool,
+  /// Assigned values are converted to lower case `-l`
+  pub lowercase: bool,
+  /// Assigned values are converted to upper case `-u`
+  pub uppercase: bool,
+}
+
+impl VarAttributes {
+  /// Converts the case of an assigned value
+  pub fn apply_case(&self, value: &str) -> String {
+    if self.lowercase {
+      value.to_lowercase()
+    } else if self.uppercase {
+      value.to_uppercase()
+    } else {
+      value.to_string()
+    }
+  }
 }
 
 #[derive(Clone, Copy, Hash, PartialEq, Eq, Debug, PartialOrd)]
@@ -346,6 +994,70 @@ pub enum ShellOptions {
   ExitOnError,
   /// If set, the shell print a trace of simple commands when they are invoked `-x`
   PrintTrace,
+  /// If set, expanding an unset variable is an error `-u`
+  NoUnset,
+  /// If set, a pipeline fails if any of its commands fails `-o pipefail`
+  PipeFail,
+  /// If set, glob patterns are not expanded `-f`
+  NoGlob,
+  /// If set, a glob pattern that matches nothing is removed `-o nullglob`
+  NullGlob,
+  /// If set, a glob pattern that matches nothing is an error, otherwise it
+  /// is kept as it is `-o failglob`. On by default, `nullglob` takes
+  /// precedence
+  FailGlob,
+  /// If set, glob patterns also match hidden files `-o dotglob`
+  DotGlob,
+  /// If set, the interactive line editor uses emacs key bindings `-o emacs`
+  Emacs,
+  /// If set, the interactive line editor uses vi key bindings `-o vi`
+  Vi,
+  /// If set, foreground commands run as jobs that can be stopped with
+  /// Ctrl-Z `-m`
+  Monitor,
+  /// If set, commands are read but not executed `-n`. Since nothing runs
+  /// anymore, it can't be turned off again
+  NoExec,
+}
+
+impl ShellOptions {
+  /// All options, in the order they are listed by `set -o`.
+  pub const ALL: [ShellOptions; 12] = [
+    ShellOptions::DotGlob,
+    ShellOptions::Emacs,
+    ShellOptions::ExitOnError,
+    ShellOptions::FailGlob,
+    ShellOptions::Monitor,
+    ShellOptions::NoExec,
+    ShellOptions::NoGlob,
+    ShellOptions::NoUnset,
+    ShellOptions::NullGlob,
+    ShellOptions::PipeFail,
+    ShellOptions::Vi,
+    ShellOptions::PrintTrace,
+  ];
+
+  /// The name used with `set -o`
+  pub fn name(&self) -> &'static str {
+    match self {
+      ShellOptions::ExitOnError => "errexit",
+      ShellOptions::PrintTrace => "xtrace",
+      ShellOptions::NoUnset => "nounset",
+      ShellOptions::PipeFail => "pipefail",
+      ShellOptions::NoGlob => "noglob",
+      ShellOptions::NullGlob => "nullglob",
+      ShellOptions::FailGlob => "failglob",
+      ShellOptions::DotGlob => "dotglob",
+      ShellOptions::Emacs => "emacs",
+      ShellOptions::Vi => "vi",
+      ShellOptions::Monitor => "monitor",
+      ShellOptions::NoExec => "noexec",
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<ShellOptions> {
+    Self::ALL.into_iter().find(|option| option.name() == name)
+  }
 }
 
 pub type FutureExecuteResult = LocalBoxFuture<'static, ExecuteResult>;
@@ -358,6 +1070,21 @@ pub const CANCELLATION_EXIT_CODE: i32 = 130;
 pub enum ExecuteResult {
   Exit(i32, Vec<JoinHandle<i32>>),
   Continue(i32, Vec<EnvChange>, Vec<JoinHandle<i32>>),
+  /// `return` was used in a function or a sourced script. Execution stops
+  /// until the caller, but unlike `Exit` the changes so far are kept.
+  Return(i32, Vec<EnvChange>, Vec<JoinHandle<i32>>),
+  /// `break` or `continue` was used in a loop. Execution stops until the
+  /// loop it refers to, keeping the changes so far.
+  LoopControl(LoopControl, Vec<EnvChange>, Vec<JoinHandle<i32>>),
+}
+
+/// How `break` or `continue` leaves the enclosing loops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopControl {
+  /// Leave this many of the enclosing loops
+  Break(usize),
+  /// Go on with the next iteration of the n-th enclosing loop
+  Continue(usize),
 }
 
 impl ExecuteResult {
@@ -369,10 +1096,21 @@ impl ExecuteResult {
     ExecuteResult::Continue(exit_code, Vec::new(), Vec::new())
   }
 
+  pub fn exit_code(&self) -> i32 {
+    match self {
+      ExecuteResult::Exit(code, _)
+      | ExecuteResult::Continue(code, _, _)
+      | ExecuteResult::Return(code, _, _) => *code,
+      ExecuteResult::LoopControl(_, _, _) => 0,
+    }
+  }
+
   pub fn into_exit_code_and_handles(self) -> (i32, Vec<JoinHandle<i32>>) {
     match self {
       ExecuteResult::Exit(code, handles) => (code, handles),
-      ExecuteResult::Continue(code, _, handles) => (code, handles),
+      ExecuteResult::Continue(code, _, handles)
+      | ExecuteResult::Return(code, _, handles) => (code, handles),
+      ExecuteResult::LoopControl(_, _, handles) => (0, handles),
     }
   }
 
@@ -383,7 +1121,9 @@ impl ExecuteResult {
   pub fn into_changes(self) -> Vec<EnvChange> {
     match self {
       ExecuteResult::Exit(_, _) => Vec::new(),
-      ExecuteResult::Continue(_, changes, _) => changes,
+      ExecuteResult::Continue(_, changes, _)
+      | ExecuteResult::Return(_, changes, _)
+      | ExecuteResult::LoopControl(_, changes, _) => changes,
     }
   }
 
@@ -392,7 +1132,9 @@ impl ExecuteResult {
   ) -> (Vec<JoinHandle<i32>>, Vec<EnvChange>) {
     match self {
       ExecuteResult::Exit(_, handles) => (handles, Vec::new()),
-      ExecuteResult::Continue(_, changes, handles) => (handles, changes),
+      ExecuteResult::Continue(_, changes, handles)
+      | ExecuteResult::Return(_, changes, handles)
+      | ExecuteResult::LoopControl(_, changes, handles) => (handles, changes),
     }
   }
 }
@@ -418,6 +1160,17 @@ impl ShellPipeReader {
     ShellPipeReader::from_raw(os_pipe::dup_stdin().unwrap())
   }
 
+  /// Whether this reads from a terminal
+  #[cfg(unix)]
+  pub fn is_terminal(&self) -> bool {
+    use std::io::IsTerminal;
+    use std::os::fd::AsFd;
+    match self {
+      Self::OsPipe(pipe) => pipe.as_fd().is_terminal(),
+      Self::StdFile(file) => file.is_terminal(),
+    }
+  }
+
   pub fn from_raw(reader: os_pipe::PipeReader) -> Self {
     Self::OsPipe(reader)
   }
@@ -505,6 +1258,30 @@ impl ShellPipeReader {
       ShellPipeReader::StdFile(file) => file.read(buf).into_diagnostic(),
     }
   }
+
+  /// Reads a single line without the line ending, or `None` at the end of
+  /// the input. One byte is read at a time so nothing after the line is
+  /// consumed, which is left for the commands reading next.
+  pub fn read_line(&mut self) -> Result<Option<String>> {
+    let mut line = Vec::new();
+    let mut byte = [0; 1];
+    loop {
+      if self.read(&mut byte)? == 0 {
+        if line.is_empty() {
+          return Ok(None);
+        }
+        break;
+      }
+      if byte[0] == b'\n' {
+        break;
+      }
+      line.push(byte[0]);
+    }
+    if line.last() == Some(&b'\r') {
+      line.pop();
+    }
+    Ok(Some(String::from_utf8_lossy(&line).to_string()))
+  }
 }
 
 /// Writer side of a pipe.
@@ -542,6 +1319,20 @@ impl ShellPipeWriter {
     Self::Stdout
   }
 
+  /// Whether this writes to a terminal
+  #[cfg(unix)]
+  pub fn is_terminal(&self) -> bool {
+    use std::io::IsTerminal;
+    use std::os::fd::AsFd;
+    match self {
+      Self::OsPipe(pipe) => pipe.as_fd().is_terminal(),
+      Self::StdFile(file) => file.is_terminal(),
+      Self::Stdout => std::io::stdout().is_terminal(),
+      Self::Stderr => std::io::stderr().is_terminal(),
+      Self::Null => false,
+    }
+  }
+
   pub fn stderr() -> Self {
     Self::Stderr
   }
@@ -591,6 +1382,24 @@ impl ShellPipeWriter {
   }
 }
 
+/// Why a file can't be sourced. Both hold the chain of files being
+/// sourced, ending with the refused one.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum SourceError {
+  #[error("{} is already being sourced: {}", .0.last().unwrap().display(), format_source_chain(.0))]
+  Cycle(Vec<PathBuf>),
+  #[error("maximum nesting depth exceeded ({}): {}", .0, format_source_chain(.1))]
+  TooDeep(usize, Vec<PathBuf>),
+}
+
+fn format_source_chain(chain: &[PathBuf]) -> String {
+  chain
+    .iter()
+    .map(|path| path.display().to_string())
+    .collect::<Vec<_>>()
+    .join(" -> ")
+}
+
 /// Used to communicate between commands.
 pub fn pipe() -> (ShellPipeReader, ShellPipeWriter) {
   let (reader, writer) = os_pipe::pipe().unwrap();
@@ -600,7 +1409,7 @@ pub fn pipe() -> (ShellPipeReader, ShellPipeWriter) {
   )
 }
 
-#[derive(Debug, Clone, PartialEq, PartialOrd, thiserror::Error)]
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
 pub struct ArithmeticResult {
   pub value: ArithmeticValue,
   pub changes: Vec<EnvChange>,
@@ -1337,3 +2146,34 @@ impl<'a> FromIterator<&'a char> for Text {
     Text::new(vec![TextPart::Text(parts)])
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn refuses_source_cycles_and_deep_nesting() {
+    let mut state = ShellState::new(
+      HashMap::from([("SHELL_MAX_SOURCE_DEPTH".to_string(), "3".to_string())]),
+      Path::new("/"),
+      HashMap::new(),
+    );
+    let files = ["/rc", "/a", "/b"].map(PathBuf::from);
+    state.enter_source(&files[0]).unwrap();
+    state.enter_source(&files[1]).unwrap();
+    let err = state.enter_source(&files[0]).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "/rc is already being sourced: /rc -> /a -> /rc"
+    );
+    assert_eq!(state.source_stack(), &files[..2]);
+
+    state.enter_source(&files[2]).unwrap();
+    let err = state.enter_source(Path::new("/c")).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "maximum nesting depth exceeded (3): /rc -> /a -> /b -> /c"
+    );
+    assert_eq!(state.source_depth(), 3);
+  }
+}
@@ -0,0 +1,124 @@
+//! Abbreviations, which unlike aliases are expanded in the line editor: typing
+//! `gco` and then space or enter replaces it with `git checkout`, so the
+//! history records the expanded command.
+//!
+//! Abbreviations are defined with the `abbr` builtin, e.g. in `~/.shellrc`.
+//! They are only expanded in command position, at the start of the line or
+//! after `|`, `&&`, `||`, `;`, `&` or `(`.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+thread_local! {
+    // `abbr` runs as a shell command, which has no access to the editor, so
+    // the abbreviations are kept here for the editor to look up
+    static ABBREVIATIONS: RefCell<BTreeMap<String, String>> = RefCell::default();
+}
+
+/// Adds an abbreviation, replacing any earlier one with the same name.
+pub fn add(name: &str, expansion: &str) -> Result<(), String> {
+    if name.is_empty() || name.chars().any(|c| c.is_whitespace() || is_separator(c)) {
+        return Err(format!("invalid abbreviation name `{name}`"));
+    }
+    ABBREVIATIONS.with_borrow_mut(|abbreviations| {
+        abbreviations.insert(name.to_string(), expansion.to_string())
+    });
+    Ok(())
+}
+
+/// Removes an abbreviation, returns whether it existed.
+pub fn erase(name: &str) -> bool {
+    ABBREVIATIONS.with_borrow_mut(|abbreviations| abbreviations.remove(name).is_some())
+}
+
+/// All abbreviations, sorted by name.
+pub fn list() -> Vec<(String, String)> {
+    ABBREVIATIONS.with_borrow(|abbreviations| {
+        abbreviations
+            .iter()
+            .map(|(name, expansion)| (name.clone(), expansion.clone()))
+            .collect()
+    })
+}
+
+/// Finds the abbreviation that ends at `pos` in `line`. Returns the start of
+/// the abbreviation and its expansion.
+pub fn expansion_at(line: &str, pos: usize) -> Option<(usize, String)> {
+    if line[pos..]
+        .chars()
+        .next()
+        .is_some_and(|c| !c.is_whitespace())
+    {
+        return None;
+    }
+    let before = &line[..pos];
+    let start = before
+        .rfind(|c: char| c.is_whitespace() || is_separator(c))
+        .map_or(0, |i| i + before[i..].chars().next().unwrap().len_utf8());
+    let word = &before[start..];
+    if word.is_empty() || !is_command_position(&before[..start]) {
+        return None;
+    }
+    ABBREVIATIONS
+        .with_borrow(|abbreviations| abbreviations.get(word).cloned())
+        .map(|expansion| (start, expansion))
+}
+
+/// Expands an abbreviation at the end of `line`, as done when the line is
+/// accepted.
+pub fn expand_line(line: &str) -> Option<String> {
+    let end = line.trim_end().len();
+    let (start, expansion) = expansion_at(line, end)?;
+    Some(format!("{}{expansion}{}", &line[..start], &line[end..]))
+}
+
+/// Whether a word after `before` is a command name, not an argument.
+fn is_command_position(before: &str) -> bool {
+    let before = before.trim_end();
+    before.is_empty() || before.ends_with(['|', '&', ';', '('])
+}
+
+fn is_separator(c: char) -> bool {
+    matches!(c, '|' | '&' | ';' | '(' | ')' | '<' | '>')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expansion_at() {
+        add("gco", "git checkout").unwrap();
+        assert_eq!(
+            expansion_at("gco", 3),
+            Some((0, "git checkout".to_string()))
+        );
+        assert_eq!(
+            expansion_at("ls | gco", 8),
+            Some((5, "git checkout".to_string()))
+        );
+        assert_eq!(
+            expansion_at("true&&gco main", 9),
+            Some((6, "git checkout".to_string()))
+        );
+        assert_eq!(
+            expansion_at("cd a; gco", 9),
+            Some((6, "git checkout".to_string()))
+        );
+        // only command names are expanded
+        assert_eq!(expansion_at("echo gco", 8), None);
+        assert_eq!(expansion_at("gcom", 4), None);
+        assert_eq!(expansion_at("gco", 2), None);
+
+        assert_eq!(
+            expand_line("ls && gco  "),
+            Some("ls && git checkout  ".to_string())
+        );
+        assert_eq!(expand_line("gco main"), None);
+
+        assert!(add("a b", "c").is_err());
+        assert!(erase("gco"));
+        assert!(!erase("gco"));
+        assert_eq!(expansion_at("gco", 3), None);
+    }
+}
@@ -0,0 +1,358 @@
+//! The colors of the line editor that can be configured with environment
+//! variables: `SHELL_HINT_COLOR` for the autosuggestions,
+//! `SHELL_COMPLETION_COLOR` for the completion candidates and
+//! `SHELL_HIGHLIGHT_THEME` for the syntax highlighting.
+//!
+//! The theme is a `:` separated list of `kind=color` entries, such as
+//! `command=bold blue:flag=cyan`. The kinds are listed in [`THEME_KINDS`],
+//! and the ones that aren't given keep their default color.
+//!
+//! A color is a list of names such as `bold blue`: the named colors, their
+//! `bright-` variants, `bg-` for background colors, a 256 color number such
+//! as `244`, a true color such as `#8a8a8a`, or an attribute (`bold`, `dim`,
+//! `italic`, `underline`). `none` turns the coloring off.
+//!
+//! Following <https://no-color.org>, nothing is colored, not even the
+//! prompt, if `NO_COLOR` or `SHELL_NO_COLOR` is set to a non-empty value or
+//! stdout isn't a terminal.
+
+/// The escape codes of the configurable colors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Colors {
+    pub hint: String,
+    pub completion: String,
+    pub highlight: HighlightTheme,
+    /// Whether anything is colored, the prompt included
+    pub enabled: bool,
+}
+
+/// The escape codes of the syntax highlighting, one for each of the
+/// [`THEME_KINDS`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HighlightTheme {
+    pub command: String,
+    /// Unknown commands and closing brackets without an opener
+    pub error: String,
+    pub string: String,
+    pub flag: String,
+    pub operator: String,
+    pub variable: String,
+    pub comment: String,
+    /// The partner of the bracket or quote at the cursor
+    pub matching: String,
+}
+
+/// The variables that set the colors, with their defaults.
+const HINT_COLOR: (&str, &str) = ("SHELL_HINT_COLOR", "dim");
+const COMPLETION_COLOR: (&str, &str) = ("SHELL_COMPLETION_COLOR", "none");
+const HIGHLIGHT_THEME: &str = "SHELL_HIGHLIGHT_THEME";
+/// The variables that turn all colors off.
+const NO_COLOR: [&str; 2] = ["NO_COLOR", "SHELL_NO_COLOR"];
+
+/// The kinds of tokens in a highlighting theme, with their default colors.
+pub const THEME_KINDS: [(&str, &str); 8] = [
+    ("command", "bold green"),
+    ("error", "red"),
+    ("string", "yellow"),
+    ("flag", "none"),
+    ("operator", "cyan"),
+    ("variable", "magenta"),
+    ("comment", "dim"),
+    ("matching", "bold blue"),
+];
+
+impl Default for Colors {
+    fn default() -> Self {
+        Colors {
+            hint: parse_color(HINT_COLOR.1).unwrap(),
+            completion: parse_color(COMPLETION_COLOR.1).unwrap(),
+            highlight: HighlightTheme::default(),
+            enabled: true,
+        }
+    }
+}
+
+impl Default for HighlightTheme {
+    fn default() -> Self {
+        let mut theme = HighlightTheme::none();
+        for (kind, default) in THEME_KINDS {
+            *theme.kind_mut(kind).unwrap() = parse_color(default).unwrap();
+        }
+        theme
+    }
+}
+
+impl HighlightTheme {
+    /// A theme that colors nothing.
+    fn none() -> HighlightTheme {
+        HighlightTheme {
+            command: String::new(),
+            error: String::new(),
+            string: String::new(),
+            flag: String::new(),
+            operator: String::new(),
+            variable: String::new(),
+            comment: String::new(),
+            matching: String::new(),
+        }
+    }
+
+    /// Parses a theme such as `command=bold blue:flag=cyan`. Invalid entries
+    /// are skipped and reported as warnings.
+    pub fn parse(spec: &str) -> (HighlightTheme, Vec<String>) {
+        let mut theme = HighlightTheme::default();
+        let mut warnings = Vec::new();
+        for entry in spec.split(':').filter(|entry| !entry.trim().is_empty()) {
+            let Some((kind, color)) = entry.split_once('=') else {
+                warnings.push(format!("missing `=` in `{}`", entry.trim()));
+                continue;
+            };
+            let kind = kind.trim();
+            let Some(code) = theme.kind_mut(kind) else {
+                warnings.push(format!("unknown token kind `{kind}`"));
+                continue;
+            };
+            match parse_color(color) {
+                Ok(color) => *code = color,
+                Err(err) => warnings.push(format!("{kind}: {err}")),
+            }
+        }
+        (theme, warnings)
+    }
+
+    fn kind_mut(&mut self, kind: &str) -> Option<&mut String> {
+        Some(match kind {
+            "command" => &mut self.command,
+            "error" => &mut self.error,
+            "string" => &mut self.string,
+            "flag" => &mut self.flag,
+            "operator" => &mut self.operator,
+            "variable" => &mut self.variable,
+            "comment" => &mut self.comment,
+            "matching" => &mut self.matching,
+            _ => return None,
+        })
+    }
+}
+
+impl Colors {
+    /// Reads the colors from the variables looked up with `get_var`. Invalid
+    /// colors fall back to the defaults and are reported as warnings. Without
+    /// `is_terminal`, the colors are off.
+    pub fn from_vars(
+        get_var: impl Fn(&str) -> Option<String>,
+        is_terminal: bool,
+    ) -> (Colors, Vec<String>) {
+        let no_color = NO_COLOR
+            .iter()
+            .any(|name| get_var(name).is_some_and(|value| !value.is_empty()));
+        if no_color || !is_terminal {
+            return (Colors::none(), Vec::new());
+        }
+        let mut warnings = Vec::new();
+        let mut color = |(name, default): (&str, &str)| {
+            let Some(spec) = get_var(name) else {
+                return parse_color(default).unwrap();
+            };
+            parse_color(&spec).unwrap_or_else(|err| {
+                warnings.push(format!("{name}: {err}, using `{default}`"));
+                parse_color(default).unwrap()
+            })
+        };
+        let hint = color(HINT_COLOR);
+        let completion = color(COMPLETION_COLOR);
+        let highlight = match get_var(HIGHLIGHT_THEME) {
+            Some(spec) => {
+                let (theme, theme_warnings) = HighlightTheme::parse(&spec);
+                warnings.extend(
+                    theme_warnings
+                        .into_iter()
+                        .map(|warning| format!("{HIGHLIGHT_THEME}: {warning}, ignoring it")),
+                );
+                theme
+            }
+            None => HighlightTheme::default(),
+        };
+        let colors = Colors {
+            hint,
+            completion,
+            highlight,
+            enabled: true,
+        };
+        (colors, warnings)
+    }
+
+    /// The colors with everything turned off.
+    pub fn none() -> Colors {
+        Colors {
+            hint: String::new(),
+            completion: String::new(),
+            highlight: HighlightTheme::none(),
+            enabled: false,
+        }
+    }
+
+    /// Wraps `text` in `color`, unless the color is turned off.
+    pub fn paint(color: &str, text: &str) -> String {
+        if color.is_empty() {
+            text.to_string()
+        } else {
+            format!("{color}{text}\x1b[0m")
+        }
+    }
+}
+
+const COLOR_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// Parses a color and returns its escape code, which is empty for `none`.
+pub fn parse_color(spec: &str) -> Result<String, String> {
+    let mut codes = Vec::new();
+    for name in spec.split_whitespace() {
+        let lower = name.to_ascii_lowercase();
+        let (background, color) = match lower.strip_prefix("bg-") {
+            Some(color) => (true, color),
+            None => (false, lower.as_str()),
+        };
+        let (base, extended) = if background { (40, 48) } else { (30, 38) };
+        let code = match color {
+            "none" if !background && spec.trim().len() == name.len() => return Ok(String::new()),
+            "bold" if !background => "1".to_string(),
+            "dim" if !background => "2".to_string(),
+            "italic" if !background => "3".to_string(),
+            "underline" if !background => "4".to_string(),
+            _ => {
+                if let Some(index) = COLOR_NAMES.iter().position(|&c| c == color) {
+                    (base + index).to_string()
+                } else if let Some(index) = color
+                    .strip_prefix("bright-")
+                    .and_then(|color| COLOR_NAMES.iter().position(|&c| c == color))
+                {
+                    (base + 60 + index).to_string()
+                } else if let Ok(number) = color.parse::<u8>() {
+                    format!("{extended};5;{number}")
+                } else if let Some(rgb) = parse_hex(color) {
+                    format!("{extended};2;{};{};{}", rgb[0], rgb[1], rgb[2])
+                } else {
+                    return Err(format!("invalid color `{name}`"));
+                }
+            }
+        };
+        codes.push(code);
+    }
+    if codes.is_empty() {
+        return Err("missing color".to_string());
+    }
+    Ok(format!("\x1b[{}m", codes.join(";")))
+}
+
+fn parse_hex(color: &str) -> Option<[u8; 3]> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("dim").unwrap(), "\x1b[2m");
+        assert_eq!(parse_color("bold Blue").unwrap(), "\x1b[1;34m");
+        assert_eq!(parse_color("bright-black bg-white").unwrap(), "\x1b[90;47m");
+        assert_eq!(parse_color("244").unwrap(), "\x1b[38;5;244m");
+        assert_eq!(
+            parse_color("#8a8A00 bg-17").unwrap(),
+            "\x1b[38;2;138;138;0;48;5;17m"
+        );
+        assert_eq!(parse_color(" none ").unwrap(), "");
+        assert_eq!(parse_color("purple").unwrap_err(), "invalid color `purple`");
+        assert_eq!(parse_color("256").unwrap_err(), "invalid color `256`");
+        assert_eq!(parse_color("#12345").unwrap_err(), "invalid color `#12345`");
+        assert_eq!(
+            parse_color("bg-bold").unwrap_err(),
+            "invalid color `bg-bold`"
+        );
+        assert_eq!(parse_color("none red").unwrap_err(), "invalid color `none`");
+        assert_eq!(parse_color("").unwrap_err(), "missing color");
+    }
+
+    #[test]
+    fn test_colors_from_vars() {
+        let (colors, warnings) = Colors::from_vars(|_| None, true);
+        assert_eq!(colors, Colors::default());
+        assert!(warnings.is_empty());
+
+        let (colors, warnings) = Colors::from_vars(
+            |name| match name {
+                "SHELL_HINT_COLOR" => Some("nope".to_string()),
+                "NO_COLOR" | "SHELL_NO_COLOR" => Some(String::new()),
+                _ => Some("cyan".to_string()),
+            },
+            true,
+        );
+        assert_eq!(
+            colors,
+            Colors {
+                hint: "\x1b[2m".to_string(),
+                completion: "\x1b[36m".to_string(),
+                highlight: HighlightTheme::default(),
+                enabled: true,
+            }
+        );
+        assert_eq!(
+            warnings,
+            [
+                "SHELL_HINT_COLOR: invalid color `nope`, using `dim`",
+                "SHELL_HIGHLIGHT_THEME: missing `=` in `cyan`, ignoring it",
+            ]
+        );
+
+        // any non-empty value turns the colors off, without warnings
+        for name in ["NO_COLOR", "SHELL_NO_COLOR"] {
+            let (colors, warnings) = Colors::from_vars(
+                |var| match var {
+                    "SHELL_HINT_COLOR" => Some("nope".to_string()),
+                    _ if var == name => Some("1".to_string()),
+                    _ => None,
+                },
+                true,
+            );
+            assert_eq!(colors, Colors::none());
+            assert!(warnings.is_empty());
+        }
+        assert_eq!(Colors::from_vars(|_| None, false).0, Colors::none());
+        assert_eq!(Colors::paint(&Colors::none().hint, "text"), "text");
+    }
+
+    #[test]
+    fn test_parse_highlight_theme() {
+        let (theme, warnings) = HighlightTheme::parse("");
+        assert_eq!(theme, HighlightTheme::default());
+        assert!(warnings.is_empty());
+        assert_eq!(theme.command, "\x1b[1;32m");
+        assert_eq!(theme.flag, "");
+
+        let (theme, warnings) = HighlightTheme::parse(
+            "command=bold blue: flag = #00ff00 ::string=nope:keyword=red:comment",
+        );
+        assert_eq!(theme.command, "\x1b[1;34m");
+        assert_eq!(theme.flag, "\x1b[38;2;0;255;0m");
+        // the invalid entries keep the defaults
+        assert_eq!(theme.string, HighlightTheme::default().string);
+        assert_eq!(theme.comment, HighlightTheme::default().comment);
+        assert_eq!(
+            warnings,
+            [
+                "string: invalid color `nope`",
+                "unknown token kind `keyword`",
+                "missing `=` in `comment`",
+            ]
+        );
+    }
+}
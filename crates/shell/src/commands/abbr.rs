@@ -0,0 +1,65 @@
+use deno_task_shell::{ExecuteResult, ShellCommand, ShellCommandContext};
+use futures::future::LocalBoxFuture;
+
+use crate::abbreviations;
+
+pub struct AbbrCommand;
+
+impl ShellCommand for AbbrCommand {
+    fn description(&self) -> Option<&'static str> {
+        Some("Define abbreviations that expand while typing")
+    }
+
+    fn execute(&self, mut context: ShellCommandContext) -> LocalBoxFuture<'static, ExecuteResult> {
+        Box::pin(futures::future::ready(match execute_abbr(&mut context) {
+            Ok(_) => ExecuteResult::from_exit_code(0),
+            Err(exit_code) => ExecuteResult::from_exit_code(exit_code),
+        }))
+    }
+}
+
+fn execute_abbr(context: &mut ShellCommandContext) -> Result<(), i32> {
+    let args = context.args.clone();
+    match args.as_slice() {
+        [] => {
+            for (name, expansion) in abbreviations::list() {
+                context
+                    .stdout
+                    .write_line(&format!("abbr {name} {}", quote(&expansion)))
+                    .ok();
+            }
+            Ok(())
+        }
+        [flag, names @ ..] if flag == "-e" && !names.is_empty() => {
+            let mut result = Ok(());
+            for name in names {
+                if !abbreviations::erase(name) {
+                    context
+                        .stderr
+                        .write_line(&format!("abbr: no such abbreviation `{name}`"))
+                        .ok();
+                    result = Err(1);
+                }
+            }
+            result
+        }
+        [name, expansion @ ..] if !name.starts_with('-') && !expansion.is_empty() => {
+            abbreviations::add(name, &expansion.join(" ")).map_err(|err| {
+                context.stderr.write_line(&format!("abbr: {err}")).ok();
+                1
+            })
+        }
+        _ => {
+            context
+                .stderr
+                .write_line("abbr: usage: abbr [<name> <expansion> | -e <name>...]")
+                .ok();
+            Err(2)
+        }
+    }
+}
+
+/// Quotes `text` so that the listing can be run again.
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
@@ -0,0 +1,67 @@
+use deno_task_shell::{ExecuteResult, ShellCommand, ShellCommandContext};
+use futures::future::LocalBoxFuture;
+
+use crate::keybindings;
+
+pub struct BindCommand;
+
+impl ShellCommand for BindCommand {
+    fn description(&self) -> Option<&'static str> {
+        Some("Bind a key chord to an editor action")
+    }
+
+    fn execute(&self, mut context: ShellCommandContext) -> LocalBoxFuture<'static, ExecuteResult> {
+        Box::pin(futures::future::ready(match execute_bind(&mut context) {
+            Ok(_) => ExecuteResult::from_exit_code(0),
+            Err(exit_code) => ExecuteResult::from_exit_code(exit_code),
+        }))
+    }
+}
+
+fn execute_bind(context: &mut ShellCommandContext) -> Result<(), i32> {
+    let args = context.args.clone();
+    let result = match args.as_slice() {
+        [] => {
+            for binding in keybindings::bindings() {
+                context.stdout.write_line(&binding.to_string()).ok();
+            }
+            Ok(())
+        }
+        [flag] if flag == "-l" => {
+            for name in keybindings::action_names() {
+                context.stdout.write_line(name).ok();
+            }
+            Ok(())
+        }
+        [flag] if flag == "-p" => {
+            for binding in keybindings::bindings() {
+                context
+                    .stdout
+                    .write_line(&keybindings::format_readline(&binding))
+                    .ok();
+            }
+            Ok(())
+        }
+        specs
+            if specs
+                .iter()
+                .all(|spec| keybindings::is_readline_binding(spec)) =>
+        {
+            keybindings::bind_readline(specs)
+        }
+        [chord, action] => keybindings::bind(chord, action),
+        _ => {
+            context
+                .stderr
+                .write_line(
+                    "bind: usage: bind [-l | -p | <chord> <action> | '\"<keys>\": <action>' ...]",
+                )
+                .ok();
+            return Err(2);
+        }
+    };
+    result.map_err(|err| {
+        context.stderr.write_line(&format!("bind: {err}")).ok();
+        1
+    })
+}
@@ -0,0 +1,71 @@
+use deno_task_shell::{ExecuteResult, ShellCommand, ShellCommandContext};
+use futures::future::LocalBoxFuture;
+
+pub struct ClearCommand;
+
+impl ShellCommand for ClearCommand {
+    fn description(&self) -> Option<&'static str> {
+        Some("Clear the terminal screen")
+    }
+
+    fn execute(&self, mut context: ShellCommandContext) -> LocalBoxFuture<'static, ExecuteResult> {
+        let exit_code = match parse_args(&context.args) {
+            Ok(keep_scrollback) => {
+                let term = context.state.get_var("TERM");
+                let sequence = clear_sequence(term.as_deref(), keep_scrollback);
+                let _ = context.stdout.write_all(sequence.as_bytes());
+                0
+            }
+            Err(err) => {
+                let _ = context.stderr.write_line(&format!("clear: {err}"));
+                let _ = context.stderr.write_line("clear: usage: clear [-x]");
+                2
+            }
+        };
+        Box::pin(futures::future::ready(ExecuteResult::from_exit_code(
+            exit_code,
+        )))
+    }
+}
+
+/// Returns whether the scrollback is kept (`-x`).
+fn parse_args(args: &[String]) -> Result<bool, String> {
+    let mut keep_scrollback = false;
+    for arg in args {
+        match arg.as_str() {
+            "-x" => keep_scrollback = true,
+            _ => return Err(format!("{arg}: invalid argument")),
+        }
+    }
+    Ok(keep_scrollback)
+}
+
+/// The escape codes that move the cursor home and clear the screen, and
+/// unless `keep_scrollback` the scrollback too. A terminal without cursor
+/// movement (`TERM=dumb`) gets nothing, instead of garbage.
+fn clear_sequence(term: Option<&str>, keep_scrollback: bool) -> &'static str {
+    match term {
+        Some("dumb") => "",
+        _ if keep_scrollback => "\x1b[H\x1b[2J",
+        _ => "\x1b[H\x1b[2J\x1b[3J",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clear_sequence() {
+        assert_eq!(parse_args(&[]), Ok(false));
+        assert_eq!(parse_args(&["-x".to_string()]), Ok(true));
+        assert!(parse_args(&["-y".to_string()]).is_err());
+
+        assert_eq!(
+            clear_sequence(Some("xterm-256color"), false),
+            "\x1b[H\x1b[2J\x1b[3J"
+        );
+        assert_eq!(clear_sequence(None, true), "\x1b[H\x1b[2J");
+        assert_eq!(clear_sequence(Some("dumb"), false), "");
+    }
+}
@@ -0,0 +1,180 @@
+use deno_task_shell::{
+    CompletionSpec, EnvChange, ExecuteResult, ShellCommand, ShellCommandContext,
+};
+use futures::future::LocalBoxFuture;
+
+pub struct CompleteCommand;
+
+impl ShellCommand for CompleteCommand {
+    fn description(&self) -> Option<&'static str> {
+        Some("Define how the arguments of a command are completed")
+    }
+
+    fn execute(&self, mut context: ShellCommandContext) -> LocalBoxFuture<'static, ExecuteResult> {
+        Box::pin(futures::future::ready(
+            match execute_complete(&mut context) {
+                Ok(changes) => ExecuteResult::Continue(0, changes, Vec::new()),
+                Err(exit_code) => ExecuteResult::from_exit_code(exit_code),
+            },
+        ))
+    }
+}
+
+const USAGE: &str = "complete: usage: complete [-p | -r] [-f] [-d] [-W wordlist] [name ...]";
+
+#[derive(Debug, PartialEq)]
+enum Request {
+    Print(Vec<String>),
+    Remove(Vec<String>),
+    Set(CompletionSpec, Vec<String>),
+}
+
+fn execute_complete(context: &mut ShellCommandContext) -> Result<Vec<EnvChange>, i32> {
+    let request = parse_args(&context.args).map_err(|err| {
+        context.stderr.write_line(&format!("complete: {err}")).ok();
+        context.stderr.write_line(USAGE).ok();
+        2
+    })?;
+    let completions = context.state.completions();
+    let mut missing = Vec::new();
+    let changes = match request {
+        Request::Print(names) => {
+            let mut names = if names.is_empty() {
+                completions.keys().cloned().collect()
+            } else {
+                names
+            };
+            names.sort();
+            for name in names {
+                match completions.get(&name) {
+                    Some(spec) => {
+                        context.stdout.write_line(&format_spec(&name, spec)).ok();
+                    }
+                    None => missing.push(name),
+                }
+            }
+            Vec::new()
+        }
+        Request::Remove(names) => names
+            .into_iter()
+            .filter_map(|name| {
+                if completions.contains_key(&name) {
+                    Some(EnvChange::RemoveCompletion(name))
+                } else {
+                    missing.push(name);
+                    None
+                }
+            })
+            .collect(),
+        Request::Set(spec, names) => names
+            .into_iter()
+            .map(|name| EnvChange::SetCompletion(name, spec.clone()))
+            .collect(),
+    };
+    for name in &missing {
+        context
+            .stderr
+            .write_line(&format!("complete: {name}: no completion specification"))
+            .ok();
+    }
+    if missing.is_empty() {
+        Ok(changes)
+    } else {
+        Err(1)
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Request, String> {
+    let mut print = false;
+    let mut remove = false;
+    let mut spec = CompletionSpec::default();
+    let mut names = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') || arg == "-" || !names.is_empty() {
+            names.push(arg.clone());
+            continue;
+        }
+        if arg == "--" {
+            names.extend(args.by_ref().cloned());
+            break;
+        }
+        for flag in arg[1..].chars() {
+            match flag {
+                'p' => print = true,
+                'r' => remove = true,
+                'f' => spec.files = true,
+                'd' => spec.directories = true,
+                'W' => {
+                    let words = args
+                        .next()
+                        .ok_or_else(|| "-W: option requires an argument".to_string())?;
+                    spec.words
+                        .extend(words.split_whitespace().map(ToString::to_string));
+                }
+                _ => return Err(format!("-{flag}: invalid option")),
+            }
+        }
+    }
+    let has_spec = spec != CompletionSpec::default();
+    match (print, remove) {
+        (true, true) => Err("-p and -r can't be combined".to_string()),
+        (true, false) => Ok(Request::Print(names)),
+        (false, true) => Ok(Request::Remove(names)),
+        (false, false) if !has_spec && names.is_empty() => Ok(Request::Print(names)),
+        (false, false) if !has_spec => Err("no completion given".to_string()),
+        (false, false) if names.is_empty() => Err("no command name given".to_string()),
+        (false, false) => Ok(Request::Set(spec, names)),
+    }
+}
+
+/// Formats a completion so that the listing can be run again.
+fn format_spec(name: &str, spec: &CompletionSpec) -> String {
+    let mut line = "complete".to_string();
+    if spec.files {
+        line.push_str(" -f");
+    }
+    if spec.directories {
+        line.push_str(" -d");
+    }
+    if !spec.words.is_empty() {
+        let words = spec.words.join(" ").replace('\'', r"'\''");
+        line.push_str(&format!(" -W '{words}'"));
+    }
+    format!("{line} {name}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let spec = CompletionSpec {
+            words: args(&["start", "stop", "restart"]),
+            files: false,
+            directories: true,
+        };
+        assert_eq!(
+            parse_args(&args(&["-d", "-W", "start stop restart", "svc", "other"])),
+            Ok(Request::Set(spec.clone(), args(&["svc", "other"])))
+        );
+        assert_eq!(
+            format_spec("svc", &spec),
+            "complete -d -W 'start stop restart' svc"
+        );
+        assert_eq!(parse_args(&[]), Ok(Request::Print(Vec::new())));
+        assert_eq!(
+            parse_args(&args(&["-r", "svc"])),
+            Ok(Request::Remove(args(&["svc"])))
+        );
+        assert!(parse_args(&args(&["svc"])).is_err());
+        assert!(parse_args(&args(&["-f"])).is_err());
+        assert!(parse_args(&args(&["-W"])).is_err());
+        assert!(parse_args(&args(&["-x", "svc"])).is_err());
+    }
+}
@@ -4,9 +4,15 @@ use deno_task_shell::{ExecuteResult, ShellCommand, ShellCommandContext};
 use futures::future::LocalBoxFuture;
 use uu_date::uumain as uu_date;
 
+use super::stdio::with_stdio;
+
 pub struct DateCommand;
 
 impl ShellCommand for DateCommand {
+    fn description(&self) -> Option<&'static str> {
+        Some("Print or set the system date and time")
+    }
+
     fn execute(&self, mut context: ShellCommandContext) -> LocalBoxFuture<'static, ExecuteResult> {
         Box::pin(futures::future::ready(match execute_date(&mut context) {
             Ok(_) => ExecuteResult::from_exit_code(0),
@@ -23,7 +29,9 @@ fn execute_date(context: &mut ShellCommandContext) -> Result<(), i32> {
         .iter()
         .for_each(|arg| args.push(OsString::from(arg)));
 
-    let exit_code = uu_date(args.into_iter());
+    let exit_code = with_stdio(&context.stdout, &context.stderr, || {
+        uu_date(args.into_iter())
+    });
     if exit_code != 0 {
         return Err(exit_code);
     }
@@ -0,0 +1,292 @@
+use std::io::Write;
+
+use deno_task_shell::{ExecuteResult, ShellCommand, ShellCommandContext};
+use futures::future::LocalBoxFuture;
+use futures::FutureExt;
+
+use crate::execute;
+use crate::history;
+
+pub struct FcCommand;
+
+impl ShellCommand for FcCommand {
+    fn description(&self) -> Option<&'static str> {
+        Some("List, edit and re-run commands from the history")
+    }
+
+    fn execute(&self, mut context: ShellCommandContext) -> LocalBoxFuture<'static, ExecuteResult> {
+        let command = match execute_fc(&mut context) {
+            Ok(Some(command)) => command,
+            Ok(None) => return Box::pin(futures::future::ready(ExecuteResult::from_exit_code(0))),
+            Err(exit_code) => {
+                return Box::pin(futures::future::ready(ExecuteResult::from_exit_code(
+                    exit_code,
+                )))
+            }
+        };
+        // like bash, the commands are shown and recorded in the history
+        // instead of the `fc` line
+        context.stdout.write_line(&command).ok();
+        history::replace_current(&command);
+        // a failing command shouldn't cancel the caller, see `source`
+        let state = context.state.with_child_token();
+        let mut stderr = context.stderr;
+        async move {
+            execute::execute_inner(&command, None, state)
+                .await
+                .unwrap_or_else(|err| {
+                    let _ = stderr.write_line(&format!("fc: {err}"));
+                    ExecuteResult::from_exit_code(1)
+                })
+        }
+        .boxed_local()
+    }
+}
+
+#[derive(Default)]
+struct Options {
+    list: bool,
+    no_numbers: bool,
+    reverse: bool,
+    substitute: bool,
+    editor: Option<String>,
+    operands: Vec<String>,
+}
+
+/// Lists the history or returns the commands to run.
+fn execute_fc(context: &mut ShellCommandContext) -> Result<Option<String>, i32> {
+    let options = parse_args(&context.args).map_err(|err| {
+        context.stderr.write_line(&format!("fc: {err}")).ok();
+        context
+            .stderr
+            .write_line(
+                "fc: usage: fc [-e ename] [-lnr] [first] [last] or fc -s [pat=rep] [command]",
+            )
+            .ok();
+        2
+    })?;
+    let entries = history::entries();
+    let fail = |context: &mut ShellCommandContext, message: &str| {
+        context.stderr.write_line(&format!("fc: {message}")).ok();
+        1
+    };
+    if entries.is_empty() {
+        return Err(fail(context, "no command found"));
+    }
+
+    if options.substitute {
+        let (substitution, spec) = match options.operands.as_slice() {
+            [pair, rest @ ..] if pair.contains('=') => (pair.split_once('='), rest),
+            operands => (None, operands),
+        };
+        if spec.len() > 1 {
+            return Err(fail(context, "too many arguments"));
+        }
+        let index = match spec.first() {
+            Some(spec) => find_entry(&entries, spec),
+            None => Some(entries.len() - 1),
+        };
+        let Some(index) = index else {
+            return Err(fail(context, "no command found"));
+        };
+        let mut command = entries[index].clone();
+        if let Some((old, new)) = substitution.filter(|(old, _)| !old.is_empty()) {
+            command = command.replace(old, new);
+        }
+        return Ok(Some(command));
+    }
+
+    let default_first = if options.list { "-16" } else { "-1" };
+    let first_spec = options
+        .operands
+        .first()
+        .map_or(default_first, String::as_str);
+    let last_spec = match options.operands.get(1) {
+        Some(spec) => spec.as_str(),
+        // a single command is edited, but listed up to the latest one
+        None if options.list => "-1",
+        None => first_spec,
+    };
+    if options.operands.len() > 2 {
+        return Err(fail(context, "too many arguments"));
+    }
+    let (first, last) = if options.list {
+        // listing clamps the range to the history that exists
+        (
+            find_entry(&entries, first_spec).unwrap_or(0),
+            find_entry(&entries, last_spec).unwrap_or(entries.len() - 1),
+        )
+    } else {
+        match (
+            find_entry(&entries, first_spec),
+            find_entry(&entries, last_spec),
+        ) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Err(fail(context, "history specification out of range")),
+        }
+    };
+    let (start, end) = (first.min(last), first.max(last));
+    let mut selected = (start..=end).collect::<Vec<_>>();
+    if options.reverse != (first > last) {
+        selected.reverse();
+    }
+
+    if options.list {
+        for index in selected {
+            let line = if options.no_numbers {
+                format!("\t{}", entries[index])
+            } else {
+                format!("{}\t{}", index + 1, entries[index])
+            };
+            context.stdout.write_line(&line).ok();
+        }
+        return Ok(None);
+    }
+
+    let text = selected
+        .iter()
+        .map(|index| entries[*index].as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let editor = options
+        .editor
+        .or_else(|| context.state.get_var("VISUAL"))
+        .or_else(|| context.state.get_var("EDITOR"))
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| default_editor().to_string());
+    let edited = edit(&editor, &text).map_err(|err| fail(context, &err))?;
+    let edited = edited.trim_end_matches(['\n', '\r']);
+    Ok((!edited.trim().is_empty()).then(|| edited.to_string()))
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        // `-2` is an offset into the history, not a flag
+        let is_flag = arg.len() > 1 && arg.starts_with('-') && arg.parse::<i64>().is_err();
+        if !is_flag || !options.operands.is_empty() {
+            options.operands.push(arg.clone());
+            continue;
+        }
+        if arg == "--" {
+            options.operands.extend(args.cloned());
+            break;
+        }
+        for flag in arg[1..].chars() {
+            match flag {
+                'l' => options.list = true,
+                'n' => options.no_numbers = true,
+                'r' => options.reverse = true,
+                's' => options.substitute = true,
+                'e' => {
+                    let editor = args
+                        .next()
+                        .ok_or_else(|| "-e: option requires an argument".to_string())?;
+                    options.editor = Some(editor.clone());
+                }
+                _ => return Err(format!("-{flag}: invalid option")),
+            }
+        }
+    }
+    Ok(options)
+}
+
+/// Finds the entry for a history number, a negative offset from the latest
+/// entry, or the prefix of a command. Returns its index.
+fn find_entry(entries: &[String], spec: &str) -> Option<usize> {
+    match spec.parse::<i64>() {
+        Ok(number) if number > 0 => {
+            let index = number as usize - 1;
+            (index < entries.len()).then_some(index)
+        }
+        Ok(number) if number < 0 => entries.len().checked_sub(number.unsigned_abs() as usize),
+        Ok(_) => None,
+        Err(_) => entries.iter().rposition(|entry| entry.starts_with(spec)),
+    }
+}
+
+fn default_editor() -> &'static str {
+    if cfg!(windows) {
+        "notepad"
+    } else {
+        "vi"
+    }
+}
+
+/// Runs `editor` on a temporary file holding `text` and returns the edited
+/// text.
+fn edit(editor: &str, text: &str) -> Result<String, String> {
+    let mut args = editor.split_whitespace();
+    let program = args.next().ok_or("no editor")?;
+    // only the user can read the file, which is removed when it's dropped
+    let mut file = tempfile::Builder::new()
+        .prefix("shell-fc-")
+        .suffix(".sh")
+        .tempfile()
+        .map_err(|err| err.to_string())?;
+    writeln!(file, "{text}").map_err(|err| err.to_string())?;
+    let status = std::process::Command::new(program)
+        .args(args)
+        .arg(file.path())
+        .status();
+    match status {
+        Ok(status) if status.success() => {
+            std::fs::read_to_string(file.path()).map_err(|err| err.to_string())
+        }
+        Ok(status) => Err(format!("{program} failed with {status}")),
+        Err(err) => Err(format!("{program}: {err}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_entry() {
+        let entries = ["echo one", "ls", "echo two"].map(String::from);
+        assert_eq!(find_entry(&entries, "1"), Some(0));
+        assert_eq!(find_entry(&entries, "3"), Some(2));
+        assert_eq!(find_entry(&entries, "4"), None);
+        assert_eq!(find_entry(&entries, "-1"), Some(2));
+        assert_eq!(find_entry(&entries, "-3"), Some(0));
+        assert_eq!(find_entry(&entries, "-4"), None);
+        assert_eq!(find_entry(&entries, "0"), None);
+        assert_eq!(find_entry(&entries, "echo"), Some(2));
+        assert_eq!(find_entry(&entries, "l"), Some(1));
+        assert_eq!(find_entry(&entries, "cat"), None);
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = ["-lr", "-5", "-1"].map(String::from);
+        let options = parse_args(&args).unwrap();
+        assert!(options.list && options.reverse && !options.no_numbers);
+        assert_eq!(options.operands, ["-5", "-1"]);
+
+        let args = ["-e", "nano", "ls"].map(String::from);
+        let options = parse_args(&args).unwrap();
+        assert_eq!(options.editor.as_deref(), Some("nano"));
+        assert_eq!(options.operands, ["ls"]);
+
+        assert!(parse_args(&["-x".to_string()]).is_err());
+        assert!(parse_args(&["-e".to_string()]).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_edit() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let editor = dir.path().join("editor");
+        std::fs::write(&editor, "#!/bin/sh\necho 'ls -la' >> \"$1\"\n").unwrap();
+        std::fs::set_permissions(&editor, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let editor = editor.to_str().unwrap();
+        assert_eq!(edit(editor, "pwd").unwrap(), "pwd\nls -la\n");
+        assert!(edit("false", "pwd").is_err());
+        assert!(edit("shell-no-such-editor", "pwd").is_err());
+    }
+}
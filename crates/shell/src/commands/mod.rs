@@ -7,14 +7,27 @@ use uu_ls::uumain as uu_ls;
 
 use crate::execute;
 
+pub mod abbr;
+pub mod bind;
+pub mod clear;
+pub mod complete;
 pub mod date;
+pub mod fc;
 pub mod set;
+pub mod stdio;
+pub mod times;
 pub mod touch;
 pub mod uname;
 pub mod which;
 
+pub use abbr::AbbrCommand;
+pub use bind::BindCommand;
+pub use clear::ClearCommand;
+pub use complete::CompleteCommand;
 pub use date::DateCommand;
+pub use fc::FcCommand;
 pub use set::SetCommand;
+pub use times::TimesCommand;
 pub use touch::TouchCommand;
 pub use uname::UnameCommand;
 pub use which::WhichCommand;
@@ -62,10 +75,35 @@ pub fn get_commands() -> HashMap<String, Rc<dyn ShellCommand>> {
             "set".to_string(),
             Rc::new(SetCommand) as Rc<dyn ShellCommand>,
         ),
+        (
+            "times".to_string(),
+            Rc::new(TimesCommand) as Rc<dyn ShellCommand>,
+        ),
+        (
+            "bind".to_string(),
+            Rc::new(BindCommand) as Rc<dyn ShellCommand>,
+        ),
+        (
+            "abbr".to_string(),
+            Rc::new(AbbrCommand) as Rc<dyn ShellCommand>,
+        ),
+        ("fc".to_string(), Rc::new(FcCommand) as Rc<dyn ShellCommand>),
+        (
+            "complete".to_string(),
+            Rc::new(CompleteCommand) as Rc<dyn ShellCommand>,
+        ),
+        (
+            "clear".to_string(),
+            Rc::new(ClearCommand) as Rc<dyn ShellCommand>,
+        ),
     ])
 }
 
 impl ShellCommand for AliasCommand {
+    fn description(&self) -> Option<&'static str> {
+        Some("Define an alias")
+    }
+
     fn execute(&self, context: ShellCommandContext) -> LocalBoxFuture<'static, ExecuteResult> {
         if context.args.len() != 1 {
             return Box::pin(futures::future::ready(ExecuteResult::from_exit_code(1)));
@@ -84,6 +122,10 @@ impl ShellCommand for AliasCommand {
 }
 
 impl ShellCommand for UnAliasCommand {
+    fn description(&self) -> Option<&'static str> {
+        Some("Remove an alias")
+    }
+
     fn execute(&self, context: ShellCommandContext) -> LocalBoxFuture<'static, ExecuteResult> {
         if context.args.len() != 1 {
             return Box::pin(futures::future::ready(ExecuteResult::from_exit_code(1)));
@@ -99,6 +141,10 @@ impl ShellCommand for UnAliasCommand {
 }
 
 impl ShellCommand for LsCommand {
+    fn description(&self) -> Option<&'static str> {
+        Some("List directory contents")
+    }
+
     fn execute(&self, context: ShellCommandContext) -> LocalBoxFuture<'static, ExecuteResult> {
         let result = execute_ls(context);
         Box::pin(futures::future::ready(result))
@@ -113,34 +159,74 @@ fn execute_ls(context: ShellCommandContext) -> ExecuteResult {
         .iter()
         .for_each(|arg| args.push(OsString::from(arg)));
 
-    let exit_code = uu_ls(args.into_iter());
+    let exit_code = stdio::with_stdio(&context.stdout, &context.stderr, || uu_ls(args.into_iter()));
     ExecuteResult::from_exit_code(exit_code)
 }
 
 impl ShellCommand for SourceCommand {
-    fn execute(&self, context: ShellCommandContext) -> LocalBoxFuture<'static, ExecuteResult> {
-        if context.args.len() != 1 {
+    fn description(&self) -> Option<&'static str> {
+        Some("Execute commands from a file in the current shell")
+    }
+
+    fn execute(&self, mut context: ShellCommandContext) -> LocalBoxFuture<'static, ExecuteResult> {
+        if context.args.is_empty() {
             return Box::pin(futures::future::ready(ExecuteResult::from_exit_code(1)));
         }
 
         let script = context.args[0].clone();
+        // like bash, extra arguments become the positional parameters of the
+        // sourced script and the caller's ones are restored afterwards
+        let script_args = context.args[1..].to_vec();
         let script_file = context.state.cwd().join(script);
         match fs::read_to_string(&script_file) {
             Ok(content) => {
-                let state = context.state.clone();
+                // the nested script runs on a copy of the state, so the depth
+                // is restored automatically once it finishes. It also gets its
+                // own token so that a failing script (or `return 1`) doesn't
+                // cancel the caller
+                let mut state = context.state.with_child_token();
+                if let Err(err) = state.enter_source(&script_file) {
+                    let _ = context.stderr.write_line(&format!("source: {err}"));
+                    return Box::pin(futures::future::ready(ExecuteResult::from_exit_code(1)));
+                }
+                let has_script_args = !script_args.is_empty();
+                if has_script_args {
+                    state.set_positional_args(script_args);
+                }
+                let mut stderr = context.stderr;
                 async move {
-                    execute::execute_inner(&content, Some(script_file.display().to_string()), state)
-                        .await
-                        .unwrap_or_else(|e| {
-                            eprintln!("Could not source script: {:?}", script_file);
-                            eprintln!("Error: {}", e);
-                            ExecuteResult::from_exit_code(1)
-                        })
+                    let result = execute::execute_inner(
+                        &content,
+                        Some(script_file.display().to_string()),
+                        state,
+                    )
+                    .await
+                    .unwrap_or_else(|e| {
+                        let _ = stderr
+                            .write_line(&format!("Could not source script: {:?}", script_file));
+                        let _ = stderr.write_line(&format!("Error: {}", e));
+                        ExecuteResult::from_exit_code(1)
+                    });
+                    match result {
+                        // `return` only stops the sourced script
+                        ExecuteResult::Continue(code, mut changes, handles)
+                        | ExecuteResult::Return(code, mut changes, handles) => {
+                            if has_script_args {
+                                changes.retain(|change| {
+                                    !matches!(change, EnvChange::SetPositionalArgs(_))
+                                });
+                            }
+                            ExecuteResult::Continue(code, changes, handles)
+                        }
+                        result => result,
+                    }
                 }
                 .boxed_local()
             }
             Err(e) => {
-                eprintln!("Could not read file: {:?} ({})", script_file, e);
+                let _ = context
+                    .stderr
+                    .write_line(&format!("Could not read file: {:?} ({})", script_file, e));
                 Box::pin(futures::future::ready(ExecuteResult::from_exit_code(1)))
             }
         }
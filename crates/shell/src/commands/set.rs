@@ -6,13 +6,27 @@ use miette::Result;
 
 use deno_task_shell::{
     parse_arg_kinds, ArgKind, EnvChange, ExecuteResult, ShellCommand, ShellCommandContext,
-    ShellOptions,
+    ShellOptions, ShellState,
 };
 
 pub struct SetCommand;
 
 impl ShellCommand for SetCommand {
+    fn description(&self) -> Option<&'static str> {
+        Some("Set or unset shell options, or set the positional parameters")
+    }
+
     fn execute(&self, mut context: ShellCommandContext) -> LocalBoxFuture<'static, ExecuteResult> {
+        // `set -o` and `set +o` without an option name list the options
+        match context.args.as_slice() {
+            [flag] if flag == "-o" || flag == "+o" => {
+                let listing = list_options(&context.state, flag == "+o");
+                let _ = context.stdout.write_all(listing.as_bytes());
+                return Box::pin(futures::future::ready(ExecuteResult::from_exit_code(0)));
+            }
+            _ => {}
+        }
+
         let result = match execute_set(context.args) {
             Ok((code, env_changes)) => ExecuteResult::Continue(code, env_changes, Vec::new()),
             Err(err) => {
@@ -24,17 +38,85 @@ impl ShellCommand for SetCommand {
     }
 }
 
+/// Lists the state of all options, either as a table or (for `set +o`) as
+/// the commands that recreate the current settings.
+fn list_options(state: &ShellState, as_commands: bool) -> String {
+    let mut output = String::new();
+    for option in ShellOptions::ALL {
+        let enabled = state.shell_option(option);
+        if as_commands {
+            let flag = if enabled { '-' } else { '+' };
+            output.push_str(&format!("set {flag}o {}\n", option.name()));
+        } else {
+            let value = if enabled { "on" } else { "off" };
+            output.push_str(&format!("{:<15}\t{value}\n", option.name()));
+        }
+    }
+    output
+}
+
 fn execute_set(args: Vec<String>) -> Result<(i32, Vec<EnvChange>)> {
+    // the words after `--` replace the positional parameters, so `set --`
+    // alone clears them. Without `--` they are left as they are
+    let (args, positional_args) = match args.iter().position(|arg| arg == "--") {
+        Some(index) => (args[..index].to_vec(), Some(args[index + 1..].to_vec())),
+        None => (args, None),
+    };
     let args = parse_arg_kinds(&args);
     let mut env_changes = Vec::new();
-    for arg in args {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
         match arg {
+            ArgKind::ShortFlag('o') | ArgKind::PlusFlag('o') => {
+                let enable = matches!(arg, ArgKind::ShortFlag(_));
+                let Some(ArgKind::Arg(name)) = args.next() else {
+                    bail!("-o: option requires an argument");
+                };
+                let Some(option) = ShellOptions::from_name(name) else {
+                    bail!("{name}: invalid option name");
+                };
+                env_changes.push(EnvChange::SetShellOptions(option, enable));
+                // there is only one line editor, so turning on one of its
+                // modes turns off the other
+                let other_mode = match option {
+                    ShellOptions::Emacs => Some(ShellOptions::Vi),
+                    ShellOptions::Vi => Some(ShellOptions::Emacs),
+                    _ => None,
+                };
+                if let (Some(other_mode), true) = (other_mode, enable) {
+                    env_changes.push(EnvChange::SetShellOptions(other_mode, false));
+                }
+            }
+            ArgKind::ShortFlag('u') => {
+                env_changes.push(EnvChange::SetShellOptions(ShellOptions::NoUnset, true));
+            }
+            ArgKind::PlusFlag('u') => {
+                env_changes.push(EnvChange::SetShellOptions(ShellOptions::NoUnset, false));
+            }
+            ArgKind::ShortFlag('f') => {
+                env_changes.push(EnvChange::SetShellOptions(ShellOptions::NoGlob, true));
+            }
+            ArgKind::PlusFlag('f') => {
+                env_changes.push(EnvChange::SetShellOptions(ShellOptions::NoGlob, false));
+            }
             ArgKind::ShortFlag('e') => {
                 env_changes.push(EnvChange::SetShellOptions(ShellOptions::ExitOnError, true));
             }
             ArgKind::PlusFlag('e') => {
                 env_changes.push(EnvChange::SetShellOptions(ShellOptions::ExitOnError, false));
             }
+            ArgKind::ShortFlag('m') => {
+                env_changes.push(EnvChange::SetShellOptions(ShellOptions::Monitor, true));
+            }
+            ArgKind::PlusFlag('m') => {
+                env_changes.push(EnvChange::SetShellOptions(ShellOptions::Monitor, false));
+            }
+            ArgKind::ShortFlag('n') => {
+                env_changes.push(EnvChange::SetShellOptions(ShellOptions::NoExec, true));
+            }
+            ArgKind::PlusFlag('n') => {
+                env_changes.push(EnvChange::SetShellOptions(ShellOptions::NoExec, false));
+            }
             ArgKind::ShortFlag('x') => {
                 env_changes.push(EnvChange::SetShellOptions(ShellOptions::PrintTrace, true));
             }
@@ -44,6 +126,9 @@ fn execute_set(args: Vec<String>) -> Result<(i32, Vec<EnvChange>)> {
             _ => bail!(format!("Unsupported argument: {:?}", arg)),
         }
     }
+    if let Some(positional_args) = positional_args {
+        env_changes.push(EnvChange::SetPositionalArgs(positional_args));
+    }
     Ok((0, env_changes))
 }
 
@@ -83,3 +168,79 @@ async fn test_exit_on_error() {
 
     assert!(execute_set(vec!["-t".to_string()]).is_err());
 }
+
+#[tokio::test]
+async fn test_named_options() {
+    let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+
+    assert_eq!(
+        execute_set(args(&["-o", "pipefail", "+o", "errexit"])).unwrap(),
+        (
+            0,
+            vec![
+                EnvChange::SetShellOptions(ShellOptions::PipeFail, true),
+                EnvChange::SetShellOptions(ShellOptions::ExitOnError, false)
+            ]
+        )
+    );
+
+    assert_eq!(
+        execute_set(args(&["-euo", "noglob"])).unwrap(),
+        (
+            0,
+            vec![
+                EnvChange::SetShellOptions(ShellOptions::ExitOnError, true),
+                EnvChange::SetShellOptions(ShellOptions::NoUnset, true),
+                EnvChange::SetShellOptions(ShellOptions::NoGlob, true)
+            ]
+        )
+    );
+
+    assert_eq!(
+        execute_set(args(&["-o", "vi", "+o", "vi"])).unwrap(),
+        (
+            0,
+            vec![
+                EnvChange::SetShellOptions(ShellOptions::Vi, true),
+                EnvChange::SetShellOptions(ShellOptions::Emacs, false),
+                EnvChange::SetShellOptions(ShellOptions::Vi, false)
+            ]
+        )
+    );
+
+    assert_eq!(
+        execute_set(args(&["-o", "nope"])).unwrap_err().to_string(),
+        "nope: invalid option name"
+    );
+    assert_eq!(
+        execute_set(args(&["-e", "-o"])).unwrap_err().to_string(),
+        "-o: option requires an argument"
+    );
+}
+
+#[tokio::test]
+async fn test_positional_args() {
+    let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+
+    assert_eq!(
+        execute_set(args(&["-e", "--", "a", "-b"])).unwrap(),
+        (
+            0,
+            vec![
+                EnvChange::SetShellOptions(ShellOptions::ExitOnError, true),
+                EnvChange::SetPositionalArgs(args(&["a", "-b"]))
+            ]
+        )
+    );
+    assert_eq!(
+        execute_set(args(&["--"])).unwrap(),
+        (0, vec![EnvChange::SetPositionalArgs(Vec::new())])
+    );
+    assert_eq!(
+        execute_set(args(&["+x"])).unwrap(),
+        (
+            0,
+            vec![EnvChange::SetShellOptions(ShellOptions::PrintTrace, false)]
+        )
+    );
+}
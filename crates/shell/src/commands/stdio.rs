@@ -0,0 +1,87 @@
+//! The uutils commands write to the stdout and stderr of the process, so
+//! for their output to be redirected like that of the other builtins, the
+//! file descriptors 1 and 2 point at the command's writers while it runs.
+
+use std::io::Write;
+
+use deno_task_shell::ShellPipeWriter;
+
+/// Runs `run` with the process's stdout and stderr going to `stdout` and
+/// `stderr`, and restores them afterwards.
+#[cfg(unix)]
+pub fn with_stdio(
+    stdout: &ShellPipeWriter,
+    stderr: &ShellPipeWriter,
+    run: impl FnOnce() -> i32,
+) -> i32 {
+    use std::os::fd::AsRawFd;
+
+    if matches!(stdout, ShellPipeWriter::Stdout) && matches!(stderr, ShellPipeWriter::Stderr) {
+        return run();
+    }
+    let _ = std::io::stdout().flush();
+    let _ = std::io::stderr().flush();
+    // SAFETY: duplicating the standard descriptors has no memory safety
+    // preconditions, the copies are closed below
+    let saved = unsafe { [libc::dup(1), libc::dup(2)] };
+    if saved.contains(&-1) {
+        return run();
+    }
+    // `2>&1 > file` leaves stderr on the original stdout, so the writers that
+    // stand for the original descriptors use the saved copies
+    let null = std::fs::OpenOptions::new().write(true).open("/dev/null");
+    let source = |writer: &ShellPipeWriter| match writer {
+        ShellPipeWriter::OsPipe(pipe) => Some(pipe.as_raw_fd()),
+        ShellPipeWriter::StdFile(file) => Some(file.as_raw_fd()),
+        ShellPipeWriter::Stdout => Some(saved[0]),
+        ShellPipeWriter::Stderr => Some(saved[1]),
+        ShellPipeWriter::Null => null.as_ref().ok().map(|file| file.as_raw_fd()),
+    };
+    for (fd, writer) in [(1, stdout), (2, stderr)] {
+        if let Some(source) = source(writer) {
+            // SAFETY: both descriptors are open
+            unsafe { libc::dup2(source, fd) };
+        }
+    }
+    let exit_code = run();
+    let _ = std::io::stdout().flush();
+    let _ = std::io::stderr().flush();
+    for (fd, saved) in [(1, saved[0]), (2, saved[1])] {
+        // SAFETY: the saved descriptors are open and only closed here
+        unsafe {
+            libc::dup2(saved, fd);
+            libc::close(saved);
+        }
+    }
+    exit_code
+}
+
+#[cfg(not(unix))]
+pub fn with_stdio(
+    _stdout: &ShellPipeWriter,
+    _stderr: &ShellPipeWriter,
+    run: impl FnOnce() -> i32,
+) -> i32 {
+    let exit_code = run();
+    let _ = std::io::stdout().flush();
+    exit_code
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_stdio() {
+        let path = std::env::temp_dir().join(format!("shell-stdio-{}", std::process::id()));
+        let stdout = ShellPipeWriter::from_std(std::fs::File::create(&path).unwrap());
+        let exit_code = with_stdio(&stdout, &ShellPipeWriter::null(), || {
+            std::io::stdout().write_all(b"out\n").unwrap();
+            std::io::stderr().write_all(b"err\n").unwrap();
+            3
+        });
+        assert_eq!(exit_code, 3);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "out\n");
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,118 @@
+//! `times`, the CPU time used by the shell and its terminated children.
+//!
+//! On unix the kernel keeps the total of the children that were waited for,
+//! which `getrusage(RUSAGE_CHILDREN)` returns. Windows keeps no such total,
+//! so there the shell adds up the times of each child as it waits for it,
+//! see [`ShellState::children_times`].
+
+use std::time::Duration;
+
+use deno_task_shell::{ExecuteResult, ShellCommand, ShellCommandContext, ShellState};
+use futures::future::LocalBoxFuture;
+
+pub struct TimesCommand;
+
+impl ShellCommand for TimesCommand {
+    fn description(&self) -> Option<&'static str> {
+        Some("Print the CPU time used by the shell and its children")
+    }
+
+    fn execute(&self, mut context: ShellCommandContext) -> LocalBoxFuture<'static, ExecuteResult> {
+        let times = cpu_times(&context.state);
+        let output = format!(
+            "{} {}\n{} {}\n",
+            format_duration(times.user),
+            format_duration(times.system),
+            format_duration(times.children_user),
+            format_duration(times.children_system),
+        );
+        let _ = context.stdout.write_all(output.as_bytes());
+        Box::pin(futures::future::ready(ExecuteResult::from_exit_code(0)))
+    }
+}
+
+/// The CPU time used by the shell and by all of its terminated (and waited
+/// for) child processes.
+struct CpuTimes {
+    user: Duration,
+    system: Duration,
+    children_user: Duration,
+    children_system: Duration,
+}
+
+#[cfg(unix)]
+fn cpu_times(_state: &ShellState) -> CpuTimes {
+    fn rusage(who: libc::c_int) -> (Duration, Duration) {
+        fn to_duration(time: libc::timeval) -> Duration {
+            Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+        }
+        let mut usage = std::mem::MaybeUninit::<libc::rusage>::zeroed();
+        // SAFETY: `usage` is a valid pointer, and stays zeroed if the call fails
+        let usage = unsafe {
+            libc::getrusage(who, usage.as_mut_ptr());
+            usage.assume_init()
+        };
+        (to_duration(usage.ru_utime), to_duration(usage.ru_stime))
+    }
+
+    // the kernel accumulates the usage of every child once it has been reaped
+    let (user, system) = rusage(libc::RUSAGE_SELF);
+    let (children_user, children_system) = rusage(libc::RUSAGE_CHILDREN);
+    CpuTimes {
+        user,
+        system,
+        children_user,
+        children_system,
+    }
+}
+
+#[cfg(windows)]
+fn cpu_times(state: &ShellState) -> CpuTimes {
+    use windows_sys::Win32::Foundation::FILETIME;
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, GetProcessTimes};
+
+    fn to_duration(time: FILETIME) -> Duration {
+        // FILETIME counts in 100 nanosecond intervals
+        let ticks = ((time.dwHighDateTime as u64) << 32) | time.dwLowDateTime as u64;
+        Duration::from_nanos(ticks * 100)
+    }
+
+    let empty = FILETIME {
+        dwLowDateTime: 0,
+        dwHighDateTime: 0,
+    };
+    let (mut creation, mut exit, mut kernel, mut user) = (empty, empty, empty, empty);
+    // SAFETY: all pointers are valid, and the times stay zero if the call fails
+    unsafe {
+        GetProcessTimes(
+            GetCurrentProcess(),
+            &mut creation,
+            &mut exit,
+            &mut kernel,
+            &mut user,
+        );
+    }
+    let (children_user, children_system) = state.children_times();
+    CpuTimes {
+        user: to_duration(user),
+        system: to_duration(kernel),
+        children_user,
+        children_system,
+    }
+}
+
+/// Formats a duration as `MmSS.SSs`, e.g. `1m05.20s`.
+fn format_duration(duration: Duration) -> String {
+    let centis = duration.as_millis() / 10;
+    let minutes = centis / 6000;
+    let centis = centis % 6000;
+    format!("{}m{:02}.{:02}s", minutes, centis / 100, centis % 100)
+}
+
+#[test]
+fn test_format_duration() {
+    assert_eq!(format_duration(Duration::ZERO), "0m00.00s");
+    assert_eq!(format_duration(Duration::from_millis(1_239)), "0m01.23s");
+    assert_eq!(format_duration(Duration::from_millis(65_200)), "1m05.20s");
+    assert_eq!(format_duration(Duration::from_secs(3_600)), "60m00.00s");
+}
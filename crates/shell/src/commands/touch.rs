@@ -17,6 +17,10 @@ static ARG_FILES: &str = "files";
 pub struct TouchCommand;
 
 impl ShellCommand for TouchCommand {
+    fn description(&self) -> Option<&'static str> {
+        Some("Change file timestamps or create files")
+    }
+
     fn execute(&self, mut context: ShellCommandContext) -> LocalBoxFuture<'static, ExecuteResult> {
         Box::pin(futures::future::ready(match execute_touch(&mut context) {
             Ok(_) => ExecuteResult::from_exit_code(0),
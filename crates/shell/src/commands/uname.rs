@@ -25,6 +25,10 @@ fn display(uname: &UNameOutput) -> String {
 }
 
 impl ShellCommand for UnameCommand {
+    fn description(&self) -> Option<&'static str> {
+        Some("Print system information")
+    }
+
     fn execute(&self, mut context: ShellCommandContext) -> LocalBoxFuture<'static, ExecuteResult> {
         Box::pin(async move {
             match execute_uname(&mut context) {
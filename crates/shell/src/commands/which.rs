@@ -4,6 +4,10 @@ use futures::future::LocalBoxFuture;
 pub struct WhichCommand;
 
 impl ShellCommand for WhichCommand {
+    fn description(&self) -> Option<&'static str> {
+        Some("Locate a command")
+    }
+
     fn execute(&self, mut context: ShellCommandContext) -> LocalBoxFuture<'static, ExecuteResult> {
         Box::pin(futures::future::ready(match execute_which(&mut context) {
             Ok(_) => ExecuteResult::from_exit_code(0),
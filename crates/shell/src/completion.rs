@@ -1,49 +1,305 @@
-use rustyline::completion::{Completer, Pair};
+use deno_task_shell::CompletionSpec;
+use rustyline::completion::{Candidate, Completer};
 use rustyline::error::ReadlineError;
 use rustyline::highlight::Highlighter;
 use rustyline::hint::Hinter;
 use rustyline::validate::Validator;
 use rustyline::{Context, Helper};
 use std::borrow::Cow::{self, Owned};
+use std::collections::{BTreeSet, HashMap};
 use std::env;
 use std::fs;
 use std::os::unix::fs::PermissionsExt as _;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 
-pub struct ShellCompleter;
+use crate::frequency::CommandFrequency;
+
+mod fuzzy;
+mod git;
+
+pub struct ShellCompleter {
+    /// Names and descriptions of the built-in commands
+    builtins: Vec<(String, Option<&'static str>)>,
+    /// Names and expansions of the aliases
+    aliases: Vec<(String, String)>,
+    /// The `PATH` of the shell, see [`ShellCompleter::set_path`]
+    path: String,
+    /// The executables in `PATH`, shared with the highlighter
+    executables: Mutex<PathExecutables>,
+    /// The completions of command arguments defined with `complete`
+    completions: HashMap<String, CompletionSpec>,
+    /// How often the commands were run, to rank the command names
+    frequency: Arc<Mutex<CommandFrequency>>,
+    /// How the candidates are matched, see [`ShellCompleter::set_fuzzy`]
+    matcher: Matcher,
+}
 
 impl Default for ShellCompleter {
     fn default() -> Self {
-        ShellCompleter
+        let mut commands = deno_task_shell::builtin_commands();
+        commands.extend(crate::commands::get_commands());
+        let mut builtins: Vec<_> = commands
+            .into_iter()
+            .map(|(name, command)| (name, command.description()))
+            .collect();
+        builtins.sort();
+        ShellCompleter {
+            builtins,
+            aliases: Vec::new(),
+            path: String::new(),
+            executables: Mutex::default(),
+            completions: HashMap::new(),
+            frequency: Arc::default(),
+            matcher: Matcher::Prefix,
+        }
+    }
+}
+
+impl ShellCompleter {
+    pub fn set_completions(&mut self, completions: HashMap<String, CompletionSpec>) {
+        self.completions = completions;
+    }
+
+    /// Matches the candidates fuzzily instead of by prefix, which the shell
+    /// turns on with `SHELL_FUZZY_COMPLETE=1`.
+    pub fn set_fuzzy(&mut self, fuzzy: bool) {
+        self.matcher = if fuzzy {
+            Matcher::Fuzzy
+        } else {
+            Matcher::Prefix
+        };
+    }
+
+    /// Updates the `PATH` of the shell that executables are completed and
+    /// looked up in, which can differ from the environment of the process.
+    pub fn set_path(&mut self, path: String) {
+        self.path = path;
+    }
+
+    pub fn set_aliases(&mut self, aliases: &HashMap<String, Vec<String>>) {
+        self.aliases = aliases
+            .iter()
+            .map(|(name, expansion)| (name.clone(), expansion.join(" ")))
+            .collect();
+        self.aliases.sort();
+    }
+
+    /// The counts that the command names are ranked by, shared with the
+    /// hinter and updated by the REPL.
+    pub fn frequency(&self) -> &Arc<Mutex<CommandFrequency>> {
+        &self.frequency
+    }
+
+    /// Whether `name` is a built-in command or an executable in `PATH`.
+    pub fn is_command(&self, name: &str) -> bool {
+        self.builtins
+            .binary_search_by(|(builtin, _)| builtin.as_str().cmp(name))
+            .is_ok()
+            || self
+                .executables
+                .lock()
+                .unwrap()
+                .names(&self.path)
+                .contains(name)
+    }
+}
+
+/// A cache of the names of the executables in `PATH`, so they don't have to
+/// be listed on every keystroke. It is refreshed when `PATH` changes or one
+/// of its directories is modified.
+#[derive(Default)]
+struct PathExecutables {
+    path: String,
+    /// The directories of `PATH` with their modification times
+    dirs: Vec<(PathBuf, Option<SystemTime>)>,
+    names: BTreeSet<String>,
+}
+
+impl PathExecutables {
+    fn names(&mut self, path: &str) -> &BTreeSet<String> {
+        let is_stale = self.path != path
+            || self.dirs.is_empty()
+            || self
+                .dirs
+                .iter()
+                .any(|(dir, modified)| modified_time(dir) != *modified);
+        if is_stale {
+            self.path = path.to_string();
+            self.dirs = env::split_paths(path)
+                .map(|dir| {
+                    let modified = modified_time(&dir);
+                    (dir, modified)
+                })
+                .collect();
+            self.names = self
+                .dirs
+                .iter()
+                .filter_map(|(dir, _)| fs::read_dir(dir).ok())
+                .flat_map(|entries| entries.flatten())
+                .filter(|entry| entry.path().is_file())
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .collect();
+        }
+        &self.names
+    }
+}
+
+fn modified_time(dir: &Path) -> Option<SystemTime> {
+    fs::metadata(dir)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// A completion candidate with an optional description that is shown next
+/// to it in the list view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShellCandidate {
+    display: String,
+    replacement: String,
+    description: Option<String>,
+}
+
+impl ShellCandidate {
+    pub(crate) fn new(display: impl Into<String>, replacement: impl Into<String>) -> Self {
+        ShellCandidate {
+            display: display.into(),
+            replacement: replacement.into(),
+            description: None,
+        }
+    }
+
+    fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+}
+
+impl Candidate for ShellCandidate {
+    fn display(&self) -> &str {
+        &self.display
+    }
+
+    fn replacement(&self) -> &str {
+        &self.replacement
+    }
+}
+
+/// Appends the descriptions to the displayed candidates, aligned in a column.
+fn render_descriptions(matches: &mut [ShellCandidate]) {
+    let width = matches
+        .iter()
+        .filter(|m| m.description.is_some())
+        .map(|m| m.display.chars().count())
+        .max()
+        .unwrap_or(0);
+    for candidate in matches.iter_mut() {
+        if let Some(description) = candidate.description.take() {
+            candidate.display = format!("{:<width$}  -- {}", candidate.display, description);
+        }
+    }
+}
+
+/// How completion candidates are matched against the word being completed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Matcher {
+    /// Candidates must start with the word (the default)
+    Prefix,
+    /// Candidates must contain the characters of the word in order, enabled
+    /// with `SHELL_FUZZY_COMPLETE=1`
+    Fuzzy,
+}
+
+impl Matcher {
+    fn matches(self, candidate: &str, word: &str) -> bool {
+        match self {
+            Matcher::Prefix => candidate.starts_with(word),
+            Matcher::Fuzzy => fuzzy::score(candidate, word).is_some(),
+        }
+    }
+
+    /// Sorts the fuzzy matches best-first. Prefix matches keep their order.
+    fn rank(self, word: &str, matches: &mut [ShellCandidate]) {
+        if self == Matcher::Fuzzy {
+            // only the last path component is displayed and matched
+            let word = word.rsplit('/').next().unwrap_or(word);
+            matches.sort_by_cached_key(|pair| {
+                let display = pair.display.trim_end_matches(['/', '*']);
+                std::cmp::Reverse(fuzzy::score(display, word))
+            });
+        }
     }
 }
 
 impl Completer for ShellCompleter {
-    type Candidate = Pair;
+    type Candidate = ShellCandidate;
 
     fn complete(
         &self,
         line: &str,
         pos: usize,
         _ctx: &Context<'_>,
-    ) -> Result<(usize, Vec<Pair>), ReadlineError> {
+    ) -> Result<(usize, Vec<ShellCandidate>), ReadlineError> {
         let mut matches = Vec::new();
         let (start, word) = extract_word(line, pos);
+        let matcher = self.matcher;
+
+        // Command specific completions
+        let previous_words: Vec<_> = line[..start].split_whitespace().collect();
+        if let Some((&"git", args)) = previous_words.split_first() {
+            if git::complete(args, word, matcher, &mut matches) {
+                matcher.rank(word, &mut matches);
+                render_descriptions(&mut matches);
+                return Ok((start, matches));
+            }
+        }
+        if let Some(spec) = previous_words
+            .first()
+            .and_then(|command| self.completions.get(*command))
+        {
+            complete_spec(spec, word, matcher, &mut matches);
+            matcher.rank(word, &mut matches);
+            render_descriptions(&mut matches);
+            return Ok((start, matches));
+        }
 
         let is_start = start == 0;
         // Complete filenames
-        complete_filenames(is_start, word, &mut matches);
+        complete_filenames(is_start, word, matcher, &mut matches);
 
-        // Complete shell commands
-        complete_shell_commands(is_start, word, &mut matches);
+        // Complete aliases and shell commands
+        complete_aliases(is_start, word, matcher, &self.aliases, &mut matches);
+        complete_shell_commands(is_start, word, matcher, &self.builtins, &mut matches);
 
         // Complete executables in PATH
-        complete_executables_in_path(is_start, word, &mut matches);
+        let mut executables = self.executables.lock().unwrap();
+        complete_executables_in_path(
+            is_start,
+            word,
+            matcher,
+            executables.names(&self.path),
+            &mut matches,
+        );
 
+        // the fuzzy ranking is stable, so it keeps this order for equal scores
+        if is_start {
+            rank_by_frequency(&self.frequency.lock().unwrap(), &mut matches);
+        }
+        matcher.rank(word, &mut matches);
+        render_descriptions(&mut matches);
         Ok((start, matches))
     }
 }
 
+/// Sorts the commands that were run most often first, the others keep
+/// their order.
+fn rank_by_frequency(frequency: &CommandFrequency, matches: &mut [ShellCandidate]) {
+    matches.sort_by_key(|candidate| {
+        std::cmp::Reverse(frequency.command_count(&candidate.replacement))
+    });
+}
+
 fn extract_word(line: &str, pos: usize) -> (usize, &str) {
     if line.ends_with(' ') {
         return (pos, "");
@@ -53,7 +309,12 @@ fn extract_word(line: &str, pos: usize) -> (usize, &str) {
     (word_start, &line[word_start..pos])
 }
 
-fn complete_filenames(is_start: bool, word: &str, matches: &mut Vec<Pair>) {
+fn complete_filenames(
+    is_start: bool,
+    word: &str,
+    matcher: Matcher,
+    matches: &mut Vec<ShellCandidate>,
+) {
     let only_executable = word.starts_with("./") && is_start;
 
     // Split the word into directory path and partial filename
@@ -76,7 +337,7 @@ fn complete_filenames(is_start: bool, word: &str, matches: &mut Vec<Pair>) {
     if let Ok(entries) = fs::read_dir(Path::new(&search_dir)) {
         for entry in entries.flatten() {
             if let Ok(name) = entry.file_name().into_string() {
-                if name.starts_with(partial_name) {
+                if matcher.matches(&name, partial_name) {
                     let full_path = format!("{}{}", dir_path, name);
                     match entry.file_type() {
                         Ok(file_type) if file_type.is_dir() => {
@@ -87,10 +348,10 @@ fn complete_filenames(is_start: bool, word: &str, matches: &mut Vec<Pair>) {
                                 full_path.clone()
                             };
 
-                            matching.push(Pair {
-                                display: display + "/",
-                                replacement: full_path + "/",
-                            });
+                            matching.push(
+                                ShellCandidate::new(display + "/", full_path + "/")
+                                    .with_description("directory"),
+                            );
                         }
                         Ok(_) => {
                             let is_executable =
@@ -106,21 +367,17 @@ fn complete_filenames(is_start: bool, word: &str, matches: &mut Vec<Pair>) {
                                 full_path.clone()
                             };
 
-                            if is_executable {
-                                display.push_str("*");
-                            }
-
-                            if entry.metadata().unwrap().permissions().mode() & 0o111 != 0 {
-                                matching.push(Pair {
-                                    display,
-                                    replacement: full_path,
-                                });
+                            let description = if is_executable {
+                                display.push('*');
+                                "executable"
                             } else {
-                                matching.push(Pair {
-                                    display,
-                                    replacement: full_path,
-                                });
-                            }
+                                "file"
+                            };
+
+                            matching.push(
+                                ShellCandidate::new(display, full_path)
+                                    .with_description(description),
+                            );
                         }
                         Err(_) => {}
                     }
@@ -133,39 +390,81 @@ fn complete_filenames(is_start: bool, word: &str, matches: &mut Vec<Pair>) {
     matches.extend(matching);
 }
 
-fn complete_shell_commands(is_start: bool, word: &str, matches: &mut Vec<Pair>) {
+/// Completes the arguments of a command with a completion defined with
+/// `complete`.
+fn complete_spec(
+    spec: &CompletionSpec,
+    word: &str,
+    matcher: Matcher,
+    matches: &mut Vec<ShellCandidate>,
+) {
+    for candidate in &spec.words {
+        if matcher.matches(candidate, word) {
+            matches.push(ShellCandidate::new(candidate, candidate));
+        }
+    }
+    if spec.files || spec.directories {
+        let mut names = Vec::new();
+        complete_filenames(false, word, matcher, &mut names);
+        // directories are completed with a trailing slash
+        names.retain(|name| spec.files || name.replacement().ends_with('/'));
+        matches.extend(names);
+    }
+}
+
+fn complete_aliases(
+    is_start: bool,
+    word: &str,
+    matcher: Matcher,
+    aliases: &[(String, String)],
+    matches: &mut Vec<ShellCandidate>,
+) {
+    if !is_start {
+        return;
+    }
+    for (name, expansion) in aliases {
+        if matcher.matches(name, word) {
+            matches.push(
+                ShellCandidate::new(name, name).with_description(format!("alias for {expansion}")),
+            );
+        }
+    }
+}
+
+fn complete_shell_commands(
+    is_start: bool,
+    word: &str,
+    matcher: Matcher,
+    builtins: &[(String, Option<&'static str>)],
+    matches: &mut Vec<ShellCandidate>,
+) {
     if !is_start {
         return;
     }
-    let shell_commands = ["ls", "cat", "cd", "pwd", "echo", "grep"];
-    for &cmd in &shell_commands {
-        if cmd.starts_with(word) {
-            matches.push(Pair {
-                display: cmd.to_string(),
-                replacement: cmd.to_string(),
+    for (cmd, description) in builtins {
+        if matcher.matches(cmd, word) {
+            let candidate = ShellCandidate::new(cmd, cmd);
+            matches.push(match description {
+                Some(description) => candidate.with_description(*description),
+                None => candidate,
             });
         }
     }
 }
 
-fn complete_executables_in_path(is_start: bool, word: &str, matches: &mut Vec<Pair>) {
+fn complete_executables_in_path(
+    is_start: bool,
+    word: &str,
+    matcher: Matcher,
+    executables: &BTreeSet<String>,
+    matches: &mut Vec<ShellCandidate>,
+) {
     if !is_start {
         return;
     }
-    if let Ok(paths) = env::var("PATH") {
-        for path in env::split_paths(&paths) {
-            if let Ok(entries) = fs::read_dir(path) {
-                for entry in entries.flatten() {
-                    if let Ok(name) = entry.file_name().into_string() {
-                        if name.starts_with(word) && entry.path().is_file() {
-                            matches.push(Pair {
-                                display: name.clone(),
-                                replacement: name,
-                            });
-                        }
-                    }
-                }
-            }
+    for name in executables {
+        if matcher.matches(name, word) {
+            matches.push(ShellCandidate::new(name, name));
         }
     }
 }
@@ -183,3 +482,154 @@ impl Highlighter for ShellCompleter {
 impl Validator for ShellCompleter {}
 
 impl Helper for ShellCompleter {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_aligned_descriptions() {
+        let mut matches = vec![
+            ShellCandidate::new("cd", "cd").with_description("Change the working directory"),
+            ShellCandidate::new("src/", "src/").with_description("directory"),
+            ShellCandidate::new("cargo", "cargo"),
+        ];
+        render_descriptions(&mut matches);
+        let displays: Vec<_> = matches.iter().map(|m| m.display()).collect();
+        assert_eq!(
+            displays,
+            [
+                "cd    -- Change the working directory",
+                "src/  -- directory",
+                "cargo"
+            ]
+        );
+        // the replacement is not affected
+        assert_eq!(matches[1].replacement(), "src/");
+    }
+
+    #[test]
+    fn caches_executables_in_path() {
+        let dir = env::temp_dir().join(format!("shell-test-path-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("first-tool"), "").unwrap();
+        let path = dir.to_str().unwrap();
+
+        let mut completer = ShellCompleter::default();
+        assert!(!completer.is_command("first-tool"));
+        completer.set_path(path.to_string());
+        assert!(completer.is_command("first-tool"));
+        assert!(completer.is_command("cd"));
+        assert!(!completer.is_command("second-tool"));
+
+        // adding a file modifies the directory, which refreshes the cache
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        fs::write(dir.join("second-tool"), "").unwrap();
+        assert!(completer.is_command("second-tool"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn completes_builtins_with_descriptions() {
+        let completer = ShellCompleter::default();
+        let mut matches = Vec::new();
+        complete_shell_commands(
+            true,
+            "pw",
+            Matcher::Prefix,
+            &completer.builtins,
+            &mut matches,
+        );
+        assert_eq!(
+            matches,
+            [ShellCandidate::new("pwd", "pwd")
+                .with_description("Print the current working directory")]
+        );
+    }
+
+    #[test]
+    fn completes_aliases_with_expansions() {
+        let mut completer = ShellCompleter::default();
+        completer.set_aliases(&HashMap::from([
+            (
+                "gst".to_string(),
+                vec!["git".to_string(), "status".to_string()],
+            ),
+            ("ll".to_string(), vec!["ls".to_string(), "-l".to_string()]),
+        ]));
+        let mut matches = Vec::new();
+        complete_aliases(
+            true,
+            "gs",
+            Matcher::Prefix,
+            &completer.aliases,
+            &mut matches,
+        );
+        assert_eq!(
+            matches,
+            [ShellCandidate::new("gst", "gst").with_description("alias for git status")]
+        );
+        // only the command word is completed with aliases
+        matches.clear();
+        complete_aliases(
+            false,
+            "gs",
+            Matcher::Prefix,
+            &completer.aliases,
+            &mut matches,
+        );
+        assert!(matches.is_empty());
+    }
+
+    #[test]
+    fn ranks_commands_by_frequency() {
+        let mut frequency = CommandFrequency::default();
+        frequency.add("cargo build");
+        frequency.add("cat file");
+        frequency.add("cargo test");
+        let mut matches = vec![
+            ShellCandidate::new("cal", "cal"),
+            ShellCandidate::new("cargo", "cargo"),
+            ShellCandidate::new("case", "case"),
+            ShellCandidate::new("cat", "cat"),
+        ];
+        rank_by_frequency(&frequency, &mut matches);
+        assert_eq!(
+            matches.iter().map(|m| m.display()).collect::<Vec<_>>(),
+            ["cargo", "cat", "cal", "case"]
+        );
+    }
+
+    #[test]
+    fn completes_with_complete_specs() {
+        let spec = CompletionSpec {
+            words: ["start", "stop", "restart"].map(String::from).to_vec(),
+            ..Default::default()
+        };
+        let mut matches = Vec::new();
+        complete_spec(&spec, "st", Matcher::Prefix, &mut matches);
+        assert_eq!(
+            matches,
+            [
+                ShellCandidate::new("start", "start"),
+                ShellCandidate::new("stop", "stop")
+            ]
+        );
+
+        let dir = env::temp_dir().join(format!("shell-test-complete-{}", std::process::id()));
+        fs::create_dir_all(dir.join("subdir")).unwrap();
+        fs::write(dir.join("file"), "").unwrap();
+        let word = format!("{}/", dir.display());
+        let spec = CompletionSpec {
+            directories: true,
+            ..Default::default()
+        };
+        let mut matches = Vec::new();
+        complete_spec(&spec, &word, Matcher::Prefix, &mut matches);
+        assert_eq!(
+            matches.iter().map(|m| m.display()).collect::<Vec<_>>(),
+            ["subdir/"]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,77 @@
+//! A small subsequence matcher used for fuzzy completion.
+
+const MATCH_SCORE: i64 = 16;
+/// Bonus for a character directly following the previous match.
+const CONSECUTIVE_BONUS: i64 = 12;
+/// Bonus for matching at the start of the candidate or of a "word" in it
+/// (after `_`, `-`, `.`, ` ` or a lowercase to uppercase transition).
+const WORD_START_BONUS: i64 = 8;
+const EXACT_CASE_BONUS: i64 = 1;
+/// Penalty per skipped character between two matches.
+const GAP_PENALTY: i64 = 1;
+
+/// Scores how well `pattern` fuzzy matches `candidate`.
+///
+/// All characters of `pattern` must appear in `candidate` in order (ignoring
+/// case), otherwise `None` is returned. Higher scores are better matches.
+pub fn score(candidate: &str, pattern: &str) -> Option<i64> {
+    let candidate: Vec<char> = candidate.chars().collect();
+    let mut score = 0;
+    let mut last_match: Option<usize> = None;
+    let mut index = 0;
+    for p in pattern.chars() {
+        let position =
+            (index..candidate.len()).find(|&i| candidate[i].to_lowercase().eq(p.to_lowercase()))?;
+        score += MATCH_SCORE;
+        if candidate[position] == p {
+            score += EXACT_CASE_BONUS;
+        }
+        if is_word_start(&candidate, position) {
+            score += WORD_START_BONUS;
+        }
+        match last_match {
+            Some(last) if last + 1 == position => score += CONSECUTIVE_BONUS,
+            Some(last) => score -= GAP_PENALTY * (position - last - 1) as i64,
+            None => score -= GAP_PENALTY * position as i64,
+        }
+        last_match = Some(position);
+        index = position + 1;
+    }
+    // prefer shorter candidates when everything else is equal
+    Some(score - (candidate.len() - index) as i64)
+}
+
+fn is_word_start(candidate: &[char], position: usize) -> bool {
+    if position == 0 {
+        return true;
+    }
+    let previous = candidate[position - 1];
+    matches!(previous, '_' | '-' | '.' | ' ' | '/')
+        || (previous.is_lowercase() && candidate[position].is_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_subsequences() {
+        assert!(score("Downloads", "dwnlds").is_some());
+        assert!(score("Downloads", "").is_some());
+        assert!(score("Downloads", "dls").is_some());
+        assert!(score("Downloads", "sd").is_none());
+        assert!(score("Documents", "dwn").is_none());
+    }
+
+    #[test]
+    fn ranks_better_matches_first() {
+        // prefix beats a scattered match
+        assert!(score("Downloads", "down") > score("dotfiles_owner", "down"));
+        // consecutive characters beat gaps
+        assert!(score("cargo", "car") > score("clear", "car"));
+        // word starts beat matches in the middle of a word
+        assert!(score("git-lfs", "gl") > score("gold", "gl"));
+        // shorter candidates win ties
+        assert!(score("src", "src") > score("src_old", "src"));
+    }
+}
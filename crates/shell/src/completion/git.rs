@@ -0,0 +1,255 @@
+//! Completion for `git` subcommands and branch names.
+
+use super::{Matcher, ShellCandidate};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The subcommands offered when completing `git <tab>`.
+const SUBCOMMANDS: &[&str] = &[
+    "add",
+    "am",
+    "bisect",
+    "blame",
+    "branch",
+    "checkout",
+    "cherry-pick",
+    "clean",
+    "clone",
+    "commit",
+    "config",
+    "diff",
+    "fetch",
+    "grep",
+    "init",
+    "log",
+    "merge",
+    "mv",
+    "pull",
+    "push",
+    "rebase",
+    "reflog",
+    "remote",
+    "reset",
+    "restore",
+    "revert",
+    "rm",
+    "show",
+    "stash",
+    "status",
+    "switch",
+    "tag",
+    "worktree",
+];
+
+/// Subcommands whose arguments are usually branch names.
+const BRANCH_SUBCOMMANDS: &[&str] = &[
+    "branch",
+    "checkout",
+    "cherry-pick",
+    "diff",
+    "log",
+    "merge",
+    "rebase",
+    "reset",
+    "show",
+    "switch",
+];
+
+/// Completes the word at the cursor for a `git` command line. `args` are the
+/// words between `git` and the word being completed.
+///
+/// Returns `true` if the matches are complete and no other completion
+/// (e.g. filenames) should be added.
+pub fn complete(
+    args: &[&str],
+    word: &str,
+    matcher: Matcher,
+    matches: &mut Vec<ShellCandidate>,
+) -> bool {
+    let subcommand = match find_subcommand(args) {
+        Ok(subcommand) => subcommand,
+        // the word is the value of an option such as `-C <path>`
+        Err(()) => return false,
+    };
+    match subcommand {
+        None => {
+            if word.starts_with('-') {
+                return false;
+            }
+            complete_subcommands(word, matcher, matches);
+            true
+        }
+        Some(subcommand) => {
+            if BRANCH_SUBCOMMANDS.contains(&subcommand) && !word.starts_with('-') {
+                complete_branches(word, matcher, matches);
+            }
+            false
+        }
+    }
+}
+
+/// The global options that take a value as the next word.
+const OPTIONS_WITH_VALUE: &[&str] = &["-C", "-c", "--git-dir", "--work-tree"];
+
+/// Finds the subcommand in `args`, skipping global options such as
+/// `--no-pager` or `-C <path>`. Fails if the last word is an option whose
+/// value comes next.
+fn find_subcommand<'a>(args: &[&'a str]) -> Result<Option<&'a str>, ()> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if OPTIONS_WITH_VALUE.contains(arg) {
+            if args.next().is_none() {
+                return Err(());
+            }
+        } else if !arg.starts_with('-') {
+            return Ok(Some(arg));
+        }
+    }
+    Ok(None)
+}
+
+fn complete_subcommands(word: &str, matcher: Matcher, matches: &mut Vec<ShellCandidate>) {
+    for subcommand in SUBCOMMANDS.iter().filter(|s| matcher.matches(s, word)) {
+        matches.push(ShellCandidate::new(*subcommand, *subcommand));
+    }
+}
+
+fn complete_branches(word: &str, matcher: Matcher, matches: &mut Vec<ShellCandidate>) {
+    let Some(git_dir) = env::current_dir().ok().and_then(|cwd| find_git_dir(&cwd)) else {
+        return;
+    };
+    let mut branches = local_branches(&git_dir);
+    branches.retain(|branch| matcher.matches(branch, word));
+    branches.sort();
+    branches.dedup();
+    matches.extend(
+        branches
+            .into_iter()
+            .map(|branch| ShellCandidate::new(branch.clone(), branch).with_description("branch")),
+    );
+}
+
+/// Finds the `.git` directory of the repository containing `dir`, looking in
+/// the same place `ShellState` does (`<root>/.git/HEAD`) but also walking up
+/// through the parent directories.
+fn find_git_dir(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(".git"))
+        .find(|git_dir| git_dir.join("HEAD").is_file())
+}
+
+/// Lists the local branches from both the loose refs in `refs/heads` and the
+/// `packed-refs` file.
+fn local_branches(git_dir: &Path) -> Vec<String> {
+    let mut branches = Vec::new();
+    collect_loose_refs(&git_dir.join("refs/heads"), "", &mut branches);
+    if let Ok(packed) = fs::read_to_string(git_dir.join("packed-refs")) {
+        for line in packed.lines() {
+            // lines look like `<sha> refs/heads/<branch>`
+            if let Some((_, name)) = line.split_once(' ') {
+                if let Some(branch) = name.strip_prefix("refs/heads/") {
+                    branches.push(branch.to_string());
+                }
+            }
+        }
+    }
+    branches
+}
+
+fn collect_loose_refs(dir: &Path, prefix: &str, branches: &mut Vec<String>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        let name = format!("{prefix}{name}");
+        match entry.file_type() {
+            // branch names containing `/` are stored as nested directories
+            Ok(file_type) if file_type.is_dir() => {
+                collect_loose_refs(&entry.path(), &format!("{name}/"), branches)
+            }
+            Ok(_) => branches.push(name),
+            Err(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn displays(matches: &[ShellCandidate]) -> Vec<&str> {
+        matches.iter().map(|m| m.display.as_str()).collect()
+    }
+
+    #[test]
+    fn completes_subcommands() {
+        let mut matches = Vec::new();
+        assert!(complete(&[], "che", Matcher::Prefix, &mut matches));
+        assert_eq!(displays(&matches), ["checkout", "cherry-pick"]);
+
+        let mut matches = Vec::new();
+        assert!(complete(
+            &["--no-pager"],
+            "sta",
+            Matcher::Prefix,
+            &mut matches
+        ));
+        assert_eq!(displays(&matches), ["stash", "status"]);
+
+        let mut matches = Vec::new();
+        assert!(complete(
+            &["-C", "repo"],
+            "che",
+            Matcher::Prefix,
+            &mut matches
+        ));
+        assert_eq!(displays(&matches), ["checkout", "cherry-pick"]);
+
+        // the path of `-C` is completed as a file
+        let mut matches = Vec::new();
+        assert!(!complete(&["-C"], "che", Matcher::Prefix, &mut matches));
+        assert!(matches.is_empty());
+
+        let mut matches = Vec::new();
+        assert!(complete(&[], "cpk", Matcher::Fuzzy, &mut matches));
+        assert_eq!(displays(&matches), ["cherry-pick"]);
+    }
+
+    #[test]
+    fn finds_subcommand_after_options() {
+        assert_eq!(find_subcommand(&["-C", "repo"]), Ok(None));
+        assert_eq!(find_subcommand(&["-C", "checkout", "log"]), Ok(Some("log")));
+        assert_eq!(
+            find_subcommand(&["--git-dir", "x", "-c", "a=b", "--no-pager", "diff"]),
+            Ok(Some("diff"))
+        );
+        assert_eq!(find_subcommand(&["--work-tree"]), Err(()));
+        // the value is part of the word with `=`
+        assert_eq!(find_subcommand(&["--git-dir=x", "show"]), Ok(Some("show")));
+    }
+
+    #[test]
+    fn reads_loose_and_packed_branches() {
+        let git_dir = env::temp_dir().join(format!("shell-git-completion-{}", std::process::id()));
+        fs::create_dir_all(git_dir.join("refs/heads/feature")).unwrap();
+        fs::write(git_dir.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(git_dir.join("refs/heads/main"), "").unwrap();
+        fs::write(git_dir.join("refs/heads/feature/completion"), "").unwrap();
+        fs::write(
+            git_dir.join("packed-refs"),
+            "# pack-refs with: peeled fully-peeled sorted\n\
+             0000000000000000000000000000000000000000 refs/heads/release\n\
+             0000000000000000000000000000000000000000 refs/tags/v1.0\n",
+        )
+        .unwrap();
+
+        let mut branches = local_branches(&git_dir);
+        branches.sort();
+        fs::remove_dir_all(&git_dir).unwrap();
+        assert_eq!(branches, ["feature/completion", "main", "release"]);
+    }
+}
@@ -1,40 +1,98 @@
+use deno_task_shell::parser::{
+    ParseError, SequentialList, SequentialListItem, SimpleCommand, Word,
+};
 use deno_task_shell::{
     execute_sequential_list, AsyncCommandBehavior, ExecuteResult, ShellPipeReader, ShellPipeWriter,
     ShellState,
 };
 use miette::{Context, IntoDiagnostic};
 
+/// The name of the commands that weren't read from a file in errors.
+const INTERACTIVE_SOURCE: &str = "<interactive>";
+
 pub async fn execute_inner(
     text: &str,
     filename: Option<String>,
-    state: ShellState,
+    mut state: ShellState,
 ) -> miette::Result<ExecuteResult> {
     let list = deno_task_shell::parser::parse(text);
+    if let Some(filename) = &filename {
+        state.set_script_name(filename.clone());
+    }
+    state.set_script_source(filename.as_deref().unwrap_or(INTERACTIVE_SOURCE), text);
 
-    let mut stderr = ShellPipeWriter::stderr();
-    let stdout = ShellPipeWriter::stdout();
-    let stdin = ShellPipeReader::stdin();
-
-    if let Err(e) = list {
-        if let Some(filename) = &filename {
-            stderr.write_all(format!("Filename: {:?}\n", filename).as_bytes())?;
+    match list {
+        Ok(list) => Ok(execute_list(list, state).await),
+        Err(e) => {
+            report_syntax_error(&mut ShellPipeWriter::stderr(), e, filename)?;
+            Ok(ExecuteResult::Exit(1, vec![]))
         }
-        stderr.write_all(format!("Syntax error: {:?}", e).as_bytes())?;
-        return Ok(ExecuteResult::Exit(1, vec![]));
     }
+}
 
+async fn execute_list(list: SequentialList, state: ShellState) -> ExecuteResult {
+    // the jobs started on an interactive line keep running after it, they
+    // are in the job table and reported at a later prompt
+    let async_command_behavior = if state.is_interactive() {
+        AsyncCommandBehavior::Yield
+    } else {
+        AsyncCommandBehavior::Wait
+    };
     // spawn a sequential list and pipe its output to the environment
-    let result = execute_sequential_list(
-        list.unwrap(),
+    execute_sequential_list(
+        list,
         state,
-        stdin,
-        stdout,
-        stderr,
-        AsyncCommandBehavior::Wait,
+        ShellPipeReader::stdin(),
+        ShellPipeWriter::stdout(),
+        ShellPipeWriter::stderr(),
+        async_command_behavior,
     )
-    .await;
+    .await
+}
 
-    Ok(result)
+fn report_syntax_error(
+    stderr: &mut ShellPipeWriter,
+    error: miette::Error,
+    filename: Option<String>,
+) -> miette::Result<()> {
+    let error = match error.downcast::<ParseError>() {
+        Ok(error) => error
+            .with_filename(filename.as_deref().unwrap_or(INTERACTIVE_SOURCE))
+            .into(),
+        Err(error) => error,
+    };
+    stderr.write_all(format!("{:?}\n", error).as_bytes())
+}
+
+/// Parses `text` without running it and reports any syntax error, for
+/// `--noexec`. Returns the exit code, which is 1 if there is an error.
+pub fn check_syntax(text: &str, filename: Option<String>) -> miette::Result<i32> {
+    match deno_task_shell::parser::parse(text) {
+        Ok(_) => Ok(0),
+        Err(e) => {
+            report_syntax_error(&mut ShellPipeWriter::stderr(), e, filename)?;
+            Ok(1)
+        }
+    }
+}
+
+/// Prints each top level command of `text` on its own without running
+/// anything, not even built-ins or assignments. Returns the exit code, which
+/// is 1 if `text` has a syntax error.
+pub fn dry_run(text: &str, filename: Option<String>) -> miette::Result<i32> {
+    match deno_task_shell::parser::split_commands(text) {
+        Ok(commands) => {
+            let mut stdout = ShellPipeWriter::stdout();
+            for command in commands {
+                stdout.write_line(&command)?;
+            }
+            Ok(0)
+        }
+        Err(e) => {
+            report_syntax_error(&mut ShellPipeWriter::stderr(), e, filename)?;
+            Ok(1)
+        }
+    }
 }
 
 pub async fn execute(
@@ -43,16 +101,49 @@ pub async fn execute(
     state: &mut ShellState,
 ) -> miette::Result<i32> {
     let result = execute_inner(text, filename, state.clone()).await?;
+    apply_result(result, state)
+}
 
-    match result {
-        ExecuteResult::Continue(exit_code, changes, _) => {
-            // set CWD to the last command's CWD
-            state.apply_changes(&changes);
-            std::env::set_current_dir(state.cwd())
-                .into_diagnostic()
-                .context("Failed to set CWD")?;
-            Ok(exit_code)
-        }
-        ExecuteResult::Exit(exit_code, _) => Ok(exit_code),
-    }
+/// Runs the function or builtin `name` with `args` as they are, they aren't parsed
+/// as shell text so any quotes in them stay.
+pub async fn call_function(
+    name: &str,
+    args: &[&str],
+    state: &mut ShellState,
+) -> miette::Result<i32> {
+    let mut words = vec![Word::new_word(name)];
+    words.extend(args.iter().map(|arg| Word::new_string(arg)));
+    let command = SimpleCommand {
+        env_vars: Vec::new(),
+        args: words,
+    };
+    let text = std::iter::once(name)
+        .chain(args.iter().copied())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let list = SequentialList {
+        items: vec![SequentialListItem {
+            is_async: false,
+            line: 1,
+            text,
+            sequence: command.into(),
+        }],
+    };
+    let result = execute_list(list, state.clone()).await;
+    apply_result(result, state)
+}
+
+fn apply_result(result: ExecuteResult, state: &mut ShellState) -> miette::Result<i32> {
+    let (exit_code, changes) = match result {
+        ExecuteResult::Continue(exit_code, changes, _)
+        | ExecuteResult::Return(exit_code, changes, _) => (exit_code, changes),
+        ExecuteResult::LoopControl(_, changes, _) => (0, changes),
+        ExecuteResult::Exit(exit_code, _) => return Ok(exit_code),
+    };
+    // set CWD to the last command's CWD
+    state.apply_changes(&changes);
+    std::env::set_current_dir(state.cwd())
+        .into_diagnostic()
+        .context("Failed to set CWD")?;
+    Ok(exit_code)
 }
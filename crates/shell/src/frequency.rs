@@ -0,0 +1,208 @@
+//! How often each command line was run, to rank the completions of the
+//! command word and to pick autosuggestions.
+//!
+//! The counts are saved in a file next to the history, one line per command
+//! line with its count, ordered by when it last ran.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+
+/// The most lines that are saved, the ones that ran least recently are
+/// dropped first.
+const MAX_LINES: usize = 1000;
+
+#[derive(Default)]
+pub(crate) struct CommandFrequency {
+    lines: HashMap<String, LineUse>,
+    /// How often each command name was run, derived from the lines
+    commands: HashMap<String, usize>,
+    /// Increases with every line that is added, to order them by last use
+    clock: usize,
+}
+
+struct LineUse {
+    count: usize,
+    last_run: usize,
+}
+
+impl CommandFrequency {
+    /// Reads the counts saved with [`CommandFrequency::save`], a missing file
+    /// has no counts.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut frequency = Self::default();
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(frequency),
+            Err(err) => return Err(err),
+        };
+        for entry in text.lines() {
+            // skip what isn't understood instead of failing on a bad file
+            let Some((count, line)) = entry.split_once('\t') else {
+                continue;
+            };
+            let Ok(count) = count.parse() else {
+                continue;
+            };
+            frequency.add_count(&unescape(line), count);
+        }
+        Ok(frequency)
+    }
+
+    /// Writes the counts of the lines that ran most recently. Like the
+    /// history, the file has the command lines, so only the user can read it.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut lines: Vec<_> = self.lines.iter().collect();
+        lines.sort_by_key(|(_, line_use)| line_use.last_run);
+        let skip = lines.len().saturating_sub(MAX_LINES);
+        let text: String = lines[skip..]
+            .iter()
+            .map(|(line, line_use)| format!("{}\t{}\n", line_use.count, escape(line)))
+            .collect();
+        // a temporary file is only readable by the user, it also replaces
+        // a file that was readable by others
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let mut file = tempfile::NamedTempFile::new_in(dir)?;
+        file.write_all(text.as_bytes())?;
+        file.persist(path).map_err(|err| err.error)?;
+        Ok(())
+    }
+
+    /// Records that `line` ran.
+    pub fn add(&mut self, line: &str) {
+        self.add_count(line, 1);
+    }
+
+    fn add_count(&mut self, line: &str, count: usize) {
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+        self.clock += 1;
+        let line_use = self.lines.entry(line.to_string()).or_insert(LineUse {
+            count: 0,
+            last_run: 0,
+        });
+        line_use.count += count;
+        line_use.last_run = self.clock;
+        if let Some(name) = command_name(line) {
+            *self.commands.entry(name.to_string()).or_default() += count;
+        }
+    }
+
+    /// How often a line starting with the command `name` ran.
+    pub fn command_count(&self, name: &str) -> usize {
+        self.commands.get(name).copied().unwrap_or(0)
+    }
+
+    /// The line starting with `prefix` that ran most often, or most recently
+    /// of the ones that ran equally often.
+    pub fn suggestion(&self, prefix: &str) -> Option<&str> {
+        self.lines
+            .iter()
+            .filter(|(line, _)| line.len() > prefix.len() && line.starts_with(prefix))
+            .max_by_key(|(_, line_use)| (line_use.count, line_use.last_run))
+            .map(|(line, _)| line.as_str())
+    }
+}
+
+/// The command that a line runs, after the variable assignments before it.
+fn command_name(line: &str) -> Option<&str> {
+    line.split_whitespace().find(|word| !is_assignment(word))
+}
+
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        !name.is_empty()
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+/// Keeps a multi-line command on one line of the file.
+fn escape(line: &str) -> String {
+    line.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(line: &str) -> String {
+    let mut unescaped = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts() {
+        let mut frequency = CommandFrequency::default();
+        frequency.add("git status");
+        frequency.add("cargo test");
+        frequency.add("git commit");
+        frequency.add("  cargo test ");
+        frequency.add("RUST_LOG=debug cargo build");
+        frequency.add("");
+
+        assert_eq!(frequency.command_count("cargo"), 3);
+        assert_eq!(frequency.command_count("git"), 2);
+        assert_eq!(frequency.command_count("RUST_LOG=debug"), 0);
+        assert_eq!(frequency.command_count("ls"), 0);
+
+        assert_eq!(frequency.suggestion("ca"), Some("cargo test"));
+        // the most recent of the lines that ran equally often
+        assert_eq!(frequency.suggestion("git "), Some("git commit"));
+        assert_eq!(frequency.suggestion("cargo test"), None);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("shell-test-counts-{}", std::process::id()));
+        let mut frequency = CommandFrequency::default();
+        frequency.add("ls");
+        frequency.add("echo 'a\\b'\necho c");
+        frequency.add("ls");
+        frequency.save(&path).unwrap();
+
+        let loaded = CommandFrequency::load(&path).unwrap();
+        assert_eq!(loaded.command_count("ls"), 2);
+        assert_eq!(loaded.command_count("echo"), 1);
+        assert_eq!(loaded.suggestion("echo"), Some("echo 'a\\b'\necho c"));
+        // the last run is kept, so ties still go to the most recent line
+        assert_eq!(loaded.lines["ls"].last_run, 2);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(CommandFrequency::load(&path).unwrap().lines.len(), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_save_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("counts");
+        fs::write(&path, "").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        let mut frequency = CommandFrequency::default();
+        frequency.add("export TOKEN=secret");
+        frequency.save(&path).unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(CommandFrequency::load(&path).unwrap().lines.len(), 1);
+    }
+}
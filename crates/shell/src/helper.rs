@@ -1,43 +1,599 @@
+use deno_task_shell::parser;
+use deno_task_shell::CompletionSpec;
 use rustyline::{
-    highlight::Highlighter, validate::MatchingBracketValidator, Completer, Helper, Hinter,
-    Validator,
+    completion::Completer,
+    highlight::Highlighter,
+    hint::{Hinter, HistoryHinter},
+    validate::{ValidationContext, ValidationResult, Validator},
+    Cmd, CompletionType, ConditionalEventHandler, EditMode, Event, EventContext, Helper, Hinter,
+    InputMode, KeyCode, KeyEvent, Modifiers, RepeatCount,
 };
 
+use crate::abbreviations;
+use crate::colors::Colors;
 use crate::completion;
+use crate::frequency::CommandFrequency;
+use crate::highlight;
 
-use std::borrow::Cow::Borrowed;
+use std::borrow::Cow::{self, Borrowed, Owned};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
-#[derive(Helper, Completer, Hinter, Validator)]
+#[derive(Helper, Hinter)]
 pub(crate) struct ShellPromptHelper {
-    #[rustyline(Completer)]
     completer: completion::ShellCompleter,
 
-    #[rustyline(Validator)]
-    validator: MatchingBracketValidator,
+    #[rustyline(Hinter)]
+    hinter: FrequencyHinter,
+
+    /// How often each command was run, see [`CommandFrequency`]
+    pub frequency: Arc<Mutex<CommandFrequency>>,
 
     pub colored_prompt: String,
+
+    /// Whether the editor uses vi key bindings, for the `{vi_mode}` placeholder
+    pub vi_mode: bool,
+
+    /// Whether vi mode is in normal mode (as opposed to insert mode), kept up
+    /// to date by [`ViModeTracker`]
+    pub vi_normal_mode: Arc<AtomicBool>,
+
+    /// The last arguments of the previous commands, for [`YankLastArg`]
+    pub last_args: Arc<Mutex<LastArgs>>,
+
+    /// Text that the next completion replaces the text between the given
+    /// position and the cursor with, see [`PendingReplacement`]
+    pub pending_replacement: PendingReplacement,
+
+    /// The `$VISUAL` or `$EDITOR` of the shell, for [`EditCommandLine`]
+    pub editor: Arc<Mutex<Option<String>>>,
+
+    /// The text from the editor that runs instead of the accepted line, set
+    /// by [`EditCommandLine`]
+    pub edited_line: Arc<Mutex<Option<String>>>,
+
+    /// The configured colors of the hints, completions and highlighting
+    pub colors: Colors,
+
+    /// The aliases of the shell, to find unknown commands
+    aliases: HashSet<String>,
+    /// Whether the command names looked up so far are known, kept until
+    /// `PATH` or the aliases may have changed
+    known_commands: Mutex<HashMap<String, bool>>,
 }
 
 impl Default for ShellPromptHelper {
     fn default() -> Self {
+        let completer = completion::ShellCompleter::default();
+        let frequency = completer.frequency().clone();
         Self {
-            completer: completion::ShellCompleter,
-            validator: MatchingBracketValidator::new(),
+            completer,
+            hinter: FrequencyHinter(frequency.clone()),
+            frequency,
             colored_prompt: String::new(),
+            vi_mode: false,
+            vi_normal_mode: Arc::new(AtomicBool::new(false)),
+            last_args: Arc::default(),
+            pending_replacement: Arc::default(),
+            editor: Arc::default(),
+            edited_line: Arc::default(),
+            colors: Colors::default(),
+            aliases: HashSet::new(),
+            known_commands: Mutex::default(),
+        }
+    }
+}
+
+/// The reserved words and the test brackets, which are part of the syntax
+/// and highlighted like commands.
+const RESERVED_WORDS: [&str; 19] = [
+    "if", "then", "else", "elif", "fi", "do", "done", "case", "esac", "while", "until", "for",
+    "select", "in", "{", "}", "!", "[", "[[",
+];
+
+impl ShellPromptHelper {
+    /// Updates the `PATH` and aliases that commands are looked up in, before
+    /// reading a line.
+    pub fn set_command_lookup(&mut self, path: String, aliases: &HashMap<String, Vec<String>>) {
+        self.aliases = aliases.keys().cloned().collect();
+        self.known_commands.get_mut().unwrap().clear();
+        self.completer.set_path(path);
+        self.completer.set_aliases(aliases);
+    }
+
+    /// Turns fuzzy completion on or off, before reading a line.
+    pub fn set_fuzzy_complete(&mut self, fuzzy: bool) {
+        self.completer.set_fuzzy(fuzzy);
+    }
+
+    /// Updates the completions defined with `complete`, before reading a line.
+    pub fn set_completions(&mut self, completions: HashMap<String, CompletionSpec>) {
+        self.completer.set_completions(completions);
+    }
+
+    /// Whether `name` is a reserved word, alias, built-in or executable.
+    fn is_command(&self, name: &str) -> bool {
+        let mut known_commands = self.known_commands.lock().unwrap();
+        if let Some(&known) = known_commands.get(name) {
+            return known;
+        }
+        let known = RESERVED_WORDS.contains(&name)
+            || self.aliases.contains(name)
+            || if name.contains('/') {
+                Path::new(name).is_file()
+            } else {
+                self.completer.is_command(name)
+            };
+        known_commands.insert(name.to_string(), known);
+        known
+    }
+}
+
+impl Validator for ShellPromptHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        // keep editing on a new line while the command is unfinished, syntax
+        // errors are reported when the command is run
+        if parser::is_incomplete(ctx.input()) {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
         }
     }
 }
 
 impl Highlighter for ShellPromptHelper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        if !self.colors.enabled {
+            return Borrowed(line);
+        }
+        highlight::highlight(line, pos, &self.colors.highlight, |name| {
+            self.is_command(name)
+        })
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, _forced: bool) -> bool {
+        // any keystroke can change the highlighting of the rest of the line
+        // (e.g. typing a quote), so always redraw it
+        true
+    }
+
     fn highlight_prompt<'b, 's: 'b, 'p: 'b>(
         &'s self,
         prompt: &'p str,
         default: bool,
-    ) -> std::borrow::Cow<'b, str> {
+    ) -> Cow<'b, str> {
         if default {
-            Borrowed(&self.colored_prompt)
+            let indicator = match (self.vi_mode, self.vi_normal_mode.load(Ordering::Relaxed)) {
+                (false, _) => "",
+                (true, true) => "[N]",
+                (true, false) => "[I]",
+            };
+            Owned(self.colored_prompt.replace("{vi_mode}", indicator))
         } else {
             Borrowed(prompt)
         }
     }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Owned(Colors::paint(&self.colors.hint, hint))
+    }
+
+    fn highlight_candidate<'c>(&self, candidate: &'c str, _: CompletionType) -> Cow<'c, str> {
+        if self.colors.completion.is_empty() {
+            Borrowed(candidate)
+        } else {
+            Owned(Colors::paint(&self.colors.completion, candidate))
+        }
+    }
+}
+
+/// Suggests the line starting with the typed text that was run most often,
+/// or the most recent one from the history if none was counted.
+pub(crate) struct FrequencyHinter(Arc<Mutex<CommandFrequency>>);
+
+impl Hinter for FrequencyHinter {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, ctx: &rustyline::Context<'_>) -> Option<String> {
+        if line.is_empty() || pos < line.len() {
+            return None;
+        }
+        match self.0.lock().unwrap().suggestion(line) {
+            Some(suggestion) => Some(suggestion[pos..].to_string()),
+            None => HistoryHinter::new().hint(line, pos, ctx),
+        }
+    }
+}
+
+/// Accepts the whole autosuggestion, bound to End (Right arrow already does
+/// this by default).
+pub(crate) struct AcceptHint;
+
+impl ConditionalEventHandler for AcceptHint {
+    fn handle(&self, _: &Event, _: RepeatCount, _: bool, ctx: &EventContext) -> Option<Cmd> {
+        (ctx.has_hint() && ctx.pos() == ctx.line().len()).then_some(Cmd::CompleteHint)
+    }
+}
+
+/// Accepts the next word of the autosuggestion, bound to Alt-Right.
+pub(crate) struct AcceptHintWord;
+
+impl ConditionalEventHandler for AcceptHintWord {
+    fn handle(&self, _: &Event, _: RepeatCount, _: bool, ctx: &EventContext) -> Option<Cmd> {
+        if ctx.pos() != ctx.line().len() {
+            return None;
+        }
+        let word = next_word(ctx.hint_text()?);
+        (!word.is_empty()).then(|| Cmd::Insert(1, word.to_string()))
+    }
+}
+
+/// The last argument of every command in the history, oldest first.
+#[derive(Default)]
+pub(crate) struct LastArgs {
+    args: Vec<String>,
+    /// The previous insertion, if the line wasn't changed since
+    last_yank: Option<LastYank>,
+}
+
+struct LastYank {
+    /// The line and cursor position right after the insertion
+    line: String,
+    pos: usize,
+    /// How many commands back the inserted argument is from
+    back: usize,
+    /// The inserted argument
+    text: String,
+}
+
+impl LastArgs {
+    /// Records the last argument of a command added to the history.
+    pub fn add(&mut self, line: &str) {
+        if let Some(arg) = last_arg(line) {
+            self.args.push(arg.to_string());
+        }
+    }
+
+    /// Inserts the last argument of the previous command, or replaces the
+    /// one inserted right before with that of the command before it. Returns
+    /// the start of the text to replace up to the cursor and the argument.
+    fn yank(&mut self, line: &str, pos: usize) -> Option<(usize, String)> {
+        // any other key changes the line or moves the cursor, which starts
+        // over from the previous command
+        let previous = self
+            .last_yank
+            .take()
+            .filter(|yank| yank.line == line && yank.pos == pos);
+        let back = previous.as_ref().map_or(0, |yank| yank.back + 1);
+        let Some(text) = self.args.iter().rev().nth(back).cloned() else {
+            // there is nothing older, keep what was inserted
+            self.last_yank = previous;
+            return None;
+        };
+        let start = previous.map_or(pos, |previous| pos - previous.text.len());
+        self.last_yank = Some(LastYank {
+            line: format!("{}{text}{}", &line[..start], &line[pos..]),
+            pos: start + text.len(),
+            back,
+            text: text.clone(),
+        });
+        Some((start, text))
+    }
+}
+
+/// Inserts the last argument of the previous command, bound to Alt-. by
+/// default. Pressing it again cycles through the older commands.
+pub(crate) struct YankLastArg(pub Arc<Mutex<LastArgs>>, pub PendingReplacement);
+
+impl ConditionalEventHandler for YankLastArg {
+    fn handle(&self, _: &Event, _: RepeatCount, _: bool, ctx: &EventContext) -> Option<Cmd> {
+        let mut last_args = self.0.lock().unwrap();
+        let Some((start, text)) = last_args.yank(ctx.line(), ctx.pos()) else {
+            return Some(Cmd::Noop);
+        };
+        if start == ctx.pos() {
+            return Some(Cmd::Insert(1, text));
+        }
+        *self.1.lock().unwrap() = Some((start, text));
+        Some(Cmd::Complete)
+    }
+}
+
+/// A replacement of the text from a position up to the cursor.
+///
+/// rustyline has no command that replaces text and moves the cursor after
+/// it, besides completing a single candidate. So handlers store the
+/// replacement here and return [`Cmd::Complete`], which then applies it.
+pub(crate) type PendingReplacement = Arc<Mutex<Option<(usize, String)>>>;
+
+impl Completer for ShellPromptHelper {
+    type Candidate = completion::ShellCandidate;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Self::Candidate>)> {
+        if let Some((start, text)) = self.pending_replacement.lock().unwrap().take() {
+            return Ok((
+                start,
+                vec![completion::ShellCandidate::new(text.clone(), text)],
+            ));
+        }
+        self.completer.complete(line, pos, ctx)
+    }
+}
+
+/// Expands the abbreviation before the cursor, bound to space. The space is
+/// inserted as usual otherwise.
+pub(crate) struct ExpandAbbreviation(pub PendingReplacement);
+
+impl ConditionalEventHandler for ExpandAbbreviation {
+    fn handle(&self, _: &Event, _: RepeatCount, _: bool, ctx: &EventContext) -> Option<Cmd> {
+        if ctx.mode() == EditMode::Vi && ctx.input_mode() != InputMode::Insert {
+            return None;
+        }
+        let (start, expansion) = abbreviations::expansion_at(ctx.line(), ctx.pos())?;
+        *self.0.lock().unwrap() = Some((start, format!("{expansion} ")));
+        Some(Cmd::Complete)
+    }
+}
+
+/// The keys that open the current line in the editor.
+pub(crate) const EDIT_COMMAND_LINE_KEYS: [KeyEvent; 2] = [
+    KeyEvent(KeyCode::Char('X'), Modifiers::CTRL),
+    KeyEvent(KeyCode::Char('E'), Modifiers::CTRL),
+];
+
+/// Opens the current line in `$VISUAL` or `$EDITOR` (`vi` if neither is set),
+/// bound to Ctrl-X Ctrl-E. The edited text runs as if it was typed, and the
+/// line is kept as it was if the editor fails or the text wasn't changed.
+pub(crate) struct EditCommandLine {
+    pub editor: Arc<Mutex<Option<String>>>,
+    /// Where the edited text is left for the REPL, rustyline can't replace
+    /// the line and accept it with one command
+    pub edited_line: Arc<Mutex<Option<String>>>,
+    /// The terminal settings outside of the line editor, which runs the
+    /// terminal in raw mode
+    #[cfg(unix)]
+    terminal: Option<libc::termios>,
+}
+
+impl EditCommandLine {
+    /// Must be created before reading a line, so the terminal is still in
+    /// its normal mode.
+    pub fn new(
+        editor: Arc<Mutex<Option<String>>>,
+        edited_line: Arc<Mutex<Option<String>>>,
+    ) -> Self {
+        Self {
+            editor,
+            edited_line,
+            #[cfg(unix)]
+            terminal: terminal_mode(),
+        }
+    }
+}
+
+impl ConditionalEventHandler for EditCommandLine {
+    fn handle(&self, _: &Event, _: RepeatCount, _: bool, ctx: &EventContext) -> Option<Cmd> {
+        let editor = self.editor.lock().unwrap().clone();
+        let editor = editor.as_deref().filter(|editor| !editor.trim().is_empty());
+        let editor = editor.unwrap_or("vi");
+        #[cfg(unix)]
+        let raw_mode = terminal_mode();
+        #[cfg(unix)]
+        set_terminal_mode(self.terminal);
+        let edited = edit_line(editor, ctx.line());
+        #[cfg(unix)]
+        set_terminal_mode(raw_mode);
+        Some(match edited {
+            Some(line) if line != ctx.line() => {
+                *self.edited_line.lock().unwrap() = Some(line);
+                Cmd::AcceptLine
+            }
+            _ => Cmd::Repaint,
+        })
+    }
+}
+
+#[cfg(unix)]
+fn terminal_mode() -> Option<libc::termios> {
+    let mut termios = std::mem::MaybeUninit::<libc::termios>::zeroed();
+    // SAFETY: `termios` is valid for writes and only read if the call succeeds
+    unsafe {
+        (libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) == 0)
+            .then(|| termios.assume_init())
+    }
+}
+
+#[cfg(unix)]
+fn set_terminal_mode(termios: Option<libc::termios>) {
+    if let Some(termios) = termios {
+        // SAFETY: `termios` was filled in by `tcgetattr`
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &termios);
+        }
+    }
+}
+
+/// Runs `editor` on a temporary file holding `line` and returns the edited
+/// text, or `None` if the editor couldn't be run or failed.
+fn edit_line(editor: &str, line: &str) -> Option<String> {
+    let mut args = editor.split_whitespace();
+    let program = args.next()?;
+    // only the user can read the file, which is removed when it's dropped
+    let mut file = tempfile::Builder::new()
+        .prefix("shell-edit-")
+        .suffix(".sh")
+        .tempfile()
+        .ok()?;
+    writeln!(file, "{line}").ok()?;
+    let status = std::process::Command::new(program)
+        .args(args)
+        .arg(file.path())
+        .status();
+    let edited = match status {
+        Ok(status) if status.success() => std::fs::read_to_string(file.path()).ok(),
+        _ => None,
+    };
+    // editors end the file with a newline, which would run the command
+    Some(edited?.trim_end_matches(['\n', '\r']).to_string())
+}
+
+/// Keys that switch between vi insert and normal mode.
+pub(crate) const VI_MODE_KEYS: [KeyEvent; 9] = [
+    KeyEvent(KeyCode::Esc, Modifiers::NONE),
+    KeyEvent(KeyCode::Char('i'), Modifiers::NONE),
+    KeyEvent(KeyCode::Char('I'), Modifiers::NONE),
+    KeyEvent(KeyCode::Char('a'), Modifiers::NONE),
+    KeyEvent(KeyCode::Char('A'), Modifiers::NONE),
+    KeyEvent(KeyCode::Char('s'), Modifiers::NONE),
+    KeyEvent(KeyCode::Char('S'), Modifiers::NONE),
+    KeyEvent(KeyCode::Char('c'), Modifiers::NONE),
+    KeyEvent(KeyCode::Char('C'), Modifiers::NONE),
+];
+
+/// Records vi mode switches for the prompt, bound to [`VI_MODE_KEYS`].
+/// rustyline has no hook for mode changes, so this watches the keys that
+/// cause them and then lets rustyline handle the key as usual.
+pub(crate) struct ViModeTracker(pub Arc<AtomicBool>);
+
+impl ConditionalEventHandler for ViModeTracker {
+    fn handle(&self, evt: &Event, _: RepeatCount, _: bool, ctx: &EventContext) -> Option<Cmd> {
+        if ctx.mode() != EditMode::Vi {
+            return None;
+        }
+        let normal_mode = match (ctx.input_mode(), evt.get(0)) {
+            (InputMode::Insert | InputMode::Replace, Some(KeyEvent(KeyCode::Esc, _))) => true,
+            (InputMode::Command, Some(KeyEvent(KeyCode::Char(_), _))) => false,
+            _ => return None,
+        };
+        self.0.store(normal_mode, Ordering::Relaxed);
+        None
+    }
+}
+
+/// The leading whitespace and the first word of `text`.
+fn next_word(text: &str) -> &str {
+    let start = text.len() - text.trim_start().len();
+    let end = text[start..]
+        .find(char::is_whitespace)
+        .map_or(text.len(), |i| start + i);
+    &text[..end]
+}
+
+/// The last word of a command line as it was written, where quoted text
+/// and escaped spaces belong to the word.
+fn last_arg(line: &str) -> Option<&str> {
+    let mut last = None;
+    let mut start = None;
+    let mut quote = None;
+    let mut chars = line.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') => {
+                chars.next();
+            }
+            (Some(_), _) => {}
+            (None, c) if c.is_whitespace() => {
+                if let Some(start) = start.take() {
+                    last = Some(&line[start..i]);
+                }
+            }
+            (None, c) => {
+                start.get_or_insert(i);
+                match c {
+                    '\'' | '"' => quote = Some(c),
+                    '\\' => {
+                        chars.next();
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    start.map(|start| &line[start..]).or(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_word() {
+        assert_eq!(next_word("cho hello world"), "cho");
+        assert_eq!(next_word(" hello world"), " hello");
+        assert_eq!(next_word("  world"), "  world");
+        assert_eq!(next_word(""), "");
+    }
+
+    #[test]
+    fn test_last_arg() {
+        assert_eq!(last_arg("ls -la src"), Some("src"));
+        assert_eq!(last_arg("cp a \"my file.txt\"  "), Some("\"my file.txt\""));
+        assert_eq!(last_arg("cat 'a b'/c"), Some("'a b'/c"));
+        assert_eq!(last_arg("vim my\\ file"), Some("my\\ file"));
+        assert_eq!(last_arg("echo \"a \\\" b\""), Some("\"a \\\" b\""));
+        assert_eq!(
+            last_arg("echo 'unterminated quote"),
+            Some("'unterminated quote")
+        );
+        assert_eq!(last_arg("pwd"), Some("pwd"));
+        assert_eq!(last_arg("  "), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_edit_line() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let editor = std::env::temp_dir().join(format!("shell-test-editor-{}", std::process::id()));
+        std::fs::write(
+            &editor,
+            "#!/bin/sh\n[ \"$(cat \"$1\")\" = 'ls -la' ] && printf 'ls\\n-la\\n\\n' > \"$1\"\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&editor, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let editor = editor.to_str().unwrap();
+
+        assert_eq!(edit_line(editor, "ls -la"), Some("ls\n-la".to_string()));
+        // the editor fails for any other line
+        assert_eq!(edit_line(editor, "pwd"), None);
+        assert_eq!(edit_line("shell-no-such-editor", "pwd"), None);
+        std::fs::remove_file(editor).unwrap();
+    }
+
+    #[test]
+    fn test_yank_last_arg() {
+        let mut last_args = LastArgs::default();
+        last_args.add("cd /tmp");
+        last_args.add("cp a \"b c\"");
+        last_args.add("");
+
+        assert_eq!(last_args.yank("ls ", 3), Some((3, "\"b c\"".to_string())));
+        // pressing it again replaces the argument with the older one
+        assert_eq!(
+            last_args.yank("ls \"b c\"", 8),
+            Some((3, "/tmp".to_string()))
+        );
+        // there is nothing older
+        assert_eq!(last_args.yank("ls /tmp", 7), None);
+        assert_eq!(
+            last_args.yank("ls /tmp", 7),
+            None,
+            "the cycle is kept at the oldest argument"
+        );
+
+        // after another key, it starts over from the previous command
+        assert_eq!(
+            last_args.yank("ls /tmp ", 8),
+            Some((8, "\"b c\"".to_string()))
+        );
+    }
 }
@@ -0,0 +1,598 @@
+//! Live syntax highlighting of the command line.
+//!
+//! The line is scanned with a small hand written lexer instead of the pest
+//! grammar: it runs on every keystroke and must cope with incomplete input,
+//! such as an unterminated quote, which the parser would reject outright.
+//!
+//! On top of that, the bracket or quote matching the one at the cursor is
+//! highlighted, and closing brackets without an opener are shown in red.
+//! So are command names that don't resolve to a command. The colors come
+//! from a [`HighlightTheme`].
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+use crate::colors::HighlightTheme;
+
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Style {
+    Command,
+    /// A command name that is not a built-in, alias or executable
+    UnknownCommand,
+    String,
+    /// An argument starting with `-`
+    Flag,
+    Operator,
+    Variable,
+    Comment,
+    /// The partner of the bracket or quote at the cursor
+    Matching,
+    /// A closing bracket without an opener
+    Unmatched,
+}
+
+impl Style {
+    fn color(self, theme: &HighlightTheme) -> &str {
+        match self {
+            Style::Command => &theme.command,
+            Style::UnknownCommand | Style::Unmatched => &theme.error,
+            Style::String => &theme.string,
+            Style::Flag => &theme.flag,
+            Style::Operator => &theme.operator,
+            Style::Variable => &theme.variable,
+            Style::Comment => &theme.comment,
+            Style::Matching => &theme.matching,
+        }
+    }
+}
+
+/// Returns the line with ANSI color codes inserted, or the line itself if
+/// there is nothing to highlight. `pos` is the position of the cursor, and
+/// `is_command` tells whether a command name resolves to a command.
+pub fn highlight<'l>(
+    line: &'l str,
+    pos: usize,
+    theme: &HighlightTheme,
+    is_command: impl Fn(&str) -> bool,
+) -> Cow<'l, str> {
+    let mut spans = Scanner::new(line).scan();
+    for (range, style) in &mut spans {
+        if *style == Style::Command
+            && is_plain_word(line, range)
+            && !is_command(&line[range.clone()])
+        {
+            *style = Style::UnknownCommand;
+        }
+    }
+    let brackets = find_brackets(line);
+    let mut marks = brackets
+        .unmatched
+        .iter()
+        .map(|&index| (index, Style::Unmatched))
+        .collect::<Vec<_>>();
+    if let Some(index) = brackets.partner(pos) {
+        marks.push((index, Style::Matching));
+    }
+    if !marks.is_empty() {
+        spans = overlay(line.len(), spans, &marks);
+    }
+    if spans.is_empty() {
+        return Cow::Borrowed(line);
+    }
+
+    let mut output = String::with_capacity(line.len() + spans.len() * 10);
+    let mut last = 0;
+    for (range, style) in spans {
+        output.push_str(&line[last..range.start]);
+        let color = style.color(theme);
+        if color.is_empty() {
+            // turned off in the theme
+            output.push_str(&line[range.clone()]);
+        } else {
+            output.push_str(color);
+            output.push_str(&line[range.clone()]);
+            output.push_str(RESET);
+        }
+        last = range.end;
+    }
+    output.push_str(&line[last..]);
+    Cow::Owned(output)
+}
+
+/// Gives the single bytes in `marks` their own style, on top of the spans.
+fn overlay(
+    len: usize,
+    spans: Vec<(Range<usize>, Style)>,
+    marks: &[(usize, Style)],
+) -> Vec<(Range<usize>, Style)> {
+    let mut styles = vec![None; len];
+    for (range, style) in spans {
+        styles[range].fill(Some(style));
+    }
+    for &(index, style) in marks {
+        styles[index] = Some(style);
+    }
+    let mut result: Vec<(Range<usize>, Style)> = Vec::new();
+    for (index, style) in styles.into_iter().enumerate() {
+        let Some(style) = style else {
+            continue;
+        };
+        match result.last_mut() {
+            Some((range, last)) if range.end == index && *last == style => range.end += 1,
+            _ => result.push((index..index + 1, style)),
+        }
+    }
+    result
+}
+
+/// The brackets and quotes of a line that belong together.
+#[derive(Debug, Default, PartialEq)]
+struct Brackets {
+    /// The positions of each opener and its closer
+    pairs: Vec<(usize, usize)>,
+    /// The positions of closing brackets without an opener
+    unmatched: Vec<usize>,
+}
+
+impl Brackets {
+    /// The partner of the bracket or quote at `pos`, or else of the one
+    /// right before it.
+    fn partner(&self, pos: usize) -> Option<usize> {
+        let partner_of = |index: usize| {
+            self.pairs.iter().find_map(|&(open, close)| {
+                if open == index {
+                    Some(close)
+                } else if close == index {
+                    Some(open)
+                } else {
+                    None
+                }
+            })
+        };
+        partner_of(pos).or_else(|| pos.checked_sub(1).and_then(partner_of))
+    }
+}
+
+/// Pairs up the `()`, `{}`, `[]` and quotes of a line. Brackets inside
+/// quotes don't count, except for the `$(` and `${` that start a nested
+/// command or variable within double quotes.
+fn find_brackets(line: &str) -> Brackets {
+    let bytes = line.as_bytes();
+    let mut brackets = Brackets::default();
+    // the brackets and double quotes that are still open, innermost last
+    let mut open: Vec<(usize, u8)> = Vec::new();
+    let mut i = 0;
+    while let Some(&c) = bytes.get(i) {
+        let in_quotes = open.last().is_some_and(|&(_, c)| c == b'"');
+        match c {
+            b'\\' => i += 1,
+            b'"' if in_quotes => {
+                let (start, _) = open.pop().unwrap();
+                brackets.pairs.push((start, i));
+            }
+            b'"' => open.push((i, c)),
+            b'$' if in_quotes && matches!(bytes.get(i + 1), Some(b'(' | b'{')) => {
+                i += 1;
+                open.push((i, bytes[i]));
+            }
+            _ if in_quotes => {}
+            b'\'' => match bytes[i + 1..].iter().position(|&c| c == b'\'') {
+                Some(len) => {
+                    brackets.pairs.push((i, i + 1 + len));
+                    i += 1 + len;
+                }
+                // the rest of the line is quoted
+                None => break,
+            },
+            b'#' if i == 0 || bytes[i - 1].is_ascii_whitespace() => {
+                i = bytes[i..]
+                    .iter()
+                    .position(|&c| c == b'\n')
+                    .map_or(bytes.len(), |len| i + len);
+            }
+            b'(' | b'{' | b'[' => open.push((i, c)),
+            b')' | b'}' | b']' => {
+                let opener = match c {
+                    b')' => b'(',
+                    b'}' => b'{',
+                    _ => b'[',
+                };
+                match open.last() {
+                    Some(&(start, c)) if c == opener => {
+                        open.pop();
+                        brackets.pairs.push((start, i));
+                    }
+                    _ => brackets.unmatched.push(i),
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    brackets
+}
+
+/// Whether `range` is a whole word without quotes or escapes, whose text is
+/// the name of the command as is.
+fn is_plain_word(line: &str, range: &Range<usize>) -> bool {
+    let bytes = line.as_bytes();
+    let is_boundary = |c: u8| c.is_ascii_whitespace() || is_operator_char(c);
+    !line[range.clone()].contains('\\')
+        && (range.start == 0 || is_boundary(bytes[range.start - 1]))
+        && bytes.get(range.end).is_none_or(|&c| is_boundary(c))
+}
+
+fn is_operator_char(c: u8) -> bool {
+    matches!(c, b'|' | b'&' | b';' | b'<' | b'>' | b'(' | b')')
+}
+
+fn is_name_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
+}
+
+/// Only ASCII bytes are ever significant below, so every index a span
+/// starts or ends at is also a valid char boundary.
+struct Scanner<'a> {
+    line: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+    spans: Vec<(Range<usize>, Style)>,
+    /// Whether the next word is the name of a command.
+    command_position: bool,
+    /// Whether the next word is the target of a redirect.
+    redirect_target: bool,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(line: &'a str) -> Self {
+        Self {
+            line,
+            bytes: line.as_bytes(),
+            pos: 0,
+            spans: Vec::new(),
+            command_position: true,
+            redirect_target: false,
+        }
+    }
+
+    fn scan(mut self) -> Vec<(Range<usize>, Style)> {
+        while let Some(&c) = self.bytes.get(self.pos) {
+            if c == b'\n' {
+                self.command_position = true;
+                self.pos += 1;
+            } else if c.is_ascii_whitespace() {
+                self.pos += 1;
+            } else if c == b'#' {
+                let end = self.find(self.pos, |c| c == b'\n');
+                self.push(self.pos..end, Style::Comment);
+                self.pos = end;
+            } else if is_operator_char(c) {
+                self.operator();
+            } else if c == b'$' && self.bytes.get(self.pos + 1) == Some(&b'(') {
+                self.push(self.pos..self.pos + 2, Style::Operator);
+                self.pos += 2;
+                self.command_position = true;
+            } else {
+                self.word();
+            }
+        }
+        self.spans
+    }
+
+    fn push(&mut self, range: Range<usize>, style: Style) {
+        if range.is_empty() {
+            return;
+        }
+        // merge adjacent spans to keep the escape codes to a minimum
+        if let Some((last, last_style)) = self.spans.last_mut() {
+            if last.end == range.start && *last_style == style {
+                last.end = range.end;
+                return;
+            }
+        }
+        self.spans.push((range, style));
+    }
+
+    fn find(&self, from: usize, pred: impl Fn(u8) -> bool) -> usize {
+        self.bytes[from..]
+            .iter()
+            .position(|&c| pred(c))
+            .map_or(self.bytes.len(), |i| from + i)
+    }
+
+    /// The end of the backslash escape at the current position.
+    fn escape_end(&self) -> usize {
+        let escaped = self.line[self.pos + 1..].chars().next();
+        self.pos + 1 + escaped.map_or(0, char::len_utf8)
+    }
+
+    fn operator(&mut self) {
+        let start = self.pos;
+        let c = self.bytes[start];
+        self.pos += 1;
+        if let Some(&next) = self.bytes.get(self.pos) {
+            // `&&`, `||`, `>>`, `<<`, `&>`, `>&` and `|&`
+            let pair = matches!(
+                (c, next),
+                (b'&', b'&')
+                    | (b'|', b'|')
+                    | (b'>', b'>')
+                    | (b'<', b'<')
+                    | (b'&', b'>')
+                    | (b'>', b'&')
+                    | (b'|', b'&')
+            );
+            if pair {
+                self.pos += 1;
+            }
+        }
+        self.push(start..self.pos, Style::Operator);
+
+        let text = &self.bytes[start..self.pos];
+        if text.contains(&b'<') || text.contains(&b'>') {
+            self.redirect_target = true;
+        } else if c != b')' {
+            self.command_position = true;
+        }
+    }
+
+    fn word(&mut self) {
+        let start = self.pos;
+        let style = if self.redirect_target {
+            None
+        } else if self.command_position {
+            Some(Style::Command)
+        } else if self.bytes[start] == b'-' {
+            Some(Style::Flag)
+        } else {
+            None
+        };
+        let is_assignment = self.command_position && self.is_assignment();
+
+        while let Some(&c) = self.bytes.get(self.pos) {
+            if c.is_ascii_whitespace() || is_operator_char(c) {
+                break;
+            }
+            match c {
+                b'\'' => {
+                    let end = self.find(self.pos + 1, |c| c == b'\'');
+                    let end = (end + 1).min(self.bytes.len());
+                    self.push(self.pos..end, Style::String);
+                    self.pos = end;
+                }
+                b'"' => self.double_quoted(),
+                b'$' if self.bytes.get(self.pos + 1) == Some(&b'(') => break,
+                b'$' => self.variable(),
+                b'\\' => {
+                    let end = self.escape_end();
+                    self.plain(self.pos..end, style, is_assignment);
+                    self.pos = end;
+                }
+                _ => {
+                    self.plain(self.pos..self.pos + 1, style, is_assignment);
+                    self.pos += 1;
+                }
+            }
+        }
+
+        if self.redirect_target {
+            self.redirect_target = false;
+        } else if !is_assignment && self.pos > start {
+            self.command_position = false;
+        }
+    }
+
+    fn plain(&mut self, range: Range<usize>, style: Option<Style>, is_assignment: bool) {
+        if let (Some(style), false) = (style, is_assignment) {
+            self.push(range, style);
+        }
+    }
+
+    /// Whether the word at the current position looks like `NAME=value`.
+    fn is_assignment(&self) -> bool {
+        let rest = &self.bytes[self.pos..];
+        let name_len = rest.iter().take_while(|&&c| is_name_char(c)).count();
+        name_len > 0 && !rest[0].is_ascii_digit() && rest.get(name_len) == Some(&b'=')
+    }
+
+    fn double_quoted(&mut self) {
+        let mut segment_start = self.pos;
+        self.pos += 1;
+        while let Some(&c) = self.bytes.get(self.pos) {
+            match c {
+                b'"' => {
+                    self.pos += 1;
+                    break;
+                }
+                b'\\' => self.pos = self.escape_end(),
+                b'$' if self
+                    .bytes
+                    .get(self.pos + 1)
+                    .is_some_and(|&c| c != b'(' && c != b'"') =>
+                {
+                    self.push(segment_start..self.pos, Style::String);
+                    self.variable();
+                    segment_start = self.pos;
+                }
+                _ => self.pos += 1,
+            }
+        }
+        self.push(segment_start..self.pos, Style::String);
+    }
+
+    fn variable(&mut self) {
+        let start = self.pos;
+        self.pos += 1;
+        match self.bytes.get(self.pos) {
+            Some(b'{') => {
+                let end = self.find(self.pos, |c| c == b'}');
+                self.pos = (end + 1).min(self.bytes.len());
+            }
+            Some(b'?' | b'#' | b'@' | b'*' | b'0'..=b'9') => self.pos += 1,
+            Some(_) => {
+                let len = self.bytes[self.pos..]
+                    .iter()
+                    .take_while(|&&c| is_name_char(c))
+                    .count();
+                self.pos += len;
+            }
+            None => {}
+        }
+        // a lone `$` is just text
+        if self.pos > start + 1 {
+            self.push(start..self.pos, Style::Variable);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn theme() -> HighlightTheme {
+        HighlightTheme::default()
+    }
+
+    fn styles(line: &str) -> Vec<(&str, Style)> {
+        Scanner::new(line)
+            .scan()
+            .into_iter()
+            .map(|(range, style)| (&line[range], style))
+            .collect()
+    }
+
+    #[test]
+    fn test_highlight_command_line() {
+        assert_eq!(
+            styles("echo \"hi $USER\" | grep -v x && ls > out.txt; # done"),
+            vec![
+                ("echo", Style::Command),
+                ("\"hi ", Style::String),
+                ("$USER", Style::Variable),
+                ("\"", Style::String),
+                ("|", Style::Operator),
+                ("grep", Style::Command),
+                ("-v", Style::Flag),
+                ("&&", Style::Operator),
+                ("ls", Style::Command),
+                (">", Style::Operator),
+                (";", Style::Operator),
+                ("# done", Style::Comment),
+            ]
+        );
+        assert_eq!(
+            styles("FOO=bar env $(which ls)"),
+            vec![
+                ("env", Style::Command),
+                ("$(", Style::Operator),
+                ("which", Style::Command),
+                (")", Style::Operator),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_highlight_incomplete_input() {
+        assert_eq!(
+            styles("echo 'unterminated $HOME"),
+            vec![
+                ("echo", Style::Command),
+                ("'unterminated $HOME", Style::String)
+            ]
+        );
+        assert_eq!(
+            styles("echo \"${FOO"),
+            vec![
+                ("echo", Style::Command),
+                ("\"", Style::String),
+                ("${FOO", Style::Variable),
+            ]
+        );
+        for line in ["\\", "$", "echo \"\\", "a |", "'", "ä $ö", "\\ä", "\"\\ö"] {
+            for pos in 0..=line.len() {
+                highlight(line, pos, &theme(), |_| true);
+            }
+        }
+        assert_eq!(
+            highlight("   ", 0, &theme(), |_| true),
+            Cow::Borrowed("   ")
+        );
+    }
+
+    #[test]
+    fn test_find_brackets() {
+        let line = "echo $(ls \"$(pwd)\" ')' [a]) {x}";
+        let brackets = find_brackets(line);
+        assert_eq!(
+            brackets.pairs,
+            vec![(12, 16), (10, 17), (19, 21), (23, 25), (6, 26), (28, 30)]
+        );
+        assert!(brackets.unmatched.is_empty());
+
+        // brackets in quotes, comments and escapes don't count
+        let brackets = find_brackets("echo ')' \"}\" \\] # (");
+        assert_eq!(brackets.pairs, vec![(5, 7), (9, 11)]);
+        assert!(brackets.unmatched.is_empty());
+
+        let brackets = find_brackets("echo (a] b) }");
+        assert_eq!(brackets.pairs, vec![(5, 10)]);
+        assert_eq!(brackets.unmatched, vec![7, 12]);
+
+        // unterminated quotes and openers are fine while typing
+        let brackets = find_brackets("echo \"$(ls 'a");
+        assert!(brackets.pairs.is_empty());
+        assert!(brackets.unmatched.is_empty());
+    }
+
+    #[test]
+    fn test_highlight_matching_bracket() {
+        let line = "echo $(ls)";
+        let matching = |pos| highlight(line, pos, &theme(), |_| true).into_owned();
+        let opener = format!(
+            "\x1b[36m$\x1b[0m{}({RESET}",
+            Style::Matching.color(&theme())
+        );
+        // the cursor right after the closer, or on it
+        assert!(matching(10).contains(&opener), "{:?}", matching(10));
+        assert!(matching(9).contains(&opener));
+        let closer = format!("{}){RESET}", Style::Matching.color(&theme()));
+        assert!(matching(6).contains(&closer));
+        assert!(!matching(3).contains(Style::Matching.color(&theme())));
+
+        let unmatched = highlight("ls)", 0, &theme(), |_| true);
+        assert!(unmatched.ends_with(&format!("{}){RESET}", Style::Unmatched.color(&theme()))));
+    }
+
+    #[test]
+    fn test_highlight_theme() {
+        let (theme, _) = HighlightTheme::parse("command=blue:operator=none");
+        assert_eq!(
+            highlight("ls -l | wc", 0, &theme, |_| true),
+            format!("\x1b[34mls{RESET} -l | \x1b[34mwc{RESET}")
+        );
+        let (theme, _) = HighlightTheme::parse("flag=cyan");
+        assert!(highlight("ls -l", 0, &theme, |_| true).ends_with(&format!(" \x1b[36m-l{RESET}")));
+    }
+
+    #[test]
+    fn test_highlight_unknown_command() {
+        let is_command = |name: &str| ["echo", "grep"].contains(&name);
+        let known = |name: &str| format!("{}{name}{RESET}", Style::Command.color(&theme()));
+        let unknown =
+            |name: &str| format!("{}{name}{RESET}", Style::UnknownCommand.color(&theme()));
+
+        let line = highlight("ech foo | grep x; FOO=1 nope", 0, &theme(), is_command);
+        assert!(line.starts_with(&unknown("ech")), "{line:?}");
+        assert!(line.contains(&known("grep")));
+        assert!(line.ends_with(&unknown("nope")));
+        assert!(highlight("echo", 4, &theme(), is_command).starts_with(&known("echo")));
+        // names that are only known once they are expanded
+        let line = highlight("e\\cho; \"ls\"", 0, &theme(), is_command);
+        assert!(
+            !line.contains(Style::UnknownCommand.color(&theme())),
+            "{line:?}"
+        );
+    }
+}
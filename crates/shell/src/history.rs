@@ -0,0 +1,70 @@
+//! The command history as seen by shell commands such as `fc`.
+//!
+//! The line editor owns the history, so the REPL mirrors every line it adds
+//! here. Commands can also ask for the line that ran them to be recorded as
+//! something else, like `fc` does with the commands it re-runs.
+
+use std::cell::RefCell;
+
+use deno_task_shell::pattern_matches;
+
+#[derive(Default)]
+struct History {
+    entries: Vec<String>,
+    /// What to record instead of the line that is running
+    replacement: Option<String>,
+}
+
+thread_local! {
+    // commands have no access to the editor, so the entries are kept here
+    static HISTORY: RefCell<History> = RefCell::default();
+}
+
+/// Adds an entry that the editor added to its history.
+pub fn add(entry: &str) {
+    HISTORY.with_borrow_mut(|history| history.entries.push(entry.to_string()));
+}
+
+/// All entries, the oldest first. The first one is number 1.
+pub fn entries() -> Vec<String> {
+    HISTORY.with_borrow(|history| history.entries.clone())
+}
+
+/// Records `entry` in the history instead of the line that is running.
+pub fn replace_current(entry: &str) {
+    HISTORY.with_borrow_mut(|history| history.replacement = Some(entry.to_string()));
+}
+
+/// Returns the entry set with [`replace_current`] since the last call.
+pub fn take_replacement() -> Option<String> {
+    HISTORY.with_borrow_mut(|history| history.replacement.take())
+}
+
+/// Whether `entry` matches one of the colon-separated glob `patterns` of
+/// `HISTIGNORE` and is left out of the history. An empty pattern matches
+/// nothing, so an empty `HISTIGNORE` ignores no lines.
+pub fn is_ignored(entry: &str, patterns: &str) -> bool {
+    let entry = entry.trim();
+    patterns
+        .split(':')
+        .any(|pattern| !pattern.is_empty() && pattern_matches(pattern, entry))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_ignored() {
+        let patterns = "ls:cd *:*SECRET*";
+        assert!(is_ignored("ls", patterns));
+        assert!(is_ignored("cd /tmp ", patterns));
+        assert!(is_ignored("export API_SECRET=x", patterns));
+        assert!(!is_ignored("ls -la", patterns));
+        assert!(!is_ignored("cd", patterns));
+        assert!(!is_ignored("ls", ""));
+        assert!(!is_ignored("ls", "::"));
+        // `[` without its `]` isn't a glob and only matches itself
+        assert!(is_ignored("[", "["));
+    }
+}
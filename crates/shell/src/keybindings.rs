@@ -0,0 +1,565 @@
+//! The key bindings of the line editor.
+//!
+//! Bindings map a key chord such as `ctrl-f` to an editor action or a named
+//! shell widget. They are read from `~/.shell_keybindings` (one `chord action`
+//! pair per line) and from `bind` commands, e.g. in `~/.shellrc`. Both also
+//! accept the readline syntax `"\C-f": accept-hint`, which is how `bind -p`
+//! prints the bindings. A later binding for the same chord replaces the
+//! earlier one.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::path::Path;
+
+use rustyline::{Anchor, At, Cmd, KeyCode, KeyEvent, Modifiers, Movement, Word};
+
+/// What a key chord does when pressed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// A built-in rustyline command
+    Editor(Cmd),
+    /// A shell widget, which may fall back to the default handling of the key
+    Widget(Widget),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Widget {
+    /// Accept the whole autosuggestion
+    AcceptHint,
+    /// Accept the next word of the autosuggestion
+    AcceptHintWord,
+    /// Insert the last argument of the previous command, older ones when
+    /// repeated
+    YankLastArg,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyBinding {
+    pub chord: KeyEvent,
+    pub action: Action,
+    /// The action as it was written, for listing the bindings
+    pub action_name: String,
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", format_chord(self.chord), self.action_name)
+    }
+}
+
+#[derive(Default)]
+struct KeyBindings {
+    bindings: Vec<KeyBinding>,
+    /// Whether there are bindings that the editor hasn't picked up yet
+    changed: bool,
+}
+
+thread_local! {
+    // `bind` runs as a shell command, which has no access to the editor, so
+    // the bindings are collected here until the prompt applies them
+    static KEY_BINDINGS: RefCell<KeyBindings> = RefCell::new(KeyBindings {
+        bindings: default_bindings(),
+        changed: true,
+    });
+}
+
+fn default_bindings() -> Vec<KeyBinding> {
+    [
+        ("end", "accept-hint"),
+        ("alt-right", "accept-hint-word"),
+        ("alt-.", "yank-last-arg"),
+    ]
+    .into_iter()
+    .map(|(chord, action)| parse_binding(chord, action).unwrap())
+    .collect()
+}
+
+/// Adds a binding, replacing any earlier binding of the same chord.
+pub fn bind(chord: &str, action: &str) -> Result<(), String> {
+    add_bindings(vec![parse_binding(chord, action)?]);
+    Ok(())
+}
+
+/// Adds bindings written in readline syntax, e.g. `"\C-f": accept-hint` or
+/// `up: previous-history`. Nothing is changed if any of them is invalid.
+pub fn bind_readline<S: AsRef<str>>(specs: &[S]) -> Result<(), String> {
+    let bindings = specs
+        .iter()
+        .map(|spec| parse_readline_binding(spec.as_ref()))
+        .collect::<Result<Vec<_>, _>>()?;
+    add_bindings(bindings);
+    Ok(())
+}
+
+fn add_bindings(bindings: Vec<KeyBinding>) {
+    KEY_BINDINGS.with_borrow_mut(|table| {
+        for binding in bindings {
+            table.bindings.retain(|b| b.chord != binding.chord);
+            table.bindings.push(binding);
+        }
+        table.changed = true;
+    });
+}
+
+/// All current bindings, in the order they were defined.
+pub fn bindings() -> Vec<KeyBinding> {
+    KEY_BINDINGS.with_borrow(|table| table.bindings.clone())
+}
+
+/// Returns the bindings if any were added since the last call.
+pub fn take_changed() -> Option<Vec<KeyBinding>> {
+    KEY_BINDINGS
+        .with_borrow_mut(|table| std::mem::take(&mut table.changed).then(|| table.bindings.clone()))
+}
+
+/// Reads a key bindings file. Invalid lines are skipped and reported as
+/// `path:line: error`.
+pub fn load_file(path: &Path) -> Result<(), Vec<String>> {
+    let Ok(text) = std::fs::read_to_string(path) else {
+        return Ok(());
+    };
+    let mut errors = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        if let Err(err) = parse_line(line) {
+            errors.push(format!("{}:{}: {err}", path.display(), number + 1));
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn parse_line(line: &str) -> Result<(), String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(());
+    }
+    if is_readline_binding(line) {
+        return bind_readline(&[line]);
+    }
+    let mut parts = line.split_whitespace();
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(chord), Some(action), None) => bind(chord, action),
+        _ => Err(format!("expected `<chord> <action>`, found `{line}`")),
+    }
+}
+
+fn parse_binding(chord: &str, action: &str) -> Result<KeyBinding, String> {
+    let chord = parse_chord(chord)?;
+    let Some(parsed_action) = parse_action(action) else {
+        return Err(format!("unknown action `{action}`"));
+    };
+    Ok(KeyBinding {
+        chord,
+        action: parsed_action,
+        action_name: action.to_string(),
+    })
+}
+
+/// Whether `spec` is written in readline syntax rather than as
+/// `<chord> <action>`.
+pub fn is_readline_binding(spec: &str) -> bool {
+    spec.starts_with('"')
+        || spec
+            .split_whitespace()
+            .next()
+            .is_some_and(|keys| keys.len() > 1 && keys.ends_with(':'))
+}
+
+/// Parses `"<key sequence>": <action>` or `<chord>: <action>`.
+fn parse_readline_binding(spec: &str) -> Result<KeyBinding, String> {
+    let invalid = || format!("expected `\"<keys>\": <action>`, found `{spec}`");
+    let (chord, action) = match spec.strip_prefix('"') {
+        Some(rest) => {
+            let mut escaped = false;
+            let end = rest
+                .char_indices()
+                .find(|&(_, c)| {
+                    let is_end = c == '"' && !escaped;
+                    escaped = c == '\\' && !escaped;
+                    is_end
+                })
+                .map(|(i, _)| i)
+                .ok_or_else(invalid)?;
+            let action = rest[end + 1..].trim_start().strip_prefix(':');
+            (parse_key_sequence(&rest[..end])?, action)
+        }
+        None => {
+            let (chord, action) = spec.split_once(':').ok_or_else(invalid)?;
+            (parse_chord(chord.trim())?, Some(action))
+        }
+    };
+    let action = action
+        .map(str::trim)
+        .filter(|a| !a.is_empty())
+        .ok_or_else(invalid)?;
+    let Some(parsed_action) = parse_action(action) else {
+        return Err(format!("unknown action `{action}`"));
+    };
+    Ok(KeyBinding {
+        chord,
+        action: parsed_action,
+        action_name: action.to_string(),
+    })
+}
+
+/// Parses a readline key sequence such as `\C-f`, `\M-.`, `\e.` or `a`. Only
+/// sequences of a single key are supported.
+fn parse_key_sequence(keys: &str) -> Result<KeyEvent, String> {
+    let invalid = |reason: &str| format!("invalid key sequence `\"{keys}\"`: {reason}");
+    let mut chord = String::new();
+    let mut rest = keys;
+    loop {
+        if let Some(after) = rest.strip_prefix("\\C-") {
+            chord.push_str("ctrl-");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("\\M-") {
+            chord.push_str("alt-");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("\\e").filter(|after| !after.is_empty()) {
+            chord.push_str("alt-");
+            rest = after;
+        } else {
+            break;
+        }
+    }
+    let mut chars = rest.chars();
+    let key = match (chars.next(), chars.next(), chars.next()) {
+        (Some('\\'), Some(c @ ('\\' | '"' | '\'')), None) | (Some(c), None, None) => c,
+        (Some('\\'), Some('e'), None) => return Ok(KeyEvent(KeyCode::Esc, Modifiers::NONE)),
+        (None, ..) => return Err(invalid("missing key")),
+        _ => return Err(invalid("only single keys can be bound")),
+    };
+    chord.push(key);
+    parse_chord(&chord).map_err(|err| invalid(err.rsplit(": ").next().unwrap_or(&err)))
+}
+
+/// Formats a binding in readline syntax, which [`bind_readline`] accepts.
+pub fn format_readline(binding: &KeyBinding) -> String {
+    let KeyEvent(code, mods) = binding.chord;
+    let keys = match code {
+        KeyCode::Char(c) => {
+            let mut keys = String::new();
+            if mods.contains(Modifiers::CTRL) {
+                keys.push_str("\\C-");
+            }
+            if mods.contains(Modifiers::ALT) {
+                keys.push_str("\\M-");
+            }
+            let c = if mods.contains(Modifiers::CTRL) {
+                c.to_ascii_lowercase()
+            } else {
+                c
+            };
+            if matches!(c, '\\' | '"') {
+                keys.push('\\');
+            }
+            keys.push(c);
+            format!("\"{keys}\"")
+        }
+        _ => format_chord(binding.chord),
+    };
+    format!("{keys}: {}", binding.action_name)
+}
+
+const MODIFIER_PREFIXES: [(&str, Modifiers); 7] = [
+    ("ctrl-", Modifiers::CTRL),
+    ("c-", Modifiers::CTRL),
+    ("alt-", Modifiers::ALT),
+    ("meta-", Modifiers::ALT),
+    ("m-", Modifiers::ALT),
+    ("shift-", Modifiers::SHIFT),
+    ("s-", Modifiers::SHIFT),
+];
+
+/// Parses chords like `ctrl-f`, `alt-c`, `ctrl-alt-x`, `shift-tab`, `up` or
+/// `f5`. `c-`, `m-`, `meta-` and `s-` are accepted as modifier prefixes too.
+pub fn parse_chord(chord: &str) -> Result<KeyEvent, String> {
+    let invalid = |reason: &str| format!("invalid key chord `{chord}`: {reason}");
+
+    let mut mods = Modifiers::NONE;
+    let mut rest = chord;
+    // a trailing `-` is the key itself, as in `alt--`
+    while let Some((prefix, modifier)) = MODIFIER_PREFIXES.into_iter().find(|(prefix, _)| {
+        rest.len() > prefix.len()
+            && rest
+                .get(..prefix.len())
+                .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+    }) {
+        mods |= modifier;
+        rest = &rest[prefix.len()..];
+    }
+    let is_modifier = |(prefix, _): &(&str, Modifiers)| rest.eq_ignore_ascii_case(prefix);
+    if rest.is_empty() || MODIFIER_PREFIXES.iter().any(is_modifier) {
+        return Err(invalid("missing key"));
+    }
+
+    let mut chars = rest.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) => KeyCode::Char(c),
+        _ => match rest.to_ascii_lowercase().as_str() {
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "insert" => KeyCode::Insert,
+            "delete" => KeyCode::Delete,
+            "backspace" => KeyCode::Backspace,
+            "tab" => KeyCode::Tab,
+            "enter" => KeyCode::Enter,
+            "esc" | "escape" => KeyCode::Esc,
+            "space" => KeyCode::Char(' '),
+            name => match name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                Some(n @ 1..=24) => KeyCode::F(n),
+                _ => return Err(invalid(&format!("unknown key `{rest}`"))),
+            },
+        },
+    };
+
+    // normalize to the events rustyline produces
+    Ok(match code {
+        KeyCode::Char(c) if mods.contains(Modifiers::CTRL) => {
+            if !c.is_ascii_alphabetic() && !"@[\\]^_".contains(c) {
+                return Err(invalid("only letters and @[\\]^_ can be used with ctrl"));
+            }
+            KeyEvent(
+                KeyCode::Char(c.to_ascii_uppercase()),
+                mods - Modifiers::SHIFT,
+            )
+        }
+        KeyCode::Char(c) if mods.contains(Modifiers::SHIFT) => KeyEvent(
+            KeyCode::Char(c.to_ascii_uppercase()),
+            mods - Modifiers::SHIFT,
+        ),
+        KeyCode::Tab if mods.contains(Modifiers::SHIFT) => {
+            KeyEvent(KeyCode::BackTab, mods - Modifiers::SHIFT)
+        }
+        code => KeyEvent(code, mods),
+    })
+}
+
+fn format_chord(KeyEvent(code, mods): KeyEvent) -> String {
+    let mut chord = String::new();
+    for (modifier, prefix) in [
+        (Modifiers::CTRL, "ctrl-"),
+        (Modifiers::ALT, "alt-"),
+        (Modifiers::SHIFT, "shift-"),
+    ] {
+        if mods.contains(modifier) {
+            chord.push_str(prefix);
+        }
+    }
+    let key = match code {
+        KeyCode::Char(' ') => "space".to_string(),
+        KeyCode::Char(c) if mods.contains(Modifiers::CTRL) => c.to_ascii_lowercase().to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::BackTab => "shift-tab".to_string(),
+        KeyCode::F(n) => format!("f{n}"),
+        code => format!("{code:?}").to_ascii_lowercase(),
+    };
+    chord.push_str(&key);
+    chord
+}
+
+fn parse_action(name: &str) -> Option<Action> {
+    actions()
+        .into_iter()
+        .find(|(action_name, _)| *action_name == name)
+        .map(|(_, action)| action)
+}
+
+/// The names of all actions that can be bound.
+pub fn action_names() -> Vec<&'static str> {
+    actions().into_iter().map(|(name, _)| name).collect()
+}
+
+fn actions() -> Vec<(&'static str, Action)> {
+    use Movement as M;
+    let widgets = [
+        ("accept-hint", Widget::AcceptHint),
+        ("accept-hint-word", Widget::AcceptHintWord),
+        ("yank-last-arg", Widget::YankLastArg),
+    ]
+    .map(|(name, widget)| (name, Action::Widget(widget)));
+    let commands = [
+        ("accept-line", Cmd::AcceptLine),
+        ("insert-newline", Cmd::Newline),
+        ("complete", Cmd::Complete),
+        ("complete-backward", Cmd::CompleteBackward),
+        ("beginning-of-line", Cmd::Move(M::BeginningOfLine)),
+        ("end-of-line", Cmd::Move(M::EndOfLine)),
+        ("backward-char", Cmd::Move(M::BackwardChar(1))),
+        ("forward-char", Cmd::Move(M::ForwardChar(1))),
+        ("backward-word", Cmd::Move(M::BackwardWord(1, Word::Emacs))),
+        (
+            "forward-word",
+            Cmd::Move(M::ForwardWord(1, At::AfterEnd, Word::Emacs)),
+        ),
+        ("kill-line", Cmd::Kill(M::EndOfLine)),
+        ("backward-kill-line", Cmd::Kill(M::BeginningOfLine)),
+        ("kill-whole-line", Cmd::Kill(M::WholeLine)),
+        (
+            "kill-word",
+            Cmd::Kill(M::ForwardWord(1, At::AfterEnd, Word::Emacs)),
+        ),
+        (
+            "backward-kill-word",
+            Cmd::Kill(M::BackwardWord(1, Word::Big)),
+        ),
+        ("delete-char", Cmd::Kill(M::ForwardChar(1))),
+        ("backward-delete-char", Cmd::Kill(M::BackwardChar(1))),
+        ("yank", Cmd::Yank(1, Anchor::Before)),
+        ("yank-pop", Cmd::YankPop),
+        ("transpose-chars", Cmd::TransposeChars),
+        ("transpose-words", Cmd::TransposeWords(1)),
+        ("upcase-word", Cmd::UpcaseWord),
+        ("downcase-word", Cmd::DowncaseWord),
+        ("capitalize-word", Cmd::CapitalizeWord),
+        ("undo", Cmd::Undo(1)),
+        ("clear-screen", Cmd::ClearScreen),
+        ("previous-history", Cmd::PreviousHistory),
+        ("next-history", Cmd::NextHistory),
+        ("beginning-of-history", Cmd::BeginningOfHistory),
+        ("end-of-history", Cmd::EndOfHistory),
+        ("history-search-backward", Cmd::HistorySearchBackward),
+        ("history-search-forward", Cmd::HistorySearchForward),
+        ("reverse-search-history", Cmd::ReverseSearchHistory),
+        ("forward-search-history", Cmd::ForwardSearchHistory),
+        ("quoted-insert", Cmd::QuotedInsert),
+        ("interrupt", Cmd::Interrupt),
+        ("end-of-file", Cmd::EndOfFile),
+        ("abort", Cmd::Abort),
+        ("noop", Cmd::Noop),
+    ]
+    .map(|(name, cmd)| (name, Action::Editor(cmd)));
+    widgets.into_iter().chain(commands).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_chord() {
+        let key = |code, mods| Ok(KeyEvent(code, mods));
+        assert_eq!(
+            parse_chord("ctrl-f"),
+            key(KeyCode::Char('F'), Modifiers::CTRL)
+        );
+        assert_eq!(parse_chord("C-F"), key(KeyCode::Char('F'), Modifiers::CTRL));
+        assert_eq!(
+            parse_chord("alt-c"),
+            key(KeyCode::Char('c'), Modifiers::ALT)
+        );
+        assert_eq!(
+            parse_chord("ctrl-alt-x"),
+            key(KeyCode::Char('X'), Modifiers::CTRL_ALT)
+        );
+        assert_eq!(parse_chord("M--"), key(KeyCode::Char('-'), Modifiers::ALT));
+        assert_eq!(
+            parse_chord("shift-tab"),
+            key(KeyCode::BackTab, Modifiers::NONE)
+        );
+        assert_eq!(parse_chord("Up"), key(KeyCode::Up, Modifiers::NONE));
+        assert_eq!(parse_chord("f5"), key(KeyCode::F(5), Modifiers::NONE));
+
+        assert_eq!(
+            parse_chord("ctrl-").unwrap_err(),
+            "invalid key chord `ctrl-`: missing key"
+        );
+        assert_eq!(
+            parse_chord("").unwrap_err(),
+            "invalid key chord ``: missing key"
+        );
+        assert_eq!(
+            parse_chord("alt-foo").unwrap_err(),
+            "invalid key chord `alt-foo`: unknown key `foo`"
+        );
+    }
+
+    #[test]
+    fn test_format_chord() {
+        for chord in [
+            "ctrl-f",
+            "alt-c",
+            "ctrl-alt-x",
+            "shift-tab",
+            "up",
+            "f5",
+            "space",
+        ] {
+            assert_eq!(format_chord(parse_chord(chord).unwrap()), chord);
+        }
+    }
+
+    #[test]
+    fn test_bind_overrides() {
+        bind("ctrl-f", "forward-char").unwrap();
+        bind("ctrl-f", "accept-hint").unwrap();
+        let bindings = bindings();
+        let ctrl_f: Vec<_> = bindings
+            .iter()
+            .filter(|b| b.chord == KeyEvent(KeyCode::Char('F'), Modifiers::CTRL))
+            .collect();
+        assert_eq!(ctrl_f.len(), 1);
+        assert_eq!(ctrl_f[0].action, Action::Widget(Widget::AcceptHint));
+        assert!(take_changed().is_some());
+        assert!(take_changed().is_none());
+
+        assert_eq!(bind("ctrl-f", "fly").unwrap_err(), "unknown action `fly`");
+        assert_eq!(
+            parse_line("ctrl-f").unwrap_err(),
+            "expected `<chord> <action>`, found `ctrl-f`"
+        );
+        assert_eq!(parse_line("# comment"), Ok(()));
+    }
+
+    #[test]
+    fn test_readline_bindings() {
+        let binding = parse_readline_binding(r#""\C-f": accept-hint"#).unwrap();
+        assert_eq!(binding.chord, KeyEvent(KeyCode::Char('F'), Modifiers::CTRL));
+        assert_eq!(binding.action, Action::Widget(Widget::AcceptHint));
+        assert_eq!(
+            parse_key_sequence(r"\e."),
+            Ok(KeyEvent(KeyCode::Char('.'), Modifiers::ALT))
+        );
+        assert_eq!(
+            parse_key_sequence(r#"\M-\""#),
+            Ok(KeyEvent(KeyCode::Char('"'), Modifiers::ALT))
+        );
+        assert_eq!(
+            parse_readline_binding("up: previous-history")
+                .unwrap()
+                .chord,
+            KeyEvent(KeyCode::Up, Modifiers::NONE)
+        );
+
+        // the printed form can be read back
+        for spec in [r#""\C-\M-x": undo"#, r#""\M-\\": undo"#, "f5: complete"] {
+            let binding = parse_readline_binding(spec).unwrap();
+            assert_eq!(format_readline(&binding), spec);
+        }
+
+        assert_eq!(
+            parse_key_sequence("ab").unwrap_err(),
+            r#"invalid key sequence `"ab"`: only single keys can be bound"#
+        );
+        assert_eq!(
+            parse_readline_binding(r#""\C-g" undo"#).unwrap_err(),
+            r#"expected `"<keys>": <action>`, found `"\C-g" undo`"#
+        );
+
+        // nothing is bound if one of the bindings is invalid
+        let before = bindings();
+        assert!(bind_readline(&[r#""\C-g": undo"#, r#""\C-h": fly"#]).is_err());
+        assert_eq!(bindings(), before);
+        assert!(is_readline_binding(r#""\C-g": undo"#));
+        assert!(!is_readline_binding("ctrl-g undo"));
+    }
+}
@@ -1,2 +1,5 @@
+pub mod abbreviations;
 pub mod commands;
 pub mod execute;
+pub mod history;
+pub mod keybindings;
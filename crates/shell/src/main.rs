@@ -1,31 +1,58 @@
 use std::collections::HashMap;
+use std::io::IsTerminal;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use clap::Parser;
 use deno_task_shell::parser::debug_parse;
-use deno_task_shell::ShellState;
+use deno_task_shell::{ShellOptions, ShellState, CANCELLATION_EXIT_CODE};
 use miette::Context;
 use miette::IntoDiagnostic;
+use rustyline::config::Configurer;
 use rustyline::error::ReadlineError;
-use rustyline::{CompletionType, Config, Editor};
+use rustyline::highlight::Highlighter;
+use rustyline::{
+    Cmd, CompletionType, Config, EditMode, Editor, Event, EventHandler, KeyCode, KeyEvent,
+    Modifiers,
+};
+use tokio_util::sync::CancellationToken;
 
+use crate::frequency::CommandFrequency;
+
+mod abbreviations;
+mod colors;
 mod commands;
 mod completion;
 mod execute;
+mod frequency;
 mod helper;
+mod highlight;
+mod history;
+mod keybindings;
+mod notify;
+mod prompt;
+mod transient_prompt;
 
 pub use execute::execute;
+
 #[derive(Parser)]
 struct Options {
-    /// The path to the file that should be executed
+    /// The path to the file that should be executed. Without a file or `-c`,
+    /// a script piped on stdin is executed. With `-c`, this is `$0`
     file: Option<PathBuf>,
 
+    /// The positional parameters of the script (`$1`, `$2`, ...)
+    #[clap(trailing_var_arg = true, allow_hyphen_values = true)]
+    args: Vec<String>,
+
     /// Continue in interactive mode after the file has been executed
     #[clap(long)]
     interact: bool,
 
-    /// Do not source ~/.shellrc on startup
+    /// Do not source ~/.shellrc and ~/.config/shell/shellrc.d on startup
     #[clap(long)]
     norc: bool,
 
@@ -35,33 +62,190 @@ struct Options {
 
     #[clap(short, long)]
     debug: bool,
+
+    /// Print the commands of the file or `-c` command instead of running
+    /// them. Nothing is executed, including built-ins such as `cd` and
+    /// variable assignments, so the commands are printed as written without
+    /// expanding variables. Has no effect in interactive mode
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Only check the file or `-c` command for syntax errors, like `set -n`.
+    /// Nothing is executed or printed, the exit code is 1 if there are errors
+    #[clap(short = 'n', long)]
+    noexec: bool,
+
+    /// Stop at the first command that fails, as if the file or `-c` command
+    /// started with `set -e`. Like in bash, they keep going after a failure
+    /// otherwise. A `set +e` in the script still turns it off from there on,
+    /// and a later `set -e` back on
+    #[clap(long)]
+    exit_on_error: bool,
+}
+
+/// Splits the arguments of the shell into its options and the operands,
+/// which are the script file (or `$0` with `-c`) and its arguments. Like in
+/// `sh`, the options end at the first operand, `--` or the `-c` command
+/// string, so the operands are passed as they are even if they look like
+/// options, e.g. `shell -c 'echo "$@"' -- -a b`.
+fn split_operands(args: &[String]) -> (&[String], &[String]) {
+    for (i, arg) in args.iter().enumerate().skip(1) {
+        if arg == "--" {
+            return (&args[..i], &args[i + 1..]);
+        }
+        if !arg.starts_with('-') || arg == "-" {
+            return (&args[..i], &args[i..]);
+        }
+        if arg.starts_with("--") {
+            continue;
+        }
+        if let Some(position) = arg.find('c') {
+            // the command string is either attached (`-cecho`) or the next
+            // argument, a missing one is left to clap to report
+            let end = if position + 1 < arg.len() {
+                i + 1
+            } else {
+                i + 2
+            };
+            let end = end.min(args.len());
+            return (&args[..end], &args[end..]);
+        }
+    }
+    (args, &[])
 }
 
 fn init_state() -> ShellState {
     let mut env_vars: HashMap<String, String> = std::env::vars().collect();
-    let default_ps1 = "{display_cwd}{git_branch}$ ";
+    let default_ps1 = "{ssh}{display_cwd}{git_branch}$ ";
     env_vars.insert("PS1".to_string(), default_ps1.to_string());
     let cwd = std::env::current_dir().unwrap();
+    // prefer the inherited logical `$PWD` (which may go through symlinks) as
+    // long as it still points at the actual working directory
+    let cwd = std::env::var("PWD")
+        .ok()
+        .map(PathBuf::from)
+        .filter(|pwd| pwd.is_absolute() && pwd.canonicalize().ok() == cwd.canonicalize().ok())
+        .unwrap_or(cwd);
     ShellState::new(env_vars, &cwd, commands::get_commands())
 }
 
+/// The state a file or `-c` command runs with. Unlike at the prompt, it
+/// only stops at a failing command with `exit_on_error`, like in bash.
+fn script_state(exit_on_error: bool) -> ShellState {
+    let mut state = init_state();
+    state.set_shell_option(ShellOptions::ExitOnError, exit_on_error);
+    state
+}
+
+/// The `*.shellrc` and `*.sh` files in `dir` in sorted order, none if it
+/// doesn't exist.
+fn rc_snippets(dir: &Path) -> Vec<PathBuf> {
+    let mut files = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|extension| extension == "shellrc" || extension == "sh")
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
+/// Sources the file at `path`. The path is passed to `source` as it is and
+/// not parsed as shell text, so that any file name works.
+async fn source_file(path: &Path, state: &mut ShellState) -> miette::Result<i32> {
+    execute::call_function("source", &[&path.to_string_lossy()], state).await
+}
+
 async fn interactive(state: Option<ShellState>, norc: bool) -> miette::Result<()> {
     let config = Config::builder()
         .history_ignore_space(true)
         .completion_type(CompletionType::List)
+        // insert pasted text (including newlines and tabs) as-is into the
+        // buffer, instead of running each line or completing on tabs
+        .bracketed_paste(true)
         .build();
 
-    ctrlc::set_handler(move || {
-        println!("Received Ctrl+C");
-    })
-    .expect("Error setting Ctrl-C handler");
+    // Ctrl-C cancels the running command, at the prompt the editor reads it
+    // as a key instead and reports `ReadlineError::Interrupted`
+    let running_token = Arc::new(Mutex::new(CancellationToken::new()));
+    let interrupted = Arc::new(AtomicBool::new(false));
+    {
+        let running_token = running_token.clone();
+        let interrupted = interrupted.clone();
+        ctrlc::set_handler(move || {
+            // on Windows, an external command gets Ctrl-C from the console
+            // as well and decides itself whether to exit
+            #[cfg(windows)]
+            if deno_task_shell::has_foreground_process() {
+                return;
+            }
+            interrupted.store(true, Ordering::Relaxed);
+            running_token.lock().unwrap().cancel();
+        })
+        .expect("Error setting Ctrl-C handler");
+    }
 
     let mut rl = Editor::with_config(config).into_diagnostic()?;
 
     let helper = helper::ShellPromptHelper::default();
     rl.set_helper(Some(helper));
+    let vi_normal_mode = rl.helper().unwrap().vi_normal_mode.clone();
+    let last_args = rl.helper().unwrap().last_args.clone();
+    let frequency = rl.helper().unwrap().frequency.clone();
+    let pending_replacement = rl.helper().unwrap().pending_replacement.clone();
+    let editor = rl.helper().unwrap().editor.clone();
+    let edited_line = rl.helper().unwrap().edited_line.clone();
+    rl.bind_sequence(
+        Event::KeySeq(helper::EDIT_COMMAND_LINE_KEYS.to_vec()),
+        EventHandler::Conditional(Box::new(helper::EditCommandLine::new(
+            editor,
+            edited_line.clone(),
+        ))),
+    );
+    rl.bind_sequence(
+        KeyEvent(KeyCode::Char(' '), Modifiers::NONE),
+        EventHandler::Conditional(Box::new(helper::ExpandAbbreviation(
+            pending_replacement.clone(),
+        ))),
+    );
+    // rustyline only binds Ctrl-L to clear the screen (keeping the line) in
+    // emacs mode
+    rl.bind_sequence(KeyEvent::ctrl('L'), EventHandler::Simple(Cmd::ClearScreen));
+    for key in helper::VI_MODE_KEYS {
+        rl.bind_sequence(
+            key,
+            EventHandler::Conditional(Box::new(helper::ViModeTracker(vi_normal_mode.clone()))),
+        );
+    }
 
     let mut state = state.unwrap_or_else(init_state);
+    state.set_interactive(true);
+
+    // EDITMODE picks the initial key bindings, ~/.shellrc can still override them
+    let edit_mode = state.env_vars().get("EDITMODE").cloned();
+    match edit_mode.as_deref() {
+        Some("vi") => {
+            state.set_shell_option(ShellOptions::Vi, true);
+            state.set_shell_option(ShellOptions::Emacs, false);
+        }
+        Some("emacs") => {
+            state.set_shell_option(ShellOptions::Emacs, true);
+            state.set_shell_option(ShellOptions::Vi, false);
+        }
+        _ => {}
+    }
+
+    // Interactive shells stop and resume commands attached to the terminal
+    #[cfg(unix)]
+    if std::io::stdin().is_terminal() {
+        deno_task_shell::job_control::init_shell();
+        state.set_shell_option(ShellOptions::Monitor, true);
+    }
 
     let home = dirs::home_dir().ok_or(miette::miette!("Couldn't get home directory"))?;
 
@@ -73,29 +257,123 @@ async fn interactive(state: Option<ShellState>, norc: bool) -> miette::Result<()
         rl.load_history(history_file.as_path())
             .into_diagnostic()
             .context("Failed to read the command history")?;
+        let mut last_args = last_args.lock().unwrap();
+        for entry in rl.history().iter() {
+            last_args.add(entry);
+            history::add(entry);
+        }
+    }
+
+    // Load .shell_command_counts, which starts out with the history
+    let counts_file: PathBuf = [home.as_path(), Path::new(".shell_command_counts")]
+        .iter()
+        .collect();
+    {
+        let mut frequency = frequency.lock().unwrap();
+        if counts_file.exists() {
+            *frequency = CommandFrequency::load(&counts_file)
+                .into_diagnostic()
+                .context("Failed to read the command counts")?;
+        } else {
+            for entry in rl.history().iter() {
+                frequency.add(entry);
+            }
+        }
     }
 
     // Load ~/.shellrc
     let shellrc_file: PathBuf = [home.as_path(), Path::new(".shellrc")].iter().collect();
     if !norc && Path::new(shellrc_file.as_path()).exists() {
-        let line = "source '".to_owned() + shellrc_file.to_str().unwrap() + "'";
-        let prev_exit_code = execute(
-            &line,
-            Some(shellrc_file.as_path().display().to_string()),
-            &mut state,
-        )
-        .await
-        .context("Failed to source ~/.shellrc")?;
+        let prev_exit_code = source_file(&shellrc_file, &mut state)
+            .await
+            .context("Failed to source ~/.shellrc")?;
         state.set_last_command_exit_code(prev_exit_code);
     }
 
+    // Load the snippets in ~/.config/shell/shellrc.d, a failing one doesn't
+    // keep the others from loading
+    if !norc {
+        for file in rc_snippets(&home.join(".config/shell/shellrc.d")) {
+            // a snippet that failed under errexit cancelled the token
+            state.reset_cancellation_token();
+            match source_file(&file, &mut state).await {
+                Ok(exit_code) => state.set_last_command_exit_code(exit_code),
+                Err(err) => {
+                    eprintln!(
+                        "{:?}",
+                        err.context(format!("Failed to source {}", file.display()))
+                    )
+                }
+            }
+        }
+    }
+
+    // Load ~/.shell_keybindings, `bind` in ~/.shellrc may have added more
+    let keybindings_file: PathBuf = [home.as_path(), Path::new(".shell_keybindings")]
+        .iter()
+        .collect();
+    if let Err(errors) = keybindings::load_file(&keybindings_file) {
+        for error in errors {
+            eprintln!("{error}");
+        }
+    }
+
     let mut _prev_exit_code = 0;
+    let mut color_warnings = Vec::new();
+    let mut first_prompt = true;
     loop {
         // Reset cancellation flag
         state.reset_cancellation_token();
+        *running_token.lock().unwrap() = state.token().clone();
+        interrupted.store(false, Ordering::Relaxed);
+
+        // Report background jobs that finished since the last prompt. The
+        // tasks of the jobs only run while the shell waits, so they get to
+        // record the exit of their processes first
+        tokio::task::yield_now().await;
+        #[cfg(unix)]
+        for job in state.jobs_mut().update_statuses() {
+            println!("{}", job.describe(' '));
+        }
+
+        // Run the `precmd` hook, it may still change the prompt or bindings
+        run_hook(&mut state, &running_token, "precmd", None).await;
+
+        // Apply `bind` commands from the previous line
+        if let Some(bindings) = keybindings::take_changed() {
+            for binding in bindings {
+                rl.bind_sequence(
+                    binding.chord,
+                    event_handler(binding.action, &last_args, &pending_replacement),
+                );
+            }
+        }
+
+        // Apply `set -o vi` / `set -o emacs`, this keeps the history
+        let vi_mode = state.shell_option(ShellOptions::Vi);
+        rl.set_edit_mode(if vi_mode {
+            EditMode::Vi
+        } else {
+            EditMode::Emacs
+        });
+        let helper = rl.helper_mut().unwrap();
+        helper.vi_mode = vi_mode;
+        // every line starts out in insert mode
+        helper.vi_normal_mode.store(false, Ordering::Relaxed);
+
+        // Pick up changes of the colors, invalid ones are only reported once
+        let (colors, warnings) =
+            colors::Colors::from_vars(|name| state.get_var(name), std::io::stdout().is_terminal());
+        helper.colors = colors;
+        if warnings != color_warnings {
+            for warning in &warnings {
+                eprintln!("{warning}");
+            }
+            color_warnings = warnings;
+        }
 
         // Display the prompt and read a line
-        let readline = {
+        let (prompt, transient, readline) = {
             let cwd = state.cwd().to_string_lossy().to_string();
             let home_str = home.to_str().ok_or(miette::miette!(
                 "Couldn't convert home directory path to UTF-8 string"
@@ -119,7 +397,7 @@ async fn interactive(state: Option<ShellState>, norc: bool) -> miette::Result<()
                 git_branch = "(".to_owned() + &git_branch + ")";
             }
 
-            let mut display_cwd = if let Some(stripped) = cwd.strip_prefix(home_str) {
+            let display_cwd = if let Some(stripped) = cwd.strip_prefix(home_str) {
                 format!("~{}", stripped.replace('\\', "/"))
             } else {
                 cwd.to_string()
@@ -128,29 +406,147 @@ async fn interactive(state: Option<ShellState>, norc: bool) -> miette::Result<()
             // Read the PS1 environment variable
             let ps1 = state.env_vars().get("PS1").map_or("", |v| v);
 
-            fn replace_placeholders(ps1: &str, display_cwd: &str, git_branch: &str) -> String {
-                ps1.replace(&format!("{{{}}}", "display_cwd"), display_cwd)
-                    .replace(&format!("{{{}}}", "git_branch"), git_branch)
+            fn replace_placeholders(ps1: &str, placeholders: &[(&str, &str)]) -> String {
+                let mut prompt = ps1.to_string();
+                for (name, value) in placeholders {
+                    prompt = prompt.replace(&format!("{{{name}}}"), value);
+                }
+                prompt
             }
 
-            let prompt = replace_placeholders(ps1, &display_cwd, &git_branch);
-            display_cwd = format!("\x1b[34m{display_cwd}\x1b[0m");
-            git_branch = format!("\x1b[32m{git_branch}\x1b[0m");
-            let color_prompt = replace_placeholders(ps1, &display_cwd, &git_branch);
+            let ssh = prompt::ssh_marker(|name| state.get_var(name));
+            let host = prompt::hostname();
+            let cmd_duration =
+                prompt::cmd_duration(state.last_command_duration(), |name| state.get_var(name));
+
+            // the colored prompt keeps `{vi_mode}` so the helper can fill in
+            // the current mode, the indicators all have the same width
+            let vi_mode_width = if vi_mode { "[I]" } else { "" };
+            let prompt = replace_placeholders(
+                ps1,
+                &[
+                    ("display_cwd", &display_cwd),
+                    ("git_branch", &git_branch),
+                    ("ssh", &ssh),
+                    ("host", &host),
+                    ("cmd_duration", &cmd_duration),
+                    ("newline", "\n"),
+                    ("space", " "),
+                ],
+            )
+            .replace("{vi_mode}", vi_mode_width);
+            let (display_cwd, git_branch, ssh) = if rl.helper().unwrap().colors.enabled {
+                (
+                    format!("\x1b[34m{display_cwd}\x1b[0m"),
+                    format!("\x1b[32m{git_branch}\x1b[0m"),
+                    format!("\x1b[33m{ssh}\x1b[0m"),
+                )
+            } else {
+                (display_cwd, git_branch, ssh)
+            };
+            let placeholders = [
+                ("display_cwd", display_cwd.as_str()),
+                ("git_branch", git_branch.as_str()),
+                ("ssh", ssh.as_str()),
+                ("host", host.as_str()),
+                ("cmd_duration", cmd_duration.as_str()),
+                ("newline", "\n"),
+                ("space", " "),
+            ];
+            let transient =
+                transient_prompt::template(|name| state.get_var(name)).map(|template| {
+                    replace_placeholders(&template, &placeholders).replace("{vi_mode}", "")
+                });
+            let color_prompt = replace_placeholders(ps1, &placeholders);
             rl.helper_mut().unwrap().colored_prompt = color_prompt;
-            rl.readline(&prompt)
+            update_helper(rl.helper_mut().unwrap(), &state);
+            // `SHELL_PROMPT_NEWLINE_BEFORE=1` separates each command from the
+            // output of the previous one. An unfinished command continues
+            // within the same `readline`, so it doesn't get one
+            if !first_prompt && state.get_var("SHELL_PROMPT_NEWLINE_BEFORE").as_deref() == Some("1")
+            {
+                println!();
+            }
+            first_prompt = false;
+            let readline = rl.readline(&prompt);
+            (prompt, transient, readline)
         };
 
         match readline {
             Ok(line) => {
-                // Add the line to history
-                rl.add_history_entry(line.as_str()).into_diagnostic()?;
+                // Run the text from the editor if the line was accepted with
+                // Ctrl-X Ctrl-E, or expand an abbreviation that was typed
+                // right before enter. The line is redrawn to show what runs
+                let edited = edited_line.lock().unwrap().take();
+                let expanded = edited.or_else(|| abbreviations::expand_line(&line));
+                // Collapse the prompt to the transient one if it is enabled
+                if transient.is_some() || expanded.is_some() {
+                    let new_prompt = match transient {
+                        Some(transient) => transient,
+                        None => rl
+                            .helper()
+                            .unwrap()
+                            .highlight_prompt(&prompt, true)
+                            .into_owned(),
+                    };
+                    let new_line = expanded.as_deref().unwrap_or(&line);
+                    transient_prompt::redraw(&prompt, &line, &new_prompt, new_line);
+                }
+                let line = expanded.unwrap_or(line);
 
-                // Process the input (here we just echo it back)
-                let prev_exit_code = execute(&line, None, &mut state)
-                    .await
-                    .context("Failed to execute")?;
-                state.set_last_command_exit_code(prev_exit_code);
+                // the jobs also catch up on what happened while the line was
+                // read, e.g. for `jobs`
+                tokio::task::yield_now().await;
+                run_hook(&mut state, &running_token, "preexec", Some(&line)).await;
+
+                // Process the input, an error only fails this line and the
+                // session goes on
+                let started = Instant::now();
+                let prev_exit_code = match execute(&line, None, &mut state).await {
+                    Ok(exit_code) => exit_code,
+                    Err(err) => {
+                        eprintln!("{:?}", err.context("Failed to execute"));
+                        1
+                    }
+                };
+                let duration = started.elapsed();
+                state.set_last_command_duration(duration);
+                if let Some(notification) =
+                    notify::notification(&line, prev_exit_code, duration, |name| {
+                        state.get_var(name)
+                    })
+                {
+                    print!("{notification}");
+                    let _ = std::io::Write::flush(&mut std::io::stdout());
+                }
+
+                // Add the line to history, after running it so that `fc`
+                // only sees the earlier lines and can record what it ran
+                let entry = history::take_replacement().unwrap_or_else(|| line.clone());
+                let ignored = state
+                    .get_var("HISTIGNORE")
+                    .or_else(|| state.get_var("SHELL_HISTORY_IGNORE"))
+                    .is_some_and(|patterns| history::is_ignored(&entry, &patterns));
+                // a repeated line isn't added to the history again, but still
+                // counts as run
+                if !ignored && !entry.starts_with(char::is_whitespace) {
+                    frequency.lock().unwrap().add(&entry);
+                }
+                if !ignored && rl.add_history_entry(entry.as_str()).into_diagnostic()? {
+                    last_args.lock().unwrap().add(&entry);
+                    history::add(&entry);
+                }
+                // a command that got Ctrl-C itself exits with 130
+                if interrupted.load(Ordering::Relaxed) || prev_exit_code == CANCELLATION_EXIT_CODE {
+                    // the `^C` is echoed without a newline
+                    println!();
+                    state.set_last_command_exit_code(CANCELLATION_EXIT_CODE);
+                } else {
+                    state.set_last_command_exit_code(prev_exit_code);
+                }
+                // `set -n` only lasts until the end of the line, otherwise
+                // the shell would not run anything anymore
+                state.set_shell_option(ShellOptions::NoExec, false);
 
                 // Check for exit command
                 if line.trim().eq_ignore_ascii_case("exit") {
@@ -173,37 +569,148 @@ async fn interactive(state: Option<ShellState>, norc: bool) -> miette::Result<()
             }
         }
     }
+    // like in bash, the jobs are hung up when the shell exits
+    #[cfg(unix)]
+    state.jobs().hang_up();
     rl.save_history(history_file.as_path())
         .into_diagnostic()
         .context("Failed to write the command history")?;
+    frequency
+        .lock()
+        .unwrap()
+        .save(&counts_file)
+        .into_diagnostic()
+        .context("Failed to write the command counts")?;
 
     Ok(())
 }
 
-#[tokio::main]
-async fn main() -> miette::Result<()> {
-    let options = Options::parse();
+/// Passes the settings of the shell that the line editor uses to `helper`,
+/// before reading a line.
+fn update_helper(helper: &mut helper::ShellPromptHelper, state: &ShellState) {
+    *helper.editor.lock().unwrap() = state.get_var("VISUAL").or_else(|| state.get_var("EDITOR"));
+    helper.set_command_lookup(state.get_var("PATH").unwrap_or_default(), state.alias_map());
+    helper.set_completions(state.completions().clone());
+    helper.set_fuzzy_complete(state.get_var("SHELL_FUZZY_COMPLETE").as_deref() == Some("1"));
+}
+
+/// Calls the function `name` if it is defined, like the `precmd` and
+/// `preexec` hooks of zsh. The hook doesn't change `$?`, and errors are only
+/// reported.
+async fn run_hook(
+    state: &mut ShellState,
+    running_token: &Mutex<CancellationToken>,
+    name: &str,
+    arg: Option<&str>,
+) {
+    if state.function(name).is_none() {
+        return;
+    }
+    let exit_code = state.last_command_exit_code();
+    let args = Vec::from_iter(arg);
+    if let Err(err) = execute::call_function(name, &args, state).await {
+        eprintln!(
+            "{:?}",
+            err.context(format!("Failed to run the {name} hook"))
+        );
+    }
+    state.set_last_command_exit_code(exit_code);
+    // a hook that fails under errexit cancels the token, which must not
+    // stop the commands of the line
+    state.reset_cancellation_token();
+    *running_token.lock().unwrap() = state.token().clone();
+}
+
+fn event_handler(
+    action: keybindings::Action,
+    last_args: &Arc<Mutex<helper::LastArgs>>,
+    pending_replacement: &helper::PendingReplacement,
+) -> EventHandler {
+    use keybindings::{Action, Widget};
+    match action {
+        Action::Editor(cmd) => EventHandler::Simple(cmd),
+        Action::Widget(Widget::AcceptHint) => {
+            EventHandler::Conditional(Box::new(helper::AcceptHint))
+        }
+        Action::Widget(Widget::AcceptHintWord) => {
+            EventHandler::Conditional(Box::new(helper::AcceptHintWord))
+        }
+        Action::Widget(Widget::YankLastArg) => EventHandler::Conditional(Box::new(
+            helper::YankLastArg(last_args.clone(), pending_replacement.clone()),
+        )),
+    }
+}
 
-    if options.file.is_some() || options.command.is_some() {
+/// The stack of the thread that runs the shell. Nested function calls and
+/// `source`s are nested futures, and polling them takes more stack than the
+/// main thread has in debug builds before the depth limits stop them.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+fn main() -> miette::Result<()> {
+    let shell = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(|| {
+            // commands run in the background and process substitutions are
+            // spawned as local tasks, since the shell state isn't `Send`
+            let local_set = tokio::task::LocalSet::new();
+            tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()
+                .into_diagnostic()?
+                .block_on(local_set.run_until(run()))
+        })
+        .into_diagnostic()?;
+    shell
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+async fn run() -> miette::Result<()> {
+    let raw_args: Vec<String> = std::env::args_os()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let (option_args, operands) = split_operands(&raw_args);
+    let mut options = Options::parse_from(option_args);
+    options.file = operands.first().map(PathBuf::from);
+    options.args = operands.iter().skip(1).cloned().collect();
+    // e.g. `curl https://example.com/setup.sh | shell`
+    let stdin_script =
+        options.file.is_none() && options.command.is_none() && !std::io::stdin().is_terminal();
+
+    if options.file.is_some() || options.command.is_some() || stdin_script {
         let script_text;
         let filename: Option<String>;
-        if options.file.is_some() {
-            script_text = std::fs::read_to_string(options.file.clone().unwrap())
-                .expect("Failed to read file");
-            filename = Some(options.file.unwrap().display().to_string());
-        } else if options.command.is_some() {
-            script_text = options.command.unwrap();
-            filename = None;
+        if let Some(command) = options.command {
+            // like `sh -c 'command' name args...`, the first argument is `$0`
+            script_text = command;
+            filename = options.file.map(|name| name.display().to_string());
+        } else if let Some(file) = options.file {
+            script_text = std::fs::read_to_string(&file).expect("Failed to read file");
+            filename = Some(file.display().to_string());
         } else {
-            panic!();
+            script_text = std::io::read_to_string(std::io::stdin())
+                .into_diagnostic()
+                .context("Failed to read the script from stdin")?;
+            filename = Some("<stdin>".to_string());
         }
-        let mut state = init_state();
+        let mut state = script_state(options.exit_on_error);
+        state.set_positional_args(options.args);
         if options.debug {
             debug_parse(&script_text);
             return Ok(());
         }
+        if options.noexec {
+            std::process::exit(execute::check_syntax(&script_text, filename)?);
+        }
+        if options.dry_run {
+            std::process::exit(execute::dry_run(&script_text, filename)?);
+        }
         let exit_code = execute(&script_text, filename, &mut state).await?;
         if options.interact {
+            if stdin_script {
+                eprintln!("shell: --interact needs a terminal, but stdin was the script");
+                std::process::exit(1);
+            }
             interactive(Some(state), options.norc).await?;
         }
         std::process::exit(exit_code); // Exit with the correct code
@@ -213,3 +720,143 @@ async fn main() -> miette::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_on_error() {
+        let run = |exit_on_error: bool, script: &str| {
+            let mut state = script_state(exit_on_error);
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            let exit_code = runtime.block_on(execute(script, None, &mut state)).unwrap();
+            (exit_code, state.get_var("x"))
+        };
+        let after = || Some("after".to_string());
+        assert_eq!(run(false, "false; x=after"), (0, after()));
+        assert_eq!(run(true, "false; x=after"), (1, None));
+        // `set +e` turns the flag off again, and `set -e` back on
+        assert_eq!(run(true, "set +e; false; x=after"), (0, after()));
+        assert_eq!(run(true, "set +e; set -e; false; x=after"), (1, None));
+        assert_eq!(run(false, "set -e; false; x=after"), (1, None));
+    }
+
+    #[test]
+    fn test_split_operands() {
+        let split = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(ToString::to_string).collect();
+            let (options, operands) = split_operands(&args);
+            (options.len(), operands.to_vec())
+        };
+        let strings = |args: &[&str]| args.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            split(&["shell", "-c", "echo", "--", "-a", "b"]),
+            (3, strings(&["--", "-a", "b"]))
+        );
+        assert_eq!(
+            split(&["shell", "--norc", "-nc", "echo", "name"]),
+            (4, strings(&["name"]))
+        );
+        assert_eq!(split(&["shell", "-cecho", "x"]), (2, strings(&["x"])));
+        assert_eq!(split(&["shell", "-c"]), (2, strings(&[])));
+        // the options of a script are its own
+        assert_eq!(
+            split(&["shell", "-n", "script.sh", "-c", "echo"]),
+            (2, strings(&["script.sh", "-c", "echo"]))
+        );
+        assert_eq!(
+            split(&["shell", "--", "-script.sh", "a"]),
+            (1, strings(&["-script.sh", "a"]))
+        );
+        assert_eq!(split(&["shell", "--norc"]), (2, strings(&[])));
+        assert_eq!(
+            split(&["shell", "--exit-on-error", "script.sh", "a"]),
+            (2, strings(&["script.sh", "a"]))
+        );
+    }
+
+    #[test]
+    fn test_fuzzy_complete_setting() {
+        use rustyline::completion::{Candidate, Completer};
+
+        let complete = |script: &str| {
+            let mut state = script_state(false);
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            runtime.block_on(execute(script, None, &mut state)).unwrap();
+            let mut helper = helper::ShellPromptHelper::default();
+            update_helper(&mut helper, &state);
+            let history = rustyline::history::DefaultHistory::new();
+            let (_, matches) = helper
+                .complete("eho", 3, &rustyline::Context::new(&history))
+                .unwrap();
+            matches.iter().any(|m| m.replacement() == "echo")
+        };
+        assert!(!complete("true"));
+        assert!(complete("export SHELL_FUZZY_COMPLETE=1"));
+        // the shell variable is enough, like for the other settings
+        assert!(complete("SHELL_FUZZY_COMPLETE=1"));
+        assert!(!complete("SHELL_FUZZY_COMPLETE=0"));
+    }
+
+    #[test]
+    fn test_path_complete() {
+        use rustyline::completion::{Candidate, Completer};
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("state-path-tool"), "").unwrap();
+        // only the shell variable is set, not the environment of the process
+        let mut state = script_state(false);
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let script = format!("PATH='{}'", dir.path().display());
+        runtime
+            .block_on(execute(&script, None, &mut state))
+            .unwrap();
+        let mut helper = helper::ShellPromptHelper::default();
+        update_helper(&mut helper, &state);
+        let history = rustyline::history::DefaultHistory::new();
+        let (_, matches) = helper
+            .complete("state-path", 10, &rustyline::Context::new(&history))
+            .unwrap();
+        assert!(matches.iter().any(|m| m.replacement() == "state-path-tool"));
+    }
+
+    #[test]
+    fn test_rc_snippets() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        std::fs::create_dir_all(dir.join("dir.sh")).unwrap();
+        for file in ["20-git.shellrc", "10-path.sh", "README.md", "30-old.sh.bak"] {
+            std::fs::write(dir.join(file), "").unwrap();
+        }
+        assert_eq!(
+            rc_snippets(dir),
+            [dir.join("10-path.sh"), dir.join("20-git.shellrc")]
+        );
+        assert!(rc_snippets(&dir.join("missing")).is_empty());
+    }
+
+    #[test]
+    fn test_source_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("d'x.sh");
+        std::fs::write(&file, "x=sourced").unwrap();
+        let mut state = script_state(false);
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let exit_code = runtime.block_on(source_file(&file, &mut state)).unwrap();
+        assert_eq!(exit_code, 0);
+        assert_eq!(state.get_var("x").as_deref(), Some("sourced"));
+    }
+}
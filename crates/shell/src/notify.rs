@@ -0,0 +1,79 @@
+//! Notifications for commands that take long, so that a build that was left
+//! running in the background gets noticed when it is done. They are off
+//! unless `SHELL_NOTIFY_THRESHOLD` is set to a number of seconds: a command
+//! that takes longer rings the bell, and with `SHELL_NOTIFY_OSC=1` also
+//! sends an OSC 777 notification, which terminals show on the desktop.
+
+use std::time::Duration;
+
+use crate::prompt::format_duration;
+
+/// The text to print after `command` finished with `exit_code` after
+/// `duration`, if it took long enough to notify about it.
+pub fn notification(
+    command: &str,
+    exit_code: i32,
+    duration: Duration,
+    get_var: impl Fn(&str) -> Option<String>,
+) -> Option<String> {
+    let threshold = get_var("SHELL_NOTIFY_THRESHOLD")?;
+    let threshold = Duration::try_from_secs_f64(threshold.trim().parse().ok()?).ok()?;
+    if duration <= threshold {
+        return None;
+    }
+    let mut text = "\x07".to_string();
+    if get_var("SHELL_NOTIFY_OSC").as_deref() == Some("1") {
+        let status = if exit_code == 0 {
+            "finished".to_string()
+        } else {
+            format!("failed with exit code {exit_code}")
+        };
+        // `;` separates the title from the body, and control characters
+        // would end the sequence
+        let command = command
+            .trim()
+            .chars()
+            .map(|c| if c.is_control() || c == ';' { ' ' } else { c })
+            .collect::<String>();
+        text.push_str(&format!(
+            "\x1b]777;notify;shell;{command} {status} after {}\x07",
+            format_duration(duration)
+        ));
+    }
+    Some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &'static [(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        move |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    #[test]
+    fn test_notification() {
+        let long = Duration::from_secs(40);
+        assert_eq!(notification("make", 0, long, vars(&[])), None);
+        let threshold = vars(&[("SHELL_NOTIFY_THRESHOLD", "30")]);
+        assert_eq!(
+            notification("make", 0, Duration::from_secs(20), &threshold),
+            None
+        );
+        assert_eq!(
+            notification("make", 0, long, &threshold).as_deref(),
+            Some("\x07")
+        );
+        let osc = vars(&[("SHELL_NOTIFY_THRESHOLD", "30"), ("SHELL_NOTIFY_OSC", "1")]);
+        assert_eq!(
+            notification("make; make install\n", 2, long, &osc).as_deref(),
+            Some("\x07\x1b]777;notify;shell;make  make install failed with exit code 2 after 40.0s\x07")
+        );
+        let invalid = vars(&[("SHELL_NOTIFY_THRESHOLD", "later")]);
+        assert_eq!(notification("make", 0, long, invalid), None);
+    }
+}
@@ -0,0 +1,124 @@
+//! Placeholders of `PS1` that describe the machine: `{host}` is the host
+//! name and `{ssh}` a marker that is only shown in an SSH session, so that
+//! a remote shell can't be mistaken for a local one. The marker is
+//! `$SHELL_SSH_MARKER`, or `(ssh) ` if that isn't set.
+//!
+//! `{cmd_duration}` is how long the last command took, like `2.3s`, but
+//! only when that is more than `$SHELL_CMD_DURATION_MIN` seconds (5 by
+//! default), so that slow commands stand out.
+
+use std::time::Duration;
+
+/// The marker used when `SHELL_SSH_MARKER` isn't set.
+const DEFAULT_SSH_MARKER: &str = "(ssh) ";
+
+/// The threshold used when `SHELL_CMD_DURATION_MIN` isn't set or invalid.
+const DEFAULT_CMD_DURATION_MIN: Duration = Duration::from_secs(5);
+
+/// The text of `{ssh}`, which is empty unless `SSH_CONNECTION` or `SSH_TTY`
+/// is set.
+pub fn ssh_marker(get_var: impl Fn(&str) -> Option<String>) -> String {
+    let is_ssh = ["SSH_CONNECTION", "SSH_TTY"]
+        .iter()
+        .any(|name| get_var(name).is_some_and(|value| !value.is_empty()));
+    if !is_ssh {
+        return String::new();
+    }
+    get_var("SHELL_SSH_MARKER").unwrap_or_else(|| DEFAULT_SSH_MARKER.to_string())
+}
+
+/// The text of `{host}`, the host name up to the first `.`.
+pub fn hostname() -> String {
+    let name = full_hostname().unwrap_or_default();
+    match name.split_once('.') {
+        Some((short, _)) => short.to_string(),
+        None => name,
+    }
+}
+
+/// The text of `{cmd_duration}`, which is empty for the first command and
+/// for fast ones.
+pub fn cmd_duration(
+    duration: Option<Duration>,
+    get_var: impl Fn(&str) -> Option<String>,
+) -> String {
+    let min = get_var("SHELL_CMD_DURATION_MIN")
+        .and_then(|min| Duration::try_from_secs_f64(min.trim().parse().ok()?).ok())
+        .unwrap_or(DEFAULT_CMD_DURATION_MIN);
+    match duration {
+        Some(duration) if duration > min => format_duration(duration),
+        _ => String::new(),
+    }
+}
+
+/// `2.3s` below a minute, `1m5s` and `1h2m` above.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs < 60 {
+        format!("{:.1}s", duration.as_secs_f64())
+    } else if secs < 3600 {
+        format!("{}m{}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h{}m", secs / 3600, secs % 3600 / 60)
+    }
+}
+
+#[cfg(unix)]
+fn full_hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    // SAFETY: the buffer is valid for its length, which leaves room for
+    // the terminating NUL
+    if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len() - 1) } != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    Some(String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+#[cfg(not(unix))]
+fn full_hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &'static [(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        move |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    #[test]
+    fn test_ssh_marker() {
+        assert_eq!(ssh_marker(vars(&[])), "");
+        assert_eq!(ssh_marker(vars(&[("SSH_TTY", "")])), "");
+        assert_eq!(ssh_marker(vars(&[("SSH_TTY", "/dev/pts/0")])), "(ssh) ");
+        assert_eq!(
+            ssh_marker(vars(&[
+                ("SSH_CONNECTION", "10.0.0.1 50000 10.0.0.2 22"),
+                ("SHELL_SSH_MARKER", "⚡")
+            ])),
+            "⚡"
+        );
+        // the marker alone doesn't make it an SSH session
+        assert_eq!(ssh_marker(vars(&[("SHELL_SSH_MARKER", "⚡")])), "");
+    }
+
+    #[test]
+    fn test_cmd_duration() {
+        let secs = |secs| Some(Duration::from_secs_f64(secs));
+        assert_eq!(cmd_duration(None, vars(&[])), "");
+        assert_eq!(cmd_duration(secs(4.9), vars(&[])), "");
+        assert_eq!(cmd_duration(secs(5.34), vars(&[])), "5.3s");
+        assert_eq!(cmd_duration(secs(65.0), vars(&[])), "1m5s");
+        assert_eq!(cmd_duration(secs(3720.0), vars(&[])), "1h2m");
+        let min = vars(&[("SHELL_CMD_DURATION_MIN", "0.5")]);
+        assert_eq!(cmd_duration(secs(0.7), min), "0.7s");
+        let invalid = vars(&[("SHELL_CMD_DURATION_MIN", "soon")]);
+        assert_eq!(cmd_duration(secs(2.0), invalid), "");
+    }
+}
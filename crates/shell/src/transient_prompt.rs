@@ -0,0 +1,137 @@
+//! Transient prompts: once a line is accepted, the prompt in front of it is
+//! replaced with a shorter one such as `❯ `, so that the scrollback only
+//! shows the full prompt for the line being edited. Enabled by setting
+//! `TRANSIENT_PROMPT` to the short prompt, which can use the placeholders
+//! of `PS1`, or with `SHELL_TRANSIENT_PROMPT=1` for `$ `. The same redraw
+//! shows an
+//! abbreviation expanded when it is accepted with enter, and the text from
+//! the editor when the line is edited with Ctrl-X Ctrl-E.
+
+use std::io::{IsTerminal, Write};
+
+use unicode_width::UnicodeWidthChar;
+
+/// The tab stops used by the line editor.
+const TAB_STOP: usize = 8;
+
+/// The short prompt used with `SHELL_TRANSIENT_PROMPT=1` and no
+/// `TRANSIENT_PROMPT`.
+const DEFAULT_TEMPLATE: &str = "$ ";
+
+/// The template of the transient prompt, or `None` if it is off.
+pub fn template(get_var: impl Fn(&str) -> Option<String>) -> Option<String> {
+    get_var("TRANSIENT_PROMPT").or_else(|| {
+        (get_var("SHELL_TRANSIENT_PROMPT").as_deref() == Some("1"))
+            .then(|| DEFAULT_TEMPLATE.to_string())
+    })
+}
+
+/// Redraws the accepted `line` and its `prompt` as `new_prompt` followed by
+/// `new_line`. The cursor must be on the row below the line, where the
+/// editor leaves it after the line is accepted.
+pub fn redraw(prompt: &str, line: &str, new_prompt: &str, new_line: &str) {
+    let mut stdout = std::io::stdout();
+    if !stdout.is_terminal() {
+        return;
+    }
+    let Some((terminal_size::Width(width), _)) = terminal_size::terminal_size() else {
+        return;
+    };
+    let rows = rows(&format!("{prompt}{line}"), width.into());
+    // go back to the start of the prompt and clear everything below it
+    let _ = write!(stdout, "\x1b[{rows}A\r\x1b[J{new_prompt}{new_line}\n");
+    let _ = stdout.flush();
+}
+
+/// The number of terminal rows that `text` takes up in a terminal that is
+/// `width` columns wide, wrapped the same way as the line editor does. A
+/// full last row also counts the next one, which the cursor moves to.
+fn rows(text: &str, width: usize) -> usize {
+    let mut row = 0;
+    let mut col = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        let char_width = match c {
+            '\n' => {
+                row += 1;
+                col = 0;
+                continue;
+            }
+            // escape sequences such as colors take up no space
+            '\x1b' => {
+                if chars.next() == Some('[') {
+                    for c in chars.by_ref() {
+                        if c.is_ascii_alphabetic() {
+                            break;
+                        }
+                    }
+                }
+                continue;
+            }
+            '\t' => TAB_STOP - col % TAB_STOP,
+            c => c.width().unwrap_or(0),
+        };
+        col += char_width;
+        if col > width {
+            row += 1;
+            col = char_width;
+        }
+    }
+    if col == width {
+        row += 1;
+    }
+    row + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template() {
+        let vars = |vars: &'static [(&str, &str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+        assert_eq!(template(vars(&[])), None);
+        assert_eq!(template(vars(&[("SHELL_TRANSIENT_PROMPT", "0")])), None);
+        assert_eq!(
+            template(vars(&[("SHELL_TRANSIENT_PROMPT", "1")])).as_deref(),
+            Some("$ ")
+        );
+        assert_eq!(
+            template(vars(&[
+                ("SHELL_TRANSIENT_PROMPT", "1"),
+                ("TRANSIENT_PROMPT", "❯ ")
+            ]))
+            .as_deref(),
+            Some("❯ ")
+        );
+        // setting the template alone still turns it on
+        assert_eq!(
+            template(vars(&[("TRANSIENT_PROMPT", "> ")])).as_deref(),
+            Some("> ")
+        );
+    }
+
+    #[test]
+    fn test_rows() {
+        assert_eq!(rows("~/src$ ls", 80), 1);
+        assert_eq!(rows("", 80), 1);
+        // multi-line prompts and input
+        assert_eq!(rows("~/src (main)\n$ ls", 80), 2);
+        assert_eq!(rows("$ for i in 1 2; do\necho $i\ndone", 80), 3);
+        // wrapped lines, where a full row moves the cursor to the next one
+        assert_eq!(rows(&"x".repeat(25), 10), 3);
+        assert_eq!(rows(&"x".repeat(20), 10), 3);
+        assert_eq!(rows(&format!("{}\nls", "x".repeat(10)), 10), 2);
+        // wide characters that don't fit move to the next row as a whole
+        assert_eq!(rows(&format!("{}界", "x".repeat(9)), 10), 2);
+        // colors don't count
+        assert_eq!(rows(&format!("\x1b[32m{}\x1b[0m$ ", "x".repeat(7)), 10), 1);
+        assert_eq!(rows("\tx", 10), 1);
+    }
+}
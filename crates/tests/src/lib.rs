@@ -105,7 +105,7 @@ async fn commands() {
     TestBuilder::new()
         .command("deno eval 'console.log(1)'")
         .env_var("PATH", "")
-        .assert_stderr("deno: command not found\n")
+        .assert_stderr("shell: command not found: deno\n")
         .assert_exit_code(127)
         .run()
         .await;
@@ -152,6 +152,34 @@ async fn boolean_logic() {
         .await;
 }
 
+#[tokio::test]
+async fn negated_pipelines() {
+    TestBuilder::new()
+        .command(
+            "! false; echo $?; ! true; echo $?; ! echo a | grep -q b; echo $?; ! ! false; echo $?",
+        )
+        .assert_stdout("0\n1\n0\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+
+    // with `set -e`, a negated command or one that ends its `&&` list early
+    // doesn't exit, and neither do the failures in a negated group
+    TestBuilder::new()
+        .directory("dir")
+        .command("! test -d dir && echo missing; ! { false; echo inner; }; if ! test -d other; then echo no-other; fi; echo done")
+        .assert_stdout("inner\nno-other\ndone\n")
+        .run()
+        .await;
+
+    // a function or subshell is a command of its own
+    TestBuilder::new()
+        .command("f() { ! true; }; f; echo after")
+        .assert_exit_code(1)
+        .run()
+        .await;
+}
+
 #[tokio::test]
 async fn exit() {
     TestBuilder::new()
@@ -203,6 +231,27 @@ async fn exit() {
         .assert_exit_code(2)
         .run()
         .await;
+
+    // the rest of the script doesn't run, also when exiting from a function
+    // or the body of a loop
+    TestBuilder::new()
+        .command("echo a\nexit 3\necho b\n")
+        .assert_stdout("a\n")
+        .assert_exit_code(3)
+        .run()
+        .await;
+    TestBuilder::new()
+        .command("f() { for i in 1 2; do exit 4; done; }\nf\necho after\n")
+        .assert_exit_code(4)
+        .run()
+        .await;
+
+    // a sub shell or command substitution only ends itself
+    TestBuilder::new()
+        .command("set +e\necho \"$(echo a; exit 5; echo b)\"\n(exit 6)\necho $?\n")
+        .assert_stdout("a\n6\n")
+        .run()
+        .await;
 }
 
 #[tokio::test]
@@ -228,7 +277,125 @@ async fn command_substitution() {
     TestBuilder::new()
         .command("set +e\n$(sleep 0.1 && echo 1 && exit 5 &) ; echo 2")
         .assert_stdout("2\n")
-        .assert_stderr("1: command not found\n")
+        .assert_stderr("shell: command not found: 1\n")
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn backtick_command_substitution() {
+    TestBuilder::new()
+        .command(r#"echo `echo 1 && echo 2` "[`printf 'a\n\n'`]" x`echo y`z"#)
+        .assert_stdout("1 2 [a] xyz\n")
+        .run()
+        .await;
+
+    // `\``, `\\` and `\$` are unescaped before the command is parsed
+    TestBuilder::new()
+        .command(r#"A=outer && echo `echo \`echo nested\`` `echo \$A` `echo '\\'`"#)
+        .assert_stdout("nested outer \\\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command(r#"echo "`echo \"quoted\"`" "\`not\`" '`single`'"#)
+        .assert_stdout("quoted `not` `single`\n")
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn process_substitution() {
+    // builtins and functions read and write temporary files
+    TestBuilder::new()
+        .command("cat <(echo hi) <(echo there)")
+        .assert_stdout("hi\nthere\n")
+        .run()
+        .await;
+    TestBuilder::new()
+        .command("f() { cat \"$1\"; }; f <(echo a; echo b)")
+        .assert_stdout("a\nb\n")
+        .run()
+        .await;
+    TestBuilder::new()
+        .command("echo out > >(cat) && echo done")
+        .assert_stdout("out\ndone\n")
+        .run()
+        .await;
+    TestBuilder::new()
+        .command("cat < <(echo redirected)")
+        .assert_stdout("redirected\n")
+        .run()
+        .await;
+    // the substitution writes to the output of the command before its
+    // redirect
+    TestBuilder::new()
+        .command("echo hi | tee >(cat) > /dev/null")
+        .assert_stdout("hi\n")
+        .run()
+        .await;
+
+    // the files are removed once the command finished
+    TestBuilder::new()
+        .command("path=$(echo <(true)) && test ! -e \"$path\" && echo removed")
+        .assert_stdout("removed\n")
+        .run()
+        .await;
+
+    // a failing list is reported, but doesn't fail the command
+    TestBuilder::new()
+        .command("cat <(echo a; exit 3)")
+        .assert_stdout("a\n")
+        .assert_stderr("shell: <(echo a; exit 3): exited with code 3\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("x=<(echo a)")
+        .assert_stderr(
+            "<(echo a): process substitution is only supported in the arguments and redirects of commands\n",
+        )
+        .assert_exit_code(1)
+        .run()
+        .await;
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn process_substitution_fifo() {
+    // external commands read and write FIFOs while the lists run
+    TestBuilder::new()
+        .command("diff <(printf 'a\\nb\\n') <(printf 'a\\nc\\n') || echo differ")
+        .assert_stdout("2c2\n< b\n---\n> c\ndiffer\n")
+        .run()
+        .await;
+    TestBuilder::new()
+        .command("printf 'b\\na\\n' | sort > >(cat)")
+        .assert_stdout("a\nb\n")
+        .run()
+        .await;
+    // a FIFO the command never opened doesn't block the list
+    TestBuilder::new()
+        .command("/bin/echo <(echo unused) > /dev/null && echo done")
+        .assert_stdout("done\n")
+        .run()
+        .await;
+    TestBuilder::new()
+        .command("sh -c 'cat \"$1\"' sh <(exit 5)")
+        .assert_stderr("shell: <(exit 5): exited with code 5\n")
+        .run()
+        .await;
+
+    // the files are in a directory only the user can access, which is
+    // removed with them
+    TestBuilder::new()
+        .command("f() { ls -ld \"$(dirname \"$1\")\" | cut -c1-10; }; f <(true)")
+        .assert_stdout("drwx------\n")
+        .run()
+        .await;
+    TestBuilder::new()
+        .command("dir=$(dirname <(true)) && test ! -e \"$dir\" && echo removed")
+        .assert_stdout("removed\n")
         .run()
         .await;
 }
@@ -240,6 +407,20 @@ async fn sequential_lists() {
         .assert_stdout("1\n2\n3\n4\n")
         .run()
         .await;
+
+    // a failing background job neither cancels the commands after it nor
+    // changes the exit code
+    TestBuilder::new()
+        .command("set +e; false & echo hi; echo after")
+        .assert_stdout("hi\nafter\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("(exit 4) & sleep 0.1 && echo after")
+        .assert_stdout("after\n")
+        .run()
+        .await;
 }
 #[tokio::test]
 async fn pipeline() {
@@ -288,6 +469,71 @@ async fn redirects_input() {
         .await;
 }
 
+#[tokio::test]
+async fn here_strings() {
+    // expanded without word splitting or globbing, and followed by a newline
+    TestBuilder::new()
+        .command(r#"x="a   b *" && cat <<< $x && cat <<< "$x" && wc -c <<< hi"#)
+        .assert_stdout("a   b *\na   b *\n3\n")
+        .run()
+        .await;
+    TestBuilder::new()
+        .command(r#"name=world && head -n 1 <<<hello\ $name'!'"#)
+        .assert_stdout("hello world!\n")
+        .run()
+        .await;
+    TestBuilder::new()
+        .command(r#"f() { cat; } && f <<< function && { cat; cat; } <<< once"#)
+        .assert_stdout("function\nonce\n")
+        .run()
+        .await;
+    // combined with other redirects on the same command
+    TestBuilder::new()
+        .command(r#"cat <<< "to file" > out.txt && cat out.txt"#)
+        .assert_stdout("to file\n")
+        .run()
+        .await;
+    TestBuilder::new()
+        .command(r#"x=shout && tr a-z A-Z <<< "$x" 2> /dev/null"#)
+        .assert_stdout("SHOUT\n")
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn multiple_redirects() {
+    TestBuilder::new()
+        .file("in.txt", "input\n")
+        .command("file=out.txt && cat < in.txt > $file && cat $file")
+        .assert_stdout("input\n")
+        .run()
+        .await;
+    // applied in order, each one seeing the streams of the ones before it
+    TestBuilder::new()
+        .command("sh -c 'echo out; echo err >&2' > both.txt 2>&1 && cat both.txt")
+        .assert_stdout("out\nerr\n")
+        .run()
+        .await;
+    TestBuilder::new()
+        .command("sh -c 'echo out; echo err >&2' 2>&1 > out.txt")
+        .assert_stdout("err\n")
+        .assert_file_equals("out.txt", "out\n")
+        .run()
+        .await;
+    TestBuilder::new()
+        .command("f() { echo out; echo err >&2; } && f &> both.txt && f &>> both.txt && f 2> err.txt && f 2>> err.txt")
+        .assert_stdout("out\nout\n")
+        .assert_file_equals("both.txt", "out\nerr\nout\nerr\n")
+        .assert_file_equals("err.txt", "err\nerr\n")
+        .run()
+        .await;
+    TestBuilder::new()
+        .command("echo to-stderr >&2 && echo hidden 2> /dev/null >&2")
+        .assert_stderr("to-stderr\n")
+        .run()
+        .await;
+}
+
 #[tokio::test]
 async fn pwd() {
     TestBuilder::new()
@@ -309,6 +555,83 @@ async fn pwd() {
         .await;
 }
 
+#[tokio::test]
+async fn tilde_expansion() {
+    TestBuilder::new()
+        .env_var("HOME", "/home/test")
+        .command(r#"echo ~ ~/a "~" '~/a' a~ ~unknown-user/a && A=~/bin:~:b~:~unknown-user && echo $A"#)
+        .assert_stdout("/home/test /home/test/a ~ ~/a a~ ~unknown-user/a\n/home/test/bin:/home/test:b~:~unknown-user\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .env_var("HOME", "/home/test")
+        .command("export A=~/bin:~ && printenv A && B=~/x printenv B")
+        .assert_stdout("/home/test/bin:/home/test\n/home/test/x\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .directory("sub_dir")
+        .command("cd sub_dir && echo ~+ ~-")
+        .assert_stdout(&format!("$TEMP_DIR{FOLDER_SEPARATOR}sub_dir $TEMP_DIR\n"))
+        .run()
+        .await;
+
+    #[cfg(unix)]
+    TestBuilder::new()
+        .command("case ~root/a in /*/a) echo expanded;; esac")
+        .assert_stdout("expanded\n")
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn dir_stack() {
+    TestBuilder::new()
+        .directory("a")
+        .directory("b")
+        .command("pushd a && pushd ../b && dirs -v && pushd +2 && pushd -1 && pwd")
+        .assert_stdout(&format!(
+            "$TEMP_DIR{FOLDER_SEPARATOR}a $TEMP_DIR\n\
+             $TEMP_DIR{FOLDER_SEPARATOR}b $TEMP_DIR{FOLDER_SEPARATOR}a $TEMP_DIR\n \
+             0  $TEMP_DIR{FOLDER_SEPARATOR}b\n \
+             1  $TEMP_DIR{FOLDER_SEPARATOR}a\n \
+             2  $TEMP_DIR\n\
+             $TEMP_DIR $TEMP_DIR{FOLDER_SEPARATOR}b $TEMP_DIR{FOLDER_SEPARATOR}a\n\
+             $TEMP_DIR{FOLDER_SEPARATOR}b $TEMP_DIR{FOLDER_SEPARATOR}a $TEMP_DIR\n\
+             $TEMP_DIR{FOLDER_SEPARATOR}b\n"
+        ))
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .directory("a")
+        .directory("b")
+        .command("pushd a > /dev/null && pushd ../b > /dev/null && popd +1 > /dev/null && dirs -p && popd && pwd")
+        .assert_stdout(&format!(
+            "$TEMP_DIR{FOLDER_SEPARATOR}b\n$TEMP_DIR\n$TEMP_DIR\n$TEMP_DIR\n"
+        ))
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .directory("a")
+        .command("pushd a > /dev/null && dirs -c && dirs && popd")
+        .assert_stdout(&format!("$TEMP_DIR{FOLDER_SEPARATOR}a\n"))
+        .assert_stderr("popd: directory stack empty\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("pushd +1")
+        .assert_stderr("pushd: +1: directory stack index out of range\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+}
+
 #[tokio::test]
 async fn subshells() {
     TestBuilder::new()
@@ -343,6 +666,29 @@ async fn subshells() {
         .assert_exit_code(0)
         .run()
         .await;
+    TestBuilder::new()
+        .command("x=1; f() { echo f; }; (x=2; f() { echo g; }; alias f=false); echo $x; f")
+        .assert_stdout("1\nf\n")
+        .run()
+        .await;
+    TestBuilder::new()
+        .command("x=1; (x=2; (x=3; echo $x); echo $x); { x=4; }; echo $x")
+        .assert_stdout("3\n2\n4\n")
+        .run()
+        .await;
+    // redirects apply to the whole group
+    TestBuilder::new()
+        .command("(echo a; echo b) > out.txt; { echo c; } >> out.txt; cat out.txt")
+        .assert_stdout("a\nb\nc\n")
+        .run()
+        .await;
+    TestBuilder::new()
+        .command(
+            "f() { echo f; } > out.txt; f; for i in 1 2; do echo $i; done >> out.txt; cat out.txt",
+        )
+        .assert_stdout("f\n1\n2\n")
+        .run()
+        .await;
 }
 
 #[tokio::test]
@@ -350,8 +696,22 @@ async fn subshells() {
 async fn pwd_logical() {
     TestBuilder::new()
         .directory("main")
-        .command("ln -s main symlinked_main && cd symlinked_main && pwd && pwd -L")
-        .assert_stdout("$TEMP_DIR/symlinked_main\n$TEMP_DIR/main\n")
+        .command("ln -s main symlinked_main && cd symlinked_main && pwd && pwd -P && pwd -L")
+        .assert_stdout("$TEMP_DIR/symlinked_main\n$TEMP_DIR/main\n$TEMP_DIR/symlinked_main\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .directory("main")
+        .command("ln -s main symlinked_main && cd -P symlinked_main && pwd && echo $PWD")
+        .assert_stdout("$TEMP_DIR/main\n$TEMP_DIR/main\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .directory("main/sub")
+        .command("ln -s main/sub linked_sub && cd -L linked_sub && cd .. && pwd")
+        .assert_stdout("$TEMP_DIR\n")
         .run()
         .await;
 }
@@ -792,88 +1152,192 @@ async fn glob_case_insensitive() {
 }
 
 #[tokio::test]
-async fn paren_escapes() {
-    TestBuilder::new()
-        .command(r"echo \( foo bar \)")
-        .assert_stdout("( foo bar )\n")
+async fn glob_recursive() {
+    TestBuilder::new()
+        .directory("a/b/c")
+        .directory(".hidden")
+        .file("top.rs", "")
+        .file("a/one.rs", "")
+        .file("a/b/two.txt", "")
+        .file("a/b/c/three.rs", "")
+        .file(".hidden/four.rs", "")
+        .command("echo **/*.rs && echo a/**/t* && echo **/ && echo t**.rs && echo .hidden/**")
+        .assert_stdout(&format!(
+            "a{FOLDER_SEPARATOR}b{FOLDER_SEPARATOR}c{FOLDER_SEPARATOR}three.rs \
+             a{FOLDER_SEPARATOR}one.rs top.rs\n\
+             a{FOLDER_SEPARATOR}b{FOLDER_SEPARATOR}c{FOLDER_SEPARATOR}three.rs \
+             a{FOLDER_SEPARATOR}b{FOLDER_SEPARATOR}two.txt\n\
+             a/ a{FOLDER_SEPARATOR}b/ a{FOLDER_SEPARATOR}b{FOLDER_SEPARATOR}c/\n\
+             top.rs\n\
+             .hidden{FOLDER_SEPARATOR}four.rs\n"
+        ))
         .run()
         .await;
 }
 
 #[tokio::test]
-async fn uname() {
+async fn glob_options() {
+    // without a match, the pattern is an error by default (`failglob`)
     TestBuilder::new()
-        .command("uname")
-        .assert_exit_code(0)
-        .check_stdout(false)
+        .file("a.txt", "")
+        .command("echo *.log")
+        .assert_stderr("glob: no matches found '$TEMP_DIR/*.log'\n")
+        .assert_exit_code(1)
         .run()
         .await;
 
     TestBuilder::new()
-        .command("uname -a")
-        .assert_exit_code(0)
-        .check_stdout(false)
+        .file("a.txt", "")
+        .command("set +o failglob && echo *.log [ \"*\"")
+        .assert_stdout("*.log [ *\n")
         .run()
         .await;
-}
 
-#[tokio::test]
-async fn which() {
+    // `nullglob` removes the pattern in arguments, `for` lists and redirects
     TestBuilder::new()
-        .command("which ls")
-        .assert_exit_code(0)
-        .assert_stdout("<builtin function>\n")
+        .file("a.txt", "")
+        .command("set -o nullglob && echo *.log *.txt && for f in *.log; do echo $f; done")
+        .assert_stdout("a.txt\n")
         .run()
         .await;
 
     TestBuilder::new()
-        .command("which bla foo")
+        .file("a.txt", "")
+        .command("set -o nullglob && echo hi > *.log")
+        .assert_stderr_contains("redirect path must be 1 argument, but found 0")
         .assert_exit_code(1)
-        .assert_stderr("Expected one argument\n")
         .run()
         .await;
 
     TestBuilder::new()
-        .command("alias ll=\"ls -al\" && which ll")
-        .assert_exit_code(0)
-        .assert_stdout("alias: \"ls -al\"\n")
+        .file("a.txt", "")
+        .command("set +o failglob && echo hi > *.log && cat '*.log'")
+        .assert_stdout("hi\n")
         .run()
         .await;
-}
 
-#[tokio::test]
-async fn arithmetic() {
     TestBuilder::new()
-        .command("echo $((1 + 2 * 3 + (4 / 5)))")
-        .assert_stdout("7\n")
+        .directory(".hidden")
+        .file(".hidden/b.txt", "")
+        .file("a.txt", "")
+        .command("echo * && set -o dotglob && echo * && echo **/*.txt")
+        .assert_stdout(&format!(
+            "a.txt\n.hidden a.txt\n.hidden{FOLDER_SEPARATOR}b.txt a.txt\n"
+        ))
         .run()
         .await;
+}
 
+#[tokio::test]
+async fn brace_expansion() {
     TestBuilder::new()
-        .command("echo $((a=1, b=2))")
-        .assert_stdout("2\n")
+        .command("echo a{b,c}d {1..3} {01..10..3} {c..a} x{,y} {a,b{1,2}}")
+        .assert_stdout("abd acd 1 2 3 01 04 07 10 c b a x xy a b1 b2\n")
         .run()
         .await;
-
+    // before the other expansions, which happen in each of the words
     TestBuilder::new()
-        .command("echo $((a=1, b=2, a+b))")
-        .assert_stdout("3\n")
+        .command(r#"x=1 && echo {$x,"a b"}{.txt,} && for i in {1..2}; do echo $i; done"#)
+        .assert_stdout("1.txt 1 a b.txt a b\n1\n2\n")
         .run()
         .await;
-
     TestBuilder::new()
-        .command("echo $((1 + 2))")
-        .assert_stdout("3\n")
+        .command("mkdir -p src/{commands,completion} && touch src/{commands,completion}/mod.rs && echo src/*/mod.rs")
+        .assert_stdout("src/commands/mod.rs src/completion/mod.rs\n")
+        .assert_exists("src/commands/mod.rs")
         .run()
         .await;
-
+    // quoted braces and the ones without a comma or a range stay
     TestBuilder::new()
-        .command("echo $((5 * 4))")
-        .assert_stdout("20\n")
+        .command(r#"echo "{a,b}" '{1..2}' \{a,b} {a\,b} {} {a} {1..b} a}b a,b"#)
+        .assert_stdout("{a,b} {1..2} {a,b} {a,b} {} {a} {1..b} a}b a,b\n")
         .run()
         .await;
+}
 
-    TestBuilder::new()
+#[tokio::test]
+async fn paren_escapes() {
+    TestBuilder::new()
+        .command(r"echo \( foo bar \)")
+        .assert_stdout("( foo bar )\n")
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn uname() {
+    TestBuilder::new()
+        .command("uname")
+        .assert_exit_code(0)
+        .check_stdout(false)
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("uname -a")
+        .assert_exit_code(0)
+        .check_stdout(false)
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn which() {
+    TestBuilder::new()
+        .command("which ls")
+        .assert_exit_code(0)
+        .assert_stdout("<builtin function>\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("which bla foo")
+        .assert_exit_code(1)
+        .assert_stderr("Expected one argument\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("alias ll=\"ls -al\" && which ll")
+        .assert_exit_code(0)
+        .assert_stdout("alias: \"ls -al\"\n")
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn arithmetic() {
+    TestBuilder::new()
+        .command("echo $((1 + 2 * 3 + (4 / 5)))")
+        .assert_stdout("7\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("echo $((a=1, b=2))")
+        .assert_stdout("2\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("echo $((a=1, b=2, a+b))")
+        .assert_stdout("3\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("echo $((1 + 2))")
+        .assert_stdout("3\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("echo $((5 * 4))")
+        .assert_stdout("20\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
         .command("echo $((10 / 3))")
         .assert_stdout("3\n")
         .run()
@@ -896,6 +1360,44 @@ async fn arithmetic() {
         .assert_stdout("16\n")
         .run()
         .await;
+
+    TestBuilder::new()
+        .command(
+            "i=2; echo $((3 * (i + 1))) $((0x1f + 010 + 2#11)) $((i > 1 ? i : -i)) $((-2 ** 2))",
+        )
+        .assert_stdout("9 42 2 4\n")
+        .run()
+        .await;
+
+    // assignments, increments and decrements write back to the variables
+    TestBuilder::new()
+        .command("x=5; echo $((x -= 2)) $((x++)) $x $((--x)) $((y = x << 2)) $y $((z += 1))")
+        .assert_stdout("3 3 4 3 12 12 1\n")
+        .run()
+        .await;
+
+    // a value that isn't a number is evaluated as an expression, and the
+    // right side of `&&`, `||` and the unused side of `?:` aren't
+    TestBuilder::new()
+        .command(r#"a="b + 1"; b=2; echo $((a * 2)) $((0 && (c = 1))) $((1 || (c = 1))) $((1 ? 2 : (c = 1))) ${c:-unset}"#)
+        .assert_stdout("6 0 1 2 unset\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("echo $((1 / 0)) && echo after")
+        .assert_stderr("Division by zero: 1 / 0\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command(
+            "set +e; i=0; while (( i < 3 )); do echo $i; (( i++ )); done; (( i == 0 )); echo $?",
+        )
+        .assert_stdout("0\n1\n2\n1\n")
+        .run()
+        .await;
 }
 
 #[tokio::test]
@@ -934,6 +1436,19 @@ async fn if_clause() {
         .run()
         .await;
 
+    TestBuilder::new()
+        .command(r#"if echo hi | grep -q hi; then echo "found"; fi"#)
+        .assert_stdout("found\n")
+        .run()
+        .await;
+
+    // a failing condition doesn't exit with `set -e`
+    TestBuilder::new()
+        .command(r#"if false; then echo "no"; elif true; then echo "elif"; fi"#)
+        .assert_stdout("elif\n")
+        .run()
+        .await;
+
     TestBuilder::new()
         .script_file("../../scripts/if_else.sh")
         .assert_exit_code(0)
@@ -1309,6 +1824,116 @@ async fn variable_expansion() {
         .await;
 }
 
+#[tokio::test]
+async fn parameter_expansion_defaults() {
+    // with the colon an empty value counts as unset, without it only unset does
+    TestBuilder::new()
+        .command(r#"E= && echo "[${E:-a}] [${E-b}] [${U-c}] [${E:+d}] [${E+e}] [${U+f}]""#)
+        .assert_stdout("[a] [] [c] [] [e] []\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command(r#"E= && echo "[${E=a}] [${U=b}] [${E:=c}]" && echo "$E $U""#)
+        .assert_stdout("[] [b] [c]\nc b\n")
+        .run()
+        .await;
+
+    // the word is expanded, and can contain `:` and quotes
+    TestBuilder::new()
+        .file("a.txt", "")
+        .command(r#"T=/tmp && echo "${U:-$T/sub}" ${U:-$(echo cmd)} "${U:-"a b"}" "${U:-*}" ${U:-*.txt} ${U:-a:b}"#)
+        .assert_stdout("/tmp/sub cmd a b * a.txt a:b\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command(r#"set -- a && echo ${1:?usage: foo FILE} && E= && echo "[${E?}]" && echo ${U?}"#)
+        .assert_stdout("a\n[]\n")
+        .assert_stderr("U: parameter not set\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+
+    // the error ends the script
+    TestBuilder::new()
+        .command(r#"set +e; echo before; echo ${1:?usage: foo FILE}; echo after"#)
+        .assert_stdout("before\n")
+        .assert_stderr("1: usage: foo FILE\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command(r#"E= && echo ${E:?}"#)
+        .assert_stderr("E: parameter null or not set\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+
+    // the guard at the start of a script or function
+    TestBuilder::new()
+        .command(r#"f() { : "${1:?usage: f FILE}"; echo "got $1"; }; f a.txt; f; echo after"#)
+        .assert_stdout("got a.txt\n")
+        .assert_stderr("1: usage: f FILE\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn parameter_pattern_expansion() {
+    TestBuilder::new()
+        .command(r#"F=archive.tar.gz && echo ${F%.tar.gz} ${F%.*} ${F%%.*} ${F#*.} ${F##*.}"#)
+        .assert_stdout(
+            "archive archive.tar archive tar.gz gz
+",
+        )
+        .run()
+        .await;
+
+    // `*` also matches `/`, and quoted parts of the pattern match literally
+    TestBuilder::new()
+        .command(r#"P=/usr/lib/libfoo.so && echo ${P##*/} ${P%/*} "${P#"/usr"}" ${P%"*"}"#)
+        .assert_stdout("libfoo.so /usr/lib /lib/libfoo.so /usr/lib/libfoo.so\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command(r#"P=/usr/lib/libfoo.so && echo ${P/lib/LIB} ${P//lib/LIB} ${P/#\/usr/X} ${P/%so/dll} ${P//o} ${P//\//:}"#)
+        .assert_stdout("/usr/LIB/libfoo.so /usr/LIB/LIBfoo.so X/lib/libfoo.so /usr/lib/libfoo.dll /usr/lib/libf.s :usr:lib:libfoo.so\n")
+        .run()
+        .await;
+
+    // patterns match characters, not bytes
+    TestBuilder::new()
+        .command(r#"E="é*ü" && echo "${E#?}" "${E%"*ü"}" "${E/"*"/-}" ${#E}"#)
+        .assert_stdout("*ü é é-ü 3\n")
+        .run()
+        .await;
+
+    // the offset and length are arithmetic expressions
+    TestBuilder::new()
+        .command(r#"F=archive.tar.gz && echo ${F:(-3):2} ${F:1+1:3} ${F: -2}"#)
+        .assert_stdout(".g chi gz\n")
+        .run()
+        .await;
+
+    // arrays are modified element by element
+    TestBuilder::new()
+        .command(r#"A=(x.rs y.rs z.txt) && echo ${A[@]%.rs} ${A[@]/#/-} && echo "[${U#x}]""#)
+        .assert_stdout("x y z.txt -x.rs -y.rs -z.txt\n[]\n")
+        .run()
+        .await;
+
+    // a backslash makes the next character match literally
+    TestBuilder::new()
+        .command(r#"P='a*b' && echo "${P/\*/X}" ${P//\*/Y} "${P#a\*}" "${P%\*b}" && Q='a\b?' && echo "${Q/\\/S}" "${Q##*\\}" "${Q%\?}""#)
+        .assert_stdout("aXb aYb b a\naSb? b? a\\b\n")
+        .run()
+        .await;
+}
+
 #[tokio::test]
 async fn test_set() {
     let no_such_file_error_text = no_such_file_error_text();
@@ -1463,6 +2088,1284 @@ async fn test_set() {
         .await;
 }
 
+#[tokio::test]
+async fn source() {
+    TestBuilder::new()
+        .file("vars.sh", "FOO=bar\n")
+        .command("source vars.sh && echo $FOO")
+        .assert_stdout("bar\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .file("vars.sh", "FOO=bar\n")
+        .env_var("SHELL_MAX_SOURCE_DEPTH", "0")
+        .command("source vars.sh")
+        .assert_stderr("source: maximum nesting depth exceeded (0): $TEMP_DIR/vars.sh\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+
+    // a file that is already being sourced is refused instead of recursing
+    TestBuilder::new()
+        .file("recurse.sh", "source recurse.sh\n")
+        .command("source recurse.sh")
+        .assert_exit_code(1)
+        .run()
+        .await;
+    TestBuilder::new()
+        .file("rc.sh", "source other.sh\n")
+        .file("other.sh", "source rc.sh\n")
+        .command("source rc.sh")
+        .assert_exit_code(1)
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn shift() {
+    TestBuilder::new()
+        .file(
+            "args.sh",
+            "FIRST=$1\nshift\nSECOND=$1\nCOUNT=$#\nshift 2\nREST=\"$@\"\n",
+        )
+        .command("source args.sh a b c d && echo $FIRST $SECOND $COUNT $REST")
+        .assert_stdout("a b 3 d\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .file("args.sh", "shift 3 || FAILED=yes\nFIRST=$1\nCOUNT=$#\n")
+        .command("source args.sh a b && echo $FAILED $FIRST $COUNT")
+        .assert_stdout("yes a 2\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("shift")
+        .assert_stderr("shift: shift count out of range\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("shift a")
+        .assert_stderr("shift: a: numeric argument required\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn set_positional_args() {
+    TestBuilder::new()
+        .file(
+            "args.sh",
+            "set -- a \"b c\"\nCOUNT=$#\nSECOND=$2\nset -e\nSTILL=$#\nset --\nCLEARED=$#\n",
+        )
+        .command("source args.sh x && echo $COUNT $SECOND $STILL $CLEARED")
+        .assert_stdout("2 b c 2 0\n")
+        .run()
+        .await;
+
+    // e.g. to split the output of a command into parameters
+    TestBuilder::new()
+        .file(
+            "split.sh",
+            "set -- $(echo one two three)\nshift\nREST=\"$*\"\n",
+        )
+        .command("source split.sh && echo $REST")
+        .assert_stdout("two three\n")
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn r#return() {
+    TestBuilder::new()
+        .file(
+            "early.sh",
+            "FOO=1\nif [[ $FOO == 1 ]]; then\n  return 3\nfi\nFOO=2\n",
+        )
+        .command("source early.sh || echo $FOO")
+        .assert_stdout("1\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .file("early.sh", "FOO=1 && return && FOO=2\n")
+        .command("source early.sh && echo $FOO")
+        .assert_stdout("1\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("return 1")
+        .assert_stderr("return: can only `return' from a function or sourced script\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn disown() {
+    TestBuilder::new()
+        .command("disown")
+        .assert_stderr("disown: current: no such job\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("disown %1 %2")
+        .assert_stderr("disown: %1: no such job\ndisown: %2: no such job\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("disown -a && disown -r")
+        .assert_exit_code(0)
+        .run()
+        .await;
+
+    // a disowned job keeps running, but isn't listed or waited for anymore
+    #[cfg(unix)]
+    TestBuilder::new()
+        .command("env sleep 0.2 & disown %1; jobs")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("sleep 0.2 & disown %1; wait %1")
+        .assert_stderr("wait: %1: no such job\n")
+        .assert_exit_code(127)
+        .run()
+        .await;
+
+    // `-h` keeps the job in the table
+    TestBuilder::new()
+        .command("sleep 0.2 & disown -h %1; jobs")
+        .assert_stdout("[1]+  Running                 sleep 0.2 &\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("sleep 0.2 & sleep 0.3 & disown -r; jobs")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("sleep 0.2 & sleep 0.3 & disown -a; jobs; wait %2")
+        .assert_stderr("wait: %2: no such job\n")
+        .assert_exit_code(127)
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn set_options() {
+    TestBuilder::new()
+        .command("set -o pipefail && set +o errexit && set -o")
+        .assert_stdout(concat!(
+            "dotglob        \toff\n",
+            "emacs          \ton\n",
+            "errexit        \toff\n",
+            "failglob       \ton\n",
+            "monitor        \toff\n",
+            "noexec         \toff\n",
+            "noglob         \toff\n",
+            "nounset        \toff\n",
+            "nullglob       \toff\n",
+            "pipefail       \ton\n",
+            "vi             \toff\n",
+            "xtrace         \toff\n",
+        ))
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("set -u && set -o vi && set +o")
+        .assert_stdout(concat!(
+            "set +o dotglob\n",
+            "set +o emacs\n",
+            "set -o errexit\n",
+            "set -o failglob\n",
+            "set +o monitor\n",
+            "set +o noexec\n",
+            "set +o noglob\n",
+            "set -o nounset\n",
+            "set +o nullglob\n",
+            "set +o pipefail\n",
+            "set -o vi\n",
+            "set +o xtrace\n",
+        ))
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("set -o nope")
+        .assert_stderr("set: nope: invalid option name\n")
+        .assert_exit_code(2)
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn nounset() {
+    TestBuilder::new()
+        .command("set -u && echo before && echo $UNSET && echo after")
+        .assert_stdout("before\n")
+        .assert_stderr("UNSET: unbound variable\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("set -u; echo $1; echo after")
+        .assert_stderr("1: unbound variable\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("set -u && echo ${UNSET:-default} ${UNSET:+alt}x")
+        .assert_stdout("default x\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("set -u && set +u && echo \"[$UNSET]\"")
+        .assert_stdout("[]\n")
+        .run()
+        .await;
+
+    // at the prompt only the command fails, and what it changed is kept,
+    // while a sub shell still exits
+    TestBuilder::new()
+        .interactive()
+        .command("set -u +e; x=1; echo $UNSET; echo \"after $x\"; (echo $1; echo no); echo done")
+        .assert_stdout("after 1\ndone\n")
+        .assert_stderr("UNSET: unbound variable\n1: unbound variable\n")
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn pipefail() {
+    TestBuilder::new()
+        .command("false | true")
+        .assert_exit_code(0)
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("set -o pipefail && false | true")
+        .assert_exit_code(1)
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("set -o pipefail && exit 3 | exit 2 | true")
+        .assert_exit_code(2)
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("set -o pipefail && true | echo ok")
+        .assert_stdout("ok\n")
+        .assert_exit_code(0)
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn pipestatus() {
+    TestBuilder::new()
+        .command("set +e; true | false | (exit 3); echo ${PIPESTATUS[@]} $? $PIPESTATUS")
+        .assert_stdout("0 1 3 3 0\n")
+        .run()
+        .await;
+
+    // the next pipeline replaces it, a single command is a pipeline of one
+    TestBuilder::new()
+        .command("set +e; false | true; false; echo ${#PIPESTATUS[@]} ${PIPESTATUS[0]}")
+        .assert_stdout("1 1\n")
+        .run()
+        .await;
+
+    // the exit codes are the ones before `!` and `pipefail`
+    TestBuilder::new()
+        .command("set +e -o pipefail; ! exit 2 | true; echo ${PIPESTATUS[@]} $?")
+        .assert_stdout("2 0 0\n")
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn noglob() {
+    TestBuilder::new()
+        .file("test.txt", "test\n")
+        .command("set -o noglob && echo *.txt test?.txt")
+        .assert_stdout("*.txt test?.txt\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .file("test.txt", "test\n")
+        .command("set -f && set +f && echo *.txt")
+        .assert_stdout("test.txt\n")
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn mapfile() {
+    TestBuilder::new()
+        .file("lines.txt", "first\nsecond\n")
+        .command("mapfile -t LINES < lines.txt && echo \"[$LINES]\"")
+        .assert_stdout("[first]\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("echo one | readarray && echo \"[$MAPFILE]\"")
+        .assert_stdout("[one\n]\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("echo one | mapfile -n x")
+        .assert_stderr("mapfile: x: invalid line count\n")
+        .assert_exit_code(2)
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn arrays() {
+    TestBuilder::new()
+        .command(r#"arr=(a "b c" d); echo ${arr[0]} "${arr[1]}" ${arr[-1]} ${#arr[@]} ${#arr[1]}"#)
+        .assert_stdout("a b c d 3 3\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command(r#"arr=(a "b c" d); printf "<%s>" "x${arr[@]}y" "${arr[*]}""#)
+        .assert_stdout("<xa><b c><dy><a b c d>")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("arr=(a b); arr+=(c d); arr[1]=B; i=1; echo ${arr[@]:1:2} ${arr[i+1]} $arr")
+        .assert_stdout("B c c a\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command(r#"empty=(); echo "${#empty[@]}" "[${empty[@]}]" "${empty[0]:-def}""#)
+        .assert_stdout("0 [] def\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command(r#"arr=(a); echo ${arr["x"]}"#)
+        .assert_stderr("arr[x]: bad array subscript\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+
+    // arrays can be sparse
+    TestBuilder::new()
+        .command(r#"arr=(x y); arr[5]=z; echo ${#arr[@]} "${!arr[@]}" "[${arr[3]}]" ${arr[-1]}; arr+=(w [9]=v u); echo ${!arr[*]}"#)
+        .assert_stdout("3 0 1 5 [] z\n0 1 5 6 9 10\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command(r#"arr=([2]=a [4]=b [6]=c); echo ${arr[@]:3} "|" ${arr[@]:1:2} "|" ${arr[@]: -3}"#)
+        .assert_stdout("b c | a b | b c\n")
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn associative_arrays() {
+    TestBuilder::new()
+        .command(
+            r#"declare -A map && map[one]=1 && map[two words]=2 && k=three && map[$k]=3 && echo ${map[one]} "${map[two words]}" ${map[three]} ${#map[@]}"#,
+        )
+        .assert_stdout("1 2 3 3\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command(
+            r#"declare -A map && map=([b]=2 [a]=1) && map+=([c]=3) && printf "<%s>" "${!map[@]}" "${map[@]}""#,
+        )
+        .assert_stdout("<a><b><c><1><2><3>")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command(r#"declare -A map && echo "[${map[missing]}]" ${map[missing]:-def}"#)
+        .assert_stdout("[] def\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("declare -A map && map=(value)")
+        .assert_stderr("map: value: must use subscript when assigning associative array\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("list=(a b) && declare -A list")
+        .assert_stderr("declare: list: cannot convert indexed to associative array\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+
+    // array assignments as arguments of declare
+    TestBuilder::new()
+        .command(
+            r#"k=b && declare -A map=([a]=1 [$k]="2 3") && printf "<%s>" "${!map[@]}" "${map[@]}""#,
+        )
+        .assert_stdout("<a><b><1><2 3>")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command(r#"declare -a list=(1 "2 3") && typeset -ra copy=("${list[@]}" 4) && echo ${#copy[@]} "${copy[1]}""#)
+        .assert_stdout("3 2 3\n")
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn declare() {
+    TestBuilder::new()
+        .command(
+            r#"declare -i n="2 * 3" && echo $n && n=n+1 && echo $n && typeset -i m && m="" && echo $m"#,
+        )
+        .assert_stdout("6\n7\n0\n")
+        .run()
+        .await;
+
+    // `name+=value` appends, or adds for integer variables
+    TestBuilder::new()
+        .command(
+            r#"declare -i n=5 && n+=1 && echo $n && s=ab && s+=cd && s+="e f" && echo "$s" && arr=(x y) && arr+=z && echo ${arr[@]}"#,
+        )
+        .assert_stdout("6\nabcde f\nxz y\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command(r#"P=a && P+=b sh -c 'echo $P' && echo $P"#)
+        .assert_stdout("ab\na\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command(
+            r#"declare -u up=abc && declare -l low=ABC && echo $up $low && up=def && echo $up"#,
+        )
+        .assert_stdout("ABC abc\nDEF\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command(r#"declare -x EXPORTED=1 && sh -c 'echo $EXPORTED'"#)
+        .assert_stdout("1\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("declare -r ro=1 && echo $ro && ro=2")
+        .assert_stdout("1\n")
+        .assert_stderr("ro: readonly variable\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("declare -r ro=1 && unset ro")
+        .assert_stderr("unset: ro: cannot unset: readonly variable\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("declare -r ro=1 && declare ro=2")
+        .assert_stderr("declare: ro: readonly variable\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn fg_bg() {
+    TestBuilder::new()
+        .command("fg")
+        .assert_stderr("fg: current: no such job\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("bg %2")
+        .assert_stderr("bg: %2: no such job\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+
+    // commands only become jobs when attached to a terminal with `set -m`
+    TestBuilder::new()
+        .command("set -m && echo $(sh -c 'echo 1') && set -o | grep monitor")
+        .assert_stdout("1\nmonitor        \ton\n")
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn jobs_kill_wait() {
+    TestBuilder::new()
+        .command("jobs && jobs -l && wait && jobs %+")
+        .assert_stderr("jobs: %+: no such job\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("wait %1")
+        .assert_stderr("wait: %1: no such job\n")
+        .assert_exit_code(127)
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("kill -l 9 TERM 130")
+        .assert_stdout("KILL\n15\nINT\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("kill -s FOO 1")
+        .assert_stderr("kill: FOO: invalid signal specification\n")
+        .assert_exit_code(2)
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("kill %1 x")
+        .assert_stderr("kill: %1: no such job\nkill: x: arguments must be process or job IDs\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+
+    #[cfg(unix)]
+    TestBuilder::new()
+        .command("env sleep 10 & kill $! && echo killed")
+        .assert_stdout("killed\n")
+        .run()
+        .await;
+
+    // jobs started with `&` are in the job table
+    TestBuilder::new()
+        .command("(sleep 0.1; exit 3) & jobs; wait %1")
+        .assert_stdout("[1]+  Running                 (sleep 0.1; exit 3) &\n")
+        .assert_exit_code(3)
+        .run()
+        .await;
+
+    // the builtin `sleep` has no process, so it is stopped by the shell
+    #[cfg(unix)]
+    TestBuilder::new()
+        .command("sleep 1 & jobs -l; kill %1; wait %1")
+        .assert_stdout("[1]+       Running                 sleep 1 &\n")
+        .assert_exit_code(143)
+        .run()
+        .await;
+
+    #[cfg(unix)]
+    TestBuilder::new()
+        .command("/bin/sleep 1 & wait %1")
+        .run()
+        .await;
+
+    #[cfg(unix)]
+    TestBuilder::new()
+        .command("env sleep 10 & kill %1; wait %1")
+        .assert_exit_code(143)
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn for_loop() {
+    TestBuilder::new()
+        .command("for i in 1 2 3; do echo $i; done")
+        .assert_stdout("1\n2\n3\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("for i in a b\ndo\n  echo \"item $i\"\ndone\necho \"last $i\"")
+        .assert_stdout("item a\nitem b\nlast b\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .file("a.txt", "")
+        .file("b.txt", "")
+        .command("for f in *.txt; do echo $f; done")
+        .assert_stdout("a.txt\nb.txt\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("for i in; do echo $i; done")
+        .assert_stdout("")
+        .run()
+        .await;
+
+    // the exit code is the one of the last command in the body
+    TestBuilder::new()
+        .command("for i in 1 2; do sh -c \"exit $i\"; done")
+        .assert_exit_code(1)
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("set +e; for i in 1 2; do sh -c \"exit $i\"; done")
+        .assert_exit_code(2)
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn while_loop() {
+    TestBuilder::new()
+        .command("n=0; while [ $n -lt 3 ]; do echo $n; n=$((n + 1)); done")
+        .assert_stdout("0\n1\n2\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("n=3; until [ $n -eq 0 ]; do n=$((n - 1)); done; echo $n")
+        .assert_stdout("0\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("while false; do echo never; done; echo $?")
+        .assert_stdout("0\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("n=0; while echo $n | grep -q 0; do n=1; echo in; done; echo $n")
+        .assert_stdout("in\n1\n")
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn case_clause() {
+    TestBuilder::new()
+        .command(
+            r#"for f in a.txt b.rs c.c "d e" f; do
+  case $f in
+    *.txt) echo "$f text";;
+    *.rs | *.c) echo "$f source" ;;
+    "d e") echo quoted;;
+    *) echo other
+  esac
+done"#,
+        )
+        .assert_stdout("a.txt text\nb.rs source\nc.c source\nquoted\nother\n")
+        .run()
+        .await;
+
+    // only the first matching item runs
+    TestBuilder::new()
+        .command("case abc in a*) echo first;; ab*) echo second;; esac")
+        .assert_stdout("first\n")
+        .run()
+        .await;
+
+    // quoted patterns match literally
+    TestBuilder::new()
+        .command(r#"case "a*" in "a*") echo literal;; esac; case ab in "a*") echo no;; esac"#)
+        .assert_stdout("literal\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("case x in y) echo y;; esac; echo $?")
+        .assert_stdout("0\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("case x in x) sh -c 'exit 3';; esac")
+        .assert_exit_code(3)
+        .run()
+        .await;
+
+    // `;&` runs the next body without testing its patterns
+    TestBuilder::new()
+        .command("case a in b) echo b;& a) echo a;& c) echo c;; *) echo other;; esac")
+        .assert_stdout("a\nc\n")
+        .run()
+        .await;
+
+    // `;;&` tests the patterns of the next items, which see the changes of
+    // the body
+    TestBuilder::new()
+        .command(
+            "x=a; case ab in a*) echo a; x=ab;;& b*) echo b;;& $x) echo $x;; *) echo other;; esac",
+        )
+        .assert_stdout("a\nab\n")
+        .run()
+        .await;
+
+    // the subject is not split or expanded to file names
+    TestBuilder::new()
+        .file("a.txt", "")
+        .command(r#"v="a b"; case $v in "a b") echo one word;; esac; v='*.txt'; case $v in a.txt) echo no;; '*.txt') echo pattern;; esac"#)
+        .assert_stdout("one word\npattern\n")
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn break_continue() {
+    TestBuilder::new()
+        .command("for i in 1 2 3; do if [ $i -eq 2 ]; then break; fi; echo $i; done")
+        .assert_stdout("1\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("for i in 1 2 3; do if [ $i -eq 2 ]; then continue; fi; echo $i; done")
+        .assert_stdout("1\n3\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command(
+            r#"for i in a b c; do
+  for j in 1 2 3; do
+    if [ $j -eq 2 ]; then continue 2; fi
+    if [ $i = c ]; then break 2; fi
+    echo "$i$j"
+  done
+  echo "not reached"
+done
+echo done $i"#,
+        )
+        .assert_stdout("a1\nb1\ndone c\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("n=0; while true; do n=$((n + 1)); until false; do break 2; done; done; echo $n")
+        .assert_stdout("1\n")
+        .run()
+        .await;
+
+    // a count larger than the number of loops leaves all of them
+    TestBuilder::new()
+        .command("for i in 1 2; do break 5; done; echo $i")
+        .assert_stdout("1\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("for i in a b c; do case $i in b) break;; esac; echo $i; done")
+        .assert_stdout("a\n")
+        .run()
+        .await;
+
+    // like in bash, sub shells and the commands of a pipeline can't leave
+    // the loops they were started from
+    TestBuilder::new()
+        .command("for i in 1 2; do (break); echo $i; done")
+        .assert_stdout("1\n2\n")
+        .assert_stderr("break: only meaningful in a `for', `while', or `until' loop\n")
+        .assert_stderr("break: only meaningful in a `for', `while', or `until' loop\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("for i in 1 2; do echo $(continue) $i; done")
+        .assert_stdout("1\n2\n")
+        .assert_stderr("continue: only meaningful in a `for', `while', or `until' loop\n")
+        .assert_stderr("continue: only meaningful in a `for', `while', or `until' loop\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("for i in 1 2; do echo | for j in 1; do break 2; done; echo $i; done")
+        .assert_stdout("1\n2\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("break")
+        .assert_stderr("break: only meaningful in a `for', `while', or `until' loop\n")
+        .assert_exit_code(0)
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("for i in 1; do continue 0; done")
+        .assert_stderr("continue: 0: loop count out of range\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn select_loop() {
+    let menu = "1) apple\n2) green pear\n3) cherry\n";
+    TestBuilder::new()
+        .command(
+            r#"PS3="pick: "
+select fruit in apple "green pear" cherry; do
+  echo "$REPLY:$fruit"
+  if [ "$fruit" = cherry ]; then break; fi
+done
+echo after"#,
+        )
+        .stdin("2\n\n 9 \n3\nunused\n")
+        .assert_stdout("2:green pear\n9:\n3:cherry\nafter\n")
+        .assert_stderr(&format!("{menu}pick: pick: {menu}pick: pick: "))
+        .run()
+        .await;
+
+    // the loop fails at the end of the input
+    TestBuilder::new()
+        .command("select x in a; do echo $x; done || echo failed")
+        .stdin("1\n")
+        .assert_stdout("a\nfailed\n")
+        .assert_stderr("1) a\n#? #? \n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("select x in; do echo $x; done; echo done")
+        .assert_stdout("done\n")
+        .run()
+        .await;
+
+    let menu = (1..=10)
+        .map(|i| format!("{i:>2}) w{i}\n"))
+        .collect::<String>();
+    TestBuilder::new()
+        .command("select x in w1 w2 w3 w4 w5 w6 w7 w8 w9 w10; do echo $x; break; done")
+        .stdin("10\n")
+        .assert_stdout("w10\n")
+        .assert_stderr(&format!("{menu}#? "))
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn noexec() {
+    TestBuilder::new()
+        .command("echo a; set -n; echo b; set +n; echo c")
+        .assert_stdout("a\n")
+        .run()
+        .await;
+
+    // the commands after it are skipped in loops and nested lists too
+    TestBuilder::new()
+        .command(
+            "while true; do echo a; if true; then set -o noexec; echo b; fi; echo c; done; echo d",
+        )
+        .assert_stdout("a\n")
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn line_number() {
+    TestBuilder::new()
+        .command("echo $LINENO\n\necho $LINENO; echo $LINENO\nif true; then\n  echo $LINENO\nfi")
+        .assert_stdout("1\n3\n3\n5\n")
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn dynamic_variables() {
+    // the same sequence as bash for the same seed
+    TestBuilder::new()
+        .command("RANDOM=42; echo $RANDOM $RANDOM $RANDOM")
+        .assert_stdout("17772 26794 1435\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("a=$RANDOM; if [[ $a -le 32767 ]]; then echo ok; fi")
+        .assert_stdout("ok\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("echo $SECONDS; SECONDS=100; echo $SECONDS")
+        .assert_stdout("0\n100\n")
+        .run()
+        .await;
+
+    #[cfg(unix)]
+    TestBuilder::new()
+        .command("if [[ $PPID -gt 0 ]]; then echo ok; fi")
+        .assert_stdout("ok\n")
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn special_parameters() {
+    // `$?` is updated after each command
+    TestBuilder::new()
+        .command(
+            "false || echo $?; true; echo $?; (exit 3) || echo \"$? ${?}\"; if false; then :; else echo $?; fi",
+        )
+        .assert_stdout("1\n0\n3 3\n1\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("set +e; false; echo $?; false || if [ $? -ne 0 ]; then echo failed; fi")
+        .assert_stdout("1\nfailed\n")
+        .run()
+        .await;
+
+    // in arithmetic, also inside double quotes
+    TestBuilder::new()
+        .command("false || echo $(( $? + 1 )) \"$(( $? * 2 ))\"; x=4; echo $(( $x * ${x} ))")
+        .assert_stdout("2 2\n16\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("(exit 3) || exit $?")
+        .assert_exit_code(3)
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("if [[ $$ -gt 0 ]]; then echo ok; fi")
+        .assert_stdout("ok\n")
+        .run()
+        .await;
+
+    // `$!` is the process of the last external command run with `&`
+    #[cfg(unix)]
+    TestBuilder::new()
+        .command("echo \"[$!]\"; env sleep 0.1 & if [[ $! -gt 0 ]]; then echo ok; fi")
+        .assert_stdout("[]\nok\n")
+        .run()
+        .await;
+
+    // a job of builtins has no process, but `wait` and `kill` find it by `$!`
+    TestBuilder::new().command("sleep 1 & wait $!").run().await;
+
+    TestBuilder::new()
+        .command("f() { return 4; }; f & pid=$!; wait $pid")
+        .assert_exit_code(4)
+        .run()
+        .await;
+
+    #[cfg(unix)]
+    TestBuilder::new()
+        .command("sleep 10 & pid=$!; kill $pid; wait $pid")
+        .assert_exit_code(143)
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn functions() {
+    TestBuilder::new()
+        .command(
+            "mkcd() { mkdir -p \"$1\" && cd \"$1\"; }\nmkcd sub/dir && pwd | sed 's/.*sub/sub/'",
+        )
+        .assert_stdout("sub/dir\n")
+        .run()
+        .await;
+
+    // the arguments are the positional parameters during the call only
+    TestBuilder::new()
+        .command("greet() {\n  echo \"hello $1 ($#)\"\n  shift\n}\ngreet world x\necho \"[$1] $#\"")
+        .assert_stdout("hello world (2)\n[] 0\n")
+        .run()
+        .await;
+
+    // `return` sets the exit code, otherwise it's the one of the body
+    TestBuilder::new()
+        .command(
+            "f() { return 3; echo unreachable; }; g() { false; }; set +e; f; echo $?; g; echo $?",
+        )
+        .assert_stdout("3\n1\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("fact() { if [ $1 -le 1 ]; then echo 1; else r=$(fact $(( $1 - 1 ))); echo $(( $1 * r )); fi; }; fact 4")
+        .assert_stdout("24\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("f() { f; }; f")
+        .env_var("SHELL_MAX_FUNCTION_DEPTH", "3")
+        .assert_stderr("f: maximum function nesting level exceeded (3)\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+
+    // `command` and `unset -f` get back to the builtin
+    TestBuilder::new()
+        .command("echo() { command echo \"wrapped $@\"; }; echo a; unset -f echo; echo b")
+        .assert_stdout("wrapped a\nb\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("f() (echo sub); g() { echo g; }; declare -F; declare -f f; type g; type -t f cd")
+        .assert_stdout("declare -f f\ndeclare -f g\nf() (echo sub)\ng is a function\ng() { echo g; }\nfunction\nbuiltin\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("type no_such_function")
+        .assert_stderr("type: no_such_function: not found\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn complete() {
+    TestBuilder::new()
+        .command("complete -W 'start stop' svc other && complete -f -d cmd && complete -r other && complete")
+        .assert_stdout("complete -f -d cmd\ncomplete -W 'start stop' svc\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("complete -p svc")
+        .assert_stderr("complete: svc: no completion specification\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn positional_args() {
+    // `"$@"` keeps each argument a separate word, `"$*"` joins them
+    TestBuilder::new()
+        .command("f() { for a in \"$@\"; do echo \"[$a]\"; done; echo \"<$*>\"; }; f 'a b' c; f")
+        .assert_stdout("[a b]\n[c]\n<a b c>\n<>\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("f() { echo \"x$@y\"; }; f 1 2 3; g() { REST=\"$@\"; echo $REST; }; g p q")
+        .assert_stdout("x1 2 3y\np q\n")
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn repeat() {
+    TestBuilder::new()
+        .command("repeat 3 echo hi")
+        .assert_stdout("hi\nhi\nhi\n")
+        .run()
+        .await;
+
+    // each run sees the changes of the previous ones
+    TestBuilder::new()
+        .command("n=0; inc() { n=$((n + 1)); echo $n; }; repeat 3 inc; echo \"done $n\"")
+        .assert_stdout("1\n2\n3\ndone 3\n")
+        .run()
+        .await;
+
+    // stops at the first failure with errexit, otherwise the last one counts
+    TestBuilder::new()
+        .command("n=0; f() { n=$((n + 1)); echo $n; test $n -ne 2; }; repeat 3 f")
+        .assert_stdout("1\n2\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+    TestBuilder::new()
+        .command("set +e; n=0; f() { n=$((n + 1)); test $n -ne 2; }; repeat 3 f; echo $? $n")
+        .assert_stdout("0 3\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("repeat 0 echo hi")
+        .assert_stderr("repeat: 0: expected a positive integer\n")
+        .assert_exit_code(2)
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn eval() {
+    TestBuilder::new()
+        .command("eval \"x=$((1+1))\"; echo $x")
+        .assert_stdout("2\n")
+        .run()
+        .await;
+
+    // the arguments are joined and parsed again, so quotes and variables in
+    // them are evaluated once more
+    TestBuilder::new()
+        .command(
+            r#"cmd='echo "$greeting"'; greeting=hi; eval $cmd; eval 'f() { echo "f $1"; }'; f a"#,
+        )
+        .assert_stdout("hi\nf a\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("set +e; eval 'echo a; false'; echo $?; eval; echo $?")
+        .assert_stdout("a\n1\n0\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("eval 'echo \"unterminated'")
+        .assert_stderr_contains("Failed to parse input")
+        .assert_exit_code(2)
+        .run()
+        .await;
+
+    // a command that evaluates itself stops at the maximum depth
+    TestBuilder::new()
+        .command(r#"x='eval "$x"'; eval "$x""#)
+        .env_var("SHELL_MAX_EVAL_DEPTH", "5")
+        .assert_stderr("eval: maximum nesting level exceeded (5)\n")
+        .assert_exit_code(1)
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn exec() {
+    // a command that can't be run leaves the shell in place
+    TestBuilder::new()
+        .command("set +e; exec no-such-command; echo \"still here $?\"")
+        .env_var("PATH", "")
+        .assert_stdout("still here 127\n")
+        .assert_stderr("exec: command not found: no-such-command\n")
+        .run()
+        .await;
+
+    // without a command or redirects nothing changes
+    TestBuilder::new()
+        .command("exec; echo $?")
+        .assert_stdout("0\n")
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn command_not_found() {
+    TestBuilder::new()
+        .command("gerp x")
+        .env_var("PATH", "")
+        .custom_command(
+            "grep",
+            Box::new(|_| async { ExecuteResult::from_exit_code(0) }.boxed_local()),
+        )
+        .assert_stderr("shell: command not found: gerp\ndid you mean: grep?\n")
+        .assert_exit_code(127)
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("alias hello='echo hello'; hell() { true; }; hellp")
+        .env_var("PATH", "")
+        .assert_stderr("shell: command not found: hellp\ndid you mean: hell, hello?\n")
+        .assert_exit_code(127)
+        .run()
+        .await;
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn shebang() {
+    // the shebang of a script run by the shell is a comment
+    TestBuilder::new()
+        .command("#!/usr/bin/env shell\necho hi")
+        .assert_stdout("hi\n")
+        .run()
+        .await;
+
+    // the interpreter of the shebang runs the script
+    TestBuilder::new()
+        .file("script.sh", "#!/bin/sh -e\necho \"sh $1\"\n")
+        .command("chmod +x script.sh && ./script.sh arg")
+        .assert_stdout("sh arg\n")
+        .run()
+        .await;
+
+    // an interpreter that doesn't exist is looked up by its name
+    TestBuilder::new()
+        .file("script.sh", "#!/no/such/dir/sh\necho \"sh $1\"\n")
+        .command("chmod +x script.sh && ./script.sh arg")
+        .assert_stdout("sh arg\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .file("script.sh", "#!/bin/sh\necho hi\n")
+        .command("./script.sh")
+        .assert_stderr("shell: permission denied: ./script.sh\n")
+        .assert_exit_code(126)
+        .run()
+        .await;
+}
+
+#[tokio::test]
+async fn comments() {
+    TestBuilder::new()
+        .command("# a comment\necho a # trailing\necho b;# after a separator\n  # indented")
+        .assert_stdout("a\nb\n")
+        .run()
+        .await;
+
+    // a `#` only starts a comment at the start of a word
+    TestBuilder::new()
+        .command("echo a#b c# '#d' \"#e\" \\#f")
+        .assert_stdout("a#b c# #d #e #f\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("set -- x y; v=abc; echo ${#v} $# # ${#v}")
+        .assert_stdout("3 2\n")
+        .run()
+        .await;
+
+    TestBuilder::new()
+        .command("if true; then # why\n  echo yes # because\nfi # done")
+        .assert_stdout("yes\n")
+        .run()
+        .await;
+}
+
 #[cfg(test)]
 fn no_such_file_error_text() -> &'static str {
     if cfg!(windows) {
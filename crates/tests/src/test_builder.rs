@@ -69,6 +69,7 @@ pub struct TestBuilder {
     assertions: Vec<TestAssertion>,
     assert_stdout: bool,
     assert_stderr: bool,
+    interactive: bool,
 }
 
 impl Default for TestBuilder {
@@ -106,6 +107,7 @@ impl TestBuilder {
             assertions: Default::default(),
             assert_stdout: true,
             assert_stderr: false,
+            interactive: false,
         }
     }
 
@@ -135,6 +137,12 @@ impl TestBuilder {
         self
     }
 
+    /// Runs the command like a line typed at the prompt.
+    pub fn interactive(&mut self) -> &mut Self {
+        self.interactive = true;
+        self
+    }
+
     pub fn stdin(&mut self, stdin: &str) -> &mut Self {
         self.stdin = stdin.as_bytes().to_vec();
         self
@@ -238,11 +246,12 @@ impl TestBuilder {
 
         let local_set = tokio::task::LocalSet::new();
         self.env_var("TEMP_DIR", &cwd.display().to_string());
-        let state = ShellState::new(
+        let mut state = ShellState::new(
             self.env_vars.clone(),
             &cwd,
             self.custom_commands.drain().collect(),
         );
+        state.set_interactive(self.interactive);
         let exit_code = local_set
             .run_until(execute_with_pipes(list, state, stdin, stdout, stderr))
             .await;
@@ -31,16 +31,16 @@
 > echo $((A * B + 1))
 7
 
-# # Test increment/decrement NOT IMPLEMENTED YET!
-# > export COUNT=1
-# > echo $((COUNT++))
-# 1
-# > echo $COUNT
-# 2
-
-# > export X=5
-# > echo $((--X))
-# 4
+# Test increment/decrement
+> export COUNT=1
+> echo $((COUNT++))
+> echo $COUNT
+1
+2
+
+> export X=5
+> echo $((--X))
+4
 
 # Test complex expressions
 > export BASE=2